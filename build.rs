@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Generated gRPC code is only needed when the `grpc` feature is enabled
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/inference.proto")?;
+
+    Ok(())
+}
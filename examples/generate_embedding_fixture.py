@@ -0,0 +1,56 @@
+#!/usr/bin/env python3
+"""Record reference embeddings for `inference --check-embeddings`.
+
+Embeddings come from the sentence-transformers reference implementation, so the
+fixture checks the server's tokenization, pooling and normalization against an
+independent implementation of the same model revision.
+
+    pip install sentence-transformers
+    python examples/generate_embedding_fixture.py \
+        --model sentence-transformers/all-MiniLM-L6-v2 --revision refs/pr/21 \
+        --output fixtures/all-MiniLM-L6-v2.json
+"""
+import argparse
+import json
+
+from sentence_transformers import SentenceTransformer
+
+DEFAULT_TEXTS = [
+    "Hello, world!",
+    "The quick brown fox jumps over the lazy dog.",
+    "Machine learning models turn text into dense vectors.",
+    "Short",
+    "A noticeably longer sentence, so that batches containing it pad the shorter "
+    "texts and the check covers attention-mask handling during pooling.",
+]
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument("--model", required=True, help="Hub id, matching model_id in the server config")
+    parser.add_argument("--revision", default=None, help="Hub revision, matching revision in the server config")
+    parser.add_argument("--output", required=True, help="Path of the fixture JSON to write")
+    parser.add_argument("--min-similarity", type=float, default=0.999)
+    parser.add_argument("texts", nargs="*", help="Texts to record; a built-in set is used when omitted")
+    args = parser.parse_args()
+
+    texts = args.texts or DEFAULT_TEXTS
+    model = SentenceTransformer(args.model, revision=args.revision)
+    embeddings = model.encode(texts, normalize_embeddings=True)
+
+    fixture = {
+        "model_id": args.model,
+        "revision": args.revision,
+        "min_similarity": args.min_similarity,
+        "cases": [
+            {"text": text, "embedding": embedding.tolist()}
+            for text, embedding in zip(texts, embeddings)
+        ],
+    }
+    with open(args.output, "w") as f:
+        json.dump(fixture, f)
+    print(f"Wrote {len(texts)} reference embeddings for {args.model} to {args.output}")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,48 @@
+#!/usr/bin/env python3
+"""Load an `/index/export` into faiss and check it against its metadata sidecar.
+
+The export is a faiss `IndexFlatIP` of normalized embeddings, so inner-product
+search ranks by cosine similarity as the server's `/search` does. Row `i` of the
+index is `ids[i]` and `texts[i]` in the sidecar.
+
+    pip install faiss-cpu
+    python scripts/load_faiss_export.py data/index.faiss
+"""
+import argparse
+import json
+
+import faiss
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument("index", help="Path of the exported faiss index")
+    parser.add_argument("--metadata", default=None, help="Sidecar JSON; defaults to the index path plus .json")
+    parser.add_argument("--top-k", type=int, default=5, help="Neighbours of the first document to print")
+    args = parser.parse_args()
+
+    index = faiss.read_index(args.index)
+    with open(args.metadata or args.index + ".json", encoding="utf-8") as f:
+        metadata = json.load(f)
+
+    if not isinstance(index, faiss.IndexFlatIP):
+        raise SystemExit(f"expected an IndexFlatIP, got {type(index).__name__}")
+    if index.d != metadata["dimensions"]:
+        raise SystemExit(f"index has {index.d} dimensions but the metadata says {metadata['dimensions']}")
+    if index.ntotal != len(metadata["ids"]):
+        raise SystemExit(f"index has {index.ntotal} vectors but the metadata lists {len(metadata['ids'])} ids")
+    print(f"Loaded {index.ntotal} vectors of {index.d} dimensions from {metadata['model_id']}")
+
+    # Every stored vector is unit length, so the best match of one is itself, or a duplicate, at 1
+    query = index.reconstruct(0).reshape(1, -1)
+    scores, rows = index.search(query, min(args.top_k, index.ntotal))
+    if abs(scores[0][0] - 1.0) > 1e-4:
+        raise SystemExit(f"first document should match itself with score 1, got {scores[0][0]}")
+
+    print(f"Nearest to {metadata['ids'][0]!r}:")
+    for score, row in zip(scores[0], rows[0]):
+        print(f"  {score:.4f}  {metadata['ids'][row]}  {metadata['texts'][row][:80]!r}")
+
+
+if __name__ == "__main__":
+    main()
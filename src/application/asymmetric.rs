@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+
+use crate::application::use_cases::run_with_timeout;
+use crate::domain::entities::{BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::AsymmetricEncoderService;
+
+pub struct AsymmetricEncodingUseCase {
+    encoder: Arc<dyn AsymmetricEncoderService>,
+    max_batch_size: usize,
+    max_input_chars: usize,
+    request_timeout: Duration,
+}
+
+impl AsymmetricEncodingUseCase {
+    pub fn new(
+        encoder: Arc<dyn AsymmetricEncoderService>,
+        max_batch_size: usize,
+        max_input_chars: usize,
+        request_timeout: Duration,
+    ) -> Self {
+        Self {
+            encoder,
+            max_batch_size,
+            max_input_chars,
+            request_timeout,
+        }
+    }
+
+    /// Embed a search query with the query model and prefix
+    pub async fn encode_query(&self, request: EmbeddingRequest) -> Result<EmbeddingResponse> {
+        // Business logic: validate input
+        if request.text.trim().is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        self.check_input_length(0, &request.text)?;
+
+        run_with_timeout(self.request_timeout, self.encoder.encode_query(request)).await
+    }
+
+    /// Embed documents for indexing with the passage model and prefix
+    pub async fn encode_passages(&self, request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse> {
+        // Business logic: validate input; empty passages are rejected rather than dropped,
+        // since the prefix alone would otherwise be embedded in their place
+        if request.texts.is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        if let Some(index) = request.texts.iter().position(|text| text.trim().is_empty()) {
+            return Err(InferenceError::InvalidInput {
+                message: format!("Passage {} is empty", index),
+            }.into());
+        }
+        for (index, text) in request.texts.iter().enumerate() {
+            self.check_input_length(index, text)?;
+        }
+        if request.texts.len() > self.max_batch_size {
+            return Err(InferenceError::BatchSizeExceeded {
+                size: request.texts.len(),
+                max: self.max_batch_size,
+            }.into());
+        }
+
+        run_with_timeout(self.request_timeout, self.encoder.encode_passages(request)).await
+    }
+
+    fn check_input_length(&self, index: usize, text: &str) -> Result<()> {
+        let length = text.chars().count();
+        if length > self.max_input_chars {
+            return Err(InferenceError::InputTooLong {
+                index,
+                length,
+                max: self.max_input_chars,
+            }.into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::domain::errors::InferenceError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests are rejected until the recovery timeout elapses
+    Open,
+    /// A single probe request is let through to test recovery
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitBreakerStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    /// Seconds until an open circuit lets a probe through; absent unless open
+    pub retry_after_secs: Option<u64>,
+}
+
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+    probe_in_flight: bool,
+}
+
+/// Stops sending work to a model that keeps failing, e.g. under sustained GPU OOM,
+/// so requests fail fast with 503 instead of all hitting the same error path
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    recovery_timeout: Duration,
+    inner: Mutex<BreakerState>,
+}
+
+/// Releases a half-open probe slot if the guarded call is dropped before finishing,
+/// e.g. when the client disconnects, so the breaker can't get stuck half-open
+struct ProbeGuard<'a> {
+    breaker: &'a CircuitBreaker,
+    is_probe: bool,
+    finished: bool,
+}
+
+impl Drop for ProbeGuard<'_> {
+    fn drop(&mut self) {
+        if self.is_probe && !self.finished {
+            self.breaker.inner.lock().unwrap().probe_in_flight = false;
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, recovery_timeout: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            recovery_timeout,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    /// Run `call` unless the circuit is open, recording whether it failed
+    pub async fn call<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let mut guard = ProbeGuard {
+            breaker: self,
+            is_probe: self.acquire()?,
+            finished: false,
+        };
+        let result = call.await;
+        guard.finished = true;
+        self.record(&result, guard.is_probe);
+        result
+    }
+
+    pub fn status(&self) -> CircuitBreakerStatus {
+        let inner = self.inner.lock().unwrap();
+        let (state, retry_after_secs) = match inner.state {
+            CircuitState::Open => match self.recovery_timeout.checked_sub(inner.opened_at.elapsed()) {
+                Some(remaining) => (CircuitState::Open, Some(remaining.as_secs_f64().ceil() as u64)),
+                // The next request will be let through as a probe
+                None => (CircuitState::HalfOpen, None),
+            },
+            state => (state, None),
+        };
+        CircuitBreakerStatus {
+            state,
+            consecutive_failures: inner.consecutive_failures,
+            failure_threshold: self.failure_threshold,
+            retry_after_secs,
+        }
+    }
+
+    /// Admit a request, returning whether it is the half-open probe
+    fn acquire(&self) -> Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => Ok(false),
+            CircuitState::Open => match self.recovery_timeout.checked_sub(inner.opened_at.elapsed()) {
+                Some(remaining) => Err(InferenceError::CircuitOpen {
+                    retry_after_secs: (remaining.as_secs_f64().ceil() as u64).max(1),
+                }.into()),
+                None => {
+                    tracing::info!("Circuit breaker half-open, probing with the next request");
+                    inner.state = CircuitState::HalfOpen;
+                    inner.probe_in_flight = true;
+                    Ok(true)
+                }
+            },
+            CircuitState::HalfOpen if inner.probe_in_flight => {
+                Err(InferenceError::CircuitOpen { retry_after_secs: 1 }.into())
+            }
+            CircuitState::HalfOpen => {
+                inner.probe_in_flight = true;
+                Ok(true)
+            }
+        }
+    }
+
+    fn record<T>(&self, result: &Result<T>, is_probe: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if is_probe {
+            inner.probe_in_flight = false;
+        }
+
+        match result {
+            Ok(_) => {
+                if inner.state != CircuitState::Closed {
+                    tracing::info!("Circuit breaker closed, inference recovered");
+                }
+                inner.state = CircuitState::Closed;
+                inner.consecutive_failures = 0;
+            }
+            // Bad requests say nothing about the model's health
+            Err(e) if is_client_error(e) => {}
+            Err(e) => {
+                inner.consecutive_failures += 1;
+                let should_open = inner.state == CircuitState::HalfOpen
+                    || inner.consecutive_failures >= self.failure_threshold;
+                if should_open && inner.state != CircuitState::Open {
+                    tracing::warn!(
+                        "Circuit breaker opened after {} consecutive inference failures, last: {:#}",
+                        inner.consecutive_failures,
+                        e
+                    );
+                    inner.state = CircuitState::Open;
+                    inner.opened_at = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+fn is_client_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<InferenceError>(),
+        Some(
+            InferenceError::InvalidConfig { .. }
+                | InferenceError::InvalidDimensions { .. }
+                | InferenceError::InvalidInput { .. }
+                | InferenceError::EmptyInput
+                | InferenceError::InputTooLong { .. }
+                | InferenceError::TextTooLong { .. }
+                | InferenceError::BatchSizeExceeded { .. }
+                | InferenceError::ModelNotFound { .. }
+                | InferenceError::ModelLoading
+                | InferenceError::ModelNotLoaded
+        )
+    )
+}
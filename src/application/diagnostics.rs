@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use anyhow::Result;
+use candle_core::{DType, Device, Tensor};
+
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::domain::entities::{BatchEmbeddingRequest, IsotropyResponse};
+use crate::domain::errors::InferenceError;
+
+/// Bisection steps per eigenvalue; each halves the interval, so 100 reach f64 precision
+/// from any Gershgorin bound
+const BISECTION_STEPS: usize = 100;
+
+/// Measurements of how a model's embeddings are spread over their space
+pub struct DiagnosticsUseCase {
+    embedding_use_case: Arc<EmbeddingUseCase>,
+}
+
+impl DiagnosticsUseCase {
+    pub fn new(embedding_use_case: Arc<EmbeddingUseCase>) -> Self {
+        Self { embedding_use_case }
+    }
+
+    /// Encode the texts and measure the isotropy of their normalized embeddings
+    pub async fn isotropy(&self, texts: Vec<String>, model: Option<String>) -> Result<IsotropyResponse> {
+        // Business logic: empty texts would be dropped, so the reported count would be wrong
+        if let Some(index) = texts.iter().position(|text| text.trim().is_empty()) {
+            return Err(InferenceError::InvalidInput {
+                message: format!("Text {} is empty", index),
+            }.into());
+        }
+        if texts.len() < 2 {
+            return Err(InferenceError::InvalidInput {
+                message: "isotropy needs at least 2 texts".to_string(),
+            }.into());
+        }
+        let response = self
+            .embedding_use_case
+            .encode_lines(BatchEmbeddingRequest {
+                model,
+                ..BatchEmbeddingRequest::new(texts)
+            })
+            .await?;
+        let n_texts = response.embeddings.len();
+        let (score, effective_rank) =
+            tokio::task::spawn_blocking(move || isotropy(&response.embeddings)).await??;
+        Ok(IsotropyResponse {
+            score,
+            effective_rank,
+            n_texts,
+            model_id: response.model_id,
+        })
+    }
+}
+
+/// Isotropy score and effective rank from the eigenvalues of the embeddings' second
+/// moment matrix `WᵀW / n`. It's left uncentered, so a direction every embedding shares
+/// counts as anisotropy. The score is the geometric over the arithmetic mean of the
+/// eigenvalues: 1 when variance is spread evenly, towards 0 as a few directions
+/// dominate. The effective rank is the exponential of the eigenvalues' entropy
+fn isotropy(embeddings: &[Vec<f32>]) -> Result<(f32, f32)> {
+    let samples = embeddings.len();
+    let dimensions = embeddings.first().map_or(0, Vec::len);
+    let w = Tensor::from_vec(embeddings.concat(), (samples, dimensions), &Device::Cpu)?.to_dtype(DType::F64)?;
+    // With fewer texts than dimensions the smaller Gram matrix has the same non-zero
+    // eigenvalues, and leaves out the zeros that only reflect the sample size
+    let moments = if samples < dimensions {
+        w.matmul(&w.t()?)?
+    } else {
+        w.t()?.matmul(&w)?
+    };
+    let moments = (moments / samples as f64)?.to_vec2::<f64>()?;
+
+    let eigenvalues: Vec<f64> = symmetric_eigenvalues(moments).into_iter().map(|value| value.max(0.0)).collect();
+    let total: f64 = eigenvalues.iter().sum();
+    if total <= 0.0 {
+        return Err(InferenceError::InvalidInput {
+            message: "every embedding is zero".to_string(),
+        }.into());
+    }
+    let count = eigenvalues.len() as f64;
+    let arithmetic_mean = total / count;
+    let geometric_mean = (eigenvalues.iter().map(|value| value.ln()).sum::<f64>() / count).exp();
+    let entropy: f64 = eigenvalues
+        .iter()
+        .map(|value| value / total)
+        .filter(|&p| p > 0.0)
+        .map(|p| -p * p.ln())
+        .sum();
+    Ok(((geometric_mean / arithmetic_mean) as f32, entropy.exp() as f32))
+}
+
+/// Eigenvalues of a symmetric matrix, in ascending order. Householder reflections reduce
+/// it to tridiagonal form, whose eigenvalues are then bisected using Sturm counts
+fn symmetric_eigenvalues(mut a: Vec<Vec<f64>>) -> Vec<f64> {
+    let n = a.len();
+    let mut diagonal = vec![0.0; n];
+    let mut off_diagonal = vec![0.0; n.saturating_sub(1)];
+
+    for k in 0..n.saturating_sub(1) {
+        diagonal[k] = a[k][k];
+        // Reflect column k below the diagonal onto its first entry
+        let mut v: Vec<f64> = (k + 1..n).map(|i| a[i][k]).collect();
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let alpha = if v[0] > 0.0 { -norm } else { norm };
+        off_diagonal[k] = alpha;
+        v[0] -= alpha;
+        let v_norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if v_norm == 0.0 {
+            continue;
+        }
+        v.iter_mut().for_each(|x| *x /= v_norm);
+
+        // A ← HAH with H = I - 2vvᵀ, as A - 2vqᵀ - 2qvᵀ where p = Av and q = p - (vᵀp)v
+        let p: Vec<f64> = (k + 1..n)
+            .map(|i| (k + 1..n).zip(&v).map(|(j, vj)| a[i][j] * vj).sum())
+            .collect();
+        let vp: f64 = v.iter().zip(&p).map(|(x, y)| x * y).sum();
+        let q: Vec<f64> = p.iter().zip(&v).map(|(pi, vi)| pi - vp * vi).collect();
+        for (i, (vi, qi)) in v.iter().zip(&q).enumerate() {
+            for (j, (vj, qj)) in v.iter().zip(&q).enumerate() {
+                a[k + 1 + i][k + 1 + j] -= 2.0 * (vi * qj + qi * vj);
+            }
+        }
+    }
+    if n > 0 {
+        diagonal[n - 1] = a[n - 1][n - 1];
+    }
+
+    // Gershgorin discs bound every eigenvalue
+    let radius = |i: usize| {
+        let before = if i > 0 { off_diagonal[i - 1].abs() } else { 0.0 };
+        let after = off_diagonal.get(i).map_or(0.0, |e: &f64| e.abs());
+        before + after
+    };
+    let lower = (0..n).map(|i| diagonal[i] - radius(i)).fold(f64::INFINITY, f64::min);
+    let upper = (0..n).map(|i| diagonal[i] + radius(i)).fold(f64::NEG_INFINITY, f64::max);
+
+    // Number of eigenvalues below `x`: the negative pivots of T - xI
+    let count_below = |x: f64| {
+        let mut count = 0;
+        let mut pivot = 1.0;
+        for i in 0..n {
+            let coupling = if i > 0 { off_diagonal[i - 1] * off_diagonal[i - 1] } else { 0.0 };
+            pivot = diagonal[i] - x - if i > 0 { coupling / pivot } else { 0.0 };
+            if pivot == 0.0 {
+                pivot = f64::EPSILON * (x.abs() + 1.0);
+            }
+            if pivot < 0.0 {
+                count += 1;
+            }
+        }
+        count
+    };
+
+    (0..n)
+        .map(|index| {
+            let (mut low, mut high) = (lower, upper);
+            for _ in 0..BISECTION_STEPS {
+                let middle = 0.5 * (low + high);
+                if middle == low || middle == high {
+                    break;
+                }
+                if count_below(middle) > index {
+                    high = middle;
+                } else {
+                    low = middle;
+                }
+            }
+            0.5 * (low + high)
+        })
+        .collect()
+}
@@ -0,0 +1,251 @@
+use std::sync::{Arc, RwLock};
+use anyhow::Result;
+use candle_core::{DType, Device, Tensor};
+
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::domain::entities::{BatchEmbeddingRequest, PcaFitResponse, ReduceRequest, ReduceResponse};
+use crate::domain::errors::InferenceError;
+
+/// Power iterations allowed per component before settling for the current estimate
+const MAX_POWER_ITERATIONS: usize = 500;
+/// Converged once successive estimates of a component differ in angle by about 1e-6 rad
+const POWER_ITERATION_TOLERANCE: f64 = 1e-12;
+
+/// Mean and leading principal components of a set of embeddings, for projecting
+/// embeddings of the same model onto fewer dimensions
+#[derive(Debug, Clone)]
+pub struct PcaTransform {
+    mean: Vec<f32>,
+    /// One unit-length row per component, largest variance first
+    components: Vec<Vec<f32>>,
+    explained_variance_ratio: Vec<f32>,
+    model_id: String,
+    normalize: bool,
+}
+
+impl PcaTransform {
+    /// Fit on `embeddings` by eigendecomposing their sample covariance matrix. The
+    /// components are found one at a time by power iteration, each kept orthogonal to
+    /// the ones before it
+    pub fn fit(embeddings: &[Vec<f32>], target_dim: usize, model_id: &str, normalize: bool) -> Result<Self> {
+        let samples = embeddings.len();
+        let dimensions = embeddings.first().map_or(0, Vec::len);
+        if samples < 2 {
+            return Err(InferenceError::InvalidInput {
+                message: "PCA needs at least 2 texts to fit on".to_string(),
+            }.into());
+        }
+        if target_dim == 0 || target_dim > dimensions {
+            return Err(InferenceError::InvalidInput {
+                message: format!("target_dim must be between 1 and the embedding dimensions, {}", dimensions),
+            }.into());
+        }
+
+        // f64 so the covariance of many samples doesn't lose the smaller components
+        let x = Tensor::from_vec(embeddings.concat(), (samples, dimensions), &Device::Cpu)?.to_dtype(DType::F64)?;
+        let mean = x.mean(0)?;
+        let centered = x.broadcast_sub(&mean)?;
+        let covariance = (centered.t()?.matmul(&centered)? / (samples - 1) as f64)?;
+        // The trace of the covariance matrix, the sum of every component's variance
+        let total_variance = centered.sqr()?.sum_all()?.to_scalar::<f64>()? / (samples - 1) as f64;
+
+        let mut components: Option<Tensor> = None;
+        let mut variances = Vec::with_capacity(target_dim);
+        for k in 0..target_dim {
+            let (component, variance) = leading_eigenvector(&covariance, components.as_ref(), k)?;
+            variances.push(variance);
+            components = Some(match components {
+                Some(found) => Tensor::cat(&[&found, &component], 1)?,
+                None => component,
+            });
+        }
+        let components = components.expect("target_dim is at least 1");
+
+        Ok(Self {
+            mean: mean.to_dtype(DType::F32)?.to_vec1()?,
+            components: components.t()?.to_dtype(DType::F32)?.to_vec2()?,
+            explained_variance_ratio: variances
+                .iter()
+                .map(|variance| if total_variance > 0.0 { (variance / total_variance) as f32 } else { 0.0 })
+                .collect(),
+            model_id: model_id.to_string(),
+            normalize,
+        })
+    }
+
+    /// Project embeddings of the fitted model onto the components
+    pub fn transform(&self, embeddings: &[Vec<f32>]) -> Result<Vec<Vec<f32>>> {
+        if embeddings.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some(embedding) = embeddings.iter().find(|embedding| embedding.len() != self.mean.len()) {
+            return Err(InferenceError::InvalidInput {
+                message: format!(
+                    "embedding has {} dimensions but the PCA transform was fitted on {}",
+                    embedding.len(),
+                    self.mean.len()
+                ),
+            }.into());
+        }
+        let x = Tensor::from_vec(embeddings.concat(), (embeddings.len(), self.mean.len()), &Device::Cpu)?;
+        let mean = Tensor::new(self.mean.as_slice(), &Device::Cpu)?;
+        let components = Tensor::from_vec(self.components.concat(), (self.target_dim(), self.mean.len()), &Device::Cpu)?;
+        Ok(x.broadcast_sub(&mean)?.matmul(&components.t()?)?.to_vec2()?)
+    }
+
+    pub fn target_dim(&self) -> usize {
+        self.components.len()
+    }
+}
+
+/// Unit eigenvector of the symmetric `matrix` with the largest eigenvalue among those
+/// orthogonal to the columns of `found`, as a column, and its eigenvalue
+fn leading_eigenvector(matrix: &Tensor, found: Option<&Tensor>, k: usize) -> Result<(Tensor, f64)> {
+    let dimensions = matrix.dim(0)?;
+    let deflate = |v: Tensor| -> Result<Tensor> {
+        Ok(match found {
+            Some(found) => (&v - found.matmul(&found.t()?.matmul(&v)?)?)?,
+            None => v,
+        })
+    };
+    let unit = |v: Tensor| -> Result<Option<Tensor>> {
+        let norm = v.sqr()?.sum_all()?.sqrt()?.to_scalar::<f64>()?;
+        Ok((norm > f64::EPSILON).then(|| v / norm).transpose()?)
+    };
+
+    // A fixed, irregular start keeps results reproducible; a constant vector could be
+    // orthogonal to the component sought
+    let start: Vec<f64> = (0..dimensions).map(|i| ((i + k) as f64 * 0.618_034).fract() - 0.5).collect();
+    let mut v = match unit(deflate(Tensor::from_vec(start, (dimensions, 1), &Device::Cpu)?)?)? {
+        Some(v) => v,
+        None => return Ok((Tensor::zeros((dimensions, 1), DType::F64, &Device::Cpu)?, 0.0)),
+    };
+    for _ in 0..MAX_POWER_ITERATIONS {
+        // No variance left outside the components already found: any orthogonal direction will do
+        let Some(next) = unit(deflate(matrix.matmul(&v)?)?)? else {
+            break;
+        };
+        let alignment = (&next * &v)?.sum_all()?.to_scalar::<f64>()?.abs();
+        v = next;
+        if 1.0 - alignment < POWER_ITERATION_TOLERANCE {
+            break;
+        }
+    }
+    let eigenvalue = v.t()?.matmul(&matrix.matmul(&v)?)?.flatten_all()?.to_vec1::<f64>()?[0];
+
+    // Eigenvectors are only defined up to sign; make the largest entry positive so
+    // refits on the same data give the same projection
+    let values = v.flatten_all()?.to_vec1::<f64>()?;
+    let largest = values.iter().copied().fold(0.0, |largest: f64, x| if x.abs() > largest.abs() { x } else { largest });
+    let v = if largest < 0.0 { v.neg()? } else { v };
+    Ok((v, eigenvalue.max(0.0)))
+}
+
+/// PCA over embeddings from the embedding use case, keeping the most recently saved
+/// transform for reuse
+pub struct DimensionalityReductionUseCase {
+    embedding_use_case: Arc<EmbeddingUseCase>,
+    saved: RwLock<Option<Arc<PcaTransform>>>,
+}
+
+impl DimensionalityReductionUseCase {
+    pub fn new(embedding_use_case: Arc<EmbeddingUseCase>) -> Self {
+        Self {
+            embedding_use_case,
+            saved: RwLock::new(None),
+        }
+    }
+
+    /// Encode the texts, fit PCA on their embeddings and return them reduced
+    pub async fn reduce(&self, request: ReduceRequest) -> Result<ReduceResponse> {
+        let save = request.save;
+        let (transform, embeddings) = self.fit_on(request).await?;
+        let response = ReduceResponse {
+            embeddings: transform.transform(&embeddings)?,
+            explained_variance_ratio: transform.explained_variance_ratio.clone(),
+            model_id: transform.model_id.clone(),
+        };
+        if save {
+            self.save(transform);
+        }
+        Ok(response)
+    }
+
+    /// Fit PCA on the embeddings of a corpus and keep it for `transform`
+    pub async fn fit(&self, request: ReduceRequest) -> Result<PcaFitResponse> {
+        let (transform, embeddings) = self.fit_on(request).await?;
+        let response = PcaFitResponse {
+            samples: embeddings.len(),
+            input_dimensions: transform.mean.len(),
+            target_dim: transform.target_dim(),
+            explained_variance_ratio: transform.explained_variance_ratio.clone(),
+            model_id: transform.model_id.clone(),
+        };
+        self.save(transform);
+        Ok(response)
+    }
+
+    /// Encode the texts and reduce them with the saved transform. They're encoded the way
+    /// the transform's corpus was, and must come from the same model
+    pub async fn transform(&self, texts: Vec<String>, model: Option<String>) -> Result<ReduceResponse> {
+        let transform = self
+            .saved
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .ok_or_else(|| InferenceError::InvalidInput {
+                message: "no PCA transform has been fitted; call /encode/reduce/fit first".to_string(),
+            })?;
+        let (embeddings, model_id) = self.encode(texts, model, transform.normalize).await?;
+        if model_id != transform.model_id {
+            return Err(InferenceError::InvalidInput {
+                message: format!(
+                    "the PCA transform was fitted on embeddings from '{}', not '{}'",
+                    transform.model_id, model_id
+                ),
+            }.into());
+        }
+        Ok(ReduceResponse {
+            embeddings: transform.transform(&embeddings)?,
+            explained_variance_ratio: transform.explained_variance_ratio.clone(),
+            model_id,
+        })
+    }
+
+    async fn fit_on(&self, request: ReduceRequest) -> Result<(PcaTransform, Vec<Vec<f32>>)> {
+        let (target_dim, normalize) = (request.target_dim, request.normalize);
+        let (embeddings, model_id) = self.encode(request.texts, request.model, normalize).await?;
+        // The covariance of a large corpus takes a while; keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            PcaTransform::fit(&embeddings, target_dim, &model_id, normalize).map(|transform| (transform, embeddings))
+        })
+        .await?
+    }
+
+    async fn encode(&self, texts: Vec<String>, model: Option<String>, normalize: bool) -> Result<(Vec<Vec<f32>>, String)> {
+        // Business logic: empty texts would be dropped, misaligning the output with the input
+        if let Some(index) = texts.iter().position(|text| text.trim().is_empty()) {
+            return Err(InferenceError::InvalidInput {
+                message: format!("Text {} is empty", index),
+            }.into());
+        }
+        let response = self
+            .embedding_use_case
+            .encode_lines(BatchEmbeddingRequest {
+                normalize,
+                model,
+                ..BatchEmbeddingRequest::new(texts)
+            })
+            .await?;
+        Ok((response.embeddings, response.model_id))
+    }
+
+    fn save(&self, transform: PcaTransform) {
+        tracing::info!(
+            "Saved a PCA transform of {} to {} dimensions",
+            transform.model_id,
+            transform.target_dim()
+        );
+        *self.saved.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(transform));
+    }
+}
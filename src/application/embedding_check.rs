@@ -0,0 +1,93 @@
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::domain::entities::BatchEmbeddingRequest;
+use crate::domain::errors::InferenceError;
+
+fn default_min_similarity() -> f32 {
+    0.999
+}
+
+/// Reference embeddings recorded from a known model version, used to catch
+/// regressions in tokenization, pooling and normalization after an upgrade
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingFixture {
+    /// Must match the default model's `model_id`; embeddings from other models aren't comparable
+    pub model_id: String,
+    /// Cosine similarity each embedding must reach against its reference
+    #[serde(default = "default_min_similarity")]
+    pub min_similarity: f32,
+    pub cases: Vec<FixtureCase>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FixtureCase {
+    pub text: String,
+    /// Normalized reference embedding
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub text: String,
+    pub similarity: f32,
+    pub passed: bool,
+}
+
+impl EmbeddingFixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read embedding fixture {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse embedding fixture {}", path.display()))
+    }
+
+    /// Encode every case with the default model and compare it against its reference.
+    /// Cases go through the batch path, so padding is exercised along with pooling.
+    pub async fn check(&self, embedding_use_case: &EmbeddingUseCase) -> Result<Vec<CaseResult>> {
+        let current_config = embedding_use_case.get_model_info().await?;
+        if current_config.model_id != self.model_id {
+            return Err(InferenceError::InvalidConfig {
+                message: format!(
+                    "Fixture was recorded with {}, but the default model is {}",
+                    self.model_id, current_config.model_id
+                ),
+            }.into());
+        }
+
+        let max_batch_size = embedding_use_case.get_limits().await?.max_batch_size;
+        let mut results = Vec::with_capacity(self.cases.len());
+        for cases in self.cases.chunks(max_batch_size.max(1)) {
+            let texts = cases.iter().map(|case| case.text.clone()).collect();
+            let response = embedding_use_case
+                .encode_batch(BatchEmbeddingRequest::with_normalize(texts, true))
+                .await?;
+
+            for (case, embedding) in cases.iter().zip(&response.embeddings) {
+                if embedding.len() != case.embedding.len() {
+                    return Err(anyhow!(
+                        "Reference embedding for {:?} has {} dimensions, but the model produced {}",
+                        case.text,
+                        case.embedding.len(),
+                        embedding.len()
+                    ));
+                }
+                let similarity = cosine_similarity(embedding, &case.embedding);
+                results.push(CaseResult {
+                    text: case.text.clone(),
+                    similarity,
+                    passed: similarity >= self.min_similarity,
+                });
+            }
+        }
+        Ok(results)
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b)).max(f32::EPSILON)
+}
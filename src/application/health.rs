@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Startup milestones backing the liveness, readiness and startup probes
+#[derive(Debug, Default)]
+pub struct ServiceHealth {
+    models_loaded: AtomicBool,
+    warmed_up: AtomicBool,
+    startup_complete: AtomicBool,
+}
+
+impl ServiceHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mark_models_loaded(&self) {
+        self.models_loaded.store(true, Ordering::SeqCst);
+    }
+
+    pub fn mark_warmed_up(&self) {
+        self.warmed_up.store(true, Ordering::SeqCst);
+    }
+
+    pub fn mark_startup_complete(&self) {
+        self.startup_complete.store(true, Ordering::SeqCst);
+    }
+
+    /// Every configured model has been loaded, though warmup may still be running
+    pub fn is_models_loaded(&self) -> bool {
+        self.models_loaded.load(Ordering::SeqCst)
+    }
+
+    /// A model is loaded and warmup has finished, so requests can be served
+    pub fn is_ready(&self) -> bool {
+        self.models_loaded.load(Ordering::SeqCst) && self.warmed_up.load(Ordering::SeqCst)
+    }
+
+    /// All initialization, including model download and warmup, has completed
+    pub fn is_started(&self) -> bool {
+        self.startup_complete.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use anyhow::Result;
+
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::domain::entities::{
+    BatchEmbeddingRequest, EmbeddingRequest, IndexAddRequest, IndexAddResponse, IndexExportResponse, RequestType, SearchRequest,
+    SearchResponse,
+};
+use crate::domain::errors::InferenceError;
+use crate::infrastructure::config::IndexConfig;
+use crate::infrastructure::index::EmbeddingIndex;
+
+/// Stores documents embedded by the embedding use case and searches them by query
+pub struct IndexUseCase {
+    embedding_use_case: Arc<EmbeddingUseCase>,
+    index: Arc<RwLock<EmbeddingIndex>>,
+    path: PathBuf,
+    autosave_every: Option<usize>,
+    export_path: PathBuf,
+    /// Documents added since the index was last saved or loaded
+    unsaved: AtomicUsize,
+}
+
+impl IndexUseCase {
+    pub fn new(config: &IndexConfig, embedding_use_case: Arc<EmbeddingUseCase>) -> Self {
+        Self {
+            embedding_use_case,
+            index: Arc::new(RwLock::new(EmbeddingIndex::new())),
+            path: config.path.clone(),
+            autosave_every: config.autosave_every,
+            export_path: config.export_path.clone(),
+            unsaved: AtomicUsize::new(0),
+        }
+    }
+
+    /// Embed documents as passages and store them, replacing any with the same ids
+    pub async fn add(&self, request: IndexAddRequest) -> Result<IndexAddResponse> {
+        // Business logic: an empty document would be stored without an embedding to match
+        if let Some(index) = request.texts.iter().position(|text| text.trim().is_empty()) {
+            return Err(InferenceError::InvalidInput {
+                message: format!("Document {} is empty", index),
+            }.into());
+        }
+        let mut ids = request.ids.map(|ids| ids.into_iter().map(Some).collect::<Vec<_>>());
+        if ids.as_ref().is_some_and(|ids| ids.len() != request.texts.len()) {
+            return Err(InferenceError::InvalidInput {
+                message: "ids must have one entry per text".to_string(),
+            }.into());
+        }
+
+        let response = self
+            .embedding_use_case
+            .encode_batch(BatchEmbeddingRequest {
+                model: request.model,
+                request_type: Some(RequestType::Passage),
+                ..BatchEmbeddingRequest::new(request.texts)
+            })
+            .await?;
+
+        let (stored_ids, total) = {
+            let mut index = self.index.write().unwrap_or_else(|e| e.into_inner());
+            // Checked up front so a model mismatch doesn't leave the batch half added
+            index.check_model(&response.model_id)?;
+            let stored_ids = response
+                .texts
+                .into_iter()
+                .zip(response.embeddings)
+                .enumerate()
+                .map(|(position, (text, embedding))| {
+                    let id = ids.as_mut().and_then(|ids| ids[position].take());
+                    index.add(id, text, embedding, &response.model_id)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            (stored_ids, index.len())
+        };
+
+        let unsaved = self.unsaved.fetch_add(stored_ids.len(), Ordering::Relaxed) + stored_ids.len();
+        if self.autosave_every.is_some_and(|every| unsaved >= every) {
+            // The documents are stored either way, so a failed autosave doesn't fail the add
+            if let Err(e) = self.save().await {
+                tracing::warn!("Failed to autosave the index to {}: {}", self.path.display(), e);
+            }
+        }
+
+        Ok(IndexAddResponse { ids: stored_ids, total })
+    }
+
+    /// The indexed documents most similar to the query, best first
+    pub async fn search(&self, request: SearchRequest) -> Result<SearchResponse> {
+        if request.top_k == 0 {
+            return Err(InferenceError::InvalidInput {
+                message: "top_k must be at least 1".to_string(),
+            }.into());
+        }
+        let response = self
+            .embedding_use_case
+            .encode_single(EmbeddingRequest {
+                model: request.model,
+                request_type: Some(RequestType::Query),
+                ..EmbeddingRequest::new(request.query)
+            })
+            .await?;
+
+        let index = self.index.read().unwrap_or_else(|e| e.into_inner());
+        index.check_model(&response.model_id)?;
+        Ok(SearchResponse {
+            results: index.search(&response.embedding, request.top_k, request.threshold),
+            model_id: response.model_id,
+        })
+    }
+
+    /// Remove every document, returning how many were removed
+    pub fn clear(&self) -> usize {
+        self.index.write().unwrap_or_else(|e| e.into_inner()).clear()
+    }
+
+    /// Write the index to the configured path, returning how many documents were saved
+    pub async fn save(&self) -> Result<usize> {
+        let (index, path) = (self.index.clone(), self.path.clone());
+        // Adds wait for the write, so the file is a consistent snapshot
+        let count = tokio::task::spawn_blocking(move || {
+            let index = index.read().unwrap_or_else(|e| e.into_inner());
+            index.save_to_file(&path).map(|()| index.len())
+        })
+        .await??;
+        self.unsaved.store(0, Ordering::Relaxed);
+        tracing::info!("Saved {} indexed documents to {}", count, self.path.display());
+        Ok(count)
+    }
+
+    /// Export the index for faiss to the configured path, with the ids and texts in a
+    /// JSON sidecar beside it
+    pub async fn export_faiss(&self) -> Result<IndexExportResponse> {
+        let index_path = self.export_path.clone();
+        let mut metadata_path = index_path.clone().into_os_string();
+        metadata_path.push(".json");
+        let metadata_path = PathBuf::from(metadata_path);
+
+        let (index, paths) = (self.index.clone(), (index_path.clone(), metadata_path.clone()));
+        let documents = tokio::task::spawn_blocking(move || {
+            let index = index.read().unwrap_or_else(|e| e.into_inner());
+            index.export_faiss(&paths.0, &paths.1).map(|()| index.len())
+        })
+        .await??;
+        tracing::info!("Exported {} indexed documents to {}", documents, index_path.display());
+        Ok(IndexExportResponse { documents, index_path, metadata_path })
+    }
+
+    /// Replace the index with the one saved at the configured path, returning how many
+    /// documents it holds
+    pub async fn load(&self) -> Result<usize> {
+        if !self.path.exists() {
+            return Err(InferenceError::InvalidInput {
+                message: format!("no saved index at {}", self.path.display()),
+            }.into());
+        }
+        let path = self.path.clone();
+        let loaded = tokio::task::spawn_blocking(move || EmbeddingIndex::load_from_file(&path)).await??;
+        let count = loaded.len();
+        *self.index.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+        self.unsaved.store(0, Ordering::Relaxed);
+        tracing::info!("Loaded {} indexed documents from {}", count, self.path.display());
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::SearchRequest;
+    use crate::infrastructure::config::ServerConfig;
+    use crate::test_support::{embedding_use_case, tiny_model_loader};
+
+    fn add_request(texts: &[&str]) -> IndexAddRequest {
+        IndexAddRequest {
+            texts: texts.iter().map(|text| text.to_string()).collect(),
+            ids: None,
+            model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn autosaves_after_the_configured_number_of_adds_and_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = IndexConfig {
+            path: dir.path().join("index.msgpack"),
+            autosave_every: Some(3),
+            export_path: dir.path().join("index.faiss"),
+        };
+        let embeddings = Arc::new(embedding_use_case(tiny_model_loader().await, &ServerConfig::default()));
+        let use_case = IndexUseCase::new(&config, embeddings.clone());
+
+        use_case.add(add_request(&["the cat", "a dog"])).await.unwrap();
+        assert!(!config.path.exists());
+        use_case.add(add_request(&["hello world"])).await.unwrap();
+        assert!(config.path.exists());
+
+        let restarted = IndexUseCase::new(&config, embeddings);
+        assert_eq!(restarted.load().await.unwrap(), 3);
+        let search = || SearchRequest { query: "the cat".to_string(), top_k: 3, threshold: None, model: None };
+        let before = use_case.search(search()).await.unwrap();
+        let after = restarted.search(search()).await.unwrap();
+        let ranked = |response: &SearchResponse| {
+            response.results.iter().map(|result| (result.id.clone(), result.score.to_bits())).collect::<Vec<_>>()
+        };
+        assert_eq!(ranked(&after), ranked(&before));
+    }
+}
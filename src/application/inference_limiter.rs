@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::domain::entities::OverloadMode;
+use crate::domain::errors::InferenceError;
+
+/// Caps how many forward passes run at once, so a flood of large batches queues
+/// or is turned away instead of exhausting memory
+#[derive(Clone)]
+pub struct InferenceLimiter {
+    permits: Arc<Semaphore>,
+    max_concurrent: usize,
+    mode: OverloadMode,
+}
+
+impl InferenceLimiter {
+    pub fn new(max_concurrent: usize, mode: OverloadMode) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            mode,
+        }
+    }
+
+    /// Claim a slot for one inference, released when the permit drops. Queue mode waits
+    /// up to `timeout` for one to free up; reject mode fails straight away.
+    pub async fn acquire(&self, timeout: Duration) -> Result<OwnedSemaphorePermit> {
+        let overloaded = || InferenceError::Overloaded { max: self.max_concurrent };
+        match self.mode {
+            OverloadMode::Reject => self.permits.clone().try_acquire_owned().map_err(|_| overloaded().into()),
+            OverloadMode::Queue => match tokio::time::timeout(timeout, self.permits.clone().acquire_owned()).await {
+                Ok(permit) => Ok(permit?),
+                Err(_) => Err(InferenceError::Timeout {
+                    timeout_ms: timeout.as_millis() as u64,
+                }.into()),
+            },
+        }
+    }
+}
@@ -1 +1,12 @@
-pub mod use_cases;
\ No newline at end of file
+pub mod asymmetric;
+pub mod circuit_breaker;
+pub mod diagnostics;
+pub mod dimensionality_reduction;
+pub mod embedding_check;
+pub mod health;
+pub mod index;
+pub mod inference_limiter;
+pub mod model_management;
+pub mod rerank;
+pub mod sparse;
+pub mod use_cases;
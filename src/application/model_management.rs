@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use anyhow::Result;
+use futures::future::join_all;
+
+use crate::domain::entities::{ModelConfig, DEFAULT_MODEL_NAME};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::ModelRepository;
+
+pub struct ModelManagementUseCase {
+    model_repository: Arc<dyn ModelRepository>,
+}
+
+impl ModelManagementUseCase {
+    pub fn new(model_repository: Arc<dyn ModelRepository>) -> Self {
+        Self { model_repository }
+    }
+
+    /// Load every model concurrently, each registered under its `model_id` so requests
+    /// can select it by id. A model that fails to load is logged and skipped rather
+    /// than aborting the others; the ids that did load are returned in input order.
+    pub async fn preload_models(&self, configs: Vec<ModelConfig>) -> Result<Vec<String>> {
+        let loads = configs.iter().map(|config| async move {
+            let result = self.model_repository.load_named_model(&config.model_id, config).await;
+            (config.model_id.clone(), result)
+        });
+
+        let mut loaded = Vec::with_capacity(configs.len());
+        for (model_id, result) in join_all(loads).await {
+            match result {
+                Ok(()) => loaded.push(model_id),
+                Err(e) => tracing::warn!("Failed to preload model {}: {:#}", model_id, e),
+            }
+        }
+
+        tracing::info!("Preloaded {}/{} models", loaded.len(), configs.len());
+        Ok(loaded)
+    }
+
+    /// Configuration of the model loaded under `name`
+    pub async fn get_model_info(&self, name: &str) -> Result<ModelConfig> {
+        self.model_repository.get_model_config(name).await
+    }
+
+    /// Free a loaded model; requests already running on it finish first
+    pub async fn unload_model(&self, name: &str) -> Result<()> {
+        // Business logic: requests without a `model` field always need the default model
+        if name == DEFAULT_MODEL_NAME {
+            return Err(InferenceError::InvalidInput {
+                message: "The default model can't be unloaded; switch it with /model/switch instead".to_string(),
+            }.into());
+        }
+        self.model_repository.unload_model(name).await
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+
+use crate::domain::entities::{RerankRequest, RerankResponse};
+use crate::application::use_cases::run_with_timeout;
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::CrossEncoderService;
+
+pub struct RerankUseCase {
+    cross_encoder: Arc<dyn CrossEncoderService>,
+    max_batch_size: usize,
+    request_timeout: Duration,
+}
+
+impl RerankUseCase {
+    pub fn new(cross_encoder: Arc<dyn CrossEncoderService>, max_batch_size: usize, request_timeout: Duration) -> Self {
+        Self {
+            cross_encoder,
+            max_batch_size,
+            request_timeout,
+        }
+    }
+
+    /// Score every passage against the query and rank them from most to least relevant
+    pub async fn rerank(&self, request: RerankRequest) -> Result<RerankResponse> {
+        // Business logic: validate input
+        if request.query.trim().is_empty() {
+            return Err(InferenceError::InvalidInput {
+                message: "Query cannot be empty".to_string(),
+            }.into());
+        }
+        if request.passages.is_empty() {
+            return Err(InferenceError::InvalidInput {
+                message: "Passage list cannot be empty".to_string(),
+            }.into());
+        }
+        if request.passages.len() > self.max_batch_size {
+            return Err(InferenceError::BatchSizeExceeded {
+                size: request.passages.len(),
+                max: self.max_batch_size,
+            }.into());
+        }
+
+        let scores = run_with_timeout(
+            self.request_timeout,
+            self.cross_encoder.score(&request.query, &request.passages),
+        )
+        .await?;
+
+        let mut ranked_indices: Vec<usize> = (0..scores.len()).collect();
+        ranked_indices.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+        Ok(RerankResponse { scores, ranked_indices })
+    }
+}
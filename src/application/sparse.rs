@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+
+use crate::application::use_cases::run_with_timeout;
+use crate::domain::entities::{SparseEmbedding, SparseEmbeddingRequest};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::SparseEncoderService;
+
+pub struct SparseEncodingUseCase {
+    sparse_encoder: Arc<dyn SparseEncoderService>,
+    max_input_chars: usize,
+    request_timeout: Duration,
+}
+
+impl SparseEncodingUseCase {
+    pub fn new(sparse_encoder: Arc<dyn SparseEncoderService>, max_input_chars: usize, request_timeout: Duration) -> Self {
+        Self {
+            sparse_encoder,
+            max_input_chars,
+            request_timeout,
+        }
+    }
+
+    /// Lexical term weights of a text, for pairing with dense embeddings in hybrid search
+    pub async fn encode(&self, request: SparseEmbeddingRequest) -> Result<SparseEmbedding> {
+        // Business logic: validate input
+        if request.text.trim().is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        let length = request.text.chars().count();
+        if length > self.max_input_chars {
+            return Err(InferenceError::InputTooLong {
+                index: 0,
+                length,
+                max: self.max_input_chars,
+            }.into());
+        }
+
+        run_with_timeout(self.request_timeout, self.sparse_encoder.encode_sparse(&request.text)).await
+    }
+}
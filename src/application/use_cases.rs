@@ -1,44 +1,486 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 use anyhow::Result;
+use candle_core::{Device, Tensor};
+use futures::StreamExt;
+use tokio::sync::{mpsc, OwnedSemaphorePermit};
+use tracing::Instrument;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 
-use crate::domain::entities::{BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse};
-use crate::domain::traits::{EmbeddingService, ModelRepository};
+use crate::application::circuit_breaker::CircuitBreaker;
+use crate::application::health::ServiceHealth;
+use crate::application::inference_limiter::InferenceLimiter;
+use crate::domain::entities::{BatchEmbeddingRequest, BatchMode, BatchEmbeddingResponse, CompareRequest, CompareResponse, DocumentChunk, DocumentEmbeddingRequest, DocumentEmbeddingResponse, EmbeddingRequest, EmbeddingResponse, IndexedEmbedding, InferenceProbe, ModelConfig, ModelStats, ModelVariant, OverloadMode, PreprocessingStep, RequestLimits, SimilarityMatrixResponse, SimilarityMetric, TokenEmbeddingRequest, TokenEmbeddingResponse, TokenizeRequest, TokenizeResponse, DEFAULT_MODEL_NAME};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::{ConfigurationService, EmbeddingCache, EmbeddingService, ModelRepository, TextPreprocessor};
 
 pub struct EmbeddingUseCase {
     embedding_service: Arc<dyn EmbeddingService>,
     model_repository: Arc<dyn ModelRepository>,
+    max_batch_size: usize,
+    /// Texts per sub-batch of a streamed batch, each sent as soon as it completes
+    stream_batch_size: usize,
+    batch_mode: BatchMode,
+    request_timeout: Duration,
+    embedding_cache: Option<Arc<dyn EmbeddingCache>>,
+    max_similarity_matrix_size: usize,
+    max_input_chars: usize,
+    max_input_bytes: usize,
+    allow_model_paths: bool,
+    config_service: Option<Arc<dyn ConfigurationService>>,
+    health: Option<Arc<ServiceHealth>>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    inference_limiter: Option<InferenceLimiter>,
+    preprocessors: HashMap<String, Arc<dyn TextPreprocessor>>,
+    preprocessing_steps: HashMap<PreprocessingStep, Arc<dyn TextPreprocessor>>,
+}
+
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_SIMILARITY_MATRIX_SIZE: usize = 256;
+const DEFAULT_MAX_INPUT_CHARS: usize = 100_000;
+const DEFAULT_MAX_INPUT_BYTES: usize = 65_536;
+const DEEP_HEALTH_PROBE_TEXT: &str = "health check";
+/// Sentence splitter documents are chunked with when a request doesn't name one
+pub const DEFAULT_SENTENCE_SPLITTER: &str = "regex";
+
+/// Fail with `InferenceError::Timeout` if the encoding work doesn't finish in time
+pub(crate) async fn run_with_timeout<T>(timeout: Duration, future: impl Future<Output = Result<T>>) -> Result<T> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => Err(InferenceError::Timeout {
+            timeout_ms: timeout.as_millis() as u64,
+        }.into()),
+    }
+}
+
+/// Cosine similarity of every pair of embeddings as `normalized · normalizedᵀ`; an
+/// all-zero embedding has similarity 0 to everything, itself included
+fn cosine_similarity_matrix(embeddings: &[Vec<f32>]) -> Result<Vec<Vec<f32>>> {
+    let n = embeddings.len();
+    let dim = embeddings.first().map(Vec::len).unwrap_or(0);
+    let matrix = Tensor::from_vec(embeddings.concat(), (n, dim), &Device::Cpu)?;
+    // Clamped so a zero row stays zero rather than dividing into NaN
+    let norms = matrix.sqr()?.sum_keepdim(1)?.sqrt()?.maximum(1e-12)?;
+    let normalized = matrix.broadcast_div(&norms)?;
+    Ok(normalized.matmul(&normalized.t()?)?.to_vec2::<f32>()?)
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Scale an embedding to unit length in place, as the model does for `normalize`
+fn normalize_l2(embedding: &mut [f32]) {
+    let norm = dot(embedding, embedding).sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Business logic: per-text normalize flags and ids must line up with the texts they apply to
+fn check_aligned_fields(request: &BatchEmbeddingRequest) -> Result<()> {
+    let lengths = [
+        ("normalize", request.normalize_each.as_ref().map(Vec::len)),
+        ("ids", request.ids.as_ref().map(Vec::len)),
+    ];
+    for (field, length) in lengths {
+        match length {
+            Some(length) if length != request.texts.len() => {
+                return Err(InferenceError::InvalidInput {
+                    message: format!("{} has {} entries for {} texts", field, length, request.texts.len()),
+                }.into());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Split `items` into those whose `keep` flag is set and the rest, both in order
+fn partition_kept<T>(items: Vec<T>, keep: &[bool]) -> (Vec<T>, Vec<T>) {
+    let (kept, dropped): (Vec<_>, Vec<_>) = items.into_iter().zip(keep).partition(|(_, keep)| **keep);
+    (kept.into_iter().map(|(item, _)| item).collect(), dropped.into_iter().map(|(item, _)| item).collect())
+}
+
+/// Attach the resolved model to the enclosing request span for structured logs
+fn record_model_id(model_id: &str) {
+    tracing::Span::current().record("model_id", model_id);
 }
 
 impl EmbeddingUseCase {
     pub fn new(
         embedding_service: Arc<dyn EmbeddingService>,
         model_repository: Arc<dyn ModelRepository>,
+        max_batch_size: usize,
     ) -> Self {
         Self { 
             embedding_service,
             model_repository,
+            max_batch_size,
+            stream_batch_size: max_batch_size,
+            batch_mode: BatchMode::Reject,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            embedding_cache: None,
+            max_similarity_matrix_size: DEFAULT_MAX_SIMILARITY_MATRIX_SIZE,
+            max_input_chars: DEFAULT_MAX_INPUT_CHARS,
+            max_input_bytes: DEFAULT_MAX_INPUT_BYTES,
+            allow_model_paths: false,
+            config_service: None,
+            health: None,
+            circuit_breaker: None,
+            inference_limiter: None,
+            preprocessors: HashMap::new(),
+            preprocessing_steps: HashMap::new(),
+        }
+    }
+
+    /// Record switched models in `config_service` so they can outlive the process
+    pub fn with_config_service(mut self, config_service: Arc<dyn ConfigurationService>) -> Self {
+        self.config_service = Some(config_service);
+        self
+    }
+
+    /// Fail fast with `CircuitOpen` while single and batch encoding keep failing
+    pub fn with_circuit_breaker(mut self, circuit_breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Allow at most `max_concurrent` inferences in flight; `mode` decides whether
+    /// requests beyond that wait for a slot or fail with `Overloaded`
+    pub fn with_concurrency_limit(mut self, max_concurrent: usize, mode: OverloadMode) -> Self {
+        self.inference_limiter = Some(InferenceLimiter::new(max_concurrent, mode));
+        self
+    }
+
+    /// Register a sentence splitter that document requests can select by `name`
+    pub fn with_preprocessor(mut self, name: &str, preprocessor: Arc<dyn TextPreprocessor>) -> Self {
+        self.preprocessors.insert(name.to_string(), preprocessor);
+        self
+    }
+
+    /// Implementation of a cleanup step that single-text requests can list in `preprocessing`
+    pub fn with_preprocessing_step(mut self, step: PreprocessingStep, preprocessor: Arc<dyn TextPreprocessor>) -> Self {
+        self.preprocessing_steps.insert(step, preprocessor);
+        self
+    }
+
+    /// Answer requests made before models finish loading with `InferenceError::ModelLoading`
+    pub fn with_health(mut self, health: Arc<ServiceHealth>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Reject texts longer than `max_input_chars` before they reach the tokenizer
+    pub fn with_max_input_chars(mut self, max_input_chars: usize) -> Self {
+        self.max_input_chars = max_input_chars;
+        self
+    }
+
+    /// Reject texts larger than `max_input_bytes`, measured after NFC normalization
+    pub fn with_max_input_bytes(mut self, max_input_bytes: usize) -> Self {
+        self.max_input_bytes = max_input_bytes;
+        self
+    }
+
+    /// Let `switch_model` load from local directories; otherwise only Hub ids are accepted
+    pub fn with_allow_model_paths(mut self, allow_model_paths: bool) -> Self {
+        self.allow_model_paths = allow_model_paths;
+        self
+    }
+
+    /// Bound how long a single encode call may spend on inference
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Serve repeated single-text requests from `cache` instead of running inference
+    pub fn with_cache(mut self, cache: Arc<dyn EmbeddingCache>) -> Self {
+        self.embedding_cache = Some(cache);
+        self
+    }
+
+    /// Send streamed batches in sub-batches of `stream_batch_size` rather than
+    /// `max_batch_size`; smaller sub-batches deliver the first results sooner
+    pub fn with_stream_batch_size(mut self, stream_batch_size: usize) -> Self {
+        self.stream_batch_size = stream_batch_size;
+        self
+    }
+
+    /// Cap the number of texts a similarity matrix may be computed over
+    pub fn with_max_similarity_matrix_size(mut self, max_similarity_matrix_size: usize) -> Self {
+        self.max_similarity_matrix_size = max_similarity_matrix_size;
+        self
+    }
+
+    /// Choose how batches larger than `max_batch_size` are handled
+    pub fn with_batch_mode(mut self, batch_mode: BatchMode) -> Self {
+        self.batch_mode = batch_mode;
+        self
+    }
+
+    /// Report the limits requests are validated against
+    pub async fn get_limits(&self) -> Result<RequestLimits> {
+        let current_config = self.resolve_model_config(DEFAULT_MODEL_NAME).await?;
+        Ok(RequestLimits {
+            max_batch_size: self.max_batch_size,
+            max_text_length: current_config.max_sequence_length,
+            max_input_chars: self.max_input_chars,
+            max_input_bytes: self.max_input_bytes,
+        })
+    }
+
+    /// Business logic: models loading in the background can't serve requests yet
+    fn ensure_models_loaded(&self) -> Result<()> {
+        match &self.health {
+            Some(health) if !health.is_models_loaded() => Err(InferenceError::ModelLoading.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Look up a loaded model, distinguishing "not loaded yet" from "unknown"
+    async fn resolve_model_config(&self, name: &str) -> Result<ModelConfig> {
+        self.ensure_models_loaded()?;
+        self.model_repository.get_model_config(name).await
+    }
+
+    /// Point `model` at the loaded model with the same `model_id` that runs as `variant`
+    /// asks, preferring the named model itself. Variants are configured and loaded like
+    /// any named model; a request never loads one
+    async fn select_variant(&self, model: &mut Option<String>, variant: ModelVariant) -> Result<()> {
+        if variant.is_any() {
+            return Ok(());
+        }
+        let requested = model.as_deref().unwrap_or(DEFAULT_MODEL_NAME).to_string();
+        let model_id = self.resolve_model_config(&requested).await?.model_id;
+
+        let mut names = self.model_repository.list_models().await?;
+        names.sort_by_key(|name| *name != requested);
+        let mut loaded = Vec::new();
+        for name in names {
+            // Skip models unloaded since they were listed
+            let Ok(config) = self.model_repository.get_model_config(&name).await else {
+                continue;
+            };
+            if config.model_id != model_id {
+                continue;
+            }
+            let Ok(stats) = self.embedding_service.get_model_stats(&name).await else {
+                continue;
+            };
+            let device_matches = variant
+                .device
+                .as_deref()
+                .is_none_or(|device| stats.devices.iter().all(|label| device_matches(device, label)));
+            let dtype_matches = variant.dtype.is_none_or(|dtype| dtype.dtype_name() == stats.dtype);
+            if device_matches && dtype_matches {
+                tracing::debug!("Selected variant '{}' of {} for {:?}", name, model_id, variant);
+                *model = Some(name);
+                return Ok(());
+            }
+            loaded.push(format!("'{}' on {} as {}", name, stats.devices.join(", "), stats.dtype));
+        }
+
+        let wanted = [
+            variant.device.map(|device| format!("on {}", device)),
+            variant.dtype.map(|dtype| format!("as {}", dtype.dtype_name())),
+        ];
+        Err(InferenceError::InvalidInput {
+            message: format!(
+                "no loaded variant of {} runs {}; loaded: {}",
+                model_id,
+                wanted.into_iter().flatten().collect::<Vec<_>>().join(" "),
+                loaded.join(", ")
+            ),
+        }.into())
+    }
+
+    /// Business logic: hold an inference slot for as long as the returned permit lives
+    async fn acquire_inference_slot(&self) -> Result<Option<OwnedSemaphorePermit>> {
+        match &self.inference_limiter {
+            Some(limiter) => Ok(Some(limiter.acquire(self.request_timeout).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Route an inference call through the concurrency limit and circuit breaker, when configured
+    async fn guard_inference<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let _slot = self.acquire_inference_slot().await?;
+        match &self.circuit_breaker {
+            Some(circuit_breaker) => circuit_breaker.call(call).await,
+            None => call.await,
+        }
+    }
+
+    /// Business logic: NFC-normalize each text, then bound tokenizer work per text,
+    /// reporting the first offender by position
+    fn normalize_inputs<'a>(&self, texts: impl IntoIterator<Item = &'a mut String>) -> Result<()> {
+        for (index, text) in texts.into_iter().enumerate() {
+            // Composed form, so canonically equivalent spellings tokenize and cache alike
+            // and decomposed sequences can't pad a text past the size checks
+            if !is_nfc(text) {
+                *text = text.nfc().collect();
+            }
+            if text.len() > self.max_input_bytes {
+                return Err(InferenceError::TextTooLong {
+                    length: text.len(),
+                    max: self.max_input_bytes,
+                }.into());
+            }
+            let length = text.chars().count();
+            if length > self.max_input_chars {
+                return Err(InferenceError::InputTooLong {
+                    index,
+                    length,
+                    max: self.max_input_chars,
+                }.into());
+            }
         }
+        Ok(())
+    }
+
+    /// Configuration of the default model
+    pub async fn get_model_info(&self) -> Result<ModelConfig> {
+        self.embedding_service.get_model_info().await
+    }
+
+    /// Device placement and size of a loaded model, the default one unless `model` names another
+    pub async fn get_model_stats(&self, model: Option<&str>) -> Result<ModelStats> {
+        let model_name = model.unwrap_or(DEFAULT_MODEL_NAME);
+        self.resolve_model_config(model_name).await?;
+        self.embedding_service.get_model_stats(model_name).await
+    }
+
+    /// Replace the default model with one built from `config`
+    pub async fn switch_model(&self, config: ModelConfig) -> Result<()> {
+        // Business logic: validate configuration before downloading anything
+        if config.model_id.trim().is_empty() {
+            return Err(InferenceError::InvalidConfig {
+                message: "model_id cannot be empty".to_string(),
+            }.into());
+        }
+        // Business logic: a client-chosen path could point the loader anywhere on the server
+        if !self.allow_model_paths {
+            for (field, id) in [("model_id", &config.model_id), ("tokenizer_repo", &config.tokenizer_repo)] {
+                if is_path_like(id) {
+                    return Err(InferenceError::InvalidConfig {
+                        message: format!(
+                            "{} '{}' is a local path; only Hub ids are accepted unless server.allow_model_paths is set",
+                            field, id
+                        ),
+                    }.into());
+                }
+            }
+        }
+
+        self.ensure_models_loaded()?;
+
+        tracing::info!("Switching default model to {}", config.model_id);
+        self.embedding_service.switch_model(config.clone()).await?;
+
+        // The switch already happened, so failing to record it is not a request error
+        if let Some(config_service) = &self.config_service {
+            if let Err(e) = config_service.update_model_config(config) {
+                tracing::warn!("Failed to record switched model configuration: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a real inference on a fixed probe text with the default model and check it yields
+    /// a finite vector of the model's dimension. This goes through batch encoding because the
+    /// single-text path may answer from a cache without touching the device.
+    pub async fn probe_inference(&self) -> Result<InferenceProbe> {
+        let current_config = self.resolve_model_config(DEFAULT_MODEL_NAME).await?;
+        let request = BatchEmbeddingRequest::new(vec![DEEP_HEALTH_PROBE_TEXT.to_string()]);
+
+        let started = std::time::Instant::now();
+        let _slot = self.acquire_inference_slot().await?;
+        let response = run_with_timeout(self.request_timeout, self.embedding_service.encode_batch(request)).await?;
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let embedding = response.embeddings.first().ok_or_else(|| InferenceError::EncodingFailed {
+            message: "probe returned no embedding".to_string(),
+        })?;
+        if let Some(expected) = current_config.embedding_dim.filter(|&dim| dim != embedding.len()) {
+            return Err(InferenceError::EncodingFailed {
+                message: format!("probe embedding has {} dimensions, expected {}", embedding.len(), expected),
+            }.into());
+        }
+        if embedding.is_empty() || !embedding.iter().all(|x| x.is_finite()) {
+            return Err(InferenceError::EncodingFailed {
+                message: "probe embedding is empty or contains non-finite values".to_string(),
+            }.into());
+        }
+
+        Ok(InferenceProbe {
+            model_id: response.model_id,
+            embedding_dim: embedding.len(),
+            duration_ms,
+        })
     }
 
     /// Encode single text with business logic and validation
-    pub async fn encode_single(&self, text: String, normalize: bool) -> Result<EmbeddingResponse> {
+    pub async fn encode_single(&self, mut request: EmbeddingRequest) -> Result<EmbeddingResponse> {
         // Business logic: validate input
-        if text.trim().is_empty() {
-            return Err(anyhow::anyhow!("Text cannot be empty"));
+        if request.text.trim().is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        self.normalize_inputs([&mut request.text])?;
+
+        // Cleaned before the cache lookup so markup variants of a text share an entry
+        for step in std::mem::take(&mut request.preprocessing) {
+            let preprocessor = self.preprocessing_steps.get(&step).ok_or_else(|| InferenceError::InvalidInput {
+                message: format!("Preprocessing step {:?} is not available", step),
+            })?;
+            request.text = preprocessor.preprocess(&request.text);
+        }
+        if request.text.trim().is_empty() {
+            return Err(InferenceError::EmptyInput.into());
         }
 
-        // Business logic: check if model is loaded
-        let current_config = self.model_repository.get_current_config().await?;
+        // Business logic: check if the requested model is loaded
+        self.select_variant(&mut request.model, std::mem::take(&mut request.variant)).await?;
+        let current_config = self.resolve_model_config(request.model_name()).await?;
         tracing::debug!("Using model: {} for encoding", current_config.model_id);
+        record_model_id(&current_config.model_id);
+
+        // Cache failures only cost a cache miss, never the request
+        if let Some(cache) = &self.embedding_cache {
+            match cache.get(&request, &current_config).await {
+                Ok(Some(mut response)) => {
+                    tracing::debug!("Embedding cache hit");
+                    response.cache_hit = true;
+                    return Ok(response);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Embedding cache lookup failed: {}", e),
+            }
+        }
 
-        let request = EmbeddingRequest::with_normalize(text, normalize);
-        
         // Orchestrate: use embedding service for actual encoding
-        let response = self.embedding_service.encode(request).await?;
+        let response = self
+            .guard_inference(run_with_timeout(self.request_timeout, self.embedding_service.encode(request.clone())))
+            .await?;
         
         // Business logic: validate response
         if response.embedding.is_empty() {
-            return Err(anyhow::anyhow!("Failed to generate embedding"));
+            return Err(InferenceError::EncodingFailed {
+                message: "model returned an empty embedding".to_string(),
+            }.into());
+        }
+
+        // Populate the cache in the background so the response isn't delayed
+        if let Some(cache) = self.embedding_cache.clone() {
+            let response = response.clone();
+            tokio::spawn(async move {
+                if let Err(e) = cache.put(&request, &current_config, &response).await {
+                    tracing::warn!("Embedding cache write failed: {}", e);
+                }
+            }.in_current_span());
         }
 
         tracing::debug!("Generated embedding with {} dimensions", response.embedding.len());
@@ -46,42 +488,870 @@ impl EmbeddingUseCase {
     }
 
     /// Encode batch with business logic and orchestration
-    pub async fn encode_batch(&self, texts: Vec<String>, normalize: bool) -> Result<BatchEmbeddingResponse> {
+    pub async fn encode_batch(&self, request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse> {
+        self.encode_batch_with_mode(request, self.batch_mode).await
+    }
+
+    /// Encode the lines of an uploaded file; uploads routinely exceed `max_batch_size`,
+    /// so they're split into sub-batches whatever `batch_mode` is set to
+    pub async fn encode_lines(&self, request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse> {
+        self.encode_batch_with_mode(request, BatchMode::Chunk).await
+    }
+
+    async fn encode_batch_with_mode(
+        &self,
+        mut request: BatchEmbeddingRequest,
+        batch_mode: BatchMode,
+    ) -> Result<BatchEmbeddingResponse> {
         // Business logic: validate input
-        if texts.is_empty() {
-            return Err(anyhow::anyhow!("Text list cannot be empty"));
+        if request.texts.is_empty() {
+            return Err(InferenceError::EmptyInput.into());
         }
+        // Before empty texts are dropped so the reported index matches the request
+        self.normalize_inputs(&mut request.texts)?;
+        check_aligned_fields(&request)?;
 
-        let non_empty_texts: Vec<String> = texts.into_iter()
-            .filter(|text| !text.trim().is_empty())
-            .collect();
+        // Empty texts are dropped; ids are split the same way so dropped ones can be reported
+        let keep: Vec<bool> = request.texts.iter().map(|text| !text.trim().is_empty()).collect();
+        let skipped_indices: Vec<usize> = keep.iter().enumerate().filter(|(_, keep)| !**keep).map(|(index, _)| index).collect();
+        let kept_indices: Vec<usize> = keep.iter().enumerate().filter(|(_, keep)| **keep).map(|(index, _)| index).collect();
+        request.normalize_each = request.normalize_each.take().map(|flags| partition_kept(flags, &keep).0);
+        let ids = request.ids.take().map(|ids| partition_kept(ids, &keep));
+        request.texts.retain(|text| !text.trim().is_empty());
 
-        if non_empty_texts.is_empty() {
-            return Err(anyhow::anyhow!("All texts are empty"));
+        if request.texts.is_empty() {
+            return Err(InferenceError::EmptyInput.into());
         }
 
         // Business logic: check batch size limits
-        const MAX_BATCH_SIZE: usize = 100;
-        if non_empty_texts.len() > MAX_BATCH_SIZE {
-            return Err(anyhow::anyhow!("Batch size {} exceeds maximum {}", non_empty_texts.len(), MAX_BATCH_SIZE));
+        if request.texts.len() > self.max_batch_size && batch_mode == BatchMode::Reject {
+            return Err(InferenceError::BatchSizeExceeded {
+                size: request.texts.len(),
+                max: self.max_batch_size,
+            }.into());
         }
 
-        // Business logic: ensure model is ready
-        let current_config = self.model_repository.get_current_config().await?;
-        tracing::debug!("Processing batch of {} texts with model: {}", non_empty_texts.len(), current_config.model_id);
+        // Business logic: ensure the requested model is ready
+        self.select_variant(&mut request.model, std::mem::take(&mut request.variant)).await?;
+        let current_config = self.resolve_model_config(request.model_name()).await?;
+        tracing::debug!("Processing batch of {} texts with model: {}", request.texts.len(), current_config.model_id);
+        record_model_id(&current_config.model_id);
+
+        // Per-text normalization is applied below, so the model returns raw vectors
+        let normalize_each = request.normalize_each.take();
+        if normalize_each.is_some() {
+            request.normalize = false;
+        }
 
-        let request = BatchEmbeddingRequest::with_normalize(non_empty_texts, normalize);
-        
         // Orchestrate: use embedding service for actual encoding
-        let response = self.embedding_service.encode_batch(request).await?;
+        let response = if request.texts.len() > self.max_batch_size {
+            self.guard_inference(run_with_timeout(self.request_timeout, self.encode_chunked(request))).await
+        } else {
+            self.guard_inference(run_with_timeout(self.request_timeout, self.embedding_service.encode_batch(request)))
+                .await
+        };
+        // Errors name inputs among the kept texts; report them by request position
+        let mut response = response.map_err(|e| {
+            InferenceError::at_request_index(e, |index| kept_indices.get(index).copied().unwrap_or(index))
+        })?;
         
         // Business logic: validate response
         if response.embeddings.is_empty() {
-            return Err(anyhow::anyhow!("Failed to generate any embeddings"));
+            return Err(InferenceError::EncodingFailed {
+                message: "model returned no embeddings".to_string(),
+            }.into());
         }
 
+        if let Some(flags) = normalize_each {
+            for (embedding, _) in response.embeddings.iter_mut().zip(flags).filter(|(_, normalize)| *normalize) {
+                normalize_l2(embedding);
+            }
+        }
+
+        if let Some((ids, skipped_ids)) = ids {
+            response.ids = Some(ids);
+            response.skipped_ids = Some(skipped_ids);
+        }
+        response.skipped_indices = skipped_indices;
+
         tracing::debug!("Generated {} embeddings", response.embeddings.len());
         Ok(response)
     }
 
-}
\ No newline at end of file
+    /// Encode a batch once and return the N×N cosine similarity matrix of its texts
+    pub async fn similarity_matrix(&self, mut request: BatchEmbeddingRequest) -> Result<SimilarityMatrixResponse> {
+        // Business logic: the matrix grows quadratically, so bound N up front
+        if request.texts.len() > self.max_similarity_matrix_size {
+            return Err(InferenceError::BatchSizeExceeded {
+                size: request.texts.len(),
+                max: self.max_similarity_matrix_size,
+            }.into());
+        }
+
+        request.normalize = true;
+        request.normalize_each = None;
+        request.ids = None;
+        // Chunked like file uploads, so `max_similarity_matrix_size` is the limit that applies
+        // rather than `max_batch_size`
+        let response = self.encode_batch_with_mode(request, BatchMode::Chunk).await?;
+        let matrix = cosine_similarity_matrix(&response.embeddings)?;
+
+        Ok(SimilarityMatrixResponse {
+            matrix,
+            texts: response.texts,
+            model_id: response.model_id,
+        })
+    }
+
+    /// Encode two texts in one batch and measure how similar they are
+    pub async fn compare(&self, request: CompareRequest) -> Result<CompareResponse> {
+        // Business logic: an empty text would be dropped from the batch, leaving nothing to compare
+        for (name, text) in [("text_a", &request.text_a), ("text_b", &request.text_b)] {
+            if text.trim().is_empty() {
+                return Err(InferenceError::InvalidInput {
+                    message: format!("{} cannot be empty", name),
+                }.into());
+            }
+        }
+
+        let response = self
+            .encode_batch(BatchEmbeddingRequest {
+                normalize: request.normalize,
+                model: request.model,
+                dimensions: request.dimensions,
+                prompt_name: request.prompt_name,
+                ..BatchEmbeddingRequest::new(vec![request.text_a, request.text_b])
+            })
+            .await?;
+        let mut embeddings = response.embeddings.into_iter();
+        let (Some(embedding_a), Some(embedding_b)) = (embeddings.next(), embeddings.next()) else {
+            return Err(InferenceError::EncodingFailed {
+                message: "model returned fewer than two embeddings".to_string(),
+            }.into());
+        };
+
+        let dot_product = dot(&embedding_a, &embedding_b);
+        let norms = dot(&embedding_a, &embedding_a).sqrt() * dot(&embedding_b, &embedding_b).sqrt();
+        let cosine_similarity = if norms > 0.0 { dot_product / norms } else { 0.0 };
+        let euclidean_distance = request.metrics.contains(&SimilarityMetric::Euclidean).then(|| {
+            embedding_a.iter().zip(&embedding_b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+        });
+
+        Ok(CompareResponse {
+            cosine_similarity,
+            dot_product: request.metrics.contains(&SimilarityMetric::Dot).then_some(dot_product),
+            euclidean_distance,
+            embedding_a,
+            embedding_b,
+            model_id: response.model_id,
+        })
+    }
+
+    /// Split a long text at sentence boundaries into chunks that fit the model, then
+    /// encode every chunk as one batch
+    pub async fn encode_document(&self, mut request: DocumentEmbeddingRequest) -> Result<DocumentEmbeddingResponse> {
+        // Business logic: validate input
+        if request.text.trim().is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        self.normalize_inputs([&mut request.text])?;
+        if request.max_chunk_tokens == Some(0) {
+            return Err(InferenceError::InvalidInput {
+                message: "max_chunk_tokens must be greater than 0".to_string(),
+            }.into());
+        }
+
+        let splitter_name = request.splitter.as_deref().unwrap_or(DEFAULT_SENTENCE_SPLITTER);
+        let Some(splitter) = self.preprocessors.get(splitter_name) else {
+            let mut available: Vec<&str> = self.preprocessors.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            return Err(InferenceError::InvalidInput {
+                message: format!("Unknown splitter '{}'; available: {}", splitter_name, available.join(", ")),
+            }.into());
+        };
+
+        let current_config = self.resolve_model_config(request.model_name()).await?;
+        record_model_id(&current_config.model_id);
+        let max_tokens = request
+            .max_chunk_tokens
+            .map_or(current_config.max_sequence_length, |max| max.min(current_config.max_sequence_length));
+
+        let sentences = splitter.split_sentences(&request.text);
+        let (texts, token_counts) = self.pack_sentences(sentences, max_tokens, &request.model).await?;
+        tracing::debug!("Split document into {} chunks of at most {} tokens", texts.len(), max_tokens);
+
+        let batch = BatchEmbeddingRequest {
+            model: request.model,
+            ..BatchEmbeddingRequest::with_normalize(texts, request.normalize)
+        };
+        let response = self.encode_batch(batch).await?;
+        let chunks = response
+            .texts
+            .into_iter()
+            .zip(response.embeddings)
+            .zip(token_counts)
+            .map(|((text, embedding), token_count)| DocumentChunk {
+                text,
+                embedding,
+                // What the model saw after truncating an over-long sentence
+                token_count: token_count.min(current_config.max_sequence_length),
+            })
+            .collect();
+
+        Ok(DocumentEmbeddingResponse {
+            chunks,
+            model_id: response.model_id,
+        })
+    }
+
+    /// Greedily join consecutive sentences while the tokenized chunk stays within `max_tokens`.
+    /// A sentence over the budget on its own becomes its own chunk and is truncated by the model.
+    async fn pack_sentences(
+        &self,
+        sentences: Vec<String>,
+        max_tokens: usize,
+        model: &Option<String>,
+    ) -> Result<(Vec<String>, Vec<usize>)> {
+        let mut chunks = Vec::new();
+        let mut token_counts = Vec::new();
+        let mut current: Option<(String, usize)> = None;
+
+        for sentence in sentences {
+            let candidate = match &current {
+                Some((chunk, _)) => format!("{} {}", chunk, sentence),
+                None => sentence.clone(),
+            };
+            let count = self.count_tokens(&candidate, model).await?;
+            match current.take() {
+                Some((chunk, chunk_count)) if count > max_tokens => {
+                    chunks.push(chunk);
+                    token_counts.push(chunk_count);
+                    let count = self.count_tokens(&sentence, model).await?;
+                    current = Some((sentence, count));
+                }
+                _ => current = Some((candidate, count)),
+            }
+        }
+        if let Some((chunk, count)) = current {
+            chunks.push(chunk);
+            token_counts.push(count);
+        }
+        Ok((chunks, token_counts))
+    }
+
+    async fn count_tokens(&self, text: &str, model: &Option<String>) -> Result<usize> {
+        let request = TokenizeRequest {
+            text: text.to_string(),
+            model: model.clone(),
+        };
+        Ok(self.embedding_service.tokenize(request).await?.count)
+    }
+
+    /// Encode pre-tokenized input, skipping the model's tokenizer
+    pub async fn encode_tokens(&self, request: TokenEmbeddingRequest) -> Result<TokenEmbeddingResponse> {
+        // Business logic: validate input
+        if request.input_ids.is_empty() || request.input_ids.iter().any(|ids| ids.is_empty()) {
+            return Err(InferenceError::InvalidInput {
+                message: "input_ids must contain at least one non-empty row".to_string(),
+            }.into());
+        }
+
+        if let Some(attention_mask) = &request.attention_mask {
+            let shapes_match = attention_mask.len() == request.input_ids.len()
+                && attention_mask.iter().zip(&request.input_ids).all(|(mask, ids)| mask.len() == ids.len());
+            if !shapes_match {
+                return Err(InferenceError::InvalidInput {
+                    message: "attention_mask must have the same shape as input_ids".to_string(),
+                }.into());
+            }
+        }
+
+        // Business logic: check batch size limits
+        if request.input_ids.len() > self.max_batch_size {
+            return Err(InferenceError::BatchSizeExceeded {
+                size: request.input_ids.len(),
+                max: self.max_batch_size,
+            }.into());
+        }
+
+        // Business logic: rows must fit the model's position embeddings
+        let current_config = self.resolve_model_config(request.model_name()).await?;
+        record_model_id(&current_config.model_id);
+        if let Some(ids) = request.input_ids.iter().find(|ids| ids.len() > current_config.max_sequence_length) {
+            return Err(InferenceError::InvalidInput {
+                message: format!(
+                    "Row of {} tokens exceeds max_sequence_length {}",
+                    ids.len(),
+                    current_config.max_sequence_length
+                ),
+            }.into());
+        }
+
+        tracing::debug!("Processing {} token rows with model: {}", request.input_ids.len(), current_config.model_id);
+        let _slot = self.acquire_inference_slot().await?;
+        run_with_timeout(self.request_timeout, self.embedding_service.encode_tokens(request)).await
+    }
+
+    /// Tokenize text with a model's tokenizer without running the model
+    pub async fn tokenize(&self, mut request: TokenizeRequest) -> Result<TokenizeResponse> {
+        self.normalize_inputs([&mut request.text])?;
+
+        let current_config = self.resolve_model_config(request.model_name()).await?;
+        record_model_id(&current_config.model_id);
+
+        run_with_timeout(self.request_timeout, self.embedding_service.tokenize(request)).await
+    }
+
+    /// Encode an oversized batch as consecutive sub-batches, preserving input order
+    async fn encode_chunked(&self, mut request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse> {
+        let texts = std::mem::take(&mut request.texts);
+        tracing::debug!("Splitting batch of {} texts into chunks of {}", texts.len(), self.max_batch_size);
+
+        let mut responses = Vec::new();
+        for (i, chunk) in texts.chunks(self.max_batch_size).enumerate() {
+            let sub_request = BatchEmbeddingRequest {
+                texts: chunk.to_vec(),
+                ..request.clone()
+            };
+            let offset = i * self.max_batch_size;
+            let response = self.embedding_service.encode_batch(sub_request).await;
+            responses.push(response.map_err(|e| InferenceError::at_request_index(e, |index| offset + index))?);
+        }
+
+        Ok(BatchEmbeddingResponse::from_batch_responses(responses))
+    }
+
+    /// Encode a batch in sub-batches of `max_batch_size`, sending each embedding
+    /// through the returned channel as soon as its sub-batch completes
+    pub async fn encode_stream(
+        &self,
+        mut request: BatchEmbeddingRequest,
+    ) -> Result<mpsc::Receiver<Result<IndexedEmbedding>>> {
+        // Business logic: validate input
+        if request.texts.is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        self.normalize_inputs(&mut request.texts)?;
+        check_aligned_fields(&request)?;
+
+        // Flags and ids stay indexed by original position, so they're looked up per embedding below
+        let normalize_each = request.normalize_each.take();
+        let ids = request.ids.take();
+        if normalize_each.is_some() {
+            request.normalize = false;
+        }
+
+        // Keep original positions so clients can match results to inputs
+        let (indices, texts): (Vec<usize>, Vec<String>) = std::mem::take(&mut request.texts)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .unzip();
+
+        if texts.is_empty() {
+            return Err(InferenceError::EmptyInput.into());
+        }
+        request.texts = texts;
+
+        // Business logic: ensure the requested model is ready
+        self.select_variant(&mut request.model, std::mem::take(&mut request.variant)).await?;
+        let current_config = self.resolve_model_config(request.model_name()).await?;
+        tracing::debug!("Streaming batch of {} texts with model: {}", indices.len(), current_config.model_id);
+        record_model_id(&current_config.model_id);
+
+        let (sender, receiver) = mpsc::channel(self.max_batch_size);
+        let embedding_service = self.embedding_service.clone();
+        let sub_batch_size = self.stream_batch_size;
+        let request_timeout = self.request_timeout;
+        let inference_limiter = self.inference_limiter.clone();
+        let circuit_breaker = self.circuit_breaker.clone();
+
+        tokio::spawn(async move {
+            let mut sub_batches = match embedding_service.encode_stream(request, sub_batch_size).await {
+                Ok(sub_batches) => sub_batches,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+
+            for chunk in indices.chunks(sub_batch_size) {
+                // Take a slot per sub-batch so a long stream doesn't starve other requests
+                let slot = match &inference_limiter {
+                    Some(limiter) => limiter.acquire(request_timeout).await.map(Some),
+                    None => Ok(None),
+                };
+                let next_sub_batch = async {
+                    sub_batches.next().await.unwrap_or_else(|| {
+                        Err(InferenceError::EncodingFailed {
+                            message: "stream ended before every sub-batch was encoded".to_string(),
+                        }.into())
+                    })
+                };
+                let guarded_sub_batch = async {
+                    let _slot = slot?;
+                    let call = run_with_timeout(request_timeout, next_sub_batch);
+                    match &circuit_breaker {
+                        Some(circuit_breaker) => circuit_breaker.call(call).await,
+                        None => call.await,
+                    }
+                };
+                let result = guarded_sub_batch.await;
+
+                match result {
+                    Ok(response) => {
+                        for (&index, mut embedding) in chunk.iter().zip(response.embeddings) {
+                            if normalize_each.as_ref().is_some_and(|flags| flags[index]) {
+                                normalize_l2(&mut embedding);
+                            }
+                            let id = ids.as_ref().map(|ids| ids[index].clone());
+                            if sender.send(Ok(IndexedEmbedding { index, id, embedding })).await.is_err() {
+                                tracing::debug!("Stream receiver dropped, stopping encoding");
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        let request_index = |index: usize| indices.get(index).copied().unwrap_or(index);
+                        let _ = sender.send(Err(InferenceError::at_request_index(e, request_index))).await;
+                        return;
+                    }
+                }
+            }
+        }.in_current_span());
+
+        Ok(receiver)
+    }
+}
+
+/// Whether `id` names a local path rather than a Hub id such as `org/name`
+fn is_path_like(id: &str) -> bool {
+    let path = std::path::Path::new(id);
+    path.is_absolute()
+        || id.starts_with(['.', '~'])
+        || id.contains('\\')
+        || id.split('/').count() > 2
+        || path.is_dir()
+}
+
+/// Whether a replica on `label` (`cpu`, `cuda:0`, `metal:0`) runs on the requested
+/// device; a bare `cuda` or `metal` accepts any ordinal
+fn device_matches(requested: &str, label: &str) -> bool {
+    let requested = requested.to_lowercase();
+    match requested.as_str() {
+        "cuda" | "gpu" => label.starts_with("cuda:"),
+        "metal" => label.starts_with("metal:"),
+        _ => requested == label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::DtypeConfig;
+    use crate::infrastructure::config::ServerConfig;
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::test_support::{
+        embedding_use_case, embedding_use_case_with, poisoned_model, tiny_model_config, tiny_model_loader,
+        RelabeledVariantService, SlowEmbeddingService, TINY_HIDDEN_SIZE, TINY_MAX_SEQUENCE_LENGTH,
+    };
+
+    #[tokio::test]
+    async fn named_models_are_served_alongside_the_default_model() {
+        let loader = tiny_model_loader().await;
+        let short = ModelConfig {
+            max_sequence_length: 8,
+            ..tiny_model_config()
+        };
+        loader.load_named_model("short", &short).await.unwrap();
+        let use_case = Arc::new(embedding_use_case(loader, &ServerConfig::default()));
+
+        let requests: Vec<_> = (0..8)
+            .map(|i| {
+                let use_case = use_case.clone();
+                tokio::spawn(async move {
+                    let request = EmbeddingRequest {
+                        model: (i % 2 == 1).then(|| "short".to_string()),
+                        ..EmbeddingRequest::new("the cat and the dog sleep on the mat often".to_string())
+                    };
+                    use_case.encode_single(request).await
+                })
+            })
+            .collect();
+        for (i, response) in futures::future::join_all(requests).await.into_iter().enumerate() {
+            let response = response.unwrap().unwrap();
+            assert_eq!(response.embedding.len(), TINY_HIDDEN_SIZE);
+            // 10 words plus [CLS] and [SEP], cut to 8 tokens by the named model
+            assert_eq!(response.token_count, if i % 2 == 1 { 8 } else { 12 });
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_model_names_are_not_found() {
+        let use_case = embedding_use_case(tiny_model_loader().await, &ServerConfig::default());
+        let request = EmbeddingRequest {
+            model: Some("missing".to_string()),
+            ..EmbeddingRequest::new("hello".to_string())
+        };
+
+        let error = use_case.encode_single(request).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::ModelNotFound { model_id }) if model_id == "missing"
+        ));
+    }
+
+    fn texts(count: usize) -> Vec<String> {
+        ["the cat", "a dog", "hello world", "the mat", "first sentence"]
+            .iter()
+            .cycle()
+            .take(count)
+            .enumerate()
+            .map(|(i, text)| format!("{} {}", text, i))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn batches_over_max_batch_size_are_rejected() {
+        let server_config = ServerConfig {
+            max_batch_size: 2,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case(tiny_model_loader().await, &server_config);
+
+        let error = use_case.encode_batch(BatchEmbeddingRequest::new(texts(3))).await.unwrap_err();
+        let response = use_case.encode_batch(BatchEmbeddingRequest::new(texts(2))).await.unwrap();
+
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::BatchSizeExceeded { size: 3, max: 2 })
+        ));
+        assert_eq!(response.embeddings.len(), 2);
+    }
+
+    /// Limit, length and index of an `InputTooLong` error, checking it maps to a 400
+    fn input_too_long(error: anyhow::Error) -> (usize, usize, usize) {
+        assert_eq!(crate::presentation::api::error_status(&error), axum::http::StatusCode::BAD_REQUEST);
+        match error.downcast_ref::<InferenceError>() {
+            Some(&InferenceError::InputTooLong { index, length, max }) => (index, length, max),
+            other => panic!("expected InputTooLong, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_single_text_over_max_input_chars_is_rejected() {
+        let use_case = embedding_use_case(tiny_model_loader().await, &ServerConfig::default()).with_max_input_chars(10);
+
+        // Ten characters of twenty bytes fit; the limit counts characters
+        use_case.encode_single(EmbeddingRequest::new("é".repeat(10))).await.unwrap();
+        let error = use_case.encode_single(EmbeddingRequest::new("é".repeat(11))).await.unwrap_err();
+
+        assert_eq!(input_too_long(error), (0, 11, 10));
+    }
+
+    #[tokio::test]
+    async fn the_first_batch_text_over_max_input_chars_is_reported_by_request_index() {
+        let use_case = embedding_use_case(tiny_model_loader().await, &ServerConfig::default()).with_max_input_chars(10);
+        let texts = vec![
+            "the cat".to_string(),
+            String::new(),
+            "a dog".to_string(),
+            "the cat sleeps".to_string(),
+            "hello world, hello".to_string(),
+        ];
+
+        let error = use_case.encode_batch(BatchEmbeddingRequest::new(texts)).await.unwrap_err();
+
+        assert_eq!(input_too_long(error), (3, 14, 10));
+    }
+
+    #[tokio::test]
+    async fn requests_select_among_loaded_variants_by_device_and_dtype() {
+        // The "gpu" variant truncates to 4 tokens, so the token count shows which one encoded
+        let loader = tiny_model_loader().await;
+        let gpu = ModelConfig {
+            max_sequence_length: 4,
+            ..tiny_model_config()
+        };
+        loader.load_named_model("gpu", &gpu).await.unwrap();
+        let service = Arc::new(RelabeledVariantService::new(loader.clone(), "gpu", "cuda:1", "f16"));
+        let use_case = embedding_use_case_with(service, loader, &ServerConfig::default());
+        let encode = |device: Option<&str>, dtype: Option<DtypeConfig>| EmbeddingRequest {
+            variant: ModelVariant { device: device.map(str::to_string), dtype },
+            ..EmbeddingRequest::new("the cat sleeps on the mat".to_string())
+        };
+
+        let token_count = |response: EmbeddingResponse| response.token_count;
+        assert_eq!(token_count(use_case.encode_single(encode(None, None)).await.unwrap()), 8);
+        assert_eq!(token_count(use_case.encode_single(encode(Some("cpu"), None)).await.unwrap()), 8);
+        assert_eq!(token_count(use_case.encode_single(encode(Some("cuda:1"), None)).await.unwrap()), 4);
+        assert_eq!(token_count(use_case.encode_single(encode(Some("cuda"), None)).await.unwrap()), 4);
+        assert_eq!(token_count(use_case.encode_single(encode(None, Some(DtypeConfig::Float16))).await.unwrap()), 4);
+        assert_eq!(
+            token_count(use_case.encode_single(encode(None, Some(DtypeConfig::Float32))).await.unwrap()),
+            8
+        );
+
+        for (device, dtype) in [(Some("cuda:0"), None), (Some("cpu"), Some(DtypeConfig::Float16))] {
+            let error = use_case.encode_single(encode(device, dtype)).await.unwrap_err();
+            assert!(error.to_string().contains("no loaded variant"), "{}", error);
+            assert!(error.to_string().contains("'gpu' on cuda:1 as f16"), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_empty_texts_are_reported_by_request_index() {
+        let use_case = embedding_use_case(tiny_model_loader().await, &ServerConfig::default());
+        let texts: Vec<String> = ["the cat", "", "a dog", " \t", "hello world"].iter().map(|t| t.to_string()).collect();
+
+        let response = use_case.encode_batch(BatchEmbeddingRequest::new(texts)).await.unwrap();
+        let all_empty = use_case
+            .encode_batch(BatchEmbeddingRequest::new(vec![String::new(), " ".to_string()]))
+            .await
+            .unwrap_err();
+
+        assert_eq!(response.skipped_indices, vec![1, 3]);
+        assert_eq!(response.texts, vec!["the cat", "a dog", "hello world"]);
+        assert_eq!(response.embeddings.len(), 3);
+        assert_eq!(response.ids, None);
+        assert!(matches!(all_empty.downcast_ref::<InferenceError>(), Some(InferenceError::EmptyInput)));
+    }
+
+    #[tokio::test]
+    async fn non_finite_embeddings_are_reported_by_request_index() {
+        let (_dir, config) = poisoned_model("dog");
+        let loader = Arc::new(CandleModelLoader::new());
+        loader.load_model(&config).await.unwrap();
+        // Two empty texts come before the poisoned one, which lands in the second chunk
+        let server_config = ServerConfig {
+            max_batch_size: 2,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case(loader, &server_config);
+        let texts: Vec<String> =
+            ["the cat", "", "hello world", " ", "the mat", "a dog"].iter().map(|t| t.to_string()).collect();
+        let non_finite_index = |error: anyhow::Error| match error.downcast_ref::<InferenceError>() {
+            Some(&InferenceError::NonFiniteEmbedding { index }) => index,
+            other => panic!("expected NonFiniteEmbedding, got {:?}", other),
+        };
+
+        let unchunked: Vec<String> = texts[..2].iter().chain(&texts[5..]).cloned().collect();
+        let whole = use_case.encode_batch(BatchEmbeddingRequest::new(unchunked));
+        let chunked = use_case.encode_lines(BatchEmbeddingRequest::new(texts.clone()));
+        let mut stream = use_case.encode_stream(BatchEmbeddingRequest::new(texts)).await.unwrap();
+        let mut streamed = Vec::new();
+        while let Some(item) = stream.recv().await {
+            streamed.push(item);
+        }
+
+        assert_eq!(non_finite_index(whole.await.unwrap_err()), 2);
+        assert_eq!(non_finite_index(chunked.await.unwrap_err()), 5);
+        let (embeddings, error) = streamed.split_at(streamed.len() - 1);
+        assert_eq!(embeddings.iter().map(|e| e.as_ref().unwrap().index).collect::<Vec<_>>(), vec![0, 2]);
+        let Err(error) = &error[0] else { panic!("the stream ends with the error") };
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::NonFiniteEmbedding { index: 5 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn chunked_batches_keep_every_embedding_in_request_order() {
+        let server_config = ServerConfig {
+            max_batch_size: 100,
+            batch_mode: BatchMode::Chunk,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case(tiny_model_loader().await, &server_config);
+        let texts = texts(250);
+
+        let chunked = use_case.encode_batch(BatchEmbeddingRequest::new(texts.clone())).await.unwrap();
+
+        assert_eq!(chunked.embeddings.len(), 250);
+        assert_eq!(chunked.texts, texts);
+        // Spot-check positions in each sub-batch against encoding the text alone
+        for i in [0, 99, 100, 199, 249] {
+            let single = use_case.encode_single(EmbeddingRequest::new(texts[i].clone())).await.unwrap();
+            for (a, b) in chunked.embeddings[i].iter().zip(&single.embedding) {
+                assert!((a - b).abs() < 1e-4, "text {}: {} != {}", i, a, b);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn switching_models_does_not_fail_concurrent_encodes() {
+        let server_config = ServerConfig {
+            allow_model_paths: true,
+            ..ServerConfig::default()
+        };
+        let use_case = Arc::new(embedding_use_case(tiny_model_loader().await, &server_config));
+
+        let encoders: Vec<_> = (0..4)
+            .map(|worker| {
+                let use_case = use_case.clone();
+                tokio::spawn(async move {
+                    for i in 0..25 {
+                        let text = format!("the cat and the dog {} {}", worker, i);
+                        use_case.encode_single(EmbeddingRequest::new(text)).await?;
+                    }
+                    anyhow::Ok(())
+                })
+            })
+            .collect();
+        for max_sequence_length in [8, 12, TINY_MAX_SEQUENCE_LENGTH] {
+            let config = ModelConfig {
+                max_sequence_length,
+                ..tiny_model_config()
+            };
+            use_case.switch_model(config).await.unwrap();
+        }
+
+        for encoder in futures::future::join_all(encoders).await {
+            encoder.unwrap().unwrap();
+        }
+        assert_eq!(use_case.get_model_info().await.unwrap().max_sequence_length, TINY_MAX_SEQUENCE_LENGTH);
+    }
+
+    #[tokio::test]
+    async fn switching_to_a_local_path_is_rejected_unless_allowed() {
+        let use_case = embedding_use_case(tiny_model_loader().await, &ServerConfig::default());
+
+        let error = use_case.switch_model(tiny_model_config()).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::InvalidConfig { message }) if message.starts_with("model_id")
+        ));
+    }
+
+    #[test]
+    fn hub_ids_are_not_path_like() {
+        for id in ["sentence-transformers/all-MiniLM-L6-v2", "bert-base-uncased"] {
+            assert!(!is_path_like(id), "{}", id);
+        }
+        for id in ["/models/bert", "./bert", "../bert", "~/bert", "a/b/c", "models\\bert", "."] {
+            assert!(is_path_like(id), "{}", id);
+        }
+    }
+
+    #[test]
+    fn cosine_similarity_matrix_matches_hand_computed_values() {
+        let embeddings = vec![vec![1.0, 0.0], vec![0.0, 2.0], vec![3.0, 3.0]];
+
+        let matrix = cosine_similarity_matrix(&embeddings).unwrap();
+
+        let half_sqrt2 = std::f32::consts::FRAC_1_SQRT_2;
+        let expected = [[1.0, 0.0, half_sqrt2], [0.0, 1.0, half_sqrt2], [half_sqrt2, half_sqrt2, 1.0]];
+        for (row, expected) in matrix.iter().zip(&expected) {
+            for (value, expected) in row.iter().zip(expected) {
+                assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_embeddings_have_zero_similarity_instead_of_nan() {
+        let embeddings = vec![vec![0.0, 0.0], vec![1.0, 0.0], vec![3.0, 4.0]];
+
+        let matrix = cosine_similarity_matrix(&embeddings).unwrap();
+
+        let expected = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.6], [0.0, 0.6, 1.0]];
+        for (row, expected) in matrix.iter().zip(&expected) {
+            for (value, expected) in row.iter().zip(expected) {
+                assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn similarity_matrices_over_the_size_limit_are_rejected() {
+        let server_config = ServerConfig {
+            max_similarity_matrix_size: 2,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case(tiny_model_loader().await, &server_config);
+
+        let error = use_case.similarity_matrix(BatchEmbeddingRequest::new(texts(3))).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::BatchSizeExceeded { size: 3, max: 2 })
+        ));
+    }
+
+    /// Use case over a slow service allowing `max_concurrent` inferences at once
+    async fn limited_use_case(
+        max_concurrent: usize,
+        overload_mode: OverloadMode,
+    ) -> (Arc<EmbeddingUseCase>, Arc<SlowEmbeddingService>) {
+        let loader = tiny_model_loader().await;
+        let service = Arc::new(SlowEmbeddingService::new(loader.clone(), Duration::from_millis(50)));
+        let server_config = ServerConfig {
+            max_concurrent_inferences: Some(max_concurrent),
+            overload_mode,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case_with(service.clone(), loader, &server_config);
+        (Arc::new(use_case), service)
+    }
+
+    async fn encode_concurrently(use_case: &Arc<EmbeddingUseCase>, count: usize) -> Vec<Result<EmbeddingResponse>> {
+        let requests = (0..count).map(|i| {
+            let use_case = use_case.clone();
+            tokio::spawn(async move { use_case.encode_single(EmbeddingRequest::new(format!("the cat {}", i))).await })
+        });
+        futures::future::join_all(requests).await.into_iter().map(|result| result.unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn queued_inferences_never_exceed_the_concurrency_limit() {
+        let (use_case, service) = limited_use_case(2, OverloadMode::Queue).await;
+
+        let results = encode_concurrently(&use_case, 8).await;
+
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(service.max_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn inferences_beyond_the_limit_are_rejected_in_reject_mode() {
+        let (use_case, service) = limited_use_case(1, OverloadMode::Reject).await;
+
+        let results = encode_concurrently(&use_case, 4).await;
+
+        let rejected = results
+            .iter()
+            .filter(|result| {
+                matches!(
+                    result.as_ref().map_err(|e| e.downcast_ref::<InferenceError>()),
+                    Err(Some(InferenceError::Overloaded { .. }))
+                )
+            })
+            .count();
+        assert!(results.iter().any(Result::is_ok));
+        assert!(rejected >= 1);
+        assert_eq!(service.max_in_flight(), 1);
+    }
+
+    #[tokio::test]
+    async fn encoding_past_the_request_timeout_fails_with_a_timeout() {
+        let loader = tiny_model_loader().await;
+        let service = Arc::new(SlowEmbeddingService::new(loader.clone(), Duration::from_secs(5)));
+        let server_config = ServerConfig {
+            request_timeout_ms: 50,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case_with(service, loader, &server_config);
+
+        let single = use_case.encode_single(EmbeddingRequest::new("the cat".to_string())).await.unwrap_err();
+        let batch = use_case.encode_batch(BatchEmbeddingRequest::new(texts(2))).await.unwrap_err();
+
+        for error in [single, batch] {
+            assert!(matches!(
+                error.downcast_ref::<InferenceError>(),
+                Some(InferenceError::Timeout { timeout_ms: 50 })
+            ));
+        }
+    }
+}
@@ -1,5 +1,166 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Logical name under which the startup model is registered
+pub const DEFAULT_MODEL_NAME: &str = "default";
+
+/// Smallest truncated embedding worth keeping; below this Matryoshka models lose most of their quality
+pub const MIN_MATRYOSHKA_DIMENSIONS: usize = 32;
+
+/// Sizes an embedding of `full` dimensions can be truncated to: powers of two from
+/// `MIN_MATRYOSHKA_DIMENSIONS` below `full`, then `full` itself
+pub fn matryoshka_dimensions(full: usize) -> Vec<usize> {
+    std::iter::successors(Some(MIN_MATRYOSHKA_DIMENSIONS), |size| size.checked_mul(2))
+        .take_while(|&size| size < full)
+        .chain(std::iter::once(full))
+        .collect()
+}
+
+/// Floating point precision used for model weights and activations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DtypeConfig {
+    #[default]
+    #[serde(alias = "f32")]
+    Float32,
+    #[serde(alias = "f16")]
+    Float16,
+    #[serde(alias = "bf16")]
+    BFloat16,
+}
+
+impl DtypeConfig {
+    /// Name of the candle dtype this loads as, as reported in model stats
+    pub fn dtype_name(&self) -> &'static str {
+        match self {
+            Self::Float32 => "f32",
+            Self::Float16 => "f16",
+            Self::BFloat16 => "bf16",
+        }
+    }
+}
+
+/// Post-training quantization schemes supported for CPU inference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuantizationType {
+    Int8,
+}
+
+impl QuantizationType {
+    pub const ALL: &'static [Self] = &[Self::Int8];
+}
+
+/// How per-token hidden states are reduced to a single sentence embedding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolingStrategy {
+    /// Average of the non-padding tokens
+    #[default]
+    Mean,
+    /// Hidden state of the first (`[CLS]`) token
+    Cls,
+    /// Element-wise maximum over the non-padding tokens
+    Max,
+    /// Sum of the non-padding tokens divided by the square root of their count
+    MeanSqrtLen,
+    /// Hidden state of the last non-padding token
+    LastToken,
+}
+
+impl PoolingStrategy {
+    pub const ALL: &'static [Self] = &[Self::Mean, Self::Cls, Self::Max, Self::MeanSqrtLen, Self::LastToken];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mean => "mean",
+            Self::Cls => "cls",
+            Self::Max => "max",
+            Self::MeanSqrtLen => "mean_sqrt_len",
+            Self::LastToken => "last_token",
+        }
+    }
+}
+
+/// Encoder family of a model, read from the `model_type` in its `config.json`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelArchitecture {
+    #[default]
+    Bert,
+    /// No token type embeddings and no pooler; takes only ids and a padding mask
+    DistilBert,
+    /// Loaded through the BERT encoder, which shares its layout; positions are
+    /// offset past the padding id and no token types are passed
+    Roberta,
+    /// No token types, with a relative position bias in every attention layer
+    MpNet,
+}
+
+impl ModelArchitecture {
+    pub const ALL: &'static [Self] = &[Self::Bert, Self::DistilBert, Self::Roberta, Self::MpNet];
+
+    /// Unknown or missing model types are treated as BERT, as they were before detection
+    pub fn from_model_type(model_type: Option<&str>) -> Self {
+        match model_type {
+            Some("distilbert") => Self::DistilBert,
+            Some("roberta" | "xlm-roberta" | "camembert") => Self::Roberta,
+            Some("mpnet") => Self::MpNet,
+            _ => Self::Bert,
+        }
+    }
+
+    /// Only BERT distinguishes sentence pairs by token type; RoBERTa has a single
+    /// token type and DistilBERT none
+    pub fn uses_token_type_ids(&self) -> bool {
+        matches!(self, Self::Bert)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Bert => "bert",
+            Self::DistilBert => "distilbert",
+            Self::Roberta => "roberta",
+            Self::MpNet => "mpnet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuantizationConfig {
+    pub method: QuantizationType,
+}
+
+/// Retries for transient Hugging Face Hub download failures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRetryConfig {
+    /// Total attempts per file, including the first
+    #[serde(default = "default_download_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each further retry
+    #[serde(default = "default_download_base_delay_ms")]
+    pub base_delay_ms: u64,
+}
+
+fn default_download_max_attempts() -> u32 {
+    3
+}
+
+fn default_download_base_delay_ms() -> u64 {
+    1000
+}
+
+impl Default for DownloadRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_download_max_attempts(),
+            base_delay_ms: default_download_base_delay_ms(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelConfig {
     pub model_id: String,
@@ -7,8 +168,55 @@ pub struct ModelConfig {
     pub revision: Option<String>,
     pub max_sequence_length: usize,
     pub device: String,
+    /// Fail to load instead of falling back to CPU when `device` is unavailable
+    #[serde(default)]
+    pub strict_device: bool,
+    /// GPU ordinal for `cuda`, `gpu`, `metal` and `auto`, defaulting to the first device;
+    /// ignored on CPU. A `cuda:N` device names its ordinal itself
+    #[serde(default)]
+    pub device_index: Option<usize>,
     pub use_pth: Option<bool>,
     pub approximate_gelu: Option<bool>,
+    #[serde(default)]
+    pub dtype: DtypeConfig,
+    pub quantization: Option<QuantizationConfig>,
+    /// Named prefixes such as `query = "query: "` for models trained with instructions
+    #[serde(default)]
+    pub prompts: HashMap<String, String>,
+    /// Prompt applied when a request doesn't name one; none when unset
+    #[serde(default)]
+    pub default_prompt_name: Option<String>,
+    /// Prepended to texts encoded as queries; BGE models fall back to their published instruction
+    #[serde(default)]
+    pub query_instruction: Option<String>,
+    /// Prepended to texts encoded as passages
+    #[serde(default)]
+    pub passage_instruction: Option<String>,
+    /// Hugging Face cache directory; defaults to `HF_HOME` or `~/.cache/huggingface`
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// Only use files already in the cache, never the network
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default)]
+    pub download_retry: DownloadRetryConfig,
+    #[serde(default)]
+    pub padding_strategy: PaddingStrategy,
+    /// Load repos whose `config.json` declares custom modelling code (`auto_map`) as the
+    /// standard architecture they name; the custom code itself is never run
+    #[serde(default)]
+    pub trust_remote_code: bool,
+    /// PEFT LoRA adapter merged into the weights at load: a local `.safetensors` file or
+    /// adapter directory, or a Hub repo id
+    #[serde(default)]
+    pub lora_adapter_path: Option<PathBuf>,
+    /// Rank in the adapter's `lora_alpha / r` scale, overriding its config's `r`; 0
+    /// loads the base model unchanged
+    #[serde(default)]
+    pub lora_rank: Option<usize>,
+    /// Length of the embeddings the model produces; filled in once the model is loaded
+    #[serde(default)]
+    pub embedding_dim: Option<usize>,
 }
 
 impl Default for ModelConfig {
@@ -18,26 +226,141 @@ impl Default for ModelConfig {
             tokenizer_repo: "sentence-transformers/all-MiniLM-L6-v2".to_string(),
             revision: Some("refs/pr/21".to_string()),
             max_sequence_length: 512,
-            device: "cpu".to_string(),
+            device: "auto".to_string(),
+            strict_device: false,
+            device_index: None,
             use_pth: Some(false),
             approximate_gelu: Some(false),
+            dtype: DtypeConfig::Float32,
+            quantization: None,
+            prompts: HashMap::new(),
+            default_prompt_name: None,
+            query_instruction: None,
+            passage_instruction: None,
+            cache_dir: None,
+            offline: false,
+            download_retry: DownloadRetryConfig::default(),
+            padding_strategy: PaddingStrategy::default(),
+            trust_remote_code: false,
+            lora_adapter_path: None,
+            lora_rank: None,
+            embedding_dim: None,
+        }
+    }
+}
+
+/// How the texts of a batch are padded to a common length before the forward pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaddingStrategy {
+    /// Pad to the longest text in the batch
+    #[default]
+    BatchLongest,
+    /// Pad, and truncate, every text to `max_sequence_length`, so every forward pass
+    /// has the same shape and GPU kernels can be reused
+    Fixed,
+    /// Don't pad; texts go through the model one at a time
+    None,
+}
+
+impl PaddingStrategy {
+    pub const ALL: &'static [Self] = &[Self::BatchLongest, Self::Fixed, Self::None];
+}
+
+/// A model known to the server, whether or not its files are available locally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub model_id: String,
+    /// Files are present in the Hugging Face cache or local model directory
+    pub cached: bool,
+    pub size_bytes: Option<u64>,
+    /// Most recent modification of any cached file
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// Instruction BGE v1.5 models were trained with for queries; passages get none
+const BGE_QUERY_INSTRUCTION: &str = "Represent this sentence for searching relevant passages: ";
+const BGE_ZH_QUERY_INSTRUCTION: &str = "为这个句子生成表示以用于检索相关文章：";
+
+impl ModelConfig {
+    /// Prefix for texts of the given side: the configured instruction, or for BGE
+    /// models (`BAAI/bge-*`, except the instruction-free `bge-m3`) their published one
+    pub fn instruction(&self, request_type: RequestType) -> Option<&str> {
+        match request_type {
+            RequestType::Query => self.query_instruction.as_deref().or_else(|| self.default_query_instruction()),
+            RequestType::Passage => self.passage_instruction.as_deref(),
         }
     }
+
+    fn default_query_instruction(&self) -> Option<&'static str> {
+        let model_id = self.model_id.as_str();
+        if !model_id.starts_with("BAAI/bge-") || model_id.starts_with("BAAI/bge-m3") {
+            return None;
+        }
+        Some(if model_id.contains("-zh") { BGE_ZH_QUERY_INSTRUCTION } else { BGE_QUERY_INSTRUCTION })
+    }
+}
+
+/// Side of an asymmetric retrieval pair a text is encoded for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestType {
+    Query,
+    Passage,
+}
+
+/// Device and dtype a request wants its model to run with. Each selects among the
+/// loaded variants of the model rather than loading a new one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModelVariant {
+    /// `cpu`, `cuda`, `metal`, or a specific GPU such as `cuda:1`
+    pub device: Option<String>,
+    pub dtype: Option<DtypeConfig>,
+}
+
+impl ModelVariant {
+    pub fn is_any(&self) -> bool {
+        self.device.is_none() && self.dtype.is_none()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct EmbeddingRequest {
     pub text: String,
     pub normalize: bool,
+    pub model: Option<String>,
+    /// Keep only the first N dimensions of the embedding (matryoshka truncation)
+    pub dimensions: Option<usize>,
+    /// Key into the model's `prompts`, prepended to the text before tokenization
+    pub prompt_name: Option<String>,
+    /// Cleanup applied to the text, in order, before anything else sees it
+    pub preprocessing: Vec<PreprocessingStep>,
+    /// Selects the model's query or passage instruction when no prompt is named
+    pub request_type: Option<RequestType>,
+    pub variant: ModelVariant,
 }
 
 impl EmbeddingRequest {
     pub fn new(text: String) -> Self {
-        Self { text, normalize: true }
+        Self {
+            text,
+            normalize: true,
+            model: None,
+            dimensions: None,
+            prompt_name: None,
+            preprocessing: Vec::new(),
+            request_type: None,
+            variant: ModelVariant::default(),
+        }
     }
     
     pub fn with_normalize(text: String, normalize: bool) -> Self {
-        Self { text, normalize }
+        Self { normalize, ..Self::new(text) }
+    }
+
+    /// Registry name of the model to encode with, falling back to the default model
+    pub fn model_name(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL_NAME)
     }
 }
 
@@ -46,21 +369,55 @@ pub struct EmbeddingResponse {
     pub embedding: Vec<f32>,
     pub text: String,
     pub model_id: String,
+    /// Number of tokens the text produced, useful for detecting truncation;
+    /// 0 for responses recorded before it was reported, e.g. older cache entries
+    #[serde(default)]
+    pub token_count: usize,
+    /// Whether the embedding was served from the cache instead of running inference
+    #[serde(default)]
+    pub cache_hit: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct BatchEmbeddingRequest {
     pub texts: Vec<String>,
     pub normalize: bool,
+    /// Per-text override of `normalize`, aligned with `texts`
+    pub normalize_each: Option<Vec<bool>>,
+    pub model: Option<String>,
+    /// Keep only the first N dimensions of each embedding (matryoshka truncation)
+    pub dimensions: Option<usize>,
+    /// Key into the model's `prompts`, prepended to the text before tokenization
+    pub prompt_name: Option<String>,
+    /// Client-supplied identifiers aligned with `texts`, echoed back in the response
+    pub ids: Option<Vec<String>>,
+    /// Selects the model's query or passage instruction when no prompt is named
+    pub request_type: Option<RequestType>,
+    pub variant: ModelVariant,
 }
 
 impl BatchEmbeddingRequest {
     pub fn new(texts: Vec<String>) -> Self {
-        Self { texts, normalize: true }
+        Self {
+            texts,
+            normalize: true,
+            normalize_each: None,
+            model: None,
+            dimensions: None,
+            prompt_name: None,
+            ids: None,
+            request_type: None,
+            variant: ModelVariant::default(),
+        }
     }
     
     pub fn with_normalize(texts: Vec<String>, normalize: bool) -> Self {
-        Self { texts, normalize }
+        Self { normalize, ..Self::new(texts) }
+    }
+
+    /// Registry name of the model to encode with, falling back to the default model
+    pub fn model_name(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL_NAME)
     }
 }
 
@@ -68,7 +425,12 @@ impl From<Vec<EmbeddingRequest>> for BatchEmbeddingRequest {
     fn from(requests: Vec<EmbeddingRequest>) -> Self {
         let texts = requests.iter().map(|r| r.text.clone()).collect();
         let normalize = requests.first().map(|r| r.normalize).unwrap_or(true);
-        Self { texts, normalize }
+        let model = requests.first().and_then(|r| r.model.clone());
+        let dimensions = requests.first().and_then(|r| r.dimensions);
+        let prompt_name = requests.first().and_then(|r| r.prompt_name.clone());
+        let request_type = requests.first().and_then(|r| r.request_type);
+        let variant = requests.first().map(|r| r.variant.clone()).unwrap_or_default();
+        Self { texts, normalize, normalize_each: None, model, dimensions, prompt_name, ids: None, request_type, variant }
     }
 }
 
@@ -77,13 +439,393 @@ pub struct BatchEmbeddingResponse {
     pub embeddings: Vec<Vec<f32>>,
     pub texts: Vec<String>,
     pub model_id: String,
+    #[serde(default)]
+    pub token_counts: Vec<usize>,
+    /// The request's ids of the encoded texts, aligned with `embeddings`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<String>>,
+    /// The request's ids of empty texts, which were dropped instead of encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped_ids: Option<Vec<String>>,
+    /// Positions in the request of empty texts, which were dropped instead of encoded
+    #[serde(default)]
+    pub skipped_indices: Vec<usize>,
 }
 
 impl BatchEmbeddingResponse {
     pub fn from_single_responses(responses: Vec<EmbeddingResponse>) -> Self {
         let embeddings = responses.iter().map(|r| r.embedding.clone()).collect();
         let texts = responses.iter().map(|r| r.text.clone()).collect();
+        let token_counts = responses.iter().map(|r| r.token_count).collect();
         let model_id = responses.first().map(|r| r.model_id.clone()).unwrap_or_default();
-        Self { embeddings, texts, model_id }
+        Self { embeddings, texts, model_id, token_counts, ids: None, skipped_ids: None, skipped_indices: Vec::new() }
     }
+
+    pub fn from_batch_responses(responses: Vec<BatchEmbeddingResponse>) -> Self {
+        let model_id = responses.first().map(|r| r.model_id.clone()).unwrap_or_default();
+        let mut embeddings = Vec::new();
+        let mut texts = Vec::new();
+        let mut token_counts = Vec::new();
+        for response in responses {
+            embeddings.extend(response.embeddings);
+            texts.extend(response.texts);
+            token_counts.extend(response.token_counts);
+        }
+        Self { embeddings, texts, model_id, token_counts, ids: None, skipped_ids: None, skipped_indices: Vec::new() }
+    }
+}
+
+/// Pre-tokenized input that bypasses the model's tokenizer
+#[derive(Debug, Clone)]
+pub struct TokenEmbeddingRequest {
+    pub input_ids: Vec<Vec<u32>>,
+    /// Defaults to all ones; rows shorter than the longest are padded
+    pub attention_mask: Option<Vec<Vec<u32>>>,
+    pub normalize: bool,
+    pub model: Option<String>,
+    pub dimensions: Option<usize>,
+}
+
+impl TokenEmbeddingRequest {
+    /// Registry name of the model to encode with, falling back to the default model
+    pub fn model_name(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL_NAME)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenEmbeddingResponse {
+    pub embeddings: Vec<Vec<f32>>,
+    pub model_id: String,
+    pub token_counts: Vec<usize>,
+}
+
+/// Device placement and size of a loaded model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelStats {
+    pub model_id: String,
+    /// One entry per replica, e.g. `["cuda:0", "cuda:1"]`
+    pub devices: Vec<String>,
+    pub dtype: String,
+    /// Estimated from the architecture config rather than counted from the weights
+    pub parameter_count: u64,
+    /// Weight memory summed over all replicas, excluding activations
+    pub approximate_memory_bytes: u64,
+    pub hidden_size: usize,
+    pub num_layers: usize,
+    pub quantized: bool,
+    pub architecture: ModelArchitecture,
+}
+
+/// Text to run through a model's tokenizer without encoding it
+#[derive(Debug, Clone)]
+pub struct TokenizeRequest {
+    pub text: String,
+    pub model: Option<String>,
+}
+
+impl TokenizeRequest {
+    /// Registry name of the model whose tokenizer to use, falling back to the default model
+    pub fn model_name(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL_NAME)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenizeResponse {
+    pub tokens: Vec<String>,
+    pub ids: Vec<u32>,
+    pub count: usize,
+    /// Whether `count` exceeds the model's `max_sequence_length`
+    pub truncated: bool,
+}
+
+/// Pairwise cosine similarities between every pair of input texts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarityMatrixResponse {
+    /// `matrix[i][j]` is the similarity between `texts[i]` and `texts[j]`
+    pub matrix: Vec<Vec<f32>>,
+    pub texts: Vec<String>,
+    pub model_id: String,
+}
+
+/// Extra measures `/compare` can report besides cosine similarity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimilarityMetric {
+    Dot,
+    Euclidean,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompareRequest {
+    pub text_a: String,
+    pub text_b: String,
+    pub normalize: bool,
+    pub model: Option<String>,
+    pub dimensions: Option<usize>,
+    pub prompt_name: Option<String>,
+    pub metrics: Vec<SimilarityMetric>,
+}
+
+/// Embeddings of two texts and how close they are
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompareResponse {
+    pub embedding_a: Vec<f32>,
+    pub embedding_b: Vec<f32>,
+    pub cosine_similarity: f32,
+    /// Depends on the embeddings' norms unless they're normalized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dot_product: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub euclidean_distance: Option<f32>,
+    pub model_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReduceRequest {
+    pub texts: Vec<String>,
+    pub target_dim: usize,
+    pub normalize: bool,
+    pub model: Option<String>,
+    /// Keep the fitted transform for `/encode/reduce/transform`, replacing any kept one
+    pub save: bool,
+}
+
+/// Embeddings projected onto the leading principal components
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReduceResponse {
+    pub embeddings: Vec<Vec<f32>>,
+    /// Share of the fitted embeddings' variance along each component, largest first
+    pub explained_variance_ratio: Vec<f32>,
+    pub model_id: String,
+}
+
+/// How evenly a set of embeddings spreads over the embedding space
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IsotropyResponse {
+    /// From 0, embeddings collapsed onto a few directions, to 1, spread evenly
+    pub score: f32,
+    /// Number of directions the embeddings effectively vary along
+    pub effective_rank: f32,
+    pub n_texts: usize,
+    pub model_id: String,
+}
+
+/// A PCA transform fitted on a corpus and kept for later requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PcaFitResponse {
+    pub samples: usize,
+    pub input_dimensions: usize,
+    pub target_dim: usize,
+    pub explained_variance_ratio: Vec<f32>,
+    pub model_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RerankRequest {
+    pub query: String,
+    pub passages: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankResponse {
+    /// Relevance of each passage, in input order
+    pub scores: Vec<f32>,
+    /// Passage indices ordered from most to least relevant
+    pub ranked_indices: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SparseEmbeddingRequest {
+    pub text: String,
+}
+
+/// Lexical weights of a text over the model's vocabulary, as produced by SPLADE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SparseEmbedding {
+    /// Vocabulary ids with a non-zero weight, in ascending order
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+/// How batches larger than the configured maximum are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchMode {
+    /// Fail the request
+    #[default]
+    Reject,
+    /// Split into sub-batches and concatenate the results in order
+    Chunk,
+}
+
+/// Markup removed from a text before it's encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreprocessingStep {
+    Html,
+    Markdown,
+}
+
+/// What happens to inference requests beyond `max_concurrent_inferences`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverloadMode {
+    /// Wait for a slot, up to the request timeout
+    #[default]
+    Queue,
+    /// Fail immediately with 503
+    Reject,
+}
+
+/// What a request for a model unloaded after being idle does while it loads again
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleReloadMode {
+    /// Wait for the reload
+    #[default]
+    Block,
+    /// Fail with 503 while the model reloads in the background
+    Reject,
+}
+
+/// Progress of the most recent model load
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum LoadStatus {
+    /// No load has started
+    #[default]
+    Idle,
+    /// Fetching `file` from the Hub; `total` is 0 until the size is known
+    Downloading { file: String, bytes: u64, total: u64 },
+    /// Files are available and the weights are being loaded onto the device
+    Loading,
+    Ready,
+    Failed { message: String },
+}
+
+/// Single embedding of a streamed batch, tagged with its position in the input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedEmbedding {
+    pub index: usize,
+    /// The request's id for this text, when ids were supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub embedding: Vec<f32>,
+}
+
+/// Model options this build of the server supports; devices and half precision
+/// depend on the GPU backends it was compiled with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    pub pooling_strategies: Vec<PoolingStrategy>,
+    pub dtypes: Vec<DtypeConfig>,
+    pub quantization: Vec<QuantizationType>,
+    pub devices: Vec<String>,
+    pub architectures: Vec<ModelArchitecture>,
+    pub padding_strategies: Vec<PaddingStrategy>,
+    /// Cargo features compiled in, such as `cuda`, `metal` or `mkl`
+    pub features: Vec<String>,
+}
+
+/// Request limits clients should respect when calling the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestLimits {
+    pub max_batch_size: usize,
+    pub max_text_length: usize,
+    pub max_input_chars: usize,
+    pub max_input_bytes: usize,
+}
+
+/// Result of a deep health check that ran a real inference on the default model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceProbe {
+    pub model_id: String,
+    pub embedding_dim: usize,
+    pub duration_ms: u64,
+}
+
+/// Long text to split at sentence boundaries into chunks that each fit the model
+#[derive(Debug, Clone)]
+pub struct DocumentEmbeddingRequest {
+    pub text: String,
+    pub normalize: bool,
+    pub model: Option<String>,
+    /// Token budget per chunk, capped at the model's `max_sequence_length`
+    pub max_chunk_tokens: Option<usize>,
+    /// Name of the registered `TextPreprocessor` to split sentences with
+    pub splitter: Option<String>,
+}
+
+impl DocumentEmbeddingRequest {
+    /// Registry name of the model to encode with, falling back to the default model
+    pub fn model_name(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL_NAME)
+    }
+}
+
+/// One chunk of a document and its embedding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentChunk {
+    pub text: String,
+    pub embedding: Vec<f32>,
+    pub token_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentEmbeddingResponse {
+    pub chunks: Vec<DocumentChunk>,
+    pub model_id: String,
+}
+
+
+/// Documents to embed and store in the vector index
+#[derive(Debug, Clone)]
+pub struct IndexAddRequest {
+    pub texts: Vec<String>,
+    /// Identifiers aligned with `texts`; a document replaces any stored under the same id.
+    /// Sequential ids are assigned when unset
+    pub ids: Option<Vec<String>>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexAddResponse {
+    /// Ids the documents were stored under, in request order
+    pub ids: Vec<String>,
+    /// Documents in the index after the add
+    pub total: usize,
+}
+
+/// Files written by a faiss export of the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexExportResponse {
+    pub documents: usize,
+    pub index_path: std::path::PathBuf,
+    pub metadata_path: std::path::PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchRequest {
+    pub query: String,
+    pub top_k: usize,
+    /// Lowest cosine similarity returned; every top-k match is returned when unset
+    pub threshold: Option<f32>,
+    pub model: Option<String>,
+}
+
+/// An indexed document and its cosine similarity to the query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub id: String,
+    pub text: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResponse {
+    /// Best matches first
+    pub results: Vec<SearchResult>,
+    pub model_id: String,
 }
\ No newline at end of file
@@ -11,6 +11,42 @@ pub enum InferenceError {
     #[error("Encoding failed: {message}")]
     EncodingFailed { message: String },
     
+    #[error("Encoding failed: embedding for input {index} contains NaN or infinite values")]
+    NonFiniteEmbedding { index: usize },
+    
+    #[error("Batch size {size} exceeds maximum {max}")]
+    BatchSizeExceeded { size: usize, max: usize },
+    
+    #[error("Input contains no non-empty text")]
+    EmptyInput,
+    
+    #[error("Invalid input: {message}")]
+    InvalidInput { message: String },
+    
+    #[error("Input {index} has {length} characters, exceeding the limit of {max}")]
+    InputTooLong { index: usize, length: usize, max: usize },
+    
+    #[error("Input of {length} bytes exceeds the limit of {max} bytes")]
+    TextTooLong { length: usize, max: usize },
+    
+    #[error("Requested {requested} dimensions but the model produces {max}; smaller sizes must be a power of two of at least {}", crate::domain::entities::MIN_MATRYOSHKA_DIMENSIONS)]
+    InvalidDimensions { requested: usize, max: usize },
+    
+    #[error("Request timed out after {timeout_ms} ms")]
+    Timeout { timeout_ms: u64 },
+    
+    #[error("Model is still loading, retry once the server is ready")]
+    ModelLoading,
+    
+    #[error("No model is loaded, load one with POST /model/switch")]
+    ModelNotLoaded,
+    
+    #[error("Inference is failing repeatedly, retry in {retry_after_secs} s")]
+    CircuitOpen { retry_after_secs: u64 },
+    
+    #[error("Server is at its limit of {max} concurrent inferences, retry shortly")]
+    Overloaded { max: usize },
+    
     #[error("Model loading failed: {message}")]
     ModelLoadFailed { message: String },
     
@@ -21,4 +57,17 @@ pub enum InferenceError {
     Io(#[from] std::io::Error),
 }
 
+impl InferenceError {
+    /// Report an error raised while encoding part of a request by the position of the
+    /// affected input in the whole request; other errors pass through unchanged
+    pub fn at_request_index(error: anyhow::Error, request_index: impl FnOnce(usize) -> usize) -> anyhow::Error {
+        match error.downcast_ref::<InferenceError>() {
+            Some(&InferenceError::NonFiniteEmbedding { index }) => {
+                InferenceError::NonFiniteEmbedding { index: request_index(index) }.into()
+            }
+            _ => error,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, InferenceError>;
\ No newline at end of file
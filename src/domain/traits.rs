@@ -1,26 +1,100 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 
 use super::entities::{
     BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse,
-    ModelConfig,
+    ModelConfig, ModelInfo, ModelStats, SparseEmbedding, TokenEmbeddingRequest, TokenEmbeddingResponse, TokenizeRequest,
+    TokenizeResponse,
 };
 
 #[async_trait]
 pub trait EmbeddingService: Send + Sync {
     async fn encode(&self, request: EmbeddingRequest) -> Result<EmbeddingResponse>;
     async fn encode_batch(&self, request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse>;
+    /// Encode `request` as consecutive sub-batches of `sub_batch_size` texts; each sub-batch
+    /// runs only when the stream is polled for it, so callers can pace and guard them
+    async fn encode_stream<'a>(
+        &'a self,
+        request: BatchEmbeddingRequest,
+        sub_batch_size: usize,
+    ) -> Result<BoxStream<'a, Result<BatchEmbeddingResponse>>>;
+    async fn encode_tokens(&self, request: TokenEmbeddingRequest) -> Result<TokenEmbeddingResponse>;
+    async fn tokenize(&self, request: TokenizeRequest) -> Result<TokenizeResponse>;
     async fn get_model_info(&self) -> Result<ModelConfig>;
+    async fn get_model_stats(&self, model_name: &str) -> Result<ModelStats>;
     async fn switch_model(&self, config: ModelConfig) -> Result<()>;
 }
 
 #[async_trait]
 pub trait ModelRepository: Send + Sync {
     async fn load_model(&self, config: &ModelConfig) -> Result<()>;
+    async fn load_named_model(&self, name: &str, config: &ModelConfig) -> Result<()>;
     async fn get_current_config(&self) -> Result<ModelConfig>;
+    async fn get_model_config(&self, name: &str) -> Result<ModelConfig>;
+    async fn list_models(&self) -> Result<Vec<String>>;
+    async fn unload_model(&self, name: &str) -> Result<()>;
+}
+
+/// Models available to load, as opposed to `ModelRepository` which tracks loaded ones
+#[async_trait]
+pub trait ModelRegistry: Send + Sync {
+    async fn list_models(&self) -> Result<Vec<ModelInfo>>;
+    async fn model_exists(&self, model_id: &str) -> Result<bool>;
+}
+
+/// Scores query/passage pairs jointly with a cross-encoder model
+#[async_trait]
+pub trait CrossEncoderService: Send + Sync {
+    /// Relevance of each passage to the query, in input order
+    async fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f32>>;
+}
+
+/// Encodes text into sparse vocabulary weights with a SPLADE model
+#[async_trait]
+pub trait SparseEncoderService: Send + Sync {
+    async fn encode_sparse(&self, text: &str) -> Result<SparseEmbedding>;
+}
+
+/// Encodes the two sides of asymmetric retrieval, each with its own prefix and model
+#[async_trait]
+pub trait AsymmetricEncoderService: Send + Sync {
+    async fn encode_query(&self, request: EmbeddingRequest) -> Result<EmbeddingResponse>;
+    async fn encode_passages(&self, request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse>;
+}
+
+/// Text processing applied before tokenization. Cleaners override `preprocess`,
+/// sentence splitters override `split_sentences`.
+pub trait TextPreprocessor: Send + Sync {
+    /// Cleaned-up text; unchanged by default
+    fn preprocess(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    /// Sentences of the preprocessed text in order, trimmed, with empty ones dropped;
+    /// the whole text as one sentence by default
+    fn split_sentences(&self, text: &str) -> Vec<String> {
+        let text = self.preprocess(text);
+        let text = text.trim();
+        if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![text.to_string()]
+        }
+    }
+}
+
+/// Store of previously computed embeddings, keyed by the request and the model that served it
+#[async_trait]
+pub trait EmbeddingCache: Send + Sync {
+    async fn get(&self, request: &EmbeddingRequest, model: &ModelConfig) -> Result<Option<EmbeddingResponse>>;
+    async fn put(&self, request: &EmbeddingRequest, model: &ModelConfig, response: &EmbeddingResponse) -> Result<()>;
 }
 
 pub trait ConfigurationService: Send + Sync {
     fn get_model_config(&self) -> Result<ModelConfig>;
+    fn get_named_model_configs(&self) -> Result<HashMap<String, ModelConfig>>;
     fn update_model_config(&self, config: ModelConfig) -> Result<()>;
 }
\ No newline at end of file
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::domain::entities::{
+    BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse, RequestType,
+};
+use crate::domain::traits::{AsymmetricEncoderService, EmbeddingService};
+use crate::infrastructure::config::AsymmetricEncodingConfig;
+
+/// Routes queries and passages to their configured models, prepending each side's
+/// prefix before tokenization and tagging the request so the model's own query or
+/// passage instruction applies too. Responses echo the texts as sent, without the prefix.
+pub struct AsymmetricEmbeddingService {
+    embedding_service: Arc<dyn EmbeddingService>,
+    config: AsymmetricEncodingConfig,
+}
+
+impl AsymmetricEmbeddingService {
+    pub fn new(embedding_service: Arc<dyn EmbeddingService>, config: AsymmetricEncodingConfig) -> Self {
+        Self {
+            embedding_service,
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl AsymmetricEncoderService for AsymmetricEmbeddingService {
+    async fn encode_query(&self, mut request: EmbeddingRequest) -> Result<EmbeddingResponse> {
+        let text = std::mem::take(&mut request.text);
+        request.text = format!("{}{}", self.config.query_prefix, text);
+        request.model = Some(self.config.query_model_id.clone());
+        request.request_type = Some(RequestType::Query);
+
+        let mut response = self.embedding_service.encode(request).await?;
+        response.text = text;
+        Ok(response)
+    }
+
+    async fn encode_passages(&self, mut request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse> {
+        let texts = std::mem::take(&mut request.texts);
+        request.texts = texts
+            .iter()
+            .map(|text| format!("{}{}", self.config.passage_prefix, text))
+            .collect();
+        request.model = Some(self.config.passage_model_id.clone());
+        request.request_type = Some(RequestType::Passage);
+
+        let mut response = self.embedding_service.encode_batch(request).await?;
+        response.texts = texts;
+        Ok(response)
+    }
+}
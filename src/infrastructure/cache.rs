@@ -0,0 +1,158 @@
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use lru::LruCache;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use sha2::{Digest, Sha256};
+
+use crate::domain::entities::{EmbeddingRequest, EmbeddingResponse, ModelConfig};
+use crate::domain::traits::EmbeddingCache;
+use crate::infrastructure::config::CacheConfig;
+
+/// Thread-safe least-recently-used cache held in process memory
+pub struct InMemoryCache<K, V> {
+    entries: Arc<Mutex<LruCache<K, V>>>,
+}
+
+impl<K: Hash + Eq, V: Clone> InMemoryCache<K, V> {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.lock().get(key).cloned()
+    }
+
+    pub fn put(&self, key: K, value: V) {
+        self.lock().put(key, value);
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<K, V>> {
+        // A panic while holding the lock can't leave the LRU inconsistent, so keep using it
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<K, V> Clone for InMemoryCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+const KEY_PREFIX: &str = "embedding:";
+/// Sorted set of cached keys scored by insertion time, used to enforce `max_entries`
+const INDEX_KEY: &str = "embedding:index";
+
+/// Embedding cache stored in Redis as MessagePack with a fixed TTL
+pub struct RedisCacheService {
+    connection: ConnectionManager,
+    ttl_seconds: u64,
+    max_entries: Option<usize>,
+}
+
+impl RedisCacheService {
+    pub async fn connect(config: &CacheConfig) -> Result<Self> {
+        let client = redis::Client::open(config.redis_url.as_str())?;
+        let connection = client.get_connection_manager().await?;
+        tracing::info!("Connected to Redis embedding cache at {}", config.redis_url);
+        Ok(Self {
+            connection,
+            ttl_seconds: config.ttl_seconds,
+            max_entries: config.max_entries,
+        })
+    }
+
+    /// SHA-256 over everything that affects the embedding: the text, request options
+    /// and the full model configuration (weights, revision, dtype, pooling, ...)
+    fn cache_key(request: &EmbeddingRequest, model: &ModelConfig) -> Result<String> {
+        let material = serde_json::to_vec(&(
+            &request.text,
+            request.normalize,
+            request.dimensions,
+            &request.prompt_name,
+            request.request_type,
+            model,
+        ))?;
+        Ok(format!("{}{:x}", KEY_PREFIX, Sha256::digest(&material)))
+    }
+
+    /// Drop the oldest entries once the index grows past `max_entries`
+    async fn evict_oldest(&self, connection: &mut ConnectionManager, max_entries: usize) -> Result<()> {
+        let count: usize = connection.zcard(INDEX_KEY).await?;
+        if count <= max_entries {
+            return Ok(());
+        }
+
+        let evicted: Vec<(String, f64)> = connection.zpopmin(INDEX_KEY, (count - max_entries) as isize).await?;
+        let keys: Vec<String> = evicted.into_iter().map(|(key, _)| key).collect();
+        connection.del::<_, ()>(keys).await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingCache for RedisCacheService {
+    async fn get(&self, request: &EmbeddingRequest, model: &ModelConfig) -> Result<Option<EmbeddingResponse>> {
+        let key = Self::cache_key(request, model)?;
+        let mut connection = self.connection.clone();
+        let bytes: Option<Vec<u8>> = connection.get(&key).await?;
+        bytes
+            .map(|bytes| Ok(rmp_serde::from_slice(&bytes)?))
+            .transpose()
+    }
+
+    async fn put(&self, request: &EmbeddingRequest, model: &ModelConfig, response: &EmbeddingResponse) -> Result<()> {
+        let key = Self::cache_key(request, model)?;
+        let bytes = rmp_serde::to_vec(response)?;
+        let mut connection = self.connection.clone();
+        connection.set_ex::<_, _, ()>(&key, bytes, self.ttl_seconds).await?;
+
+        if let Some(max_entries) = self.max_entries {
+            let inserted_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as f64;
+            connection.zadd::<_, _, _, ()>(INDEX_KEY, &key, inserted_at).await?;
+            self.evict_oldest(&mut connection, max_entries).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_cache_evicts_the_least_recently_used_entry() {
+        let cache = InMemoryCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.put("c", 3);
+
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn in_memory_cache_clones_share_entries() {
+        let cache = InMemoryCache::new(NonZeroUsize::new(2).unwrap());
+        let clone = cache.clone();
+
+        clone.put("a", vec![0.5f32]);
+        cache.clear();
+
+        assert_eq!(clone.get(&"a"), None);
+    }
+}
@@ -1,15 +1,216 @@
 use anyhow::Result;
+use axum::http::{HeaderName, Method, Uri};
 use config::{Config, ConfigError, Environment, File};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
-use crate::domain::entities::ModelConfig;
+use crate::domain::entities::{BatchMode, IdleReloadMode, ModelConfig, OverloadMode, DEFAULT_MODEL_NAME};
+use crate::domain::errors::InferenceError;
 use crate::domain::traits::ConfigurationService;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct AppConfig {
     pub model: ModelConfig,
     pub server: ServerConfig,
+    /// Additional models loaded at startup, keyed by the name requests select them with
+    #[serde(default)]
+    pub models: HashMap<String, ModelConfig>,
+    pub warmup: Option<WarmupConfig>,
+    #[serde(default)]
+    pub otel: OtelConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    pub cache: Option<CacheConfig>,
+    /// Capacity of the in-process LRU of single-text embeddings; disabled when unset
+    pub in_memory_cache_size: Option<usize>,
+    /// Cross-encoder used by `/encode/rerank`; reranking is disabled when unset
+    pub cross_encoder: Option<ModelConfig>,
+    /// SPLADE model used by `/sparse`; sparse encoding is disabled when unset
+    pub sparse_model: Option<ModelConfig>,
+    /// Query and passage settings behind `/encode/query` and `/encode/passage`; disabled when unset
+    pub asymmetric: Option<AsymmetricEncodingConfig>,
+    /// Hub ids loaded in parallel at startup with the `[model]` settings, each selectable
+    /// by its id; a model that fails to load is skipped
+    #[serde(default)]
+    pub preload_ids: Vec<String>,
+    /// Fail fast with 503 while inference keeps failing; disabled when unset
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// In-memory vector index behind `/index/*` and `/search`; disabled when unset
+    pub index: Option<IndexConfig>,
+    #[serde(default)]
+    pub config_persistence: ConfigPersistenceMode,
+}
+
+/// Whether configuration changed at runtime outlives the process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigPersistenceMode {
+    /// Changes are lost on restart
+    #[default]
+    Memory,
+    /// Changes are saved to `config/local.toml`, which overrides the other config files
+    Disk,
+}
+
+/// Directory the config files are read from, relative to the working directory
+const CONFIG_DIR: &str = "config";
+/// Highest precedence config file, and where switched models are persisted
+const LOCAL_CONFIG_FILE: &str = "local.toml";
+const LOCAL_CONFIG_TEMP_FILE: &str = "local.toml.tmp";
+
+/// Redis-backed cache of single-text embeddings
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheConfig {
+    pub redis_url: String,
+    pub ttl_seconds: u64,
+    /// Evict the oldest entries beyond this count; unbounded when unset
+    pub max_entries: Option<usize>,
+}
+
+/// Retrieval models such as E5 and BGE encode queries and passages differently, with a
+/// prefix on each side and sometimes a separate model per side
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AsymmetricEncodingConfig {
+    /// Name of the loaded model queries are encoded with: `default`, a `[models]` key or a preloaded id
+    #[serde(default = "default_asymmetric_model")]
+    pub query_model_id: String,
+    #[serde(default = "default_asymmetric_model")]
+    pub passage_model_id: String,
+    #[serde(default)]
+    pub query_prefix: String,
+    #[serde(default)]
+    pub passage_prefix: String,
+}
+
+fn default_asymmetric_model() -> String {
+    DEFAULT_MODEL_NAME.to_string()
+}
+
+/// Consecutive inference failures that open the circuit, and how long it stays open
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_recovery_timeout_secs")]
+    pub recovery_timeout_secs: u64,
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_recovery_timeout_secs() -> u64 {
+    30
+}
+
+/// Persistence of the vector index
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IndexConfig {
+    /// Written by `/index/save` and autosaves, read by `/index/load` and at startup when it exists
+    #[serde(default = "default_index_path")]
+    pub path: PathBuf,
+    /// Save after this many documents have been added since the last save; only
+    /// `/index/save` writes the file when unset
+    pub autosave_every: Option<usize>,
+    /// Where `/index/export` writes a faiss index; its ids and texts go to the same path plus `.json`
+    #[serde(default = "default_index_export_path")]
+    pub export_path: PathBuf,
+}
+
+fn default_index_path() -> PathBuf {
+    PathBuf::from("data/index.msgpack")
+}
+
+fn default_index_export_path() -> PathBuf {
+    PathBuf::from("data/index.faiss")
+}
+
+impl IndexConfig {
+    pub fn validate(&self) -> crate::domain::errors::Result<()> {
+        if self.autosave_every == Some(0) {
+            return Err(InferenceError::InvalidConfig {
+                message: "index.autosave_every must be at least 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Output format for log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable multi-field lines
+    #[default]
+    Pretty,
+    /// One JSON object per line for log aggregators
+    Json,
+    /// Abbreviated single-line output
+    Compact,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Default filter directive; `RUST_LOG` takes precedence when set
+    #[serde(default = "default_log_level")]
+    pub level: String,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            level: default_log_level(),
+        }
+    }
+}
+
+/// OpenTelemetry span export over OTLP/gRPC
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OtelConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_otel_endpoint")]
+    pub endpoint: String,
+    #[serde(default = "default_otel_service_name")]
+    pub service_name: String,
+}
+
+fn default_otel_endpoint() -> String {
+    "http://localhost:4317".to_string()
+}
+
+fn default_otel_service_name() -> String {
+    "inference".to_string()
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: default_otel_endpoint(),
+            service_name: default_otel_service_name(),
+        }
+    }
+}
+
+/// Synthetic forward passes run after loading so the first real request doesn't pay
+/// for kernel compilation and allocation
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WarmupConfig {
+    pub iterations: usize,
+    pub batch_size: usize,
+    /// Batch sizes pushed through the full encode path (tokenization, pooling, normalization)
+    #[serde(default)]
+    pub batch_sizes: Vec<usize>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -17,6 +218,192 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub workers: usize,
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    #[serde(default)]
+    pub batch_mode: BatchMode,
+    /// Texts per sub-batch of `/encode/stream` and streamed `/encode/batch`; defaults to `max_batch_size`
+    pub stream_batch_size: Option<usize>,
+    /// Forward passes allowed to run at once, bounding activation memory; unlimited when unset
+    pub max_concurrent_inferences: Option<usize>,
+    #[serde(default)]
+    pub overload_mode: OverloadMode,
+    /// Unload models that have served no request for this long, reloading on demand;
+    /// models stay loaded when unset
+    pub idle_unload_secs: Option<u64>,
+    #[serde(default)]
+    pub idle_reload_mode: IdleReloadMode,
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// How long in-flight requests may run after a shutdown signal before being dropped
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Largest number of texts accepted by the similarity matrix endpoint
+    #[serde(default = "default_max_similarity_matrix_size")]
+    pub max_similarity_matrix_size: usize,
+    /// Port for the gRPC server when built with the `grpc` feature
+    #[serde(default = "default_grpc_port")]
+    pub grpc_port: u16,
+    /// Requests with larger bodies are rejected with 413 before being buffered
+    #[serde(default = "default_max_request_body_bytes")]
+    pub max_request_body_bytes: usize,
+    /// Largest file accepted by `/encode/file`, which isn't bound by `max_request_body_bytes`
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: usize,
+    /// Longest text, in characters, accepted before tokenization
+    #[serde(default = "default_max_input_chars")]
+    pub max_input_chars: usize,
+    /// Largest text, in UTF-8 bytes after NFC normalization, accepted before tokenization
+    #[serde(default = "default_max_input_bytes")]
+    pub max_input_bytes: usize,
+    /// Fail encodes whose embeddings contain NaN or infinite values instead of returning them
+    #[serde(default = "default_check_finite_embeddings")]
+    pub check_finite_embeddings: bool,
+    /// Bearer token for admin endpoints such as `/config` and `/model/switch`; they aren't
+    /// served when unset
+    pub admin_token: Option<String>,
+    /// Let `/model/switch` load from local paths, not only Hub ids
+    #[serde(default)]
+    pub allow_model_paths: bool,
+    /// Bind the listener right away and load models in a background task
+    #[serde(default)]
+    pub background_model_loading: bool,
+    /// Serve `/ws/encode` for clients that keep one connection open for many requests
+    #[serde(default)]
+    pub enable_websocket: bool,
+    /// Gzip or brotli encode responses for clients that send `Accept-Encoding`
+    #[serde(default = "default_enable_compression")]
+    pub enable_compression: bool,
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+/// Cross-origin policy for browser clients; `"*"` in any list allows everything for it
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CorsConfig {
+    #[serde(default = "default_cors_wildcard")]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_wildcard")]
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache preflight responses
+    pub max_age_secs: Option<u64>,
+    /// Let browsers send cookies and HTTP auth; requires explicit origins, methods and headers
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+fn default_cors_wildcard() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_cors_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: default_cors_wildcard(),
+            allowed_methods: default_cors_methods(),
+            allowed_headers: default_cors_wildcard(),
+            max_age_secs: None,
+            allow_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// True when the origin list is `["*"]`, which keeps the permissive policy
+    pub fn is_permissive(&self) -> bool {
+        self.allowed_origins.len() == 1 && self.allowed_origins[0] == "*"
+    }
+
+    fn validate(&self) -> crate::domain::errors::Result<()> {
+        let invalid = |message: String| InferenceError::InvalidConfig { message };
+
+        if !self.is_permissive() {
+            for origin in &self.allowed_origins {
+                let valid = origin
+                    .parse::<Uri>()
+                    .map(|uri| uri.scheme().is_some() && uri.host().is_some() && uri.path() == "/" && uri.query().is_none())
+                    .unwrap_or(false);
+                if !valid || origin.ends_with('/') {
+                    return Err(invalid(format!(
+                        "server.cors.allowed_origins entry '{}' must be \"*\" or an origin such as https://example.com",
+                        origin
+                    )));
+                }
+            }
+        }
+        for method in self.allowed_methods.iter().filter(|m| *m != "*") {
+            method
+                .parse::<Method>()
+                .map_err(|_| invalid(format!("server.cors.allowed_methods entry '{}' is not an HTTP method", method)))?;
+        }
+        for header in self.allowed_headers.iter().filter(|h| *h != "*") {
+            header
+                .parse::<HeaderName>()
+                .map_err(|_| invalid(format!("server.cors.allowed_headers entry '{}' is not a header name", header)))?;
+        }
+        // Browsers ignore credentialed responses with wildcards, and tower-http panics on them
+        if self.allow_credentials {
+            let wildcard = self.is_permissive()
+                || self.allowed_methods.iter().any(|m| m == "*")
+                || self.allowed_headers.iter().any(|h| h == "*");
+            if wildcard {
+                return Err(invalid(
+                    "server.cors.allow_credentials requires explicit allowed_origins, allowed_methods and allowed_headers, not \"*\"".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_max_batch_size() -> usize {
+    100
+}
+
+fn default_request_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_similarity_matrix_size() -> usize {
+    256
+}
+
+fn default_grpc_port() -> u16 {
+    50051
+}
+
+fn default_max_request_body_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_max_upload_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_max_input_chars() -> usize {
+    100_000
+}
+
+fn default_max_input_bytes() -> usize {
+    65_536
+}
+
+fn default_check_finite_embeddings() -> bool {
+    true
+}
+
+fn default_enable_compression() -> bool {
+    true
 }
 
 impl Default for ServerConfig {
@@ -25,21 +412,110 @@ impl Default for ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
             workers: 4,
+            max_batch_size: default_max_batch_size(),
+            batch_mode: BatchMode::default(),
+            stream_batch_size: None,
+            max_concurrent_inferences: None,
+            overload_mode: OverloadMode::default(),
+            idle_unload_secs: None,
+            idle_reload_mode: IdleReloadMode::default(),
+            request_timeout_ms: default_request_timeout_ms(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            max_similarity_matrix_size: default_max_similarity_matrix_size(),
+            grpc_port: default_grpc_port(),
+            max_request_body_bytes: default_max_request_body_bytes(),
+            max_upload_bytes: default_max_upload_bytes(),
+            max_input_chars: default_max_input_chars(),
+            max_input_bytes: default_max_input_bytes(),
+            check_finite_embeddings: default_check_finite_embeddings(),
+            admin_token: None,
+            allow_model_paths: false,
+            background_model_loading: false,
+            enable_websocket: false,
+            enable_compression: default_enable_compression(),
+            cors: CorsConfig::default(),
         }
     }
 }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            model: ModelConfig::default(),
-            server: ServerConfig::default(),
+impl ServerConfig {
+    /// Reject settings the server cannot run with
+    pub fn validate(&self) -> crate::domain::errors::Result<()> {
+        if self.max_batch_size < 1 {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.max_batch_size must be at least 1".to_string(),
+            });
+        }
+        if self.stream_batch_size == Some(0) {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.stream_batch_size must be at least 1".to_string(),
+            });
+        }
+        if self.idle_unload_secs == Some(0) {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.idle_unload_secs must be at least 1".to_string(),
+            });
+        }
+        if self.max_concurrent_inferences == Some(0) {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.max_concurrent_inferences must be at least 1".to_string(),
+            });
+        }
+        if self.request_timeout_ms == 0 {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.request_timeout_ms must be greater than 0".to_string(),
+            });
         }
+        if self.max_request_body_bytes == 0 {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.max_request_body_bytes must be greater than 0".to_string(),
+            });
+        }
+        if self.max_upload_bytes == 0 {
+            return Err(InferenceError::InvalidConfig {
+                message: "server.max_upload_bytes must be greater than 0".to_string(),
+            });
+        }
+        self.cors.validate()
     }
 }
 
 pub struct FileConfigurationService {
     config: Arc<RwLock<AppConfig>>,
+    config_dir: PathBuf,
+    /// Config files alone, and environment variables alone, for attributing each value
+    file_layer: Config,
+    env_layer: Config,
+    model_changed_at_runtime: Arc<std::sync::atomic::AtomicBool>,
+}
+
+/// Where the effective value of a config field came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    Default,
+    File,
+    EnvVar,
+    /// Changed after startup, e.g. by switching models
+    Runtime,
+}
+
+/// The effective configuration with the source of every leaf field, keyed by dotted path
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveConfig {
+    pub config: serde_json::Value,
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+/// Fields whose values are secrets and are never returned by `effective_config`
+const REDACTED_FIELDS: &[&str] = &["server.admin_token", "cache.redis_url"];
+
+/// `INFERENCE_SERVER__PORT=3000` overrides `server.port`; `__` separates nested keys
+fn environment_source() -> Environment {
+    Environment::with_prefix("INFERENCE")
+        .prefix_separator("_")
+        .separator("__")
+        .try_parsing(true)
 }
 
 impl FileConfigurationService {
@@ -48,24 +524,30 @@ impl FileConfigurationService {
     }
 
     pub fn new_with_environment(env: Option<&str>) -> Result<Self, ConfigError> {
+        Self::load(Path::new(CONFIG_DIR), env, environment_source())
+    }
+
+    /// Layer the config files in `config_dir` under the `variables` environment overrides
+    fn load(config_dir: &Path, env: Option<&str>, variables: Environment) -> Result<Self, ConfigError> {
         let default_env = std::env::var("INFERENCE_ENV").unwrap_or_else(|_| "development".to_string());
         let environment = env.unwrap_or(&default_env);
-        
+        let config_file = |name: &str| File::from(config_dir.join(name)).required(false);
+
         let mut builder = Config::builder()
-            .add_source(File::with_name("config/default").required(false));
+            .add_source(config_file("default"));
 
         // Add environment-specific config file
         if environment != "default" {
-            builder = builder.add_source(
-                File::with_name(&format!("config/{}", environment)).required(false)
-            );
+            builder = builder.add_source(config_file(environment));
         }
 
         // Add local override file
-        builder = builder.add_source(File::with_name("config/local").required(false));
+        builder = builder.add_source(config_file(LOCAL_CONFIG_FILE));
+        let file_layer = builder.build_cloned()?;
+        let env_layer = Config::builder().add_source(variables.clone()).build()?;
 
         // Add environment variables
-        builder = builder.add_source(Environment::with_prefix("INFERENCE"));
+        builder = builder.add_source(variables);
 
         let settings = builder.build()?;
 
@@ -82,6 +564,10 @@ impl FileConfigurationService {
 
         Ok(Self {
             config: Arc::new(RwLock::new(config)),
+            config_dir: config_dir.to_path_buf(),
+            file_layer,
+            env_layer,
+            model_changed_at_runtime: Arc::new(std::sync::atomic::AtomicBool::new(false)),
         })
     }
 
@@ -98,6 +584,71 @@ impl FileConfigurationService {
         })?;
         Ok(config.server.clone())
     }
+
+    /// Current configuration annotated with where each value came from, secrets redacted
+    pub fn effective_config(&self) -> Result<EffectiveConfig> {
+        let mut config = serde_json::to_value(self.get_app_config()?)?;
+        let model_changed = self.model_changed_at_runtime.load(std::sync::atomic::Ordering::SeqCst);
+
+        let mut sources = BTreeMap::new();
+        collect_sources(&config, String::new(), &mut |path| {
+            let source = if model_changed && path.starts_with("model.") {
+                ConfigSource::Runtime
+            } else if self.env_layer.get::<config::Value>(path).is_ok() {
+                ConfigSource::EnvVar
+            } else if self.file_layer.get::<config::Value>(path).is_ok() {
+                ConfigSource::File
+            } else {
+                ConfigSource::Default
+            };
+            sources.insert(path.to_string(), source);
+        });
+
+        for path in REDACTED_FIELDS {
+            let pointer = format!("/{}", path.replace('.', "/"));
+            if let Some(value) = config.pointer_mut(&pointer).filter(|value| !value.is_null()) {
+                *value = serde_json::Value::String("[redacted]".to_string());
+            }
+        }
+
+        Ok(EffectiveConfig { config, sources })
+    }
+
+    /// Write the runtime-changeable part of the config, the `[model]` table, to the local
+    /// config file. Other settings and overrides already in that file are kept, so values
+    /// from the environment-specific files aren't frozen into it.
+    pub fn save(&self) -> Result<()> {
+        let model_config = self.get_model_config()?;
+        let path = self.config_dir.join(LOCAL_CONFIG_FILE);
+        let mut local: toml::Table = match std::fs::read_to_string(&path) {
+            Ok(content) => content.parse()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e.into()),
+        };
+        local.insert("model".to_string(), toml::Value::try_from(&model_config)?);
+
+        std::fs::create_dir_all(&self.config_dir)?;
+        // Write then rename so a crash mid-write can't leave a truncated config behind
+        let temp_path = self.config_dir.join(LOCAL_CONFIG_TEMP_FILE);
+        std::fs::write(&temp_path, toml::to_string_pretty(&local)?)?;
+        std::fs::rename(&temp_path, &path)?;
+
+        tracing::info!("Persisted model {} to {}", model_config.model_id, path.display());
+        Ok(())
+    }
+}
+
+/// Call `visit` with the dotted path of every leaf (scalar, array or null) under `value`
+fn collect_sources(value: &serde_json::Value, path: String, visit: &mut impl FnMut(&str)) {
+    match value {
+        serde_json::Value::Object(fields) if !fields.is_empty() => {
+            for (key, field) in fields {
+                let field_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                collect_sources(field, field_path, visit);
+            }
+        }
+        _ => visit(&path),
+    }
 }
 
 impl ConfigurationService for FileConfigurationService {
@@ -108,11 +659,163 @@ impl ConfigurationService for FileConfigurationService {
         Ok(config.model.clone())
     }
 
-    fn update_model_config(&self, model_config: ModelConfig) -> Result<()> {
-        let mut config = self.config.write().map_err(|_| {
-            anyhow::anyhow!("Failed to acquire write lock on configuration")
+    fn get_named_model_configs(&self) -> Result<HashMap<String, ModelConfig>> {
+        let config = self.config.read().map_err(|_| {
+            anyhow::anyhow!("Failed to acquire read lock on configuration")
         })?;
-        config.model = model_config;
-        Ok(())
+        Ok(config.models.clone())
+    }
+
+    fn update_model_config(&self, model_config: ModelConfig) -> Result<()> {
+        let persistence = {
+            let mut config = self.config.write().map_err(|_| {
+                anyhow::anyhow!("Failed to acquire write lock on configuration")
+            })?;
+            config.model = model_config;
+            config.config_persistence
+        };
+        self.model_changed_at_runtime.store(true, std::sync::atomic::Ordering::SeqCst);
+
+        match persistence {
+            ConfigPersistenceMode::Memory => Ok(()),
+            ConfigPersistenceMode::Disk => self.save(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Config directory holding a minimal `default.toml`, with `top_level` keys before its tables
+    fn config_dir(top_level: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let default = format!(
+            "{}\n{}",
+            top_level,
+            r#"
+[model]
+model_id = "sentence-transformers/all-MiniLM-L6-v2"
+tokenizer_repo = "sentence-transformers/all-MiniLM-L6-v2"
+max_sequence_length = 256
+device = "cpu"
+
+[server]
+host = "127.0.0.1"
+port = 8080
+workers = 1
+"#
+        );
+        std::fs::write(dir.path().join("default.toml"), default).unwrap();
+        dir
+    }
+
+    fn load(dir: &tempfile::TempDir) -> FileConfigurationService {
+        load_with_env(dir, &[])
+    }
+
+    /// Load `dir` as if `variables` were the only environment variables set
+    fn load_with_env(dir: &tempfile::TempDir, variables: &[(&str, &str)]) -> FileConfigurationService {
+        let variables = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        FileConfigurationService::load(dir.path(), Some("default"), environment_source().source(Some(variables))).unwrap()
     }
-}
\ No newline at end of file
+
+    fn switched_model() -> ModelConfig {
+        ModelConfig {
+            model_id: "intfloat/e5-small-v2".to_string(),
+            tokenizer_repo: "intfloat/e5-small-v2".to_string(),
+            max_sequence_length: 512,
+            ..ModelConfig::default()
+        }
+    }
+
+    #[test]
+    fn switched_models_are_read_back_after_a_restart_with_disk_persistence() {
+        let dir = config_dir(r#"config_persistence = "disk""#);
+
+        load(&dir).update_model_config(switched_model()).unwrap();
+        let restarted = load(&dir);
+
+        let model = restarted.get_model_config().unwrap();
+        assert_eq!(model.model_id, "intfloat/e5-small-v2");
+        assert_eq!(model.max_sequence_length, 512);
+    }
+
+    #[test]
+    fn saved_model_config_round_trips_and_saving_again_changes_nothing() {
+        let dir = config_dir(r#"config_persistence = "disk""#);
+        let model = ModelConfig {
+            prompts: [("query".to_string(), "query: ".to_string())].into(),
+            revision: Some("v2".to_string()),
+            ..switched_model()
+        };
+        let service = load(&dir);
+        service.update_model_config(model.clone()).unwrap();
+        let saved = std::fs::read_to_string(dir.path().join(LOCAL_CONFIG_FILE)).unwrap();
+
+        service.save().unwrap();
+        let restarted = load(&dir).get_model_config().unwrap();
+
+        assert_eq!(std::fs::read_to_string(dir.path().join(LOCAL_CONFIG_FILE)).unwrap(), saved);
+        assert!(!dir.path().join(LOCAL_CONFIG_TEMP_FILE).exists());
+        assert_eq!(serde_json::to_value(&restarted).unwrap(), serde_json::to_value(&model).unwrap());
+    }
+
+    #[test]
+    fn saving_keeps_other_local_overrides() {
+        let dir = config_dir(r#"config_persistence = "disk""#);
+        std::fs::write(dir.path().join(LOCAL_CONFIG_FILE), "[server]\nport = 9090\n").unwrap();
+
+        load(&dir).update_model_config(switched_model()).unwrap();
+        let restarted = load(&dir);
+
+        assert_eq!(restarted.get_server_config().unwrap().port, 9090);
+        assert_eq!(restarted.get_model_config().unwrap().model_id, "intfloat/e5-small-v2");
+    }
+
+    #[test]
+    fn environment_variables_override_config_files() {
+        let dir = config_dir("");
+
+        let service = load_with_env(
+            &dir,
+            &[
+                ("INFERENCE_MODEL__MODEL_ID", "intfloat/e5-small-v2"),
+                ("INFERENCE_MODEL__DEVICE", "cuda"),
+                ("INFERENCE_MODEL__MAX_SEQUENCE_LENGTH", "128"),
+                ("INFERENCE_SERVER__PORT", "3000"),
+            ],
+        );
+
+        let model = service.get_model_config().unwrap();
+        assert_eq!(model.model_id, "intfloat/e5-small-v2");
+        assert_eq!(model.device, "cuda");
+        assert_eq!(model.max_sequence_length, 128);
+        assert_eq!(service.get_server_config().unwrap().port, 3000);
+        let sources = service.effective_config().unwrap().sources;
+        assert_eq!(sources["model.model_id"], ConfigSource::EnvVar);
+        assert_eq!(sources["server.port"], ConfigSource::EnvVar);
+        assert_eq!(sources["server.host"], ConfigSource::File);
+        assert_eq!(sources["server.max_batch_size"], ConfigSource::Default);
+    }
+
+    #[test]
+    fn effective_config_redacts_secrets() {
+        let dir = config_dir("");
+
+        let service = load_with_env(&dir, &[("INFERENCE_SERVER__ADMIN_TOKEN", "secret")]);
+
+        let effective = service.effective_config().unwrap();
+        assert_eq!(service.get_server_config().unwrap().admin_token.as_deref(), Some("secret"));
+        assert_eq!(effective.config["server"]["admin_token"], "[redacted]");
+    }
+
+    #[test]
+    fn switched_models_are_forgotten_with_memory_persistence() {
+        let dir = config_dir("");
+
+        load(&dir).update_model_config(switched_model()).unwrap();
+
+        assert!(!dir.path().join(LOCAL_CONFIG_FILE).exists());
+        assert_eq!(load(&dir).get_model_config().unwrap().model_id, "sentence-transformers/all-MiniLM-L6-v2");
+    }
+}
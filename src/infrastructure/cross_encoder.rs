@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use candle_core::{DType, Device, IndexOp, Module, Tensor};
+use candle_nn::{linear, Linear, VarBuilder};
+use candle_transformers::models::bert::BertModel;
+use tokenizers::Tokenizer;
+use tokio::sync::RwLock;
+
+use crate::domain::entities::ModelConfig;
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::CrossEncoderService;
+use crate::infrastructure::hf_config::{check_remote_code, parse_bert_config};
+use crate::infrastructure::model_loader::CandleModelLoader;
+
+/// BERT with a sequence classification head, scoring (query, passage) pairs jointly
+struct CrossEncoderModel {
+    bert: BertModel,
+    pooler: Linear,
+    classifier: Linear,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl CrossEncoderModel {
+    /// Relevance of each passage to the query as `sigmoid` of the first logit
+    fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f32>> {
+        let pairs: Vec<(String, String)> = passages
+            .iter()
+            .map(|passage| (query.to_string(), passage.clone()))
+            .collect();
+        let encodings = self.tokenizer
+            .encode_batch(pairs, true)
+            .map_err(|e| anyhow!("Pair tokenization failed: {}", e))?;
+
+        let stack = |rows: Vec<&[u32]>| -> Result<Tensor> {
+            let rows = rows
+                .into_iter()
+                .map(|row| Ok(Tensor::new(row, &self.device)?))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Tensor::stack(&rows, 0)?)
+        };
+        let token_ids = stack(encodings.iter().map(|e| e.get_ids()).collect())?;
+        let token_type_ids = stack(encodings.iter().map(|e| e.get_type_ids()).collect())?;
+        let attention_mask = stack(encodings.iter().map(|e| e.get_attention_mask()).collect())?;
+
+        let hidden_states = self.bert.forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
+        // BERT pooler: dense + tanh over the [CLS] token
+        let cls = hidden_states.i((.., 0))?;
+        let pooled = self.pooler.forward(&cls)?.tanh()?;
+        let logits = self.classifier.forward(&pooled)?;
+        let scores = candle_nn::ops::sigmoid(&logits.i((.., 0))?.to_dtype(DType::F32)?)?;
+
+        Ok(scores.to_vec1::<f32>()?)
+    }
+}
+
+/// Loads and serves the cross-encoder used for reranking, separate from the embedding models
+pub struct CrossEncoderModelLoader {
+    model: RwLock<Option<Arc<CrossEncoderModel>>>,
+}
+
+impl Default for CrossEncoderModelLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CrossEncoderModelLoader {
+    pub fn new() -> Self {
+        Self {
+            model: RwLock::new(None),
+        }
+    }
+
+    pub async fn load(&self, config: &ModelConfig) -> Result<()> {
+        tracing::info!("Loading cross-encoder: {}", config.model_id);
+
+        let device = CandleModelLoader::get_device(&config.device, config.device_index, config.strict_device)?;
+        let dtype = CandleModelLoader::get_dtype(config.dtype, &device);
+        let (config_filename, tokenizer_filename, weights_filename) =
+            CandleModelLoader::resolve_model_files(config, None).await?;
+
+        let config_content = std::fs::read_to_string(config_filename)?;
+        check_remote_code(&config_content, &config.model_id, config.trust_remote_code)?;
+        let bert_config = parse_bert_config(&config_content, &config.model_id)?;
+        let num_labels = serde_json::from_str::<serde_json::Value>(&config_content)?
+            .get("id2label")
+            .and_then(|labels| labels.as_object())
+            .map(|labels| labels.len())
+            .unwrap_or(1);
+        let tokenizer = CandleModelLoader::load_tokenizer(&tokenizer_filename)?;
+
+        let vb = if config.use_pth.unwrap_or(false) {
+            VarBuilder::from_pth(&weights_filename, dtype, &device)?
+        } else {
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], dtype, &device)? }
+        };
+
+        let hidden_size = bert_config.hidden_size;
+        let pooler_prefix = if vb.contains_tensor("pooler.dense.weight") {
+            "pooler.dense"
+        } else {
+            "bert.pooler.dense"
+        };
+        let model = CrossEncoderModel {
+            pooler: linear(hidden_size, hidden_size, vb.pp(pooler_prefix))?,
+            classifier: linear(hidden_size, num_labels, vb.pp("classifier"))?,
+            bert: BertModel::load(vb, &bert_config)?,
+            tokenizer,
+            device,
+        };
+
+        *self.model.write().await = Some(Arc::new(model));
+        tracing::info!("Cross-encoder {} loaded", config.model_id);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CrossEncoderService for CrossEncoderModelLoader {
+    async fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f32>> {
+        let model = self
+            .model
+            .read()
+            .await
+            .clone()
+            .ok_or(InferenceError::ModelLoading)?;
+        let query = query.to_string();
+        let passages = passages.to_vec();
+        tokio::task::spawn_blocking(move || model.score(&query, &passages)).await?
+    }
+}
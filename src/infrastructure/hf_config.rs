@@ -0,0 +1,253 @@
+//! Tolerant reading of Hugging Face `config.json` files. Hub repos often carry keys
+//! from newer `transformers` versions, training scripts or custom code; those are
+//! ignored rather than failing the load, as long as the keys the encoder needs are
+//! present and have values it supports.
+
+use std::collections::BTreeMap;
+use anyhow::Result;
+use candle_transformers::models::bert::{Config as BertConfig, HiddenAct, PositionEmbeddingType};
+use serde::Deserialize;
+
+use crate::domain::errors::InferenceError;
+
+/// Keys of a BERT or RoBERTa `config.json` that affect loading; anything else is ignored.
+/// The first five are required, the rest fall back to the BERT defaults
+pub const SUPPORTED_BERT_CONFIG_KEYS: &[&str] = &[
+    "vocab_size",
+    "hidden_size",
+    "num_hidden_layers",
+    "num_attention_heads",
+    "intermediate_size",
+    "hidden_act",
+    "max_position_embeddings",
+    "type_vocab_size",
+    "layer_norm_eps",
+    "pad_token_id",
+    "position_embedding_type",
+    "hidden_dropout_prob",
+    "initializer_range",
+    "classifier_dropout",
+    "use_cache",
+    "model_type",
+];
+
+fn default_hidden_act() -> String {
+    "gelu".to_string()
+}
+
+fn default_position_embedding_type() -> String {
+    "absolute".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct BertConfigFile {
+    vocab_size: usize,
+    hidden_size: usize,
+    num_hidden_layers: usize,
+    num_attention_heads: usize,
+    intermediate_size: usize,
+    #[serde(default = "default_hidden_act")]
+    hidden_act: String,
+    #[serde(default)]
+    max_position_embeddings: Option<usize>,
+    #[serde(default)]
+    type_vocab_size: Option<usize>,
+    #[serde(default)]
+    layer_norm_eps: Option<f64>,
+    #[serde(default)]
+    pad_token_id: Option<usize>,
+    #[serde(default = "default_position_embedding_type")]
+    position_embedding_type: String,
+    #[serde(default)]
+    hidden_dropout_prob: Option<f64>,
+    #[serde(default)]
+    initializer_range: Option<f64>,
+    #[serde(default)]
+    classifier_dropout: Option<f64>,
+    #[serde(default)]
+    use_cache: Option<bool>,
+    #[serde(default)]
+    model_type: Option<String>,
+    /// Keys this loader doesn't use, kept only to report them
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Parse a BERT or RoBERTa `config.json`, ignoring keys that don't affect the encoder
+pub fn parse_bert_config(content: &str, model_id: &str) -> Result<BertConfig> {
+    let file: BertConfigFile = serde_json::from_str(content).map_err(|e| InferenceError::InvalidConfig {
+        message: format!(
+            "config.json of {} can't be read as a BERT config: {}; supported keys are {}",
+            model_id,
+            e,
+            SUPPORTED_BERT_CONFIG_KEYS.join(", ")
+        ),
+    })?;
+    if !file.extra.is_empty() {
+        let keys: Vec<&str> = file.extra.keys().map(String::as_str).collect();
+        tracing::debug!("Ignoring config.json keys of {}: {}", model_id, keys.join(", "));
+    }
+
+    let position_embedding_type = match file.position_embedding_type.as_str() {
+        "absolute" => PositionEmbeddingType::Absolute,
+        other => {
+            return Err(InferenceError::InvalidConfig {
+                message: format!("{} uses '{}' position embeddings; only 'absolute' is supported", model_id, other),
+            }.into())
+        }
+    };
+
+    let defaults = BertConfig::default();
+    Ok(BertConfig {
+        vocab_size: file.vocab_size,
+        hidden_size: file.hidden_size,
+        num_hidden_layers: file.num_hidden_layers,
+        num_attention_heads: file.num_attention_heads,
+        intermediate_size: file.intermediate_size,
+        hidden_act: parse_hidden_act(&file.hidden_act, model_id)?,
+        hidden_dropout_prob: file.hidden_dropout_prob.unwrap_or(defaults.hidden_dropout_prob),
+        max_position_embeddings: file.max_position_embeddings.unwrap_or(defaults.max_position_embeddings),
+        type_vocab_size: file.type_vocab_size.unwrap_or(defaults.type_vocab_size),
+        initializer_range: file.initializer_range.unwrap_or(defaults.initializer_range),
+        layer_norm_eps: file.layer_norm_eps.unwrap_or(defaults.layer_norm_eps),
+        pad_token_id: file.pad_token_id.unwrap_or(defaults.pad_token_id),
+        position_embedding_type,
+        use_cache: file.use_cache.unwrap_or(defaults.use_cache),
+        classifier_dropout: file.classifier_dropout,
+        model_type: file.model_type,
+    })
+}
+
+/// `transformers` activation names mapped onto the ones candle implements; the tanh
+/// variants all compute the same approximation
+fn parse_hidden_act(name: &str, model_id: &str) -> Result<HiddenAct> {
+    match name {
+        "gelu" => Ok(HiddenAct::Gelu),
+        "gelu_new" | "gelu_fast" | "gelu_pytorch_tanh" | "geluapproximate" => Ok(HiddenAct::GeluApproximate),
+        "relu" => Ok(HiddenAct::Relu),
+        other => Err(InferenceError::InvalidConfig {
+            message: format!(
+                "{} uses the '{}' activation; supported are gelu, gelu_new, gelu_fast, gelu_pytorch_tanh and relu",
+                model_id, other
+            ),
+        }.into()),
+    }
+}
+
+/// Refuse repos whose `config.json` points at custom modelling code (`auto_map`) unless
+/// the config trusts them. That code never runs here; the repo is loaded as the standard
+/// architecture it names, which only matches if the custom code doesn't change the model
+pub fn check_remote_code(content: &str, model_id: &str, trust_remote_code: bool) -> Result<()> {
+    #[derive(Deserialize)]
+    struct AutoMap {
+        #[serde(default)]
+        auto_map: Option<serde_json::Value>,
+    }
+
+    let auto_map: AutoMap = serde_json::from_str(content)?;
+    if auto_map.auto_map.is_some() && !trust_remote_code {
+        return Err(InferenceError::InvalidConfig {
+            message: format!(
+                "{} declares custom modelling code; set trust_remote_code = true to load it as its standard architecture anyway",
+                model_id
+            ),
+        }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::domain::entities::{EmbeddingRequest, ModelConfig};
+    use crate::domain::traits::{EmbeddingService, ModelRepository};
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+    use crate::test_support::{tiny_model_config, tiny_model_dir, TINY_HIDDEN_SIZE};
+
+    const REQUIRED: &str = r#""vocab_size": 100, "hidden_size": 32, "num_hidden_layers": 1, "num_attention_heads": 2, "intermediate_size": 64"#;
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let content = format!(
+            r#"{{{}, "architectures": ["BertModel"], "torch_dtype": "float32", "transformers_version": "4.41.0",
+                "id2label": {{"0": "LABEL_0"}}, "gradient_checkpointing": false, "classifier_dropout": null,
+                "hidden_act": "gelu_new", "layer_norm_eps": 1e-7, "type_vocab_size": 1}}"#,
+            REQUIRED
+        );
+
+        let config = parse_bert_config(&content, "extra").unwrap();
+
+        assert_eq!((config.vocab_size, config.hidden_size, config.num_hidden_layers), (100, 32, 1));
+        assert_eq!(config.hidden_act, HiddenAct::GeluApproximate);
+        assert_eq!(config.layer_norm_eps, 1e-7);
+        assert_eq!(config.type_vocab_size, 1);
+        assert_eq!(config.classifier_dropout, None);
+    }
+
+    #[test]
+    fn optional_keys_fall_back_to_the_bert_defaults() {
+        let config = parse_bert_config(&format!("{{{}}}", REQUIRED), "minimal").unwrap();
+        let defaults = BertConfig::default();
+
+        assert_eq!(config.hidden_act, HiddenAct::Gelu);
+        assert_eq!(config.max_position_embeddings, defaults.max_position_embeddings);
+        assert_eq!(config.type_vocab_size, defaults.type_vocab_size);
+        assert_eq!(config.pad_token_id, defaults.pad_token_id);
+        assert_eq!(config.model_type, None);
+    }
+
+    #[test]
+    fn unsupported_configs_fail_with_the_reason() {
+        let missing = parse_bert_config(r#"{"vocab_size": 100, "hidden_size": 32}"#, "org/missing").unwrap_err();
+        assert!(missing.to_string().contains("num_hidden_layers"), "{}", missing);
+        assert!(missing.to_string().contains("supported keys are vocab_size"), "{}", missing);
+
+        let activation = format!(r#"{{{}, "hidden_act": "swish"}}"#, REQUIRED);
+        let activation = parse_bert_config(&activation, "org/swish").unwrap_err();
+        assert!(activation.to_string().contains("'swish' activation"), "{}", activation);
+
+        let positions = format!(r#"{{{}, "position_embedding_type": "relative_key"}}"#, REQUIRED);
+        let positions = parse_bert_config(&positions, "org/relative").unwrap_err();
+        assert!(positions.to_string().contains("'relative_key' position embeddings"), "{}", positions);
+    }
+
+    #[test]
+    fn custom_code_needs_trust_remote_code() {
+        let content = format!(r#"{{{}, "auto_map": {{"AutoModel": "modeling.CustomBert"}}}}"#, REQUIRED);
+
+        let error = check_remote_code(&content, "org/custom", false).unwrap_err();
+        assert!(error.to_string().contains("trust_remote_code"), "{}", error);
+        check_remote_code(&content, "org/custom", true).unwrap();
+        check_remote_code(&format!("{{{}}}", REQUIRED), "org/plain", false).unwrap();
+    }
+
+    #[tokio::test]
+    async fn models_whose_config_has_extra_keys_still_load() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["tokenizer.json", "model.safetensors"] {
+            std::fs::copy(tiny_model_dir().join(name), dir.path().join(name)).unwrap();
+        }
+        let mut config: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(tiny_model_dir().join("config.json")).unwrap()).unwrap();
+        config["architectures"] = serde_json::json!(["BertModel"]);
+        config["_name_or_path"] = serde_json::json!("org/tiny");
+        config["custom_training_flag"] = serde_json::json!({"nested": [1, 2, 3]});
+        std::fs::write(dir.path().join("config.json"), config.to_string()).unwrap();
+
+        let loader = Arc::new(CandleModelLoader::new());
+        let model_config = ModelConfig {
+            model_id: dir.path().to_string_lossy().into_owned(),
+            ..tiny_model_config()
+        };
+        loader.load_model(&model_config).await.unwrap();
+        let response = SentenceTransformerService::new(loader)
+            .encode(EmbeddingRequest::new("hello world".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(response.embedding.len(), TINY_HIDDEN_SIZE);
+    }
+}
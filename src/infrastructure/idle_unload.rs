@@ -0,0 +1,262 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::domain::entities::{IdleReloadMode, ModelConfig, DEFAULT_MODEL_NAME};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::ModelRepository;
+use crate::infrastructure::config::WarmupConfig;
+use crate::infrastructure::model_loader::{ModelComponents, ModelProvider};
+
+/// Longest gap between idle checks, so short timeouts are still honoured promptly
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct IdleState {
+    last_used: HashMap<String, Instant>,
+    /// Configs of models unloaded for inactivity, to reload them from
+    unloaded: HashMap<String, ModelConfig>,
+    /// Models being reloaded in the background under `IdleReloadMode::Reject`
+    reloading: HashSet<String>,
+}
+
+/// Frees models that haven't served a request for `idle_timeout` and loads them
+/// again on the next request. Unloaded models keep reporting their config and stay
+/// listed, so they look loaded to everything except memory use.
+pub struct IdleUnloadingLoader {
+    inner: Arc<dyn ModelProvider>,
+    idle_timeout: Duration,
+    reload_mode: IdleReloadMode,
+    state: Arc<Mutex<IdleState>>,
+    /// Serializes blocking reloads so concurrent requests load a model only once
+    reload_lock: Mutex<()>,
+}
+
+impl IdleUnloadingLoader {
+    pub fn new(inner: Arc<dyn ModelProvider>, idle_timeout: Duration, reload_mode: IdleReloadMode) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+            reload_mode,
+            state: Arc::new(Mutex::new(IdleState::default())),
+            reload_lock: Mutex::new(()),
+        }
+    }
+
+    /// Check for idle models periodically until the loader is dropped
+    pub fn spawn_monitor(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let loader = Arc::downgrade(self);
+        let interval = (self.idle_timeout / 4).clamp(Duration::from_millis(100), MAX_CHECK_INTERVAL);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(loader) = loader.upgrade() else { break };
+                if let Err(e) = loader.unload_idle().await {
+                    tracing::warn!("Idle model check failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Unload every loaded model whose last request is older than the idle timeout
+    async fn unload_idle(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let now = Instant::now();
+        for name in self.inner.list_models().await? {
+            let last_used = *state.last_used.entry(name.clone()).or_insert(now);
+            if now.duration_since(last_used) < self.idle_timeout {
+                continue;
+            }
+            let config = self.inner.get_model_config(&name).await?;
+            self.inner.unload_model(&name).await?;
+            tracing::info!(
+                "Unloaded model '{}' after {:?} without requests; it reloads on the next request",
+                name,
+                self.idle_timeout
+            );
+            state.last_used.remove(&name);
+            state.unloaded.insert(name, config);
+        }
+        Ok(())
+    }
+
+    /// Load a model unloaded for inactivity back in, unless another request already has
+    async fn reload(&self, name: &str) -> Result<()> {
+        let _reload = self.reload_lock.lock().await;
+        let Some(config) = self.state.lock().await.unloaded.get(name).cloned() else {
+            return Ok(());
+        };
+        tracing::info!("Reloading idle-unloaded model '{}'", name);
+        self.inner.load_named_model(name, &config).await?;
+
+        let mut state = self.state.lock().await;
+        state.unloaded.remove(name);
+        state.last_used.insert(name.to_string(), Instant::now());
+        Ok(())
+    }
+
+    /// Reload in the background, failing requests with 503 until the model is back
+    fn spawn_reload(&self, name: String, config: ModelConfig) {
+        let (inner, state) = (self.inner.clone(), self.state.clone());
+        tokio::spawn(async move {
+            tracing::info!("Reloading idle-unloaded model '{}' in the background", name);
+            let result = inner.load_named_model(&name, &config).await;
+            let mut state = state.lock().await;
+            state.reloading.remove(&name);
+            match result {
+                Ok(()) => {
+                    state.unloaded.remove(&name);
+                    state.last_used.insert(name, Instant::now());
+                }
+                Err(e) => tracing::error!("Failed to reload model '{}': {}", name, e),
+            }
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelRepository for IdleUnloadingLoader {
+    async fn load_model(&self, config: &ModelConfig) -> Result<()> {
+        self.load_named_model(DEFAULT_MODEL_NAME, config).await
+    }
+
+    async fn load_named_model(&self, name: &str, config: &ModelConfig) -> Result<()> {
+        self.inner.load_named_model(name, config).await?;
+        let mut state = self.state.lock().await;
+        state.unloaded.remove(name);
+        state.last_used.insert(name.to_string(), Instant::now());
+        Ok(())
+    }
+
+    async fn get_current_config(&self) -> Result<ModelConfig> {
+        self.get_model_config(DEFAULT_MODEL_NAME).await
+    }
+
+    async fn get_model_config(&self, name: &str) -> Result<ModelConfig> {
+        if let Some(config) = self.state.lock().await.unloaded.get(name) {
+            return Ok(config.clone());
+        }
+        self.inner.get_model_config(name).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let mut names = self.inner.list_models().await?;
+        names.extend(self.state.lock().await.unloaded.keys().cloned());
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    async fn unload_model(&self, name: &str) -> Result<()> {
+        let mut state = self.state.lock().await;
+        state.last_used.remove(name);
+        if state.unloaded.remove(name).is_some() {
+            tracing::info!("Model '{}' was idle-unloaded and won't be reloaded", name);
+            return Ok(());
+        }
+        drop(state);
+        self.inner.unload_model(name).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelProvider for IdleUnloadingLoader {
+    async fn get_replicas(&self, name: &str) -> Result<Vec<Arc<ModelComponents>>> {
+        let is_unloaded = self.state.lock().await.unloaded.contains_key(name);
+        if is_unloaded {
+            match self.reload_mode {
+                IdleReloadMode::Block => self.reload(name).await?,
+                IdleReloadMode::Reject => {
+                    let mut state = self.state.lock().await;
+                    // Another request may have finished the reload since the check above
+                    if let Some(config) = state.unloaded.get(name).cloned() {
+                        if state.reloading.insert(name.to_string()) {
+                            self.spawn_reload(name.to_string(), config);
+                        }
+                        return Err(InferenceError::ModelLoading.into());
+                    }
+                }
+            }
+        }
+        // Recorded only for models that exist, so unknown names don't accumulate
+        let replicas = self.inner.get_replicas(name).await?;
+        self.state.lock().await.last_used.insert(name.to_string(), Instant::now());
+        Ok(replicas)
+    }
+
+    async fn warmup(&self, config: &WarmupConfig) -> Result<()> {
+        self.inner.warmup(config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::presentation::api::error_status;
+    use crate::test_support::{tiny_model_config, tiny_model_loader};
+
+    const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Wrap the tiny model's loader and wait until the monitor has unloaded the idle default model
+    async fn idle_unloaded(reload_mode: IdleReloadMode) -> (Arc<CandleModelLoader>, Arc<IdleUnloadingLoader>) {
+        let inner = tiny_model_loader().await;
+        let loader = Arc::new(IdleUnloadingLoader::new(inner.clone(), IDLE_TIMEOUT, reload_mode));
+        let monitor = loader.spawn_monitor();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while inner.list_models().await.unwrap().contains(&DEFAULT_MODEL_NAME.to_string()) {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the idle model is unloaded");
+        // Stopped so the reloaded model isn't unloaded again before the assertions
+        monitor.abort();
+        (inner, loader)
+    }
+
+    async fn inner_has_default(inner: &CandleModelLoader) -> bool {
+        inner.list_models().await.unwrap().contains(&DEFAULT_MODEL_NAME.to_string())
+    }
+
+    #[tokio::test]
+    async fn idle_models_are_unloaded_and_reloaded_by_the_next_request() {
+        let (inner, loader) = idle_unloaded(IdleReloadMode::Block).await;
+
+        assert_eq!(loader.list_models().await.unwrap(), vec![DEFAULT_MODEL_NAME.to_string()]);
+        assert_eq!(loader.get_current_config().await.unwrap().model_id, tiny_model_config().model_id);
+
+        assert_eq!(loader.get_replicas(DEFAULT_MODEL_NAME).await.unwrap().len(), 1);
+        assert!(inner_has_default(&inner).await);
+    }
+
+    #[tokio::test]
+    async fn reject_mode_fails_with_503_until_the_background_reload_finishes() {
+        let (inner, loader) = idle_unloaded(IdleReloadMode::Reject).await;
+
+        let Err(error) = loader.get_replicas(DEFAULT_MODEL_NAME).await else {
+            panic!("an idle-unloaded model is served before it is reloaded");
+        };
+        assert!(matches!(error.downcast_ref::<InferenceError>(), Some(InferenceError::ModelLoading)));
+        assert_eq!(error_status(&error), StatusCode::SERVICE_UNAVAILABLE);
+
+        let replicas = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match loader.get_replicas(DEFAULT_MODEL_NAME).await {
+                    Ok(replicas) => break replicas,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            }
+        })
+        .await
+        .expect("the model is reloaded in the background");
+        assert_eq!(replicas.len(), 1);
+        assert!(inner_has_default(&inner).await);
+    }
+}
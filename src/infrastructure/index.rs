@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::entities::SearchResult;
+use crate::domain::errors::InferenceError;
+
+/// Bumped when the saved layout changes, so older files fail with a clear message
+const FORMAT_VERSION: u32 = 1;
+
+/// Identifies an `IndexFlat` using inner product in a faiss index file
+const FAISS_FLAT_IP_FOURCC: &[u8; 4] = b"IxFI";
+const FAISS_METRIC_INNER_PRODUCT: i32 = 0;
+/// Written by faiss in two header fields it no longer reads
+const FAISS_UNUSED_HEADER_FIELD: i64 = 1 << 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    id: String,
+    text: String,
+    /// Unit length, so a dot product with a normalized query is the cosine similarity
+    embedding: Vec<f32>,
+}
+
+/// Documents and their embeddings, searched by brute-force cosine similarity.
+/// Every embedding must come from the same model, since scores across models are
+/// meaningless.
+#[derive(Debug, Default)]
+pub struct EmbeddingIndex {
+    entries: Vec<IndexEntry>,
+    positions: HashMap<String, usize>,
+    /// Model the stored embeddings came from; set by the first add
+    model_id: Option<String>,
+    /// Source of the ids assigned to documents added without one
+    next_id: u64,
+}
+
+impl EmbeddingIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn model_id(&self) -> Option<&str> {
+        self.model_id.as_deref()
+    }
+
+    /// Fail unless embeddings from `model_id` can be stored in or searched against this index
+    pub fn check_model(&self, model_id: &str) -> Result<(), InferenceError> {
+        match &self.model_id {
+            Some(indexed) if indexed != model_id => Err(InferenceError::InvalidInput {
+                message: format!(
+                    "the index holds embeddings from '{}', not '{}'; clear it to switch models",
+                    indexed, model_id
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Store a document, replacing any with the same id, and return the id it was stored under
+    pub fn add(
+        &mut self,
+        id: Option<String>,
+        text: String,
+        mut embedding: Vec<f32>,
+        model_id: &str,
+    ) -> Result<String, InferenceError> {
+        self.check_model(model_id)?;
+        if let Some(dimensions) = self.dimensions() {
+            if embedding.len() != dimensions {
+                return Err(InferenceError::InvalidInput {
+                    message: format!(
+                        "embedding has {} dimensions but the index holds {}",
+                        embedding.len(),
+                        dimensions
+                    ),
+                });
+            }
+        }
+        normalize(&mut embedding);
+
+        let id = id.unwrap_or_else(|| self.generate_id());
+        let entry = IndexEntry { id: id.clone(), text, embedding };
+        match self.positions.get(&id) {
+            Some(&position) => self.entries[position] = entry,
+            None => {
+                self.positions.insert(id.clone(), self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+        self.model_id.get_or_insert_with(|| model_id.to_string());
+        Ok(id)
+    }
+
+    /// Remove every document, returning how many there were
+    pub fn clear(&mut self) -> usize {
+        let removed = self.entries.len();
+        *self = Self::new();
+        removed
+    }
+
+    /// The `top_k` documents most similar to `query`, best first, leaving out any
+    /// scoring below `threshold`
+    pub fn search(&self, query: &[f32], top_k: usize, threshold: Option<f32>) -> Vec<SearchResult> {
+        let mut query = query.to_vec();
+        normalize(&mut query);
+
+        let mut scored: Vec<(usize, f32)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (position, dot(&query, &entry.embedding)))
+            // Dropped before ranking, so a selective threshold leaves little to sort
+            .filter(|&(_, score)| threshold.is_none_or(|threshold| score >= threshold))
+            .collect();
+        let by_score = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1);
+        // Partition around the k-th best so only the top k are sorted
+        if top_k < scored.len() {
+            if top_k > 0 {
+                scored.select_nth_unstable_by(top_k - 1, by_score);
+            }
+            scored.truncate(top_k);
+        }
+        scored.sort_by(by_score);
+
+        scored
+            .into_iter()
+            .map(|(position, score)| {
+                let entry = &self.entries[position];
+                SearchResult { id: entry.id.clone(), text: entry.text.clone(), score }
+            })
+            .collect()
+    }
+
+    /// Write the index as MessagePack, through a temporary file so a failed save
+    /// leaves any previous file intact
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        let saved = SavedIndexRef {
+            version: FORMAT_VERSION,
+            model_id: self.model_id.as_deref(),
+            next_id: self.next_id,
+            entries: &self.entries,
+        };
+        let bytes = rmp_serde::to_vec_named(&saved)?;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, bytes)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let saved: SavedIndex = rmp_serde::from_slice(&bytes).map_err(|e| corrupt(&e.to_string()))?;
+        if saved.version != FORMAT_VERSION {
+            return Err(corrupt(&format!("unsupported format version {}", saved.version)).into());
+        }
+
+        let mut index = Self {
+            model_id: saved.model_id,
+            next_id: saved.next_id,
+            ..Self::new()
+        };
+        for entry in saved.entries {
+            if index.dimensions().is_some_and(|dimensions| dimensions != entry.embedding.len()) {
+                return Err(corrupt("embeddings differ in dimensions").into());
+            }
+            if index.positions.insert(entry.id.clone(), index.entries.len()).is_some() {
+                return Err(corrupt(&format!("duplicate id '{}'", entry.id)).into());
+            }
+            index.entries.push(entry);
+        }
+        Ok(index)
+    }
+
+    /// Write the embeddings as a FAISS `IndexFlatIP`, readable by `faiss.read_index`,
+    /// and their ids and texts, in the same row order, as a JSON sidecar. Embeddings
+    /// are stored normalized, so inner-product search there ranks by cosine as here
+    pub fn export_faiss(&self, index_path: &Path, metadata_path: &Path) -> Result<()> {
+        let Some(dimensions) = self.dimensions() else {
+            return Err(InferenceError::InvalidInput {
+                message: "the index is empty, so there is nothing to export".to_string(),
+            }.into());
+        };
+        let dimensions = i32::try_from(dimensions)?;
+        let ntotal = i64::try_from(self.entries.len())?;
+
+        // Layout of faiss `write_index` for a flat index: fourcc, the common header
+        // (d, ntotal, two unused fields, is_trained, metric), then the vectors
+        // prefixed by their count of floats
+        let mut bytes = Vec::with_capacity(45 + self.entries.len() * dimensions as usize * 4);
+        bytes.extend_from_slice(FAISS_FLAT_IP_FOURCC);
+        bytes.extend_from_slice(&dimensions.to_le_bytes());
+        bytes.extend_from_slice(&ntotal.to_le_bytes());
+        bytes.extend_from_slice(&FAISS_UNUSED_HEADER_FIELD.to_le_bytes());
+        bytes.extend_from_slice(&FAISS_UNUSED_HEADER_FIELD.to_le_bytes());
+        bytes.push(1);
+        bytes.extend_from_slice(&FAISS_METRIC_INNER_PRODUCT.to_le_bytes());
+        bytes.extend_from_slice(&(ntotal as u64 * dimensions as u64).to_le_bytes());
+        for entry in &self.entries {
+            for value in &entry.embedding {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let metadata = FaissMetadata {
+            model_id: self.model_id.as_deref(),
+            dimensions: dimensions as usize,
+            metric: "inner_product",
+            ids: self.entries.iter().map(|entry| entry.id.as_str()).collect(),
+            texts: self.entries.iter().map(|entry| entry.text.as_str()).collect(),
+        };
+
+        for path in [index_path, metadata_path] {
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(index_path, bytes)?;
+        std::fs::write(metadata_path, serde_json::to_vec_pretty(&metadata)?)?;
+        Ok(())
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.entries.first().map(|entry| entry.embedding.len())
+    }
+
+    /// Next sequential id not already taken by a client-supplied one
+    fn generate_id(&mut self) -> String {
+        loop {
+            let id = self.next_id.to_string();
+            self.next_id += 1;
+            if !self.positions.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+}
+
+/// Layout of a saved index; `SavedIndexRef` writes the same fields without cloning
+#[derive(Deserialize)]
+struct SavedIndex {
+    version: u32,
+    model_id: Option<String>,
+    next_id: u64,
+    entries: Vec<IndexEntry>,
+}
+
+#[derive(Serialize)]
+struct SavedIndexRef<'a> {
+    version: u32,
+    model_id: Option<&'a str>,
+    next_id: u64,
+    entries: &'a [IndexEntry],
+}
+
+/// Sidecar of a faiss export; row `i` of the faiss index is `ids[i]`
+#[derive(Serialize)]
+struct FaissMetadata<'a> {
+    model_id: Option<&'a str>,
+    dimensions: usize,
+    metric: &'static str,
+    ids: Vec<&'a str>,
+    texts: Vec<&'a str>,
+}
+
+fn normalize(embedding: &mut [f32]) {
+    let norm = dot(embedding, embedding).sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn corrupt(message: &str) -> InferenceError {
+    InferenceError::InvalidInput {
+        message: format!("index file is corrupt: {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(embedding: &[f32]) -> Vec<u32> {
+        embedding.iter().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn saved_indexes_load_with_byte_identical_embeddings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/index.msgpack");
+        let mut index = EmbeddingIndex::new();
+        index.add(Some("cat".to_string()), "the cat".to_string(), vec![0.3, -1.7, 2.9e-8, 4.0], "tiny").unwrap();
+        index.add(None, "a dog".to_string(), vec![f32::MIN_POSITIVE, 1.0, -0.1, 1e7], "tiny").unwrap();
+
+        index.save_to_file(&path).unwrap();
+        let mut loaded = EmbeddingIndex::load_from_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.model_id(), Some("tiny"));
+        for (saved, restored) in index.entries.iter().zip(&loaded.entries) {
+            assert_eq!((&restored.id, &restored.text), (&saved.id, &saved.text));
+            assert_eq!(bits(&restored.embedding), bits(&saved.embedding));
+        }
+        assert!(!path.with_extension("tmp").exists());
+        // Generated ids carry on from where the saved index left off
+        let generated = loaded.add(None, "the mat".to_string(), vec![1.0, 0.0, 0.0, 0.0], "tiny").unwrap();
+        assert!(!index.positions.contains_key(&generated));
+    }
+
+    #[test]
+    fn corrupt_index_files_fail_to_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.msgpack");
+
+        std::fs::write(&path, b"not msgpack").unwrap();
+        let garbage = EmbeddingIndex::load_from_file(&path).unwrap_err();
+        assert!(garbage.to_string().contains("index file is corrupt"), "{}", garbage);
+
+        let future = SavedIndexRef { version: FORMAT_VERSION + 1, model_id: None, next_id: 0, entries: &[] };
+        std::fs::write(&path, rmp_serde::to_vec_named(&future).unwrap()).unwrap();
+        let version = EmbeddingIndex::load_from_file(&path).unwrap_err();
+        assert!(version.to_string().contains("unsupported format version"), "{}", version);
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, RwLock};
+use hf_hub::api::tokio::Progress;
+
+use crate::domain::entities::LoadStatus;
+
+/// Shared `LoadStatus` written by model loaders and read by `/model/status`
+#[derive(Debug, Clone, Default)]
+pub struct LoadStatusTracker {
+    status: Arc<RwLock<LoadStatus>>,
+}
+
+impl LoadStatusTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> LoadStatus {
+        self.status.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set(&self, status: LoadStatus) {
+        *self.status.write().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    /// Count `received` more bytes of the file being downloaded; ignored in any other state
+    fn add_downloaded(&self, received: u64) {
+        if let LoadStatus::Downloading { bytes, .. } = &mut *self.status.write().unwrap_or_else(|e| e.into_inner()) {
+            *bytes += received;
+        }
+    }
+}
+
+/// hf-hub download callback for one file. The Hub client clones it for every chunk it
+/// fetches in parallel, so the byte count lives in the shared tracker.
+#[derive(Clone)]
+pub(crate) struct DownloadProgress {
+    tracker: LoadStatusTracker,
+    file: String,
+}
+
+impl DownloadProgress {
+    pub(crate) fn new(tracker: LoadStatusTracker, file: String) -> Self {
+        Self { tracker, file }
+    }
+}
+
+impl Progress for DownloadProgress {
+    async fn init(&mut self, size: usize, _filename: &str) {
+        self.tracker.set(LoadStatus::Downloading {
+            file: self.file.clone(),
+            bytes: 0,
+            total: size as u64,
+        });
+    }
+
+    async fn update(&mut self, size: usize) {
+        self.tracker.add_downloaded(size as u64);
+    }
+
+    async fn finish(&mut self) {}
+}
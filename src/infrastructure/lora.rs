@@ -0,0 +1,324 @@
+//! Merging of PEFT LoRA adapters into base model weights. Each adapted linear layer
+//! `W` gains `B·A · lora_alpha / r`, after which the model is loaded as usual and runs
+//! at the base model's speed.
+
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::Result;
+use candle_core::{DType, Device, Tensor};
+use serde::Deserialize;
+
+use crate::domain::errors::InferenceError;
+
+/// File names of an adapter saved by PEFT's `save_pretrained`
+pub const ADAPTER_WEIGHTS_FILE: &str = "adapter_model.safetensors";
+pub const ADAPTER_CONFIG_FILE: &str = "adapter_config.json";
+
+/// Prefix PEFT puts before the base model's own parameter names
+const PEFT_PREFIX: &str = "base_model.model.";
+
+#[derive(Debug, Default, Deserialize)]
+struct AdapterConfig {
+    #[serde(default)]
+    r: Option<usize>,
+    #[serde(default)]
+    lora_alpha: Option<f64>,
+    /// Set for layers that store their weight as `[in, out]`, which BERT's don't
+    #[serde(default)]
+    fan_in_fan_out: bool,
+}
+
+/// Merge the adapter in `weights_path` into `base`, returning how many layers changed.
+/// `rank` overrides the adapter config's `r` in the `lora_alpha / r` scale; without
+/// `lora_alpha` the scale is 1. A rank of 0 leaves the weights untouched
+pub fn merge_adapter(
+    base: &mut HashMap<String, Tensor>,
+    weights_path: &Path,
+    config_path: Option<&Path>,
+    rank: Option<usize>,
+    device: &Device,
+) -> Result<usize> {
+    let config: AdapterConfig = match config_path {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => AdapterConfig::default(),
+    };
+    let rank = rank.or(config.r);
+    if rank == Some(0) {
+        tracing::info!("LoRA rank is 0, so the adapter adds nothing to the base weights");
+        return Ok(0);
+    }
+
+    let mut adapter = candle_core::safetensors::load(weights_path, device)?;
+    let modules: Vec<String> = adapter
+        .keys()
+        .filter_map(|name| name.strip_suffix(".lora_A.weight"))
+        .map(str::to_string)
+        .collect();
+    if modules.is_empty() {
+        return Err(invalid(format!("{} holds no lora_A weights", weights_path.display())).into());
+    }
+
+    for module in &modules {
+        let a = adapter.remove(&format!("{}.lora_A.weight", module)).expect("listed from the adapter's keys");
+        let b = adapter
+            .remove(&format!("{}.lora_B.weight", module))
+            .ok_or_else(|| invalid(format!("{} has lora_A but no lora_B weights", module)))?;
+        // A is [r, in] and B is [out, r]
+        let adapter_rank = a.dim(0)?;
+        if rank.is_some_and(|rank| rank != adapter_rank) {
+            return Err(invalid(format!(
+                "lora_rank is {} but the adapter's {} has rank {}",
+                rank.unwrap_or_default(),
+                module,
+                adapter_rank
+            )).into());
+        }
+        let scale = config.lora_alpha.map_or(1.0, |alpha| alpha / rank.unwrap_or(adapter_rank) as f64);
+
+        let target = base_weight_name(base, module)?;
+        let weight = &base[&target];
+        let mut delta = (b.to_dtype(DType::F32)?.matmul(&a.to_dtype(DType::F32)?)? * scale)?;
+        if config.fan_in_fan_out {
+            delta = delta.t()?;
+        }
+        if delta.dims() != weight.dims() {
+            return Err(invalid(format!(
+                "the adapter's update to {} has shape {:?} but the weight is {:?}",
+                target,
+                delta.dims(),
+                weight.dims()
+            )).into());
+        }
+        let merged = (weight.to_dtype(DType::F32)? + delta)?.to_dtype(weight.dtype())?;
+        base.insert(target, merged);
+    }
+    Ok(modules.len())
+}
+
+/// Name in the base weights of the layer an adapter module adapts. PEFT prefixes the
+/// name, and base checkpoints may or may not nest the encoder under `bert.`, so leading
+/// segments are dropped until exactly one base weight ends with what's left
+fn base_weight_name(base: &HashMap<String, Tensor>, module: &str) -> Result<String, InferenceError> {
+    let mut path = module.strip_prefix(PEFT_PREFIX).unwrap_or(module);
+    loop {
+        let suffix = format!("{}.weight", path);
+        let dotted = format!(".{}", suffix);
+        let matches: Vec<&String> = base
+            .keys()
+            .filter(|name| **name == suffix || name.ends_with(&dotted))
+            .collect();
+        match matches.as_slice() {
+            [name] => return Ok((*name).clone()),
+            [] => match path.split_once('.') {
+                Some((_, rest)) if rest.contains('.') => path = rest,
+                _ => return Err(invalid(format!("the base model has no weight for LoRA module {}", module))),
+            },
+            _ => return Err(invalid(format!("LoRA module {} matches several base weights", module))),
+        }
+    }
+}
+
+fn invalid(message: String) -> InferenceError {
+    InferenceError::InvalidConfig { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::domain::entities::{EmbeddingRequest, ModelConfig};
+    use crate::domain::traits::{EmbeddingService, ModelRepository};
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+    use crate::test_support::{tiny_model_config, TINY_HIDDEN_SIZE};
+
+    const QUERY: &str = "bert.encoder.layer.0.attention.self.query";
+
+    /// Write a rank-`a.dim(0)` adapter for `module` and, when given, its config
+    fn write_adapter(
+        dir: &Path,
+        module: &str,
+        a: Tensor,
+        b: Tensor,
+        config: Option<serde_json::Value>,
+    ) {
+        let tensors = HashMap::from([
+            (format!("{}{}.lora_A.weight", PEFT_PREFIX, module), a),
+            (format!("{}{}.lora_B.weight", PEFT_PREFIX, module), b),
+        ]);
+        candle_core::safetensors::save(&tensors, dir.join(ADAPTER_WEIGHTS_FILE)).unwrap();
+        if let Some(config) = config {
+            std::fs::write(dir.join(ADAPTER_CONFIG_FILE), config.to_string()).unwrap();
+        }
+    }
+
+    fn merge(
+        base: &mut HashMap<String, Tensor>,
+        dir: &tempfile::TempDir,
+        rank: Option<usize>,
+    ) -> Result<usize> {
+        let config = dir.path().join(ADAPTER_CONFIG_FILE);
+        let config = config.exists().then_some(config);
+        merge_adapter(
+            base,
+            &dir.path().join(ADAPTER_WEIGHTS_FILE),
+            config.as_deref(),
+            rank,
+            &Device::Cpu,
+        )
+    }
+
+    fn base_weights() -> HashMap<String, Tensor> {
+        let weight = Tensor::new(&[[1.0f32, 0.0, 0.0], [0.0, 1.0, 0.0]], &Device::Cpu).unwrap();
+        HashMap::from([(format!("{}.weight", QUERY), weight)])
+    }
+
+    /// A is `[1, 3]` and B is `[2, 1]`, so B·A is `[[1, 2, 3], [-1, -2, -3]]`
+    fn rank_one_adapter(config: Option<serde_json::Value>) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let a = Tensor::new(&[[1.0f32, 2.0, 3.0]], &Device::Cpu).unwrap();
+        let b = Tensor::new(&[[1.0f32], [-1.0]], &Device::Cpu).unwrap();
+        write_adapter(
+            dir.path(),
+            "encoder.layer.0.attention.self.query",
+            a,
+            b,
+            config,
+        );
+        dir
+    }
+
+    fn merged_query(base: &HashMap<String, Tensor>) -> Vec<Vec<f32>> {
+        base[&format!("{}.weight", QUERY)].to_vec2::<f32>().unwrap()
+    }
+
+    #[test]
+    fn deltas_are_scaled_by_alpha_over_rank() {
+        let dir = rank_one_adapter(Some(serde_json::json!({"r": 1, "lora_alpha": 0.5})));
+        let mut base = base_weights();
+
+        assert_eq!(merge(&mut base, &dir, None).unwrap(), 1);
+
+        assert_eq!(
+            merged_query(&base),
+            vec![vec![1.5, 1.0, 1.5], vec![-0.5, 0.0, -1.5]]
+        );
+    }
+
+    #[test]
+    fn adapters_without_alpha_add_the_plain_product() {
+        let dir = rank_one_adapter(None);
+        let mut base = base_weights();
+
+        merge(&mut base, &dir, None).unwrap();
+
+        assert_eq!(
+            merged_query(&base),
+            vec![vec![2.0, 2.0, 3.0], vec![-1.0, -1.0, -3.0]]
+        );
+    }
+
+    #[test]
+    fn rank_zero_leaves_the_weights_untouched() {
+        for (config, rank) in [
+            (Some(serde_json::json!({"r": 0, "lora_alpha": 8})), None),
+            (None, Some(0)),
+        ] {
+            let dir = rank_one_adapter(config);
+            let mut base = base_weights();
+
+            assert_eq!(merge(&mut base, &dir, rank).unwrap(), 0);
+
+            assert_eq!(merged_query(&base), merged_query(&base_weights()));
+        }
+    }
+
+    #[test]
+    fn mismatched_adapters_are_rejected() {
+        let dir = rank_one_adapter(Some(serde_json::json!({"lora_alpha": 2})));
+        let rank = merge(&mut base_weights(), &dir, Some(4)).unwrap_err();
+        assert!(rank.to_string().contains("lora_rank is 4"), "{}", rank);
+
+        let dir = tempfile::tempdir().unwrap();
+        let a = Tensor::ones((1, 5), DType::F32, &Device::Cpu).unwrap();
+        let b = Tensor::ones((2, 1), DType::F32, &Device::Cpu).unwrap();
+        write_adapter(
+            dir.path(),
+            "encoder.layer.0.attention.self.query",
+            a,
+            b,
+            None,
+        );
+        let shape = merge(&mut base_weights(), &dir, None).unwrap_err();
+        assert!(shape.to_string().contains("has shape [2, 5]"), "{}", shape);
+
+        let dir = tempfile::tempdir().unwrap();
+        let a = Tensor::ones((1, 3), DType::F32, &Device::Cpu).unwrap();
+        write_adapter(
+            dir.path(),
+            "encoder.layer.0.attention.self.key",
+            a.clone(),
+            a.t().unwrap(),
+            None,
+        );
+        let missing = merge(&mut base_weights(), &dir, None).unwrap_err();
+        assert!(
+            missing.to_string().contains("no weight for LoRA module"),
+            "{}",
+            missing
+        );
+    }
+
+    async fn encode_with_adapter(adapter: Option<&Path>, lora_rank: Option<usize>) -> Vec<f32> {
+        let loader = Arc::new(CandleModelLoader::new());
+        let config = ModelConfig {
+            lora_adapter_path: adapter.map(Path::to_path_buf),
+            lora_rank,
+            ..tiny_model_config()
+        };
+        loader.load_model(&config).await.unwrap();
+        SentenceTransformerService::new(loader)
+            .encode(EmbeddingRequest::new("the cat sleeps".to_string()))
+            .await
+            .unwrap()
+            .embedding
+    }
+
+    #[tokio::test]
+    async fn zero_rank_adapters_load_to_the_base_model_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let values = |count: usize| {
+            (0..count)
+                .map(|i| (i as f32 * 0.9).cos() * 0.5)
+                .collect::<Vec<f32>>()
+        };
+        let a = Tensor::from_vec(
+            values(2 * TINY_HIDDEN_SIZE),
+            (2, TINY_HIDDEN_SIZE),
+            &Device::Cpu,
+        )
+        .unwrap();
+        let b = Tensor::from_vec(
+            values(TINY_HIDDEN_SIZE * 2),
+            (TINY_HIDDEN_SIZE, 2),
+            &Device::Cpu,
+        )
+        .unwrap();
+        write_adapter(
+            dir.path(),
+            "encoder.layer.0.attention.output.dense",
+            a,
+            b,
+            Some(serde_json::json!({"r": 2, "lora_alpha": 4})),
+        );
+
+        let base = encode_with_adapter(None, None).await;
+        let zero_rank = encode_with_adapter(Some(dir.path()), Some(0)).await;
+        let adapted = encode_with_adapter(Some(dir.path()), None).await;
+
+        let bits = |embedding: &[f32]| embedding.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&zero_rank), bits(&base));
+        assert!(base.iter().zip(&adapted).any(|(a, b)| (a - b).abs() > 1e-3));
+    }
+}
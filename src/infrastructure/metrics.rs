@@ -0,0 +1,24 @@
+use std::sync::OnceLock;
+use anyhow::Result;
+use prometheus::{register_int_counter, IntCounter, TextEncoder};
+
+pub fn cache_hits_total() -> &'static IntCounter {
+    static COUNTER: OnceLock<IntCounter> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        register_int_counter!("cache_hits_total", "Embeddings served from the in-memory cache")
+            .expect("cache_hits_total is registered once")
+    })
+}
+
+pub fn cache_misses_total() -> &'static IntCounter {
+    static COUNTER: OnceLock<IntCounter> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        register_int_counter!("cache_misses_total", "Embedding lookups not found in the in-memory cache")
+            .expect("cache_misses_total is registered once")
+    })
+}
+
+/// Render all registered metrics in the Prometheus text exposition format
+pub fn render() -> Result<String> {
+    Ok(TextEncoder::new().encode_to_string(&prometheus::gather())?)
+}
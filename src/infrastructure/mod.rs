@@ -1,3 +1,20 @@
+pub mod cache;
 pub mod model_loader;
+pub mod model_registry;
+pub mod multi_device_loader;
+pub mod quantized_bert;
 pub mod sentence_transformer;
-pub mod config;
\ No newline at end of file
+pub mod telemetry;
+pub mod config;
+pub mod cross_encoder;
+pub mod sparse_encoder;
+pub mod metrics;
+pub mod preprocessing;
+pub mod asymmetric;
+pub mod load_status;
+pub mod pooling;
+pub mod mpnet;
+pub mod idle_unload;
+pub mod index;
+pub mod hf_config;
+pub mod lora;
\ No newline at end of file
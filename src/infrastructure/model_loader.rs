@@ -4,70 +4,392 @@ extern crate intel_mkl_src;
 #[cfg(feature = "accelerate")]
 extern crate accelerate_src;
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use anyhow::{anyhow, Result};
-use candle_core::Device;
+use candle_core::{DType, Device, DeviceLocation, Tensor};
 use candle_nn::VarBuilder;
-use candle_transformers::models::bert::{BertModel, Config as BertConfig, HiddenAct, DTYPE};
-use hf_hub::{api::sync::Api, Repo, RepoType};
-use tokenizers::{Tokenizer, PaddingParams};
+use candle_transformers::models::bert::{BertModel, Config as BertConfig, HiddenAct};
+use candle_transformers::models::distilbert::{Config as DistilBertConfig, DistilBertModel};
+use hf_hub::api::tokio::{Api, ApiBuilder, ApiError, ApiRepo};
+use hf_hub::{Cache, CacheRepo, Repo, RepoType};
+use rand::Rng;
+use serde::Deserialize;
+use tokenizers::{Tokenizer, PaddingParams, TruncationParams};
 use tokio::sync::RwLock;
 
-use crate::domain::entities::ModelConfig;
+use crate::domain::entities::{
+    DownloadRetryConfig, DtypeConfig, LoadStatus, ModelArchitecture, ModelCapabilities, ModelConfig, PaddingStrategy, PoolingStrategy, QuantizationType,
+    DEFAULT_MODEL_NAME,
+};
+use crate::domain::errors::InferenceError;
 use crate::domain::traits::ModelRepository;
+use crate::infrastructure::config::WarmupConfig;
+use crate::infrastructure::hf_config::{check_remote_code, parse_bert_config};
+use crate::infrastructure::load_status::{DownloadProgress, LoadStatusTracker};
+use crate::infrastructure::lora;
+use crate::infrastructure::mpnet::{MpNetConfig, MpNetModel};
+use crate::infrastructure::quantized_bert::QuantizedBertModel;
+
+const WARMUP_SEQUENCE_LENGTH: usize = 32;
+/// `model_type` values whose activation can be swapped for the approximate GELU
+const BERT_FAMILY_MODEL_TYPES: &[&str] = &["bert", "roberta", "xlm-roberta", "camembert"];
+/// Pooling module config shipped by sentence-transformers repos
+const POOLING_CONFIG_PATH: &str = "1_Pooling/config.json";
+
+/// Pooling modes enabled in a sentence-transformers `1_Pooling/config.json`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SentenceTransformersPoolingConfig {
+    pooling_mode_cls_token: bool,
+    pooling_mode_mean_tokens: bool,
+    pooling_mode_max_tokens: bool,
+    pooling_mode_mean_sqrt_len_tokens: bool,
+    pooling_mode_weightedmean_tokens: bool,
+    pooling_mode_lasttoken: bool,
+}
+
+impl SentenceTransformersPoolingConfig {
+    /// sentence-transformers concatenates every enabled mode; we serve a single one,
+    /// so pick the first supported mode and warn about the rest
+    fn strategy(&self, model_id: &str) -> PoolingStrategy {
+        if self.pooling_mode_weightedmean_tokens {
+            tracing::warn!("{} enables weighted-mean pooling, which isn't supported", model_id);
+        }
+        let enabled: Vec<PoolingStrategy> = [
+            (self.pooling_mode_cls_token, PoolingStrategy::Cls),
+            (self.pooling_mode_mean_tokens, PoolingStrategy::Mean),
+            (self.pooling_mode_max_tokens, PoolingStrategy::Max),
+            (self.pooling_mode_mean_sqrt_len_tokens, PoolingStrategy::MeanSqrtLen),
+            (self.pooling_mode_lasttoken, PoolingStrategy::LastToken),
+        ]
+        .into_iter()
+        .filter_map(|(is_enabled, strategy)| is_enabled.then_some(strategy))
+        .collect();
+
+        match enabled.as_slice() {
+            [] => {
+                tracing::warn!("{} enables no supported pooling mode, falling back to mean", model_id);
+                PoolingStrategy::Mean
+            }
+            [strategy] => *strategy,
+            [strategy, ..] => {
+                tracing::warn!(
+                    "{} enables several pooling modes, using only {}",
+                    model_id,
+                    strategy.as_str()
+                );
+                *strategy
+            }
+        }
+    }
+}
+
+/// The `model_type` field of a Hugging Face `config.json`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ModelTypeConfig {
+    model_type: Option<String>,
+}
+
+/// DistilBERT names its dimensions differently from BERT
+#[derive(Debug, Deserialize)]
+struct DistilBertDimensions {
+    vocab_size: usize,
+    dim: usize,
+    n_layers: usize,
+    n_heads: usize,
+    hidden_dim: usize,
+    max_position_embeddings: usize,
+}
+
+impl DistilBertDimensions {
+    /// The same dimensions as a BERT config, for parameter counts and model stats;
+    /// DistilBERT has no token type embeddings, hence a type vocabulary of zero
+    fn as_bert_config(&self) -> BertConfig {
+        BertConfig {
+            vocab_size: self.vocab_size,
+            hidden_size: self.dim,
+            num_hidden_layers: self.n_layers,
+            num_attention_heads: self.n_heads,
+            intermediate_size: self.hidden_dim,
+            max_position_embeddings: self.max_position_embeddings,
+            type_vocab_size: 0,
+            model_type: Some("distilbert".to_string()),
+            ..BertConfig::default()
+        }
+    }
+}
+
+/// Loaded encoder, either full precision or quantized
+pub enum EmbeddingModel {
+    Bert(BertModel),
+    QuantizedBert(QuantizedBertModel),
+    DistilBert(DistilBertModel),
+    MpNet(MpNetModel),
+}
+
+impl EmbeddingModel {
+    /// `token_type_ids` is `None` for architectures without sentence-pair types
+    pub fn forward(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        attention_mask: Option<&Tensor>,
+    ) -> candle_core::Result<Tensor> {
+        // The BERT encoder always adds a token type embedding; row 0 is the only one RoBERTa has
+        let default_token_types;
+        let token_type_ids = match token_type_ids {
+            Some(token_type_ids) => token_type_ids,
+            None => {
+                default_token_types = input_ids.zeros_like()?;
+                &default_token_types
+            }
+        };
+        match self {
+            Self::Bert(model) => model.forward(input_ids, token_type_ids, attention_mask),
+            Self::QuantizedBert(model) => model.forward(input_ids, token_type_ids, attention_mask),
+            // DistilBERT has no token types, and its mask flags the padding to hide rather than the tokens to keep
+            Self::DistilBert(model) => {
+                let padding = match attention_mask {
+                    Some(mask) => mask.eq(&mask.zeros_like()?)?,
+                    None => input_ids.zeros_like()?.to_dtype(DType::U8)?,
+                };
+                let (batch, seq_len) = padding.dims2()?;
+                model.forward(input_ids, &padding.reshape((batch, 1, 1, seq_len))?)
+            }
+            Self::MpNet(model) => model.forward(input_ids, attention_mask),
+        }
+    }
+}
+
+/// Source of loaded models for the inference service
+#[async_trait::async_trait]
+pub trait ModelProvider: ModelRepository {
+    /// Replicas of a loaded model to spread batch items across, one per device
+    async fn get_replicas(&self, name: &str) -> Result<Vec<Arc<ModelComponents>>>;
+
+    /// Run synthetic forward passes on the default model to compile kernels up front
+    async fn warmup(&self, config: &WarmupConfig) -> Result<()>;
+}
 
 pub struct ModelComponents {
-    pub model: BertModel,
+    pub model: EmbeddingModel,
     pub tokenizer: Tokenizer,
     pub device: Device,
+    pub dtype: DType,
+    pub pooling: PoolingStrategy,
+    /// Length of the pooled embeddings, read from the architecture config at load time
+    pub embedding_dim: usize,
+    pub architecture: ModelArchitecture,
+    /// Architecture dimensions; for DistilBERT and MPNet, their own config translated to BERT's names
+    pub bert_config: BertConfig,
     pub config: ModelConfig,
 }
 
+impl ModelComponents {
+    /// Parameters of the embeddings and encoder layers, computed from the architecture
+    /// config; the pooler head isn't loaded, so it isn't counted
+    pub fn parameter_count(&self) -> u64 {
+        let c = &self.bert_config;
+        let (hidden, intermediate) = (c.hidden_size as u64, c.intermediate_size as u64);
+        let layer_norm = 2 * hidden;
+        let embeddings = (c.vocab_size + c.max_position_embeddings + c.type_vocab_size) as u64 * hidden + layer_norm;
+        let attention = 4 * (hidden * hidden + hidden) + layer_norm;
+        let feed_forward = (hidden * intermediate + intermediate) + (intermediate * hidden + hidden) + layer_norm;
+        embeddings + c.num_hidden_layers as u64 * (attention + feed_forward)
+    }
+
+    pub fn is_quantized(&self) -> bool {
+        matches!(self.model, EmbeddingModel::QuantizedBert(_))
+    }
+
+    /// Short device name such as `cpu` or `cuda:1`
+    pub fn device_label(&self) -> String {
+        match self.device.location() {
+            DeviceLocation::Cpu => "cpu".to_string(),
+            DeviceLocation::Cuda { gpu_id } => format!("cuda:{}", gpu_id),
+            DeviceLocation::Metal { gpu_id } => format!("metal:{}", gpu_id),
+        }
+    }
+}
+
+/// Registry of loaded models keyed by logical name
 pub struct CandleModelLoader {
-    current_model: Arc<RwLock<Option<ModelComponents>>>,
+    models: Arc<RwLock<HashMap<String, Arc<ModelComponents>>>>,
+    load_status: LoadStatusTracker,
+}
+
+impl Default for CandleModelLoader {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CandleModelLoader {
     pub fn new() -> Self {
         Self {
-            current_model: Arc::new(RwLock::new(None)),
+            models: Arc::new(RwLock::new(HashMap::new())),
+            load_status: LoadStatusTracker::new(),
         }
     }
 
-    pub async fn get_model(&self) -> Result<Arc<RwLock<Option<ModelComponents>>>> {
-        Ok(self.current_model.clone())
+    /// Report download and load progress to `load_status`
+    pub fn with_load_status(mut self, load_status: LoadStatusTracker) -> Self {
+        self.load_status = load_status;
+        self
+    }
+
+    /// Shared handle to a loaded model, usable without holding the registry lock
+    pub async fn get_components(&self, name: &str) -> Result<Arc<ModelComponents>> {
+        let models_guard = self.models.read().await;
+        models_guard
+            .get(name)
+            .cloned()
+            .ok_or_else(|| missing_model(name))
     }
 
-    async fn download_and_load_model(&self, config: &ModelConfig) -> Result<ModelComponents> {
+    /// Make `components` visible under `name`; requests already holding the previous
+    /// `Arc` finish against it while new lookups see the replacement
+    pub(crate) async fn insert_components(&self, name: &str, components: Arc<ModelComponents>) {
+        let mut models_guard = self.models.write().await;
+        models_guard.insert(name.to_string(), components);
+    }
+
+    pub(crate) async fn download_and_load_model(&self, config: &ModelConfig) -> Result<ModelComponents> {
+        let result = self.load_components(config).await;
+        self.load_status.set(match &result {
+            Ok(_) => LoadStatus::Ready,
+            Err(e) => LoadStatus::Failed { message: format!("{:#}", e) },
+        });
+        result
+    }
+
+    async fn load_components(&self, config: &ModelConfig) -> Result<ModelComponents> {
         tracing::info!("Loading model: {}", config.model_id);
         tracing::debug!("Model config: {:?}", config);
 
-        let device = self.get_device(&config.device)?;
-        
-        let (default_model, default_revision) = self.get_default_model_config();
-        let (model_id, revision) = if config.model_id.is_empty() {
-            (default_model, default_revision)
+        let device = Self::get_device(&config.device, config.device_index, config.strict_device)?;
+        tracing::info!("🖥️  Model {} will run on {:?}", config.model_id, device);
+        let quantization = config.quantization.as_ref().map(|q| q.method);
+        if quantization.is_some() && !device.is_cpu() {
+            return Err(InferenceError::InvalidConfig {
+                message: "Quantization is only supported on CPU; use dtype = \"float16\" to reduce GPU memory instead".to_string(),
+            }.into());
+        }
+        // Quantized layers are built from full precision weights
+        let dtype = match quantization {
+            Some(_) => DType::F32,
+            None => Self::get_dtype(config.dtype, &device),
+        };
+
+        let (config_filename, tokenizer_filename, weights_filename) =
+            Self::resolve_model_files(config, Some(&self.load_status)).await?;
+        self.load_status.set(LoadStatus::Loading);
+
+        let config_content = std::fs::read_to_string(config_filename)?;
+        check_remote_code(&config_content, &config.model_id, config.trust_remote_code)?;
+        let model_type: ModelTypeConfig = serde_json::from_str(&config_content)?;
+        let architecture = ModelArchitecture::from_model_type(model_type.model_type.as_deref());
+        tracing::info!("Model {} uses the {} architecture", config.model_id, architecture.as_str());
+        let mut bert_config: BertConfig = match architecture {
+            ModelArchitecture::DistilBert => {
+                serde_json::from_str::<DistilBertDimensions>(&config_content)?.as_bert_config()
+            }
+            ModelArchitecture::MpNet => serde_json::from_str::<MpNetConfig>(&config_content)?.as_bert_config(),
+            ModelArchitecture::Bert | ModelArchitecture::Roberta => parse_bert_config(&config_content, &config.model_id)?,
+        };
+        let mut tokenizer = Self::load_tokenizer(&tokenizer_filename)?;
+        Self::apply_padding_strategy(&mut tokenizer, config)?;
+        let pooling = Self::resolve_pooling(config).await?;
+
+        let use_pth = config.use_pth.unwrap_or(false);
+        let lora_adapter = Self::resolve_lora_adapter(config).await?;
+        let vb = if architecture == ModelArchitecture::Roberta || lora_adapter.is_some() {
+            // Weights that get edited are read into memory rather than memory-mapped
+            let mut tensors = Self::read_weights(&weights_filename, use_pth, &device)?;
+            if architecture == ModelArchitecture::Roberta {
+                let offset = bert_config.pad_token_id + 1;
+                bert_config.max_position_embeddings = bert_config.max_position_embeddings.checked_sub(offset).ok_or_else(|| {
+                    InferenceError::InvalidConfig {
+                        message: format!("{} has fewer position embeddings than its padding offset", config.model_id),
+                    }
+                })?;
+                Self::offset_roberta_positions(&mut tensors, offset)?;
+            }
+            if let Some((adapter_weights, adapter_config)) = &lora_adapter {
+                let merged =
+                    lora::merge_adapter(&mut tensors, adapter_weights, adapter_config.as_deref(), config.lora_rank, &device)?;
+                tracing::info!("Merged a LoRA adapter into {} layers of {}", merged, config.model_id);
+            }
+            VarBuilder::from_tensors(tensors, dtype, &device)
+        } else if use_pth {
+            VarBuilder::from_pth(&weights_filename, dtype, &device)?
         } else {
-            (config.model_id.clone(), config.revision.clone().unwrap_or_else(|| "main".to_string()))
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], dtype, &device)? }
         };
 
-        let repo = Repo::with_revision(model_id, RepoType::Model, revision);
-        let (config_filename, tokenizer_filename, weights_filename) = {
-            let api = Api::new()?;
-            let api = api.repo(repo);
-            let config_file = api.get("config.json")?;
-            let tokenizer_file = api.get("tokenizer.json")?;
-            let weights = if config.use_pth.unwrap_or(false) {
-                api.get("pytorch_model.bin")?
-            } else {
-                api.get("model.safetensors")?
-            };
-            (config_file, tokenizer_file, weights)
+        if config.approximate_gelu.unwrap_or(false) {
+            if let Some(model_type) = bert_config.model_type.as_deref() {
+                if !BERT_FAMILY_MODEL_TYPES.contains(&model_type) {
+                    return Err(InferenceError::InvalidConfig {
+                        message: format!(
+                            "approximate_gelu is only supported for BERT-family models, {} is '{}'",
+                            config.model_id, model_type
+                        ),
+                    }.into());
+                }
+            }
+            bert_config.hidden_act = HiddenAct::GeluApproximate;
+        }
+
+        let model = match (architecture, quantization) {
+            (ModelArchitecture::DistilBert | ModelArchitecture::MpNet, Some(_)) => {
+                return Err(InferenceError::InvalidConfig {
+                    message: format!(
+                        "Quantization is only supported for BERT and RoBERTa models, {} is {}",
+                        config.model_id,
+                        architecture.as_str()
+                    ),
+                }.into());
+            }
+            (ModelArchitecture::DistilBert, None) => {
+                let distilbert_config: DistilBertConfig = serde_json::from_str(&config_content)?;
+                EmbeddingModel::DistilBert(DistilBertModel::load(vb, &distilbert_config)?)
+            }
+            (ModelArchitecture::MpNet, None) => {
+                let mpnet_config: MpNetConfig = serde_json::from_str(&config_content)?;
+                EmbeddingModel::MpNet(MpNetModel::load(vb, &mpnet_config)?)
+            }
+            (_, Some(QuantizationType::Int8)) => {
+                tracing::info!("Quantizing model weights to INT8");
+                EmbeddingModel::QuantizedBert(QuantizedBertModel::load(vb, &bert_config)?)
+            }
+            (_, None) => EmbeddingModel::Bert(BertModel::load(vb, &bert_config)?),
         };
 
-        let config_content = std::fs::read_to_string(config_filename)?;
-        let mut bert_config: BertConfig = serde_json::from_str(&config_content)?;
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename)
+        let embedding_dim = bert_config.hidden_size;
+        Ok(ModelComponents {
+            model,
+            tokenizer,
+            device,
+            dtype,
+            pooling,
+            embedding_dim,
+            architecture,
+            bert_config,
+            config: ModelConfig {
+                embedding_dim: Some(embedding_dim),
+                ..config.clone()
+            },
+        })
+    }
+
+    /// Load a tokenizer configured to pad batches to their longest sequence
+    pub(crate) fn load_tokenizer(path: &Path) -> Result<Tokenizer> {
+        let mut tokenizer = Tokenizer::from_file(path)
             .map_err(|e| anyhow!("Failed to load tokenizer: {}", e))?;
 
         // Configure tokenizer for batch processing
@@ -80,79 +402,1096 @@ impl CandleModelLoader {
             };
             tokenizer.with_padding(Some(pp));
         }
+        Ok(tokenizer)
+    }
 
-        let vb = if config.use_pth.unwrap_or(false) {
-            VarBuilder::from_pth(&weights_filename, DTYPE, &device)?
+    /// Switch a tokenizer from `load_tokenizer` to the model's configured padding, truncating
+    /// to `max_sequence_length` whatever the strategy
+    fn apply_padding_strategy(tokenizer: &mut Tokenizer, config: &ModelConfig) -> Result<()> {
+        let max_length = config.max_sequence_length;
+        match config.padding_strategy {
+            PaddingStrategy::BatchLongest => {}
+            PaddingStrategy::Fixed => {
+                if let Some(padding) = tokenizer.get_padding_mut() {
+                    padding.strategy = tokenizers::PaddingStrategy::Fixed(max_length);
+                }
+            }
+            PaddingStrategy::None => {
+                tokenizer.with_padding(None);
+            }
+        }
+
+        // Longer texts would otherwise run past the model's positions, and for fixed
+        // padding break batching; a tighter limit from tokenizer.json is kept
+        let truncation = match tokenizer.get_truncation() {
+            Some(truncation) if truncation.max_length <= max_length => truncation.clone(),
+            Some(truncation) => TruncationParams { max_length, ..truncation.clone() },
+            None => TruncationParams { max_length, ..Default::default() },
+        };
+        tokenizer
+            .with_truncation(Some(truncation))
+            .map_err(|e| anyhow!("Failed to configure truncation: {}", e))?;
+        Ok(())
+    }
+
+    /// Locate `config.json`, `tokenizer.json` and the weights, either in a local
+    /// directory named by `model_id` or by downloading them from the Hub, reporting
+    /// download progress to `load_status` when given
+    pub(crate) async fn resolve_model_files(
+        config: &ModelConfig,
+        load_status: Option<&LoadStatusTracker>,
+    ) -> Result<(PathBuf, PathBuf, PathBuf)> {
+        let weights_name = if config.use_pth.unwrap_or(false) {
+            "pytorch_model.bin"
         } else {
-            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], DTYPE, &device)? }
+            "model.safetensors"
         };
 
-        if config.approximate_gelu.unwrap_or(false) {
-            bert_config.hidden_act = HiddenAct::GeluApproximate;
+        let local_dir = Path::new(&config.model_id);
+        if !config.model_id.is_empty() && local_dir.is_dir() {
+            tracing::info!("Loading model files from local directory {}", local_dir.display());
+            let local_file = |name: &str| {
+                let path = local_dir.join(name);
+                if path.is_file() {
+                    Ok(path)
+                } else {
+                    Err(InferenceError::ModelLoadFailed {
+                        message: format!("{} not found in {}", name, local_dir.display()),
+                    })
+                }
+            };
+            return Ok((local_file("config.json")?, local_file("tokenizer.json")?, local_file(weights_name)?));
         }
 
-        let model = BertModel::load(vb, &bert_config)?;
+        let (model_id, repo) = Self::hub_repo(config);
 
-        Ok(ModelComponents {
-            model,
-            tokenizer,
-            device,
-            config: config.clone(),
+        if config.offline {
+            let cache = Self::hub_cache(config);
+            let cache_repo = cache.repo(repo.clone());
+            let cached_file = |name: &str| {
+                cache_repo.get(name).ok_or_else(|| InferenceError::ModelLoadFailed {
+                    message: format!(
+                        "{} for {} (revision {}) is not in the cache at {} and offline mode is enabled",
+                        name,
+                        model_id,
+                        repo.revision(),
+                        cache.path().display()
+                    ),
+                })
+            };
+            return Ok((cached_file("config.json")?, cached_file("tokenizer.json")?, cached_file(weights_name)?));
+        }
+
+        let cache_repo = Self::hub_cache(config).repo(repo.clone());
+        let progress = load_status.map(|load_status| (&cache_repo, load_status));
+        let api = Self::hub_api(config)?.repo(repo);
+        Ok((
+            Self::download_with_retry(&api, &model_id, "config.json", &config.download_retry, progress).await?,
+            Self::download_with_retry(&api, &model_id, "tokenizer.json", &config.download_retry, progress).await?,
+            Self::download_with_retry(&api, &model_id, weights_name, &config.download_retry, progress).await?,
+        ))
+    }
+
+    /// Pick the pooling strategy from the repo's sentence-transformers pooling config,
+    /// falling back to mean pooling for repos that don't ship one
+    async fn resolve_pooling(config: &ModelConfig) -> Result<PoolingStrategy> {
+        let Some(path) = Self::find_pooling_config(config).await? else {
+            tracing::info!("No {} for {}, using mean pooling", POOLING_CONFIG_PATH, config.model_id);
+            return Ok(PoolingStrategy::Mean);
+        };
+
+        let content = std::fs::read_to_string(&path)?;
+        let pooling_config: SentenceTransformersPoolingConfig = serde_json::from_str(&content)?;
+        let strategy = pooling_config.strategy(&config.model_id);
+        tracing::info!("Using {} pooling for {}", strategy.as_str(), config.model_id);
+        Ok(strategy)
+    }
+
+    /// Locate the optional pooling config alongside the other model files
+    async fn find_pooling_config(config: &ModelConfig) -> Result<Option<PathBuf>> {
+        let local_dir = Path::new(&config.model_id);
+        if !config.model_id.is_empty() && local_dir.is_dir() {
+            let path = local_dir.join(POOLING_CONFIG_PATH);
+            return Ok(path.is_file().then_some(path));
+        }
+
+        let (model_id, repo) = Self::hub_repo(config);
+        if config.offline {
+            return Ok(Self::hub_cache(config).repo(repo).get(POOLING_CONFIG_PATH));
+        }
+
+        let api = Self::hub_api(config)?.repo(repo);
+        match api.get(POOLING_CONFIG_PATH).await {
+            Ok(path) => Ok(Some(path)),
+            Err(e) if Self::is_transient(&e) => {
+                Self::download_with_retry(&api, &model_id, POOLING_CONFIG_PATH, &config.download_retry, None)
+                    .await
+                    .map(Some)
+            }
+            // A missing file is a 404, which just means the repo isn't a sentence-transformers one
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Hub id and repo for `config`, substituting the built-in default for an empty `model_id`
+    fn hub_repo(config: &ModelConfig) -> (String, Repo) {
+        let (default_model, default_revision) = Self::get_default_model_config();
+        let (model_id, revision) = if config.model_id.is_empty() {
+            (default_model, default_revision)
+        } else {
+            (config.model_id.clone(), config.revision.clone().unwrap_or_else(|| "main".to_string()))
+        };
+
+        let repo = Repo::with_revision(model_id.clone(), RepoType::Model, revision);
+        (model_id, repo)
+    }
+
+    fn hub_cache(config: &ModelConfig) -> Cache {
+        match &config.cache_dir {
+            Some(cache_dir) => Cache::new(cache_dir.clone()),
+            None => Cache::from_env(),
+        }
+    }
+
+    fn hub_api(config: &ModelConfig) -> Result<Api> {
+        let mut builder = ApiBuilder::from_env();
+        if let Some(cache_dir) = &config.cache_dir {
+            builder = builder.with_cache_dir(cache_dir.clone());
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Fetch one file from the Hub, retrying with jittered exponential backoff so a
+    /// transient network error doesn't fail startup. With `progress`, files missing from
+    /// the cache report their download to the tracker.
+    async fn download_with_retry(
+        api: &ApiRepo,
+        model_id: &str,
+        filename: &str,
+        retry: &DownloadRetryConfig,
+        progress: Option<(&CacheRepo, &LoadStatusTracker)>,
+    ) -> Result<PathBuf> {
+        let download = move || async move {
+            match progress {
+                Some((cache_repo, load_status)) => match cache_repo.get(filename) {
+                    Some(path) => Ok(path),
+                    None => {
+                        let progress = DownloadProgress::new(load_status.clone(), format!("{}/{}", model_id, filename));
+                        api.download_with_progress(filename, progress).await
+                    }
+                },
+                None => api.get(filename).await,
+            }
+        };
+        let description = format!("{} for {}", filename, model_id);
+        Self::retry_transient(retry, &description, Self::is_transient, download)
+            .await
+            .map_err(|(e, attempts)| {
+                InferenceError::ModelLoadFailed {
+                    message: format!("Failed to download {} after {} attempt(s): {}", description, attempts, e),
+                }
+                .into()
+            })
+    }
+
+    /// Run `attempt` until it succeeds, fails with an error `is_transient` rejects, or
+    /// `retry.max_attempts` runs out, returning the last error and the attempts made
+    async fn retry_transient<T, E, F, Fut>(
+        retry: &DownloadRetryConfig,
+        description: &str,
+        is_transient: impl Fn(&E) -> bool,
+        mut attempt: F,
+    ) -> std::result::Result<T, (E, u32)>
+    where
+        E: std::fmt::Display,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = std::result::Result<T, E>>,
+    {
+        let max_attempts = retry.max_attempts.max(1);
+        let mut backoff = Duration::from_millis(retry.base_delay_ms);
+        let mut attempts = 1;
+        loop {
+            match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempts < max_attempts && is_transient(&e) => {
+                    let delay = backoff.mul_f64(rand::thread_rng().gen_range(0.8..=1.2));
+                    tracing::warn!(
+                        "Downloading {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        description, attempts, max_attempts, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    backoff *= 2;
+                    attempts += 1;
+                }
+                Err(e) => return Err((e, attempts)),
+            }
+        }
+    }
+
+    /// Timeouts, connection failures, rate limiting and 5xx responses may succeed on retry;
+    /// anything else, such as a 404 for a missing repo or file, won't
+    fn is_transient(error: &ApiError) -> bool {
+        match error {
+            ApiError::RequestError(e) => match e.status() {
+                Some(status) => status.is_server_error() || status.as_u16() == 429,
+                None => true,
+            },
+            ApiError::TooManyRetries(_) => true,
+            _ => false,
+        }
+    }
+
+    fn read_weights(weights_filename: &Path, use_pth: bool, device: &Device) -> Result<HashMap<String, Tensor>> {
+        Ok(if use_pth {
+            candle_core::pickle::read_all(weights_filename)?
+                .into_iter()
+                .map(|(name, tensor)| Ok((name, tensor.to_device(device)?)))
+                .collect::<candle_core::Result<_>>()?
+        } else {
+            candle_core::safetensors::load(weights_filename, device)?
         })
     }
 
-    fn get_device(&self, device_str: &str) -> Result<Device> {
+    /// RoBERTa numbers positions from `pad_token_id + 1` where the BERT encoder starts
+    /// at zero, so drop the leading rows of the position table to line the two up
+    fn offset_roberta_positions(tensors: &mut HashMap<String, Tensor>, offset: usize) -> Result<()> {
+        for (name, tensor) in tensors.iter_mut() {
+            if name.ends_with("embeddings.position_embeddings.weight") {
+                let rows = tensor.dim(0)?;
+                *tensor = tensor.narrow(0, offset, rows.saturating_sub(offset))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Files of the configured LoRA adapter: its weights and, when present, its config.
+    /// `lora_adapter_path` is a weights file, a directory saved by PEFT, or else the id
+    /// of a Hub repo holding one
+    async fn resolve_lora_adapter(config: &ModelConfig) -> Result<Option<(PathBuf, Option<PathBuf>)>> {
+        let Some(path) = &config.lora_adapter_path else {
+            return Ok(None);
+        };
+        let existing = |path: PathBuf| path.is_file().then_some(path);
+        if path.is_file() {
+            let adapter_config = path.parent().and_then(|dir| existing(dir.join(lora::ADAPTER_CONFIG_FILE)));
+            return Ok(Some((path.clone(), adapter_config)));
+        }
+        if path.is_dir() {
+            let weights = existing(path.join(lora::ADAPTER_WEIGHTS_FILE)).ok_or_else(|| InferenceError::ModelLoadFailed {
+                message: format!("{} not found in {}", lora::ADAPTER_WEIGHTS_FILE, path.display()),
+            })?;
+            return Ok(Some((weights, existing(path.join(lora::ADAPTER_CONFIG_FILE)))));
+        }
+
+        let adapter_id = path.to_string_lossy().into_owned();
+        let repo = Repo::new(adapter_id.clone(), RepoType::Model);
+        if config.offline {
+            let cache_repo = Self::hub_cache(config).repo(repo);
+            let weights = cache_repo.get(lora::ADAPTER_WEIGHTS_FILE).ok_or_else(|| InferenceError::ModelLoadFailed {
+                message: format!(
+                    "LoRA adapter {} is neither a local path nor in the cache, and offline mode is enabled",
+                    adapter_id
+                ),
+            })?;
+            return Ok(Some((weights, cache_repo.get(lora::ADAPTER_CONFIG_FILE))));
+        }
+        let api = Self::hub_api(config)?.repo(repo);
+        Ok(Some((
+            Self::download_with_retry(&api, &adapter_id, lora::ADAPTER_WEIGHTS_FILE, &config.download_retry, None).await?,
+            Some(Self::download_with_retry(&api, &adapter_id, lora::ADAPTER_CONFIG_FILE, &config.download_retry, None).await?),
+        )))
+    }
+
+    pub(crate) fn get_device(device_str: &str, device_index: Option<usize>, strict: bool) -> Result<Device> {
         match device_str.to_lowercase().as_str() {
+            "auto" => Self::detect_best_device(device_index),
             "cpu" => Ok(Device::Cpu),
-            "cuda" | "gpu" => {
-                #[cfg(feature = "cuda")]
-                {
-                    Ok(Device::new_cuda(0)?)
+            s if s.starts_with("cuda:") => {
+                let ordinal: usize = s["cuda:".len()..]
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid CUDA device '{}'", device_str))?;
+                if let Some(index) = device_index.filter(|&index| index != ordinal) {
+                    return Err(InferenceError::InvalidConfig {
+                        message: format!("Device '{}' conflicts with device_index = {}", device_str, index),
+                    }.into());
+                }
+                if !candle_core::utils::cuda_is_available() {
+                    return Self::fallback_to_cpu(device_str, "requires building with the `cuda` feature", strict);
                 }
-                #[cfg(not(feature = "cuda"))]
-                {
-                    tracing::warn!("CUDA requested but not available, falling back to CPU");
-                    Ok(Device::Cpu)
+                Self::new_cuda(device_str, ordinal)
+            }
+            "cuda" | "gpu" => {
+                if !candle_core::utils::cuda_is_available() {
+                    return Self::fallback_to_cpu(device_str, "requires building with the `cuda` feature", strict);
                 }
+                Self::new_cuda(device_str, device_index.unwrap_or(0))
             }
             "metal" => {
-                #[cfg(feature = "metal")]
-                {
-                    Ok(Device::new_metal(0)?)
+                if !candle_core::utils::metal_is_available() {
+                    return Self::fallback_to_cpu(device_str, "requires building with the `metal` feature", strict);
+                }
+                Self::new_metal(device_str, device_index.unwrap_or(0))
+            }
+            _ => Self::fallback_to_cpu(device_str, "is not a known device", strict),
+        }
+    }
+
+    fn new_cuda(device_str: &str, ordinal: usize) -> Result<Device> {
+        Self::check_ordinal(device_str, ordinal, Self::cuda_device_count())?;
+        Self::new_accelerator(device_str, || Device::new_cuda(ordinal))
+    }
+
+    fn new_metal(device_str: &str, ordinal: usize) -> Result<Device> {
+        Self::check_ordinal(device_str, ordinal, Self::metal_device_count())?;
+        Self::new_accelerator(device_str, || Device::new_metal(ordinal))
+    }
+
+    /// Reject an ordinal past the devices present; when the count is unknown the
+    /// backend reports a bad ordinal itself
+    fn check_ordinal(device_str: &str, ordinal: usize, count: Option<usize>) -> Result<()> {
+        match count {
+            Some(count) if ordinal >= count => Err(InferenceError::InvalidConfig {
+                message: format!(
+                    "Device index {} is out of range for '{}': {} device(s) available",
+                    ordinal, device_str, count
+                ),
+            }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "cuda")]
+    fn cuda_device_count() -> Option<usize> {
+        // cudarc panics instead of erroring when it can't load libcuda
+        std::panic::catch_unwind(candle_core::cuda_backend::cudarc::driver::CudaDevice::count)
+            .ok()?
+            .ok()
+            .and_then(|count| usize::try_from(count).ok())
+    }
+
+    #[cfg(not(feature = "cuda"))]
+    fn cuda_device_count() -> Option<usize> {
+        None
+    }
+
+    #[cfg(feature = "metal")]
+    fn metal_device_count() -> Option<usize> {
+        Some(metal::Device::all().len())
+    }
+
+    #[cfg(not(feature = "metal"))]
+    fn metal_device_count() -> Option<usize> {
+        None
+    }
+
+    /// Use the CPU in place of an unavailable device, unless `strict` asks to fail instead
+    fn fallback_to_cpu(device_str: &str, reason: &str, strict: bool) -> Result<Device> {
+        if strict {
+            return Err(InferenceError::InvalidConfig {
+                message: format!("Device '{}' {}", device_str, reason),
+            }.into());
+        }
+        tracing::warn!("Device '{}' {}, falling back to CPU", device_str, reason);
+        Ok(Device::Cpu)
+    }
+
+    /// Construct a GPU device, reporting a missing or outdated driver as `InvalidConfig`.
+    /// These failures only surface at runtime, and the backends panic rather than
+    /// return an error: cudarc when it can't load libcuda, Metal when there's no GPU.
+    fn new_accelerator(
+        device_str: &str,
+        new_device: impl FnOnce() -> candle_core::Result<Device> + std::panic::UnwindSafe,
+    ) -> Result<Device> {
+        let reason = match std::panic::catch_unwind(new_device) {
+            Ok(Ok(device)) => return Ok(device),
+            Ok(Err(e)) => e.to_string(),
+            Err(panic) => panic
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| panic.downcast_ref::<&str>().copied())
+                .unwrap_or("the driver could not be loaded")
+                .to_string(),
+        };
+        Err(InferenceError::InvalidConfig {
+            message: format!("Device '{}' is unavailable at runtime: {}", device_str, reason),
+        }.into())
+    }
+
+    /// Log the backends this build was compiled with, so a CPU fallback is easy to diagnose
+    pub fn log_compute_capabilities() {
+        use candle_core::utils;
+        tracing::info!(
+            "Compute capabilities: cuda={} metal={} mkl={} accelerate={} avx={} neon={} f16c={} threads={}",
+            utils::cuda_is_available(),
+            utils::metal_is_available(),
+            utils::has_mkl(),
+            utils::has_accelerate(),
+            utils::with_avx(),
+            utils::with_neon(),
+            utils::with_f16c(),
+            utils::get_num_threads(),
+        );
+    }
+
+    /// Probe CUDA, then Metal, falling back to CPU
+    /// Prefer CUDA, then Metal, then CPU. An out-of-range `device_index` is an error
+    /// rather than a reason to fall back
+    fn detect_best_device(device_index: Option<usize>) -> Result<Device> {
+        let ordinal = device_index.unwrap_or(0);
+        if candle_core::utils::cuda_is_available() {
+            Self::check_ordinal("cuda", ordinal, Self::cuda_device_count())?;
+            match Self::new_accelerator("cuda", || Device::new_cuda(ordinal)) {
+                Ok(device) => {
+                    tracing::info!("Auto-selected device: CUDA {}", ordinal);
+                    return Ok(device);
                 }
-                #[cfg(not(feature = "metal"))]
-                {
-                    tracing::warn!("Metal requested but not available, falling back to CPU");
-                    Ok(Device::Cpu)
+                Err(e) => tracing::debug!("CUDA not available: {}", e),
+            }
+        }
+
+        if candle_core::utils::metal_is_available() {
+            Self::check_ordinal("metal", ordinal, Self::metal_device_count())?;
+            match Self::new_accelerator("metal", || Device::new_metal(ordinal)) {
+                Ok(device) => {
+                    tracing::info!("Auto-selected device: Metal {}", ordinal);
+                    return Ok(device);
                 }
+                Err(e) => tracing::debug!("Metal not available: {}", e),
             }
-            _ => {
-                tracing::warn!("Unknown device '{}', falling back to CPU", device_str);
-                Ok(Device::Cpu)
+        }
+
+        tracing::info!("Auto-selected device: CPU");
+        Ok(Device::Cpu)
+    }
+
+    pub(crate) fn get_dtype(dtype: DtypeConfig, device: &Device) -> DType {
+        match dtype {
+            DtypeConfig::Float32 => DType::F32,
+            // Half precision matmuls are slow or unsupported on CPU
+            DtypeConfig::Float16 | DtypeConfig::BFloat16 if device.is_cpu() => {
+                tracing::warn!("{:?} is not supported on CPU, falling back to Float32", dtype);
+                DType::F32
             }
+            DtypeConfig::Float16 => DType::F16,
+            DtypeConfig::BFloat16 => DType::BF16,
         }
     }
 
-    fn get_default_model_config(&self) -> (String, String) {
+    /// What `device`, `dtype` and the other model options accept in this build. The
+    /// devices match what `get_device` can create rather than what hardware is present
+    pub fn capabilities() -> ModelCapabilities {
+        let cuda = candle_core::utils::cuda_is_available();
+        let metal = candle_core::utils::metal_is_available();
+        let mut devices = vec!["cpu".to_string(), "auto".to_string()];
+        if cuda {
+            devices.extend(["cuda", "cuda:N", "gpu"].map(str::to_string));
+        }
+        if metal {
+            devices.push("metal".to_string());
+        }
+        // `get_dtype` runs half precision as f32 on CPU, so it's only offered with a GPU backend
+        let dtypes = if cuda || metal {
+            vec![DtypeConfig::Float32, DtypeConfig::Float16, DtypeConfig::BFloat16]
+        } else {
+            vec![DtypeConfig::Float32]
+        };
+        let features = [
+            ("cuda", cfg!(feature = "cuda")),
+            ("metal", cfg!(feature = "metal")),
+            ("mkl", cfg!(feature = "mkl")),
+            ("accelerate", cfg!(feature = "accelerate")),
+            ("grpc", cfg!(feature = "grpc")),
+        ];
+
+        ModelCapabilities {
+            pooling_strategies: PoolingStrategy::ALL.to_vec(),
+            dtypes,
+            quantization: QuantizationType::ALL.to_vec(),
+            devices,
+            architectures: ModelArchitecture::ALL.to_vec(),
+            padding_strategies: PaddingStrategy::ALL.to_vec(),
+            features: features
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        }
+    }
+
+    fn get_default_model_config() -> (String, String) {
         ("sentence-transformers/all-MiniLM-L6-v2".to_string(), "refs/pr/21".to_string())
     }
 }
 
+/// Error for a lookup of `name` that isn't loaded; only other names are unknown models
+fn missing_model(name: &str) -> anyhow::Error {
+    if name == DEFAULT_MODEL_NAME {
+        InferenceError::ModelNotLoaded.into()
+    } else {
+        InferenceError::ModelNotFound { model_id: name.to_string() }.into()
+    }
+}
+
 #[async_trait::async_trait]
 impl ModelRepository for CandleModelLoader {
     async fn load_model(&self, config: &ModelConfig) -> Result<()> {
+        self.load_named_model(DEFAULT_MODEL_NAME, config).await
+    }
+
+    async fn load_named_model(&self, name: &str, config: &ModelConfig) -> Result<()> {
+        // Load fully before touching the registry so readers never wait on a download
         let components = self.download_and_load_model(config).await?;
-        let mut model_guard = self.current_model.write().await;
-        *model_guard = Some(components);
-        tracing::info!("Model loaded successfully: {}", config.model_id);
+        self.insert_components(name, Arc::new(components)).await;
+        tracing::info!("Model loaded successfully: {} as '{}'", config.model_id, name);
         Ok(())
     }
 
     async fn get_current_config(&self) -> Result<ModelConfig> {
-        let model_guard = self.current_model.read().await;
-        match model_guard.as_ref() {
+        self.get_model_config(DEFAULT_MODEL_NAME).await
+    }
+
+    async fn get_model_config(&self, name: &str) -> Result<ModelConfig> {
+        let models_guard = self.models.read().await;
+        match models_guard.get(name) {
             Some(components) => Ok(components.config.clone()),
-            None => Err(anyhow!("No model loaded")),
+            None => Err(missing_model(name)),
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let models_guard = self.models.read().await;
+        let mut names: Vec<String> = models_guard.keys().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    async fn unload_model(&self, name: &str) -> Result<()> {
+        let mut models_guard = self.models.write().await;
+        match models_guard.remove(name) {
+            Some(components) => {
+                tracing::info!("Model unloaded: {} ('{}')", components.config.model_id, name);
+                Ok(())
+            }
+            None => Err(InferenceError::ModelNotFound { model_id: name.to_string() }.into()),
         }
     }
-}
\ No newline at end of file
+}
+
+#[async_trait::async_trait]
+impl ModelProvider for CandleModelLoader {
+    async fn get_replicas(&self, name: &str) -> Result<Vec<Arc<ModelComponents>>> {
+        Ok(vec![self.get_components(name).await?])
+    }
+
+    async fn warmup(&self, config: &WarmupConfig) -> Result<()> {
+        let components = self.get_components(DEFAULT_MODEL_NAME).await?;
+        let WarmupConfig { iterations, batch_size, .. } = config.clone();
+        if iterations == 0 || batch_size == 0 {
+            return Ok(());
+        }
+
+        let elapsed = tokio::task::spawn_blocking(move || -> Result<std::time::Duration> {
+            let pad_id = components.tokenizer.get_padding().map(|p| p.pad_id).unwrap_or(0);
+            // Fixed padding runs every request at the full length, so warm up that shape
+            let sequence_length = match components.config.padding_strategy {
+                PaddingStrategy::Fixed => components.config.max_sequence_length,
+                PaddingStrategy::BatchLongest | PaddingStrategy::None => WARMUP_SEQUENCE_LENGTH,
+            };
+            let token_ids = Tensor::full(pad_id, (batch_size, sequence_length), &components.device)?;
+            let token_type_ids = components
+                .architecture
+                .uses_token_type_ids()
+                .then(|| token_ids.zeros_like())
+                .transpose()?;
+
+            let start = Instant::now();
+            for _ in 0..iterations {
+                components.model.forward(&token_ids, token_type_ids.as_ref(), None)?;
+            }
+            Ok(start.elapsed())
+        })
+        .await??;
+
+        tracing::info!(
+            "Warmup complete: {} iterations of batch size {}, average latency {:?}",
+            iterations,
+            batch_size,
+            elapsed / iterations as u32
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::{BatchEmbeddingRequest, EmbeddingRequest};
+    use crate::domain::traits::EmbeddingService;
+    use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+    use crate::test_support::{
+        tiny_model_config, tiny_model_dir, write_weights, TINY_HIDDEN_SIZE, TINY_MAX_SEQUENCE_LENGTH,
+    };
+
+    async fn encode_with(config: ModelConfig) -> (String, Vec<f32>) {
+        let loader = Arc::new(CandleModelLoader::new());
+        loader.load_model(&config).await.unwrap();
+        let service = SentenceTransformerService::new(loader);
+        let stats = service.get_model_stats(DEFAULT_MODEL_NAME).await.unwrap();
+        let response = service.encode(EmbeddingRequest::new("the cat sleeps".to_string())).await.unwrap();
+        (stats.dtype, response.embedding)
+    }
+
+    #[test]
+    fn half_precision_falls_back_to_f32_on_cpu() {
+        for dtype in [DtypeConfig::Float16, DtypeConfig::BFloat16] {
+            assert_eq!(CandleModelLoader::get_dtype(dtype, &Device::Cpu), DType::F32);
+        }
+        assert_eq!(CandleModelLoader::get_dtype(DtypeConfig::Float32, &Device::Cpu), DType::F32);
+    }
+
+    #[tokio::test]
+    async fn half_precision_config_loads_and_encodes_on_cpu() {
+        let (_, full) = encode_with(tiny_model_config()).await;
+        let (dtype, half) = encode_with(ModelConfig {
+            dtype: DtypeConfig::Float16,
+            ..tiny_model_config()
+        })
+        .await;
+
+        assert_eq!(dtype, "f32");
+        assert_eq!(half, full);
+    }
+
+    /// Devices this build can't create: an unknown name, and GPU backends not compiled in
+    fn unavailable_devices() -> Vec<&'static str> {
+        let mut devices = vec!["tpu"];
+        if !cfg!(feature = "cuda") {
+            devices.extend(["cuda", "cuda:0", "gpu"]);
+        }
+        if !cfg!(feature = "metal") {
+            devices.push("metal");
+        }
+        devices
+    }
+
+    #[test]
+    fn unavailable_devices_fall_back_to_cpu_unless_strict() {
+        for device in unavailable_devices() {
+            assert!(CandleModelLoader::get_device(device, None, false).unwrap().is_cpu(), "{}", device);
+
+            let error = CandleModelLoader::get_device(device, None, true).unwrap_err();
+            assert!(
+                matches!(
+                    error.downcast_ref::<InferenceError>(),
+                    Some(InferenceError::InvalidConfig { message }) if message.contains(device)
+                ),
+                "{}: {}",
+                device,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_device_indices_error_rather_than_panic() {
+        let invalid_config = |result: Result<Device>| match result {
+            Err(error) => matches!(error.downcast_ref::<InferenceError>(), Some(InferenceError::InvalidConfig { .. })),
+            Ok(_) => false,
+        };
+
+        assert!(invalid_config(CandleModelLoader::get_device("cuda:1", Some(0), false)));
+        assert!(CandleModelLoader::get_device("cuda:x", None, false).is_err());
+        let error = CandleModelLoader::check_ordinal("cuda", 2, Some(2)).unwrap_err();
+        assert!(error.to_string().contains("2 device(s) available"), "{}", error);
+        assert!(CandleModelLoader::check_ordinal("cuda", 1, Some(2)).is_ok());
+        // Unknown counts defer to the backend
+        assert!(CandleModelLoader::check_ordinal("metal", 7, None).is_ok());
+        // The CPU has no ordinals to pick from
+        assert!(CandleModelLoader::get_device("cpu", Some(3), true).unwrap().is_cpu());
+    }
+
+    #[tokio::test]
+    async fn strict_device_fails_the_model_load() {
+        let loader = CandleModelLoader::new();
+        let config = |strict_device| ModelConfig {
+            device: "tpu".to_string(),
+            strict_device,
+            ..tiny_model_config()
+        };
+
+        assert!(loader.load_model(&config(true)).await.is_err());
+        loader.load_model(&config(false)).await.unwrap();
+        assert!(loader.get_components(DEFAULT_MODEL_NAME).await.unwrap().device.is_cpu());
+    }
+
+    #[tokio::test]
+    async fn models_load_from_a_local_directory() {
+        let config = tiny_model_config();
+
+        let (config_file, tokenizer_file, weights_file) =
+            CandleModelLoader::resolve_model_files(&config, None).await.unwrap();
+        let (_, embedding) = encode_with(config).await;
+
+        assert_eq!(config_file, tiny_model_dir().join("config.json"));
+        assert_eq!(tokenizer_file, tiny_model_dir().join("tokenizer.json"));
+        assert_eq!(weights_file, tiny_model_dir().join("model.safetensors"));
+        assert_eq!(embedding.len(), TINY_HIDDEN_SIZE);
+    }
+
+    #[tokio::test]
+    async fn local_directories_missing_a_file_fail_to_load() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy(tiny_model_dir().join("config.json"), dir.path().join("config.json")).unwrap();
+        let config = ModelConfig {
+            model_id: dir.path().to_string_lossy().into_owned(),
+            ..tiny_model_config()
+        };
+
+        let error = CandleModelLoader::new().load_model(&config).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::ModelLoadFailed { message }) if message.starts_with("tokenizer.json not found")
+        ));
+    }
+
+    #[tokio::test]
+    async fn padding_strategy_sets_the_sequence_dimension() {
+        let texts = vec!["hello".to_string(), "the cat sleeps on the mat".to_string()];
+        // [CLS] hello [SEP] and [CLS] the cat sleeps on the mat [SEP]
+        let cases = [
+            (PaddingStrategy::BatchLongest, vec![8, 8]),
+            (PaddingStrategy::Fixed, vec![TINY_MAX_SEQUENCE_LENGTH, TINY_MAX_SEQUENCE_LENGTH]),
+            (PaddingStrategy::None, vec![3, 8]),
+        ];
+
+        let mut embeddings = Vec::new();
+        for (padding_strategy, lengths) in cases {
+            let loader = Arc::new(CandleModelLoader::new());
+            loader
+                .load_model(&ModelConfig { padding_strategy, ..tiny_model_config() })
+                .await
+                .unwrap();
+            let components = loader.get_components(DEFAULT_MODEL_NAME).await.unwrap();
+            let encodings = components.tokenizer.encode_batch(texts.clone(), true).unwrap();
+            let actual: Vec<usize> = encodings.iter().map(|encoding| encoding.get_ids().len()).collect();
+            assert_eq!(actual, lengths, "{:?}", padding_strategy);
+
+            let service = SentenceTransformerService::new(loader);
+            embeddings.push(service.encode_batch(BatchEmbeddingRequest::new(texts.clone())).await.unwrap().embeddings);
+        }
+        // Padding is masked out, so every strategy gives the same embeddings
+        for other in &embeddings[1..] {
+            for (a, b) in other.iter().flatten().zip(embeddings[0].iter().flatten()) {
+                assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+            }
+        }
+    }
+
+    /// Directory holding the tiny tokenizer and `config.json` with `overrides` applied
+    fn model_dir_with_config(overrides: serde_json::Value) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy(tiny_model_dir().join("tokenizer.json"), dir.path().join("tokenizer.json")).unwrap();
+        let mut config: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(tiny_model_dir().join("config.json")).unwrap()).unwrap();
+        for (key, value) in overrides.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        std::fs::write(dir.path().join("config.json"), config.to_string()).unwrap();
+        dir
+    }
+
+    async fn encode_batch_from(dir: &tempfile::TempDir, texts: &[&str]) -> (ModelArchitecture, Vec<Vec<f32>>) {
+        let loader = Arc::new(CandleModelLoader::new());
+        let config = ModelConfig {
+            model_id: dir.path().to_string_lossy().into_owned(),
+            ..tiny_model_config()
+        };
+        loader.load_model(&config).await.unwrap();
+        let architecture = loader.get_components(DEFAULT_MODEL_NAME).await.unwrap().architecture;
+        let texts = texts.iter().map(|text| text.to_string()).collect();
+        let response = SentenceTransformerService::new(loader)
+            .encode_batch(BatchEmbeddingRequest::new(texts))
+            .await
+            .unwrap();
+        (architecture, response.embeddings)
+    }
+
+    #[tokio::test]
+    async fn roberta_models_skip_token_types_and_offset_positions() {
+        // One token type, and positions that start after the padding id
+        let roberta = model_dir_with_config(serde_json::json!({
+            "model_type": "roberta", "type_vocab_size": 1, "pad_token_id": 1, "max_position_embeddings": 66,
+        }));
+        let roberta_config = std::fs::read_to_string(roberta.path().join("config.json")).unwrap();
+        let bert_config = parse_bert_config(&roberta_config, "roberta").unwrap();
+        let roberta_weights = roberta.path().join("model.safetensors");
+        write_weights(&roberta_weights, |vb| BertModel::load(vb, &bert_config).map(|_| ())).unwrap();
+
+        // The same weights as a BERT model whose position table already starts at the offset
+        let bert = model_dir_with_config(serde_json::json!({"type_vocab_size": 1, "max_position_embeddings": 64}));
+        let tensors: HashMap<String, Tensor> = candle_core::safetensors::load(&roberta_weights, &Device::Cpu)
+            .unwrap()
+            .into_iter()
+            .map(|(name, tensor)| {
+                let name = name.trim_start_matches("roberta.").to_string();
+                if name.ends_with("position_embeddings.weight") {
+                    (name, tensor.narrow(0, 2, 64).unwrap())
+                } else {
+                    (name, tensor)
+                }
+            })
+            .collect();
+        candle_core::safetensors::save(&tensors, bert.path().join("model.safetensors")).unwrap();
+
+        // Texts of different lengths, so the batch is padded
+        let texts = ["the cat sleeps on the mat", "hello"];
+        let (architecture, from_roberta) = encode_batch_from(&roberta, &texts).await;
+        let (_, from_bert) = encode_batch_from(&bert, &texts).await;
+
+        assert_eq!(architecture, ModelArchitecture::Roberta);
+        for (roberta, bert) in from_roberta.iter().zip(&from_bert) {
+            assert_eq!(roberta.len(), TINY_HIDDEN_SIZE);
+            for (a, b) in roberta.iter().zip(bert) {
+                assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+            }
+        }
+    }
+
+    /// Copy of the tiny model in a temporary directory, with `pooling_config` as its
+    /// `1_Pooling/config.json` when given
+    fn model_dir_with_pooling(pooling_config: Option<serde_json::Value>) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["config.json", "tokenizer.json", "model.safetensors"] {
+            std::fs::copy(tiny_model_dir().join(name), dir.path().join(name)).unwrap();
+        }
+        if let Some(pooling_config) = pooling_config {
+            let path = dir.path().join(POOLING_CONFIG_PATH);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, pooling_config.to_string()).unwrap();
+        }
+        dir
+    }
+
+    async fn loaded_pooling(dir: &tempfile::TempDir) -> PoolingStrategy {
+        let loader = CandleModelLoader::new();
+        let config = ModelConfig {
+            model_id: dir.path().to_string_lossy().into_owned(),
+            ..tiny_model_config()
+        };
+        loader.load_model(&config).await.unwrap();
+        loader.get_components(DEFAULT_MODEL_NAME).await.unwrap().pooling
+    }
+
+    #[tokio::test]
+    async fn pooling_follows_the_sentence_transformers_config() {
+        let cls = model_dir_with_pooling(Some(serde_json::json!({
+            "word_embedding_dimension": TINY_HIDDEN_SIZE,
+            "pooling_mode_cls_token": true,
+            "pooling_mode_mean_tokens": false,
+        })));
+        let max = model_dir_with_pooling(Some(serde_json::json!({"pooling_mode_max_tokens": true})));
+        let absent = model_dir_with_pooling(None);
+
+        assert_eq!(loaded_pooling(&cls).await, PoolingStrategy::Cls);
+        assert_eq!(loaded_pooling(&max).await, PoolingStrategy::Max);
+        assert_eq!(loaded_pooling(&absent).await, PoolingStrategy::Mean);
+    }
+
+    #[tokio::test]
+    async fn max_pooled_batches_match_texts_encoded_alone() {
+        let dir = model_dir_with_pooling(Some(serde_json::json!({"pooling_mode_max_tokens": true})));
+        let loader = Arc::new(CandleModelLoader::new());
+        loader
+            .load_model(&ModelConfig {
+                model_id: dir.path().to_string_lossy().into_owned(),
+                ..tiny_model_config()
+            })
+            .await
+            .unwrap();
+        let service = SentenceTransformerService::new(loader);
+        // Different lengths, so the shorter texts are padded in the batch
+        let texts = vec!["hello".to_string(), "the cat and the dog sleep on the mat".to_string(), "a dog".to_string()];
+
+        let batch = service.encode_batch(BatchEmbeddingRequest::new(texts.clone())).await.unwrap();
+
+        for (text, batched) in texts.into_iter().zip(&batch.embeddings) {
+            let alone = service.encode(EmbeddingRequest::new(text)).await.unwrap();
+            for (a, b) in batched.iter().zip(&alone.embedding) {
+                assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn the_first_of_several_enabled_pooling_modes_wins() {
+        let config = SentenceTransformersPoolingConfig {
+            pooling_mode_mean_tokens: true,
+            pooling_mode_max_tokens: true,
+            ..SentenceTransformersPoolingConfig::default()
+        };
+
+        assert_eq!(config.strategy("test"), PoolingStrategy::Mean);
+        assert_eq!(SentenceTransformersPoolingConfig::default().strategy("test"), PoolingStrategy::Mean);
+    }
+
+    /// Hub repo id the offline tests read from the cache
+    const CACHED_REPO: &str = "test-org/tiny-bert";
+
+    /// Lay the tiny model out in `cache_dir` the way the Hub client caches a download
+    fn populate_hub_cache(cache_dir: &Path) {
+        let cache_repo = Cache::new(cache_dir.to_path_buf()).repo(Repo::model(CACHED_REPO.to_string()));
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        cache_repo.create_ref(commit).unwrap();
+        let snapshot = cache_dir.join("models--test-org--tiny-bert").join("snapshots").join(commit);
+        std::fs::create_dir_all(&snapshot).unwrap();
+        for name in ["config.json", "tokenizer.json", "model.safetensors"] {
+            std::fs::copy(tiny_model_dir().join(name), snapshot.join(name)).unwrap();
+        }
+        assert!(cache_repo.get("config.json").is_some());
+    }
+
+    fn offline_config(cache_dir: &Path) -> ModelConfig {
+        ModelConfig {
+            model_id: CACHED_REPO.to_string(),
+            tokenizer_repo: CACHED_REPO.to_string(),
+            cache_dir: Some(cache_dir.to_path_buf()),
+            offline: true,
+            ..tiny_model_config()
+        }
+    }
+
+    #[tokio::test]
+    async fn offline_mode_loads_from_the_configured_cache_dir() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        populate_hub_cache(cache_dir.path());
+
+        let (config_file, _, _) =
+            CandleModelLoader::resolve_model_files(&offline_config(cache_dir.path()), None).await.unwrap();
+        let (_, embedding) = encode_with(offline_config(cache_dir.path())).await;
+
+        assert!(config_file.starts_with(cache_dir.path()));
+        assert_eq!(embedding.len(), TINY_HIDDEN_SIZE);
+    }
+
+    #[tokio::test]
+    async fn offline_mode_with_an_empty_cache_fails_to_load() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let error = CandleModelLoader::new()
+            .load_model(&offline_config(cache_dir.path()))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<InferenceError>(),
+            Some(InferenceError::ModelLoadFailed { message })
+                if message.contains("offline mode") && message.contains(&*cache_dir.path().to_string_lossy())
+        ));
+    }
+
+    fn fast_retry(max_attempts: u32) -> DownloadRetryConfig {
+        DownloadRetryConfig {
+            max_attempts,
+            base_delay_ms: 1,
+        }
+    }
+
+    /// Download that fails with each of `failures` in turn, then returns the file
+    fn flaky_download(
+        failures: Vec<ApiError>,
+    ) -> (
+        impl FnMut() -> std::future::Ready<std::result::Result<PathBuf, ApiError>>,
+        Arc<std::sync::atomic::AtomicU32>,
+    ) {
+        let calls = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let mut failures = failures.into_iter();
+        let counter = calls.clone();
+        let download = move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            std::future::ready(match failures.next() {
+                Some(e) => Err(e),
+                None => Ok(PathBuf::from("model.safetensors")),
+            })
+        };
+        (download, calls)
+    }
+
+    fn transient() -> ApiError {
+        ApiError::TooManyRetries(Box::new(ApiError::IoError(std::io::Error::other("connection reset"))))
+    }
+
+    #[tokio::test]
+    async fn transient_download_failures_are_retried() {
+        let (download, calls) = flaky_download(vec![transient(), transient()]);
+
+        let path =
+            CandleModelLoader::retry_transient(&fast_retry(3), "model.safetensors", CandleModelLoader::is_transient, download)
+                .await
+                .unwrap();
+
+        assert_eq!(path, PathBuf::from("model.safetensors"));
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_max_attempts() {
+        let (download, calls) = flaky_download(vec![transient(), transient(), transient()]);
+
+        let (_, attempts) =
+            CandleModelLoader::retry_transient(&fast_retry(2), "model.safetensors", CandleModelLoader::is_transient, download)
+                .await
+                .unwrap_err();
+
+        assert_eq!(attempts, 2);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn permanent_download_failures_are_not_retried() {
+        let permanent = ApiError::IoError(std::io::Error::other("disk full"));
+        let (download, calls) = flaky_download(vec![permanent]);
+
+        let (error, attempts) =
+            CandleModelLoader::retry_transient(&fast_retry(3), "model.safetensors", CandleModelLoader::is_transient, download)
+                .await
+                .unwrap_err();
+
+        assert!(matches!(error, ApiError::IoError(_)));
+        assert_eq!(attempts, 1);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "cuda")]
+    #[tokio::test]
+    async fn half_precision_runs_on_cuda_and_returns_f32_embeddings() {
+        let cuda = |dtype| ModelConfig {
+            device: "cuda".to_string(),
+            dtype,
+            ..tiny_model_config()
+        };
+        let (_, full) = encode_with(cuda(DtypeConfig::Float32)).await;
+
+        for (dtype, name) in [(DtypeConfig::Float16, "f16"), (DtypeConfig::BFloat16, "bf16")] {
+            let (loaded, half) = encode_with(cuda(dtype)).await;
+
+            assert_eq!(loaded, name);
+            for (a, b) in half.iter().zip(&full) {
+                assert!((a - b).abs() < 0.05, "{}: {} != {}", name, a, b);
+            }
+        }
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn the_selected_cuda_index_is_honored() {
+        let count = CandleModelLoader::cuda_device_count().expect("CUDA devices can be counted");
+
+        for index in 0..count {
+            let device = CandleModelLoader::get_device("cuda", Some(index), true).unwrap();
+            assert_eq!(device.location(), candle_core::DeviceLocation::Cuda { gpu_id: index });
+        }
+        let error = CandleModelLoader::get_device("cuda", Some(count), true).unwrap_err();
+        assert!(matches!(error.downcast_ref::<InferenceError>(), Some(InferenceError::InvalidConfig { .. })));
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::domain::entities::{ModelConfig, ModelInfo};
+use crate::domain::traits::ModelRegistry;
+
+const MODEL_DIR_PREFIX: &str = "models--";
+
+/// Lists models found in the Hugging Face hub cache, plus configured models that
+/// haven't been downloaded yet
+#[derive(Debug, Clone)]
+pub struct FileSystemModelRegistry {
+    cache_dir: PathBuf,
+    configured_models: Vec<String>,
+}
+
+impl FileSystemModelRegistry {
+    pub fn new(cache_dir: PathBuf, configured_models: Vec<String>) -> Self {
+        Self {
+            cache_dir,
+            configured_models,
+        }
+    }
+
+    /// Registry over `cache_dir` from the model config, falling back to `HF_HOME`
+    pub fn from_configs<'a>(configs: impl IntoIterator<Item = &'a ModelConfig>) -> Self {
+        let mut cache_dir = None;
+        let mut configured_models = Vec::new();
+        for config in configs {
+            if cache_dir.is_none() {
+                cache_dir = config.cache_dir.clone();
+            }
+            configured_models.push(config.model_id.clone());
+        }
+        let cache_dir = cache_dir.unwrap_or_else(|| hf_hub::Cache::from_env().path().clone());
+        Self::new(cache_dir, configured_models)
+    }
+
+    /// `models--org--name` is how the hub cache stores `org/name`
+    fn repo_dir(&self, model_id: &str) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}{}", MODEL_DIR_PREFIX, model_id.replace('/', "--")))
+    }
+
+    fn scan(&self) -> Result<Vec<ModelInfo>> {
+        let mut models = BTreeMap::new();
+
+        if self.cache_dir.is_dir() {
+            for entry in std::fs::read_dir(&self.cache_dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let Some(repo_name) = name.strip_prefix(MODEL_DIR_PREFIX) else {
+                    continue;
+                };
+                if !entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let model_id = repo_name.replace("--", "/");
+                models.insert(model_id.clone(), cached_model_info(model_id, &entry.path()));
+            }
+        }
+
+        for model_id in &self.configured_models {
+            if models.contains_key(model_id) {
+                continue;
+            }
+            // Local model directories are "cached" in the sense that nothing needs downloading
+            let local_dir = Path::new(model_id);
+            let info = if local_dir.is_dir() {
+                cached_model_info(model_id.clone(), local_dir)
+            } else {
+                ModelInfo {
+                    model_id: model_id.clone(),
+                    cached: false,
+                    size_bytes: None,
+                    last_used: None,
+                }
+            };
+            models.insert(model_id.clone(), info);
+        }
+
+        Ok(models.into_values().collect())
+    }
+}
+
+fn cached_model_info(model_id: String, dir: &Path) -> ModelInfo {
+    let mut size_bytes = 0;
+    let mut last_modified: Option<SystemTime> = None;
+    visit_files(dir, &mut |metadata| {
+        size_bytes += metadata.len();
+        if let Ok(modified) = metadata.modified() {
+            last_modified = Some(last_modified.map_or(modified, |last| last.max(modified)));
+        }
+    });
+
+    ModelInfo {
+        model_id,
+        cached: true,
+        size_bytes: Some(size_bytes),
+        last_used: last_modified.map(DateTime::<Utc>::from),
+    }
+}
+
+/// Walk regular files under `dir` without following symlinks, so hub snapshots
+/// (symlinks into `blobs/`) aren't counted twice
+fn visit_files(dir: &Path, visit: &mut impl FnMut(&std::fs::Metadata)) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            visit_files(&entry.path(), visit);
+        } else if metadata.is_file() {
+            visit(&metadata);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelRegistry for FileSystemModelRegistry {
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let registry = self.clone();
+        tokio::task::spawn_blocking(move || registry.scan()).await?
+    }
+
+    async fn model_exists(&self, model_id: &str) -> Result<bool> {
+        Ok(self.repo_dir(model_id).is_dir() || Path::new(model_id).is_dir())
+    }
+}
@@ -0,0 +1,355 @@
+use candle_core::{DType, Module, Result, Tensor, D};
+use candle_nn::{embedding, layer_norm, linear, Embedding, LayerNorm, Linear, VarBuilder};
+use candle_transformers::models::bert::{Config as BertConfig, HiddenAct};
+use serde::Deserialize;
+
+/// Longest relative distance with its own bucket range; farther positions share the last bucket
+const RELATIVE_ATTENTION_MAX_DISTANCE: i64 = 128;
+
+fn default_layer_norm_eps() -> f64 {
+    1e-5
+}
+
+fn default_relative_attention_num_buckets() -> usize {
+    32
+}
+
+fn default_pad_token_id() -> usize {
+    1
+}
+
+/// Fields of an MPNet `config.json`
+#[derive(Debug, Clone, Deserialize)]
+pub struct MpNetConfig {
+    pub vocab_size: usize,
+    pub hidden_size: usize,
+    pub num_hidden_layers: usize,
+    pub num_attention_heads: usize,
+    pub intermediate_size: usize,
+    pub hidden_act: HiddenAct,
+    pub max_position_embeddings: usize,
+    #[serde(default = "default_layer_norm_eps")]
+    pub layer_norm_eps: f64,
+    #[serde(default = "default_relative_attention_num_buckets")]
+    pub relative_attention_num_buckets: usize,
+    #[serde(default = "default_pad_token_id")]
+    pub pad_token_id: usize,
+}
+
+impl MpNetConfig {
+    /// The same dimensions as a BERT config, for parameter counts and model stats;
+    /// MPNet has no token type embeddings, hence a type vocabulary of zero
+    pub fn as_bert_config(&self) -> BertConfig {
+        BertConfig {
+            vocab_size: self.vocab_size,
+            hidden_size: self.hidden_size,
+            num_hidden_layers: self.num_hidden_layers,
+            num_attention_heads: self.num_attention_heads,
+            intermediate_size: self.intermediate_size,
+            hidden_act: self.hidden_act,
+            max_position_embeddings: self.max_position_embeddings,
+            type_vocab_size: 0,
+            layer_norm_eps: self.layer_norm_eps,
+            pad_token_id: self.pad_token_id,
+            model_type: Some("mpnet".to_string()),
+            ..BertConfig::default()
+        }
+    }
+}
+
+struct MpNetEmbeddings {
+    word_embeddings: Embedding,
+    position_embeddings: Embedding,
+    layer_norm: LayerNorm,
+    pad_token_id: u32,
+}
+
+impl MpNetEmbeddings {
+    fn load(vb: VarBuilder, config: &MpNetConfig) -> Result<Self> {
+        Ok(Self {
+            word_embeddings: embedding(config.vocab_size, config.hidden_size, vb.pp("word_embeddings"))?,
+            position_embeddings: embedding(
+                config.max_position_embeddings,
+                config.hidden_size,
+                vb.pp("position_embeddings"),
+            )?,
+            layer_norm: layer_norm(config.hidden_size, config.layer_norm_eps, vb.pp("LayerNorm"))?,
+            pad_token_id: config.pad_token_id as u32,
+        })
+    }
+
+    /// Like RoBERTa, positions count up from `pad_token_id + 1` over the real tokens,
+    /// and padding tokens sit at `pad_token_id`
+    fn position_ids(&self, input_ids: &Tensor) -> Result<Tensor> {
+        let (batch_size, seq_len) = input_ids.dims2()?;
+        let positions: Vec<u32> = input_ids
+            .to_dtype(DType::U32)?
+            .to_vec2::<u32>()?
+            .into_iter()
+            .flat_map(|row| {
+                row.into_iter().scan(self.pad_token_id, |position, id| {
+                    if id == self.pad_token_id {
+                        return Some(self.pad_token_id);
+                    }
+                    *position += 1;
+                    Some(*position)
+                })
+            })
+            .collect();
+        Tensor::from_vec(positions, (batch_size, seq_len), input_ids.device())
+    }
+
+    fn forward(&self, input_ids: &Tensor) -> Result<Tensor> {
+        let position_ids = self.position_ids(input_ids)?;
+        let embeddings =
+            (self.word_embeddings.forward(input_ids)? + self.position_embeddings.forward(&position_ids)?)?;
+        self.layer_norm.forward(&embeddings)
+    }
+}
+
+struct MpNetLayer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_output: Linear,
+    attention_layer_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_layer_norm: LayerNorm,
+    num_attention_heads: usize,
+    attention_head_size: usize,
+    hidden_act: HiddenAct,
+}
+
+impl MpNetLayer {
+    fn load(vb: VarBuilder, config: &MpNetConfig) -> Result<Self> {
+        let hidden_size = config.hidden_size;
+        let attention = vb.pp("attention");
+        Ok(Self {
+            query: linear(hidden_size, hidden_size, attention.pp("attn.q"))?,
+            key: linear(hidden_size, hidden_size, attention.pp("attn.k"))?,
+            value: linear(hidden_size, hidden_size, attention.pp("attn.v"))?,
+            attention_output: linear(hidden_size, hidden_size, attention.pp("attn.o"))?,
+            attention_layer_norm: layer_norm(hidden_size, config.layer_norm_eps, attention.pp("LayerNorm"))?,
+            intermediate: linear(hidden_size, config.intermediate_size, vb.pp("intermediate.dense"))?,
+            output: linear(config.intermediate_size, hidden_size, vb.pp("output.dense"))?,
+            output_layer_norm: layer_norm(hidden_size, config.layer_norm_eps, vb.pp("output.LayerNorm"))?,
+            num_attention_heads: config.num_attention_heads,
+            attention_head_size: hidden_size / config.num_attention_heads,
+            hidden_act: config.hidden_act,
+        })
+    }
+
+    fn split_heads(&self, xs: &Tensor) -> Result<Tensor> {
+        let (batch_size, seq_len, _hidden_size) = xs.dims3()?;
+        xs.reshape((batch_size, seq_len, self.num_attention_heads, self.attention_head_size))?
+            .transpose(1, 2)?
+            .contiguous()
+    }
+
+    /// `attention_bias` holds the relative position bias plus the padding mask,
+    /// broadcastable to `(batch, heads, seq_len, seq_len)`
+    fn forward(&self, hidden_states: &Tensor, attention_bias: &Tensor) -> Result<Tensor> {
+        let query = self.split_heads(&self.query.forward(hidden_states)?)?;
+        let key = self.split_heads(&self.key.forward(hidden_states)?)?;
+        let value = self.split_heads(&self.value.forward(hidden_states)?)?;
+
+        let scores = (query.matmul(&key.t()?)? / (self.attention_head_size as f64).sqrt())?
+            .broadcast_add(attention_bias)?;
+        let probs = candle_nn::ops::softmax(&scores, D::Minus1)?;
+        let context = probs
+            .matmul(&value)?
+            .transpose(1, 2)?
+            .contiguous()?
+            .flatten_from(D::Minus2)?;
+
+        let attention_output = self.attention_output.forward(&context)?;
+        let attention_output = self.attention_layer_norm.forward(&(attention_output + hidden_states)?)?;
+
+        let intermediate = self.intermediate.forward(&attention_output)?;
+        let intermediate = match self.hidden_act {
+            HiddenAct::Gelu => intermediate.gelu_erf()?,
+            HiddenAct::GeluApproximate => intermediate.gelu()?,
+            HiddenAct::Relu => intermediate.relu()?,
+        };
+        let output = self.output.forward(&intermediate)?;
+        self.output_layer_norm.forward(&(output + attention_output)?)
+    }
+}
+
+/// MPNet encoder, as used by `sentence-transformers/all-mpnet-base-v2`. It differs from
+/// BERT in having no token types, RoBERTa-style positions and a learned relative
+/// position bias shared by every layer's attention.
+pub struct MpNetModel {
+    embeddings: MpNetEmbeddings,
+    layers: Vec<MpNetLayer>,
+    relative_attention_bias: Embedding,
+    num_buckets: usize,
+}
+
+impl MpNetModel {
+    pub fn load(vb: VarBuilder, config: &MpNetConfig) -> Result<Self> {
+        // Checkpoints exported from `MPNetForMaskedLM` nest the encoder under `mpnet`
+        let vb = if vb.contains_tensor("embeddings.word_embeddings.weight") { vb } else { vb.pp("mpnet") };
+        let embeddings = MpNetEmbeddings::load(vb.pp("embeddings"), config)?;
+        let layers = (0..config.num_hidden_layers)
+            .map(|index| MpNetLayer::load(vb.pp(format!("encoder.layer.{index}")), config))
+            .collect::<Result<Vec<_>>>()?;
+        let relative_attention_bias = embedding(
+            config.relative_attention_num_buckets,
+            config.num_attention_heads,
+            vb.pp("encoder.relative_attention_bias"),
+        )?;
+        Ok(Self {
+            embeddings,
+            layers,
+            relative_attention_bias,
+            num_buckets: config.relative_attention_num_buckets,
+        })
+    }
+
+    /// Bucket of the distance from query position `i` to key position `j`: half the buckets
+    /// for keys before the query and half after, each exact up to a quarter of the buckets
+    /// and logarithmic beyond, capped at `RELATIVE_ATTENTION_MAX_DISTANCE`
+    fn relative_position_bucket(&self, relative_position: i64) -> u32 {
+        let half = (self.num_buckets / 2) as i64;
+        let max_exact = half / 2;
+        let n = -relative_position;
+        let direction = if n < 0 { half } else { 0 };
+        let n = n.abs();
+        let bucket = if n < max_exact {
+            n
+        } else {
+            let scale = (n as f64 / max_exact as f64).ln()
+                / (RELATIVE_ATTENTION_MAX_DISTANCE as f64 / max_exact as f64).ln();
+            (max_exact + (scale * (half - max_exact) as f64) as i64).min(half - 1)
+        };
+        (direction + bucket) as u32
+    }
+
+    /// `(1, heads, seq_len, seq_len)` bias added to every layer's attention scores
+    fn position_bias(&self, seq_len: usize, device: &candle_core::Device) -> Result<Tensor> {
+        let buckets: Vec<u32> = (0..seq_len as i64)
+            .flat_map(|query| (0..seq_len as i64).map(move |key| (query, key)))
+            .map(|(query, key)| self.relative_position_bucket(key - query))
+            .collect();
+        let buckets = Tensor::from_vec(buckets, (seq_len, seq_len), device)?;
+        self.relative_attention_bias.forward(&buckets)?.permute((2, 0, 1))?.unsqueeze(0)
+    }
+
+    pub fn forward(&self, input_ids: &Tensor, attention_mask: Option<&Tensor>) -> Result<Tensor> {
+        let (_batch_size, seq_len) = input_ids.dims2()?;
+        let mut hidden_states = self.embeddings.forward(input_ids)?;
+        let dtype = hidden_states.dtype();
+
+        let mut attention_bias = self.position_bias(seq_len, input_ids.device())?;
+        if let Some(attention_mask) = attention_mask {
+            // Padding positions get a large negative bias so softmax ignores them
+            let mask = attention_mask.unsqueeze(1)?.unsqueeze(1)?.to_dtype(DType::F32)?;
+            let mask = ((mask.ones_like()? - &mask)? * f32::MIN as f64)?.to_dtype(dtype)?;
+            attention_bias = attention_bias.broadcast_add(&mask)?;
+        }
+
+        for layer in self.layers.iter() {
+            hidden_states = layer.forward(&hidden_states, &attention_bias)?;
+        }
+        Ok(hidden_states)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use candle_core::Device;
+
+    use crate::domain::entities::{
+        BatchEmbeddingRequest, EmbeddingRequest, ModelArchitecture, ModelConfig, PoolingStrategy, DEFAULT_MODEL_NAME,
+    };
+    use crate::domain::traits::{EmbeddingService, ModelRepository};
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+    use crate::test_support::{tiny_model_config, tiny_model_dir, write_weights, TINY_HIDDEN_SIZE};
+
+    /// Dimensions of the tiny BERT model; the tiny tokenizer pads with id 0
+    fn tiny_mpnet_config() -> serde_json::Value {
+        let bert: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(tiny_model_dir().join("config.json")).unwrap()).unwrap();
+        serde_json::json!({
+            "model_type": "mpnet",
+            "vocab_size": bert["vocab_size"],
+            "hidden_size": TINY_HIDDEN_SIZE,
+            "num_hidden_layers": 2,
+            "num_attention_heads": 2,
+            "intermediate_size": 64,
+            "hidden_act": "gelu",
+            "max_position_embeddings": 64,
+            "pad_token_id": 0,
+        })
+    }
+
+    fn zero_model(config: &MpNetConfig) -> MpNetModel {
+        MpNetModel::load(VarBuilder::zeros(DType::F32, &Device::Cpu), config).unwrap()
+    }
+
+    #[test]
+    fn relative_positions_fall_in_hand_computed_buckets() {
+        let config: MpNetConfig = serde_json::from_value(tiny_mpnet_config()).unwrap();
+        let model = zero_model(&config);
+
+        // 32 buckets: 0..16 for keys at or before the query, 16..32 after; exact below 8,
+        // then 8 + 8 * ln(n / 8) / ln(128 / 8), capped at 15
+        let expected = [(0, 0), (-3, 3), (3, 19), (-8, 8), (-16, 10), (16, 26), (-127, 15), (-1000, 15), (1000, 31)];
+        for (relative_position, bucket) in expected {
+            assert_eq!(model.relative_position_bucket(relative_position), bucket, "{}", relative_position);
+        }
+    }
+
+    #[test]
+    fn positions_count_up_past_the_padding_id_and_skip_padding() {
+        let mut config = tiny_mpnet_config();
+        config["pad_token_id"] = serde_json::json!(1);
+        let model = zero_model(&serde_json::from_value(config).unwrap());
+        let input_ids = Tensor::new(&[[0u32, 5, 6, 2, 1, 1], [0, 7, 2, 1, 1, 1]], &Device::Cpu).unwrap();
+
+        let positions = model.embeddings.position_ids(&input_ids).unwrap().to_vec2::<u32>().unwrap();
+
+        assert_eq!(positions, vec![vec![2, 3, 4, 5, 1, 1], vec![2, 3, 4, 1, 1, 1]]);
+    }
+
+    #[tokio::test]
+    async fn mpnet_models_load_and_mean_pool_padded_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy(tiny_model_dir().join("tokenizer.json"), dir.path().join("tokenizer.json")).unwrap();
+        let config = tiny_mpnet_config();
+        std::fs::write(dir.path().join("config.json"), config.to_string()).unwrap();
+        let mpnet_config: MpNetConfig = serde_json::from_value(config).unwrap();
+        write_weights(&dir.path().join("model.safetensors"), |vb| MpNetModel::load(vb, &mpnet_config).map(|_| ()))
+            .unwrap();
+
+        let loader = Arc::new(CandleModelLoader::new());
+        let model_config = ModelConfig {
+            model_id: dir.path().to_string_lossy().into_owned(),
+            ..tiny_model_config()
+        };
+        loader.load_model(&model_config).await.unwrap();
+        let components = loader.get_components(DEFAULT_MODEL_NAME).await.unwrap();
+        assert_eq!(components.architecture, ModelArchitecture::MpNet);
+        assert_eq!(components.pooling, PoolingStrategy::Mean);
+        assert_eq!(components.embedding_dim, TINY_HIDDEN_SIZE);
+
+        let service = SentenceTransformerService::new(loader);
+        let texts = vec!["hello world".to_string(), "the cat sleeps on the mat".to_string()];
+        let batch = service.encode_batch(BatchEmbeddingRequest::new(texts.clone())).await.unwrap();
+        for (text, embedding) in texts.into_iter().zip(&batch.embeddings) {
+            let single = service.encode(EmbeddingRequest::new(text)).await.unwrap();
+            assert_eq!(embedding.len(), TINY_HIDDEN_SIZE);
+            let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-4, "norm {}", norm);
+            // Padding in the batch must not change the shorter text's embedding
+            for (a, b) in embedding.iter().zip(&single.embedding) {
+                assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use tokio::sync::RwLock;
+
+use crate::domain::entities::{ModelConfig, DEFAULT_MODEL_NAME};
+use crate::domain::traits::ModelRepository;
+use crate::infrastructure::config::WarmupConfig;
+use crate::infrastructure::load_status::LoadStatusTracker;
+use crate::infrastructure::model_loader::{CandleModelLoader, ModelComponents, ModelProvider};
+
+/// Loads one replica of each model per GPU so batches can be sharded across devices
+pub struct MultiDeviceLoader {
+    loaders: Vec<(String, CandleModelLoader)>,
+    /// Held for writing while replicas are swapped so a batch never mixes old and new models
+    swap: RwLock<()>,
+}
+
+impl MultiDeviceLoader {
+    pub fn new(devices: Vec<String>) -> Self {
+        let loaders = devices
+            .into_iter()
+            .map(|device| (device, CandleModelLoader::new()))
+            .collect();
+        Self {
+            loaders,
+            swap: RwLock::new(()),
+        }
+    }
+
+    /// Report every replica's download and load progress to `load_status`
+    pub fn with_load_status(mut self, load_status: LoadStatusTracker) -> Self {
+        self.loaders = self
+            .loaders
+            .into_iter()
+            .map(|(device, loader)| (device, loader.with_load_status(load_status.clone())))
+            .collect();
+        self
+    }
+
+    /// Parse a device list such as `"cuda:0,cuda:1"`; returns `None` for a single device
+    pub fn parse_devices(device: &str) -> Option<Vec<String>> {
+        let devices: Vec<String> = device
+            .split(',')
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty())
+            .collect();
+        (devices.len() > 1).then_some(devices)
+    }
+
+    fn primary(&self) -> Result<&CandleModelLoader> {
+        self.loaders
+            .first()
+            .map(|(_, loader)| loader)
+            .ok_or_else(|| anyhow!("No devices configured"))
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelRepository for MultiDeviceLoader {
+    async fn load_model(&self, config: &ModelConfig) -> Result<()> {
+        self.load_named_model(DEFAULT_MODEL_NAME, config).await
+    }
+
+    async fn load_named_model(&self, name: &str, config: &ModelConfig) -> Result<()> {
+        let mut staged = Vec::with_capacity(self.loaders.len());
+        for (device, loader) in &self.loaders {
+            // Each entry in the device list names its own ordinal
+            let device_config = ModelConfig {
+                device: device.clone(),
+                device_index: None,
+                ..config.clone()
+            };
+            staged.push(Arc::new(loader.download_and_load_model(&device_config).await?));
+        }
+
+        let _swap = self.swap.write().await;
+        for ((_, loader), components) in self.loaders.iter().zip(staged) {
+            loader.insert_components(name, components).await;
+        }
+        tracing::info!("Model '{}' loaded on {} devices", name, self.loaders.len());
+        Ok(())
+    }
+
+    async fn get_current_config(&self) -> Result<ModelConfig> {
+        self.primary()?.get_current_config().await
+    }
+
+    async fn get_model_config(&self, name: &str) -> Result<ModelConfig> {
+        self.primary()?.get_model_config(name).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.primary()?.list_models().await
+    }
+
+    async fn unload_model(&self, name: &str) -> Result<()> {
+        let _swap = self.swap.write().await;
+        for (_, loader) in &self.loaders {
+            loader.unload_model(name).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelProvider for MultiDeviceLoader {
+    async fn get_replicas(&self, name: &str) -> Result<Vec<Arc<ModelComponents>>> {
+        let _swap = self.swap.read().await;
+        let mut replicas = Vec::with_capacity(self.loaders.len());
+        for (_, loader) in &self.loaders {
+            replicas.push(loader.get_components(name).await?);
+        }
+        Ok(replicas)
+    }
+
+    async fn warmup(&self, config: &WarmupConfig) -> Result<()> {
+        for (_, loader) in &self.loaders {
+            loader.warmup(config).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Pooling, Matryoshka truncation and normalization of encoder outputs. These work on
+//! plain tensors, so they can be exercised without a loaded model.
+
+use anyhow::Result;
+use candle_core::{DType, Tensor};
+
+use crate::domain::entities::{matryoshka_dimensions, PoolingStrategy};
+use crate::domain::errors::InferenceError;
+
+/// Reduce `(n, seq_len, hidden)` hidden states to `(n, hidden)`, skipping the positions
+/// where the `(n, seq_len)` attention mask is zero
+pub fn pool(embeddings: &Tensor, attention_mask: &Tensor, strategy: PoolingStrategy) -> Result<Tensor> {
+    let pooled = match strategy {
+        PoolingStrategy::Cls => embeddings.narrow(1, 0, 1)?.squeeze(1)?,
+        PoolingStrategy::Mean | PoolingStrategy::MeanSqrtLen => {
+            let mask = attention_mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+            let summed = embeddings.broadcast_mul(&mask)?.sum(1)?;
+            let token_counts = mask.sum(1)?.maximum(1e-9)?;
+            let divisor = match strategy {
+                PoolingStrategy::MeanSqrtLen => token_counts.sqrt()?,
+                _ => token_counts,
+            };
+            summed.broadcast_div(&divisor)?
+        }
+        PoolingStrategy::Max => {
+            // Padding positions become -inf so they never win the max
+            let mask = attention_mask.unsqueeze(2)?.broadcast_as(embeddings.shape())?;
+            let padding = Tensor::full(f32::NEG_INFINITY, embeddings.shape(), embeddings.device())?;
+            mask.where_cond(embeddings, &padding)?.max(1)?
+        }
+        PoolingStrategy::LastToken => {
+            // Batches are right-padded, so the last real token sits at `length - 1`
+            let lengths = attention_mask.to_dtype(DType::U32)?.sum(1)?.to_vec1::<u32>()?;
+            let rows = lengths
+                .iter()
+                .enumerate()
+                .map(|(i, &length)| embeddings.get(i)?.get(length.saturating_sub(1) as usize))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            Tensor::stack(&rows, 0)?
+        }
+    };
+    Ok(pooled)
+}
+
+/// Truncate pooled `(n, hidden)` embeddings to the requested dimensions, then normalize
+pub fn finalize(pooled: Tensor, normalize: bool, dimensions: Option<usize>) -> Result<Tensor> {
+    let pooled = match dimensions {
+        Some(dimensions) => {
+            let (_n_sentence, hidden_size) = pooled.dims2()?;
+            if !matryoshka_dimensions(hidden_size).contains(&dimensions) {
+                return Err(InferenceError::InvalidDimensions {
+                    requested: dimensions,
+                    max: hidden_size,
+                }.into());
+            }
+            pooled.narrow(1, 0, dimensions)?
+        }
+        None => pooled,
+    };
+
+    if normalize {
+        normalize_l2(&pooled)
+    } else {
+        Ok(pooled)
+    }
+}
+
+/// Scale each row of an `(n, hidden)` tensor to unit L2 norm; all-zero rows stay zero
+/// rather than becoming NaN
+pub fn normalize_l2(v: &Tensor) -> Result<Tensor> {
+    Ok(v.broadcast_div(&v.sqr()?.sum_keepdim(1)?.sqrt()?.maximum(1e-12)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::Device;
+
+    /// `(n, hidden)` rows with values that differ across the whole width
+    fn pooled(n: usize, hidden: usize) -> Tensor {
+        let values: Vec<f32> = (0..n * hidden).map(|i| (i as f32 * 0.37).sin() + 0.1).collect();
+        Tensor::from_vec(values, (n, hidden), &Device::Cpu).unwrap()
+    }
+
+    /// Two sequences of three tokens with hidden size 4; the second has one padding token
+    fn hidden_states() -> (Tensor, Tensor) {
+        let embeddings = Tensor::new(
+            &[
+                [[1.0f32, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0], [9.0, 10.0, 11.0, 12.0]],
+                [[2.0, 0.0, -2.0, 4.0], [4.0, 2.0, 0.0, -4.0], [100.0, 100.0, 100.0, 100.0]],
+            ],
+            &Device::Cpu,
+        )
+        .unwrap();
+        let mask = Tensor::new(&[[1u32, 1, 1], [1, 1, 0]], &Device::Cpu).unwrap();
+        (embeddings, mask)
+    }
+
+    fn pooled_with(strategy: PoolingStrategy) -> Vec<Vec<f32>> {
+        let (embeddings, mask) = hidden_states();
+        pool(&embeddings, &mask, strategy).unwrap().to_vec2::<f32>().unwrap()
+    }
+
+    fn assert_rows_close(actual: &[Vec<f32>], expected: &[Vec<f32>]) {
+        assert_eq!(actual.len(), expected.len());
+        for (row, expected_row) in actual.iter().zip(expected) {
+            assert_eq!(row.len(), expected_row.len());
+            for (a, e) in row.iter().zip(expected_row) {
+                assert!((a - e).abs() < 1e-5, "{:?} != {:?}", row, expected_row);
+            }
+        }
+    }
+
+    #[test]
+    fn l2_normalization_gives_unit_rows() {
+        let v = Tensor::new(&[[3.0f32, 4.0], [0.0, -2.0]], &Device::Cpu).unwrap();
+
+        assert_rows_close(&normalize_l2(&v).unwrap().to_vec2::<f32>().unwrap(), &[vec![0.6, 0.8], vec![0.0, -1.0]]);
+    }
+
+    #[test]
+    fn l2_normalization_leaves_zero_vectors_at_zero() {
+        let v = Tensor::new(&[[0.0f32, 0.0, 0.0], [0.0, 3.0, 4.0]], &Device::Cpu).unwrap();
+
+        let normalized = normalize_l2(&v).unwrap().to_vec2::<f32>().unwrap();
+
+        assert_eq!(normalized[0], vec![0.0, 0.0, 0.0]);
+        assert_rows_close(&normalized[1..], &[vec![0.0, 0.6, 0.8]]);
+    }
+
+    #[test]
+    fn mean_pooling_averages_only_the_unmasked_tokens() {
+        assert_rows_close(
+            &pooled_with(PoolingStrategy::Mean),
+            &[vec![5.0, 6.0, 7.0, 8.0], vec![3.0, 1.0, -1.0, 0.0]],
+        );
+    }
+
+    #[test]
+    fn mean_sqrt_len_pooling_divides_the_sum_by_the_root_of_the_length() {
+        let (three, two) = (3f32.sqrt(), 2f32.sqrt());
+
+        assert_rows_close(
+            &pooled_with(PoolingStrategy::MeanSqrtLen),
+            &[
+                vec![15.0 / three, 18.0 / three, 21.0 / three, 24.0 / three],
+                vec![6.0 / two, 2.0 / two, -2.0 / two, 0.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn cls_pooling_takes_the_first_token() {
+        assert_rows_close(&pooled_with(PoolingStrategy::Cls), &[vec![1.0, 2.0, 3.0, 4.0], vec![2.0, 0.0, -2.0, 4.0]]);
+    }
+
+    #[test]
+    fn last_token_pooling_takes_the_last_unmasked_token() {
+        assert_rows_close(
+            &pooled_with(PoolingStrategy::LastToken),
+            &[vec![9.0, 10.0, 11.0, 12.0], vec![4.0, 2.0, 0.0, -4.0]],
+        );
+    }
+
+    #[test]
+    fn max_pooling_takes_the_largest_unmasked_value_per_dimension() {
+        assert_rows_close(&pooled_with(PoolingStrategy::Max), &[vec![9.0, 10.0, 11.0, 12.0], vec![4.0, 2.0, 0.0, 4.0]]);
+    }
+
+    #[test]
+    fn pooling_then_normalizing_gives_hand_computed_unit_vectors() {
+        let (embeddings, mask) = hidden_states();
+
+        let pooled = pool(&embeddings, &mask, PoolingStrategy::Mean).unwrap();
+        let normalized = finalize(pooled, true, None).unwrap().to_vec2::<f32>().unwrap();
+
+        // [3, 1, -1, 0] has norm sqrt(11)
+        let root = 11f32.sqrt();
+        assert_rows_close(&normalized[1..], &[vec![3.0 / root, 1.0 / root, -1.0 / root, 0.0]]);
+        // An all-padding row pools to zero and stays finite
+        let empty = Tensor::zeros((1, 2, 4), DType::F32, &Device::Cpu).unwrap();
+        let no_tokens = Tensor::zeros((1, 2), DType::U32, &Device::Cpu).unwrap();
+        let pooled = pool(&empty, &no_tokens, PoolingStrategy::Mean).unwrap();
+        assert_eq!(finalize(pooled, true, None).unwrap().to_vec2::<f32>().unwrap(), vec![vec![0.0; 4]]);
+    }
+
+    #[test]
+    fn max_pooling_ignores_padding_positions() {
+        // Two tokens of hidden size 2; the second row's padding holds the largest values
+        let embeddings = Tensor::new(
+            &[[[1.0f32, -2.0], [3.0, -4.0]], [[-1.0, -5.0], [9.0, 9.0]]],
+            &Device::Cpu,
+        )
+        .unwrap();
+        let mask = Tensor::new(&[[1u32, 1], [1, 0]], &Device::Cpu).unwrap();
+
+        let pooled = pool(&embeddings, &mask, PoolingStrategy::Max).unwrap();
+
+        assert_eq!(pooled.to_vec2::<f32>().unwrap(), vec![vec![3.0, -2.0], vec![-1.0, -5.0]]);
+    }
+
+    #[test]
+    fn matryoshka_truncation_keeps_the_leading_dimensions_at_unit_norm() {
+        let full = pooled(2, 128);
+
+        let truncated = finalize(full.clone(), true, Some(64)).unwrap().to_vec2::<f32>().unwrap();
+
+        let leading = full.narrow(1, 0, 64).unwrap().to_vec2::<f32>().unwrap();
+        for (row, original) in truncated.iter().zip(&leading) {
+            assert_eq!(row.len(), 64);
+            let norm = row.iter().map(|v| v * v).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-5, "norm {}", norm);
+            // Same direction as the original leading dimensions
+            let scale = row[0] / original[0];
+            for (v, o) in row.iter().zip(original) {
+                assert!((v - o * scale).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn matryoshka_truncation_without_normalization_keeps_the_values() {
+        let full = pooled(1, 128);
+
+        let truncated = finalize(full.clone(), false, Some(32)).unwrap();
+
+        assert_eq!(truncated.to_vec2::<f32>().unwrap(), full.narrow(1, 0, 32).unwrap().to_vec2::<f32>().unwrap());
+    }
+
+    #[test]
+    fn matryoshka_truncation_rejects_unsupported_dimensions() {
+        for requested in [48, 256] {
+            let error = finalize(pooled(1, 128), true, Some(requested)).unwrap_err();
+
+            assert!(matches!(
+                error.downcast_ref::<InferenceError>(),
+                Some(InferenceError::InvalidDimensions { requested: r, max: 128 }) if *r == requested
+            ));
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::OnceLock;
+use ego_tree::iter::Edge;
+use regex::Regex;
+use scraper::{Html, Node};
+
+use crate::domain::traits::TextPreprocessor;
+
+/// Terminal punctuation that ends a sentence when followed by whitespace or the end
+/// of the text; CJK full stops end one regardless, as those scripts don't space words.
+/// Closing quotes include German `“` and `»`. A blank line ends a sentence too, so
+/// headings and list items stay separate.
+const SENTENCE_BOUNDARY: &str = r#"[.!?…]+["'”’“»)\]]*(?:\s+|$)|[。！？]+[」』”）]*\s*|\n\s*\n\s*"#;
+
+/// Words that end in a period without ending the sentence
+const ABBREVIATIONS: &[&str] = &["mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "e.g", "i.e", "fig"];
+
+fn push_sentence(sentences: &mut Vec<String>, sentence: &str) {
+    let sentence = sentence.trim();
+    if !sentence.is_empty() {
+        sentences.push(sentence.to_string());
+    }
+}
+
+/// Splits after `.`, `!` or `?` followed by whitespace; no abbreviation or CJK handling
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NaiveSentenceSplitter;
+
+impl TextPreprocessor for NaiveSentenceSplitter {
+    fn split_sentences(&self, text: &str) -> Vec<String> {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let at_boundary =
+                matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+            if at_boundary {
+                let end = i + c.len_utf8();
+                push_sentence(&mut sentences, &text[start..end]);
+                start = end;
+            }
+        }
+        push_sentence(&mut sentences, &text[start..]);
+        sentences
+    }
+}
+
+/// Splits on terminal punctuation including CJK full stops and closing quotes, and
+/// skips periods after common abbreviations and single-letter initials
+#[derive(Debug, Clone)]
+pub struct RegexSentenceSplitter {
+    boundary: Regex,
+}
+
+impl RegexSentenceSplitter {
+    pub fn new() -> Self {
+        Self {
+            boundary: Regex::new(SENTENCE_BOUNDARY).expect("sentence boundary pattern is valid"),
+        }
+    }
+}
+
+impl Default for RegexSentenceSplitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextPreprocessor for RegexSentenceSplitter {
+    fn split_sentences(&self, text: &str) -> Vec<String> {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        for boundary in self.boundary.find_iter(text) {
+            let before = &text[start..boundary.start()];
+            if boundary.as_str().starts_with('.') && ends_with_abbreviation(before) {
+                continue;
+            }
+            push_sentence(&mut sentences, &text[start..boundary.end()]);
+            start = boundary.end();
+        }
+        push_sentence(&mut sentences, &text[start..]);
+        sentences
+    }
+}
+
+/// Whether the last word of `text` is an abbreviation or an initial such as the `J` in `J. Smith`
+fn ends_with_abbreviation(text: &str) -> bool {
+    let word = text.rsplit(|c: char| c.is_whitespace() || c == '(').next().unwrap_or("");
+    let mut chars = word.chars();
+    let is_initial = matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic());
+    is_initial || ABBREVIATIONS.iter().any(|abbreviation| word.eq_ignore_ascii_case(abbreviation))
+}
+
+/// Collapse whitespace within lines and runs of blank lines, keeping line structure
+/// intact for steps that come later in a pipeline
+fn tidy_whitespace(text: &str) -> String {
+    let mut tidied = String::with_capacity(text.len());
+    let mut blank_run = true;
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            if !blank_run {
+                tidied.push('\n');
+            }
+            blank_run = true;
+        } else {
+            tidied.push_str(&line);
+            tidied.push('\n');
+            blank_run = false;
+        }
+    }
+    tidied.trim_end().to_string()
+}
+
+/// Apply `(pattern, replacement)` rules in order, each compiled once
+fn apply_rules(text: &str, rules: &[(Regex, &str)]) -> String {
+    rules
+        .iter()
+        .fold(text.to_string(), |text, (pattern, replacement)| pattern.replace_all(&text, *replacement).into_owned())
+}
+
+fn compile_rules(rules: &[(&str, &'static str)]) -> Vec<(Regex, &'static str)> {
+    rules
+        .iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).expect("preprocessing pattern is valid"), *replacement))
+        .collect()
+}
+
+/// Elements whose content is never page text
+const SKIPPED_HTML_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "head"];
+
+/// Block elements separate paragraphs, so sentences on either side don't run together
+const BLOCK_HTML_ELEMENTS: &[&str] = &[
+    "p", "div", "br", "hr", "li", "ul", "ol", "dl", "dt", "dd", "h1", "h2", "h3", "h4", "h5", "h6", "table", "tr",
+    "td", "th", "pre", "blockquote", "section", "article", "header", "footer", "nav", "aside", "main", "figure",
+    "figcaption", "title",
+];
+
+/// Removes HTML tags, scripts, styles and comments, and decodes character references.
+/// Block-level tags become paragraph breaks so a sentence splitter sees them as boundaries.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HtmlStripper;
+
+impl TextPreprocessor for HtmlStripper {
+    fn preprocess(&self, text: &str) -> String {
+        // The parser decodes character references and drops comments; a flat traversal
+        // rather than recursion keeps deeply nested markup off the stack
+        let document = Html::parse_document(text);
+        let mut stripped = String::with_capacity(text.len());
+        let mut skipped_depth = 0usize;
+        for edge in document.root_element().traverse() {
+            match edge {
+                Edge::Open(node) => match node.value() {
+                    Node::Element(element) if SKIPPED_HTML_ELEMENTS.contains(&element.name()) => skipped_depth += 1,
+                    Node::Element(element) if skipped_depth == 0 && BLOCK_HTML_ELEMENTS.contains(&element.name()) => {
+                        stripped.push_str("\n\n");
+                    }
+                    Node::Text(content) if skipped_depth == 0 => stripped.push_str(content),
+                    _ => {}
+                },
+                Edge::Close(node) => match node.value() {
+                    Node::Element(element) if SKIPPED_HTML_ELEMENTS.contains(&element.name()) => skipped_depth -= 1,
+                    Node::Element(element) if skipped_depth == 0 && BLOCK_HTML_ELEMENTS.contains(&element.name()) => {
+                        stripped.push_str("\n\n");
+                    }
+                    _ => {}
+                },
+            }
+        }
+        tidy_whitespace(&stripped)
+    }
+}
+
+fn markdown_rules() -> &'static [(Regex, &'static str)] {
+    static RULES: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        compile_rules(&[
+            // Block syntax, line by line; the code inside fences is kept
+            (r"(?m)^[ \t]*(?:```|~~~).*$", "\n"),
+            (r"(?m)^ {0,3}(?:[-*_][ \t]*){3,}$", "\n"),
+            (r"(?m)^[ \t|:-]*-{3,}[ \t|:-]*$", ""),
+            (r"(?m)^ {0,3}\[[^\]]+\]:[ \t]+\S.*$", ""),
+            (r"(?m)^ {0,3}#{1,6}[ \t]+(.*?)[ \t#]*$", "\n\n${1}\n\n"),
+            (r"(?m)^ {0,3}(?:>[ \t]?)+", ""),
+            (r"(?m)^[ \t]*(?:[-*+]|\d+[.)])[ \t]+", "\n\n"),
+            (r"(?m)^[ \t]*\|(.*)\|[ \t]*$", "${1}"),
+            (r"[ \t]\|[ \t]", " "),
+            // Inline syntax, keeping the visible text
+            (r"!\[([^\]]*)\]\([^)]*\)", "${1}"),
+            (r"\[([^\]]+)\](?:\([^)]*\)|\[[^\]]*\])", "${1}"),
+            (r"<(https?://[^>]+)>", "${1}"),
+            (r"\*\*([^*]+)\*\*|__([^_]+)__", "${1}${2}"),
+            (r"\*([^*\s][^*]*)\*|\b_([^_]+)_\b", "${1}${2}"),
+            (r"~~([^~]+)~~", "${1}"),
+            (r"`+([^`]+)`+", "${1}"),
+        ])
+    })
+}
+
+/// Removes Markdown syntax while keeping the visible text of links, emphasis and code.
+/// Headings and list items become their own paragraphs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MarkdownStripper;
+
+impl TextPreprocessor for MarkdownStripper {
+    fn preprocess(&self, text: &str) -> String {
+        tidy_whitespace(&apply_rules(text, markdown_rules()))
+    }
+}
+
+/// Preprocessors applied in order: each step's `split_sentences` runs on every segment
+/// the previous steps produced, so cleaners should come before splitters
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn TextPreprocessor>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a step; reads as a builder chain, not arithmetic
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<P: TextPreprocessor + 'static>(mut self, step: P) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+}
+
+impl TextPreprocessor for Pipeline {
+    fn preprocess(&self, text: &str) -> String {
+        self.steps.iter().fold(text.to_string(), |text, step| step.preprocess(&text))
+    }
+
+    fn split_sentences(&self, text: &str) -> Vec<String> {
+        let segments = vec![text.trim().to_string()];
+        self.steps
+            .iter()
+            .fold(segments, |segments, step| {
+                segments.iter().flat_map(|segment| step.split_sentences(segment)).collect()
+            })
+            .into_iter()
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(splitter: &impl TextPreprocessor, text: &str) -> Vec<String> {
+        splitter.split_sentences(text)
+    }
+
+    #[test]
+    fn english_paragraphs_split_at_sentence_ends_but_not_abbreviations() {
+        let text = "Dr. Smith met J. Doe for lunch, e.g. at noon. Was it planned? \"No!\" she said.\n\nNext paragraph";
+
+        assert_eq!(
+            split(&RegexSentenceSplitter::new(), text),
+            vec!["Dr. Smith met J. Doe for lunch, e.g. at noon.", "Was it planned?", "\"No!\"", "she said.", "Next paragraph"]
+        );
+        assert_eq!(
+            split(&NaiveSentenceSplitter, "The cat sleeps. Does it nap? It does!"),
+            vec!["The cat sleeps.", "Does it nap?", "It does!"]
+        );
+    }
+
+    #[test]
+    fn german_paragraphs_keep_quotes_and_umlauts_with_their_sentence() {
+        let text = "Der Hund schläft gut. Die Katze sagte: „Ich schlafe.“ Dann ging sie. Ist das nicht schön?";
+
+        assert_eq!(
+            split(&RegexSentenceSplitter::new(), text),
+            vec!["Der Hund schläft gut.", "Die Katze sagte: „Ich schlafe.“", "Dann ging sie.", "Ist das nicht schön?"]
+        );
+    }
+
+    #[test]
+    fn chinese_paragraphs_split_at_full_stops_without_spaces() {
+        let text = "今天天气很好。我们去公园吧！你来吗？他说：“好的。”然后走了";
+
+        assert_eq!(
+            split(&RegexSentenceSplitter::new(), text),
+            vec!["今天天气很好。", "我们去公园吧！", "你来吗？", "他说：“好的。”", "然后走了"]
+        );
+        // Period-based splitting sees no boundary at all
+        assert_eq!(split(&NaiveSentenceSplitter, text), vec![text]);
+    }
+
+    #[test]
+    fn pipelines_clean_before_splitting() {
+        let pipeline = Pipeline::new().add(HtmlStripper).add(RegexSentenceSplitter::new());
+        let text = "<h1>Title</h1><p>First sentence. Second one</p><script>ignored()</script>";
+
+        assert_eq!(pipeline.split_sentences(&pipeline.preprocess(text)), vec!["Title", "First sentence.", "Second one"]);
+    }
+}
@@ -0,0 +1,168 @@
+use candle_core::quantized::{GgmlDType, QMatMul, QTensor};
+use candle_core::{DType, Module, Result, Tensor, D};
+use candle_nn::{embedding, layer_norm, Embedding, LayerNorm, VarBuilder};
+use candle_transformers::models::bert::{Config as BertConfig, HiddenAct};
+
+struct QuantizedLinear {
+    weight: QMatMul,
+    bias: Tensor,
+}
+
+impl QuantizedLinear {
+    fn load(in_dim: usize, out_dim: usize, vb: VarBuilder) -> Result<Self> {
+        let weight = vb.get((out_dim, in_dim), "weight")?;
+        let bias = vb.get(out_dim, "bias")?;
+        let weight = QMatMul::from_qtensor(QTensor::quantize(&weight, GgmlDType::Q8_0)?)?;
+        Ok(Self { weight, bias })
+    }
+
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        self.weight.forward(xs)?.broadcast_add(&self.bias)
+    }
+}
+
+struct QuantizedEmbeddings {
+    word_embeddings: Embedding,
+    position_embeddings: Embedding,
+    token_type_embeddings: Embedding,
+    layer_norm: LayerNorm,
+}
+
+impl QuantizedEmbeddings {
+    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+        Ok(Self {
+            word_embeddings: embedding(config.vocab_size, config.hidden_size, vb.pp("word_embeddings"))?,
+            position_embeddings: embedding(
+                config.max_position_embeddings,
+                config.hidden_size,
+                vb.pp("position_embeddings"),
+            )?,
+            token_type_embeddings: embedding(
+                config.type_vocab_size,
+                config.hidden_size,
+                vb.pp("token_type_embeddings"),
+            )?,
+            layer_norm: layer_norm(config.hidden_size, config.layer_norm_eps, vb.pp("LayerNorm"))?,
+        })
+    }
+
+    fn forward(&self, input_ids: &Tensor, token_type_ids: &Tensor) -> Result<Tensor> {
+        let (_batch_size, seq_len) = input_ids.dims2()?;
+        let position_ids = Tensor::arange(0u32, seq_len as u32, input_ids.device())?;
+        let embeddings = (self.word_embeddings.forward(input_ids)?
+            + self.token_type_embeddings.forward(token_type_ids)?)?
+            .broadcast_add(&self.position_embeddings.forward(&position_ids)?)?;
+        self.layer_norm.forward(&embeddings)
+    }
+}
+
+struct QuantizedLayer {
+    query: QuantizedLinear,
+    key: QuantizedLinear,
+    value: QuantizedLinear,
+    attention_output: QuantizedLinear,
+    attention_layer_norm: LayerNorm,
+    intermediate: QuantizedLinear,
+    output: QuantizedLinear,
+    output_layer_norm: LayerNorm,
+    num_attention_heads: usize,
+    attention_head_size: usize,
+    hidden_act: HiddenAct,
+}
+
+impl QuantizedLayer {
+    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+        let hidden_size = config.hidden_size;
+        let attention = vb.pp("attention");
+        Ok(Self {
+            query: QuantizedLinear::load(hidden_size, hidden_size, attention.pp("self.query"))?,
+            key: QuantizedLinear::load(hidden_size, hidden_size, attention.pp("self.key"))?,
+            value: QuantizedLinear::load(hidden_size, hidden_size, attention.pp("self.value"))?,
+            attention_output: QuantizedLinear::load(hidden_size, hidden_size, attention.pp("output.dense"))?,
+            attention_layer_norm: layer_norm(hidden_size, config.layer_norm_eps, attention.pp("output.LayerNorm"))?,
+            intermediate: QuantizedLinear::load(hidden_size, config.intermediate_size, vb.pp("intermediate.dense"))?,
+            output: QuantizedLinear::load(config.intermediate_size, hidden_size, vb.pp("output.dense"))?,
+            output_layer_norm: layer_norm(hidden_size, config.layer_norm_eps, vb.pp("output.LayerNorm"))?,
+            num_attention_heads: config.num_attention_heads,
+            attention_head_size: hidden_size / config.num_attention_heads,
+            hidden_act: config.hidden_act,
+        })
+    }
+
+    fn split_heads(&self, xs: &Tensor) -> Result<Tensor> {
+        let (batch_size, seq_len, _hidden_size) = xs.dims3()?;
+        xs.reshape((batch_size, seq_len, self.num_attention_heads, self.attention_head_size))?
+            .transpose(1, 2)?
+            .contiguous()
+    }
+
+    fn forward(&self, hidden_states: &Tensor, attention_mask: &Tensor) -> Result<Tensor> {
+        let query = self.split_heads(&self.query.forward(hidden_states)?)?;
+        let key = self.split_heads(&self.key.forward(hidden_states)?)?;
+        let value = self.split_heads(&self.value.forward(hidden_states)?)?;
+
+        let scores = (query.matmul(&key.t()?)? / (self.attention_head_size as f64).sqrt())?
+            .broadcast_add(attention_mask)?;
+        let probs = candle_nn::ops::softmax(&scores, D::Minus1)?;
+        let context = probs
+            .matmul(&value)?
+            .transpose(1, 2)?
+            .contiguous()?
+            .flatten_from(D::Minus2)?;
+
+        let attention_output = self.attention_output.forward(&context)?;
+        let attention_output = self.attention_layer_norm.forward(&(attention_output + hidden_states)?)?;
+
+        let intermediate = self.intermediate.forward(&attention_output)?;
+        let intermediate = match self.hidden_act {
+            HiddenAct::Gelu => intermediate.gelu_erf()?,
+            HiddenAct::GeluApproximate => intermediate.gelu()?,
+            HiddenAct::Relu => intermediate.relu()?,
+        };
+        let output = self.output.forward(&intermediate)?;
+        self.output_layer_norm.forward(&(output + attention_output)?)
+    }
+}
+
+/// BERT encoder whose attention and feed-forward projections are quantized to
+/// INT8 (Q8_0) at load time; embeddings and layer norms stay in f32
+pub struct QuantizedBertModel {
+    embeddings: QuantizedEmbeddings,
+    layers: Vec<QuantizedLayer>,
+}
+
+impl QuantizedBertModel {
+    pub fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+        // Some checkpoints nest the encoder under the model type, e.g. `bert.embeddings`
+        let vb = match &config.model_type {
+            Some(model_type) if !vb.contains_tensor("embeddings.word_embeddings.weight") => vb.pp(model_type),
+            _ => vb,
+        };
+        let embeddings = QuantizedEmbeddings::load(vb.pp("embeddings"), config)?;
+        let layers = (0..config.num_hidden_layers)
+            .map(|index| QuantizedLayer::load(vb.pp(format!("encoder.layer.{index}")), config))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { embeddings, layers })
+    }
+
+    pub fn forward(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: Option<&Tensor>,
+    ) -> Result<Tensor> {
+        let mut hidden_states = self.embeddings.forward(input_ids, token_type_ids)?;
+        let attention_mask = match attention_mask {
+            Some(attention_mask) => attention_mask.clone(),
+            None => input_ids.ones_like()?,
+        };
+        // Padding positions get a large negative bias so softmax ignores them
+        let attention_mask = attention_mask.unsqueeze(1)?.unsqueeze(1)?.to_dtype(DType::F32)?;
+        let attention_mask = ((attention_mask.ones_like()? - &attention_mask)? * f32::MIN as f64)?;
+
+        for layer in self.layers.iter() {
+            hidden_states = layer.forward(&hidden_states, &attention_mask)?;
+        }
+        Ok(hidden_states)
+    }
+}
@@ -5,63 +5,187 @@ extern crate intel_mkl_src;
 extern crate accelerate_src;
 
 use std::sync::Arc;
+use std::time::Instant;
 use anyhow::{anyhow, Result};
-use candle_core::Tensor;
+use candle_core::{DType, Tensor};
+use futures::stream::{self, BoxStream, StreamExt};
 
 use crate::domain::entities::{
-    BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse, ModelConfig,
+    BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse, ModelConfig, ModelStats, PaddingStrategy,
+    RequestType, TokenEmbeddingRequest, TokenEmbeddingResponse, TokenizeRequest, TokenizeResponse, DEFAULT_MODEL_NAME,
 };
-use crate::domain::traits::{EmbeddingService, ModelRepository};
-use crate::infrastructure::model_loader::CandleModelLoader;
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::EmbeddingService;
+use crate::infrastructure::cache::InMemoryCache;
+use crate::infrastructure::metrics;
+use crate::infrastructure::pooling;
+use crate::infrastructure::model_loader::{ModelComponents, ModelProvider};
+
+/// Embedding of a single text along with the number of tokens it produced
+struct EncodedText {
+    embedding: Vec<f32>,
+    token_count: usize,
+}
+
+/// (model name, text, normalize, dimensions, prompt name, request type) of a single-text encode
+type CacheKey = (String, String, bool, Option<usize>, Option<String>, Option<RequestType>);
 
 pub struct SentenceTransformerService {
-    model_loader: Arc<CandleModelLoader>,
+    model_loader: Arc<dyn ModelProvider>,
+    cache: Option<InMemoryCache<CacheKey, EmbeddingResponse>>,
+    check_finite: bool,
 }
 
 impl SentenceTransformerService {
-    pub fn new(model_loader: Arc<CandleModelLoader>) -> Self {
-        Self { model_loader }
+    pub fn new(model_loader: Arc<dyn ModelProvider>) -> Self {
+        Self { model_loader, cache: None, check_finite: true }
     }
 
-    async fn encode_texts(&self, texts: &[String], normalize: bool) -> Result<Vec<Vec<f32>>> {
-        let model_ref = self.model_loader.get_model().await?;
-        let model_guard = model_ref.read().await;
-        
-        let components = model_guard
-            .as_ref()
-            .ok_or_else(|| anyhow!("No model loaded"))?;
-
-        if texts.len() == 1 {
-            // Single text encoding
-            self.encode_single_text(&texts[0], components, normalize).await
-        } else {
+    /// Whether to scan every embedding for NaN or infinite values before returning it;
+    /// turning this off saves a pass over the output on trusted models
+    pub fn with_finite_check(mut self, check_finite: bool) -> Self {
+        self.check_finite = check_finite;
+        self
+    }
+
+    /// Serve repeated single-text encodes from an in-process LRU
+    pub fn with_cache(mut self, cache: InMemoryCache<CacheKey, EmbeddingResponse>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Push a short probe text through the single and batch encode paths so the
+    /// first real request doesn't pay for allocation and kernel compilation
+    pub async fn warmup(&self, batch_sizes: &[usize]) -> Result<()> {
+        const WARMUP_TEXT: &str = "warmup";
+        let start = Instant::now();
+
+        self.encode_texts(DEFAULT_MODEL_NAME, &[WARMUP_TEXT.to_string()], true, None).await?;
+        for &batch_size in batch_sizes.iter().filter(|&&size| size > 1) {
+            let texts = vec![WARMUP_TEXT.to_string(); batch_size];
+            self.encode_texts(DEFAULT_MODEL_NAME, &texts, true, None).await?;
+        }
+
+        tracing::info!("Encode path warmed up in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    async fn encode_texts(
+        &self,
+        model_name: &str,
+        texts: &[String],
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<Vec<EncodedText>> {
+        self.encode_sharded(model_name, texts, move |shard, components| {
+            Self::encode_shard(shard, components, normalize, dimensions)
+        })
+        .await
+    }
+
+    /// Shard `items` across replicas, running each shard off the async runtime so
+    /// the forward passes proceed in parallel and callers can time out
+    async fn encode_sharded<T, F>(&self, model_name: &str, items: &[T], encode_shard: F) -> Result<Vec<EncodedText>>
+    where
+        T: Clone + Send + 'static,
+        F: Fn(&[T], &ModelComponents) -> Result<Vec<EncodedText>> + Clone + Send + 'static,
+    {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+        let replicas = self.model_loader.get_replicas(model_name).await?;
+
+        let shard_size = items.len().div_ceil(replicas.len());
+        let handles: Vec<_> = items
+            .chunks(shard_size)
+            .zip(replicas)
+            .map(|(shard, components)| {
+                let span = tracing::info_span!(
+                    "inference",
+                    model.id = %components.config.model_id,
+                    batch.size = shard.len(),
+                    pooling.strategy = components.pooling.as_str(),
+                    device = ?components.device,
+                );
+                let shard = shard.to_vec();
+                let encode_shard = encode_shard.clone();
+                tokio::task::spawn_blocking(move || span.in_scope(|| encode_shard(&shard, &components)))
+            })
+            .collect();
+
+        let mut encoded = Vec::with_capacity(items.len());
+        for handle in handles {
+            encoded.extend(handle.await??);
+        }
+        if self.check_finite {
+            Self::ensure_finite(&encoded)?;
+        }
+        Ok(encoded)
+    }
+
+    /// A broken quantized model or numerical overflow yields NaN or inf rather than an
+    /// error, so name the first affected input instead of returning garbage. The index is
+    /// within `encoded`; callers encoding part of a request shift it with
+    /// `InferenceError::at_request_index`
+    fn ensure_finite(encoded: &[EncodedText]) -> Result<()> {
+        match encoded.iter().position(|text| !text.embedding.iter().all(|x| x.is_finite())) {
+            Some(index) => Err(InferenceError::NonFiniteEmbedding { index }.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn encode_shard(
+        texts: &[String],
+        components: &ModelComponents,
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<Vec<EncodedText>> {
+        match components.config.padding_strategy {
+            PaddingStrategy::None => {
+                let mut encoded = Vec::with_capacity(texts.len());
+                for text in texts {
+                    encoded.extend(Self::encode_single_text(text, components, normalize, dimensions)?);
+                }
+                Ok(encoded)
+            }
+            // Single text encoding; a fixed length pads even one text, so it needs the mask
+            PaddingStrategy::BatchLongest if texts.len() == 1 => {
+                Self::encode_single_text(&texts[0], components, normalize, dimensions)
+            }
             // Batch encoding for better performance
-            self.encode_batch_texts(texts, components, normalize).await
+            PaddingStrategy::BatchLongest | PaddingStrategy::Fixed => {
+                Self::encode_batch_texts(texts, components, normalize, dimensions)
+            }
         }
     }
 
-    async fn encode_single_text(&self, text: &str, components: &crate::infrastructure::model_loader::ModelComponents, normalize: bool) -> Result<Vec<Vec<f32>>> {
+    fn encode_single_text(
+        text: &str,
+        components: &ModelComponents,
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<Vec<EncodedText>> {
         let encoding = components.tokenizer
             .encode(text, true)
             .map_err(|e| anyhow!("Tokenization failed: {}", e))?;
 
         let tokens = encoding.get_ids().to_vec();
         let token_ids = Tensor::new(&tokens[..], &components.device)?.unsqueeze(0)?;
-        let token_type_ids = token_ids.zeros_like()?;
+        let embedding = Self::forward_pooled(&token_ids, None, components, normalize, dimensions)?;
 
-        let ys = components.model.forward(&token_ids, &token_type_ids, None)?;
-        
-        let embedding = if normalize {
-            self.normalize_l2(&ys)?
-        } else {
-            ys
-        };
-
-        let embedding_vec = embedding.to_vec1::<f32>()?;
-        Ok(vec![embedding_vec])
+        let embedding_vec = embedding.squeeze(0)?.to_vec1::<f32>()?;
+        Ok(vec![EncodedText {
+            embedding: embedding_vec,
+            token_count: tokens.len(),
+        }])
     }
 
-    async fn encode_batch_texts(&self, texts: &[String], components: &crate::infrastructure::model_loader::ModelComponents, normalize: bool) -> Result<Vec<Vec<f32>>> {
+    fn encode_batch_texts(
+        texts: &[String],
+        components: &ModelComponents,
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<Vec<EncodedText>> {
         let tokens = components.tokenizer
             .encode_batch(texts.to_vec(), true)
             .map_err(|e| anyhow!("Batch tokenization failed: {}", e))?;
@@ -84,61 +208,296 @@ impl SentenceTransformerService {
 
         let token_ids = Tensor::stack(&token_ids, 0)?;
         let attention_mask = Tensor::stack(&attention_mask, 0)?;
-        let token_type_ids = token_ids.zeros_like()?;
-
-        tracing::debug!("Running inference on batch {:?}", token_ids.shape());
-        let embeddings = components.model.forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
-        tracing::debug!("Generated embeddings {:?}", embeddings.shape());
-
-        // Apply mean pooling by taking the mean embedding value for all tokens (including padding)
-        let (_n_sentence, n_tokens, _hidden_size) = embeddings.dims3()?;
-        let pooled_embeddings = (embeddings.sum(1)? / (n_tokens as f64))?;
-        
-        let final_embeddings = if normalize {
-            self.normalize_l2(&pooled_embeddings)?
-        } else {
-            pooled_embeddings
-        };
+        let final_embeddings =
+            Self::forward_pooled(&token_ids, Some(&attention_mask), components, normalize, dimensions)?;
 
-        tracing::debug!("Pooled embeddings {:?}", final_embeddings.shape());
-
-        // Convert to Vec<Vec<f32>>
+        // Convert to Vec<EncodedText>, counting only non-padding tokens
         let mut result = Vec::new();
-        for i in 0..texts.len() {
+        for (i, encoding) in tokens.iter().enumerate() {
             let embedding = final_embeddings.get(i)?;
             let embedding_vec = embedding.to_vec1::<f32>()?;
-            result.push(embedding_vec);
+            let token_count = encoding.get_attention_mask().iter().filter(|&&m| m == 1).count();
+            result.push(EncodedText {
+                embedding: embedding_vec,
+                token_count,
+            });
         }
 
         Ok(result)
     }
 
-    fn normalize_l2(&self, v: &Tensor) -> Result<Tensor> {
-        Ok(v.broadcast_div(&v.sqr()?.sum_keepdim(1)?.sqrt()?)?)
+    /// Encode pre-tokenized `(input_ids, attention_mask)` rows, padding them to a common length
+    fn encode_token_rows(
+        rows: &[(Vec<u32>, Vec<u32>)],
+        components: &ModelComponents,
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<Vec<EncodedText>> {
+        let vocab_size = components.tokenizer.get_vocab_size(true) as u32;
+        if let Some(&id) = rows.iter().flat_map(|(ids, _)| ids).find(|&&id| id >= vocab_size) {
+            return Err(InferenceError::InvalidInput {
+                message: format!("Token id {} is outside the vocabulary of size {}", id, vocab_size),
+            }.into());
+        }
+
+        let pad_id = components.tokenizer.get_padding().map(|p| p.pad_id).unwrap_or(0);
+        let max_len = rows.iter().map(|(ids, _)| ids.len()).max().unwrap_or(0);
+        let mut flat_ids = Vec::with_capacity(rows.len() * max_len);
+        let mut flat_mask = Vec::with_capacity(rows.len() * max_len);
+        for (ids, mask) in rows {
+            flat_ids.extend(ids.iter().copied().chain(std::iter::repeat(pad_id)).take(max_len));
+            flat_mask.extend(mask.iter().copied().chain(std::iter::repeat(0)).take(max_len));
+        }
+
+        let token_ids = Tensor::from_vec(flat_ids, (rows.len(), max_len), &components.device)?;
+        let attention_mask = Tensor::from_vec(flat_mask, (rows.len(), max_len), &components.device)?;
+        let final_embeddings =
+            Self::forward_pooled(&token_ids, Some(&attention_mask), components, normalize, dimensions)?;
+
+        rows.iter()
+            .enumerate()
+            .map(|(i, (_, mask))| {
+                Ok(EncodedText {
+                    embedding: final_embeddings.get(i)?.to_vec1::<f32>()?,
+                    token_count: mask.iter().filter(|&&m| m == 1).count(),
+                })
+            })
+            .collect()
+    }
+
+    /// Run the model on `(n, seq_len)` token ids and pool to `(n, hidden)` embeddings
+    fn forward_pooled(
+        token_ids: &Tensor,
+        attention_mask: Option<&Tensor>,
+        components: &ModelComponents,
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<Tensor> {
+        let token_type_ids = components
+            .architecture
+            .uses_token_type_ids()
+            .then(|| token_ids.zeros_like())
+            .transpose()?;
+
+        tracing::debug!("Running inference on batch {:?}", token_ids.shape());
+        // Upcast half precision activations so pooling and normalization run in f32
+        let embeddings = components.model.forward(token_ids, token_type_ids.as_ref(), attention_mask)?
+            .to_dtype(DType::F32)?;
+        tracing::debug!("Generated embeddings {:?}", embeddings.shape());
+
+        // A single unpadded text has no mask; every token counts
+        let pooling_mask = match attention_mask {
+            Some(mask) => mask.clone(),
+            None => token_ids.ones_like()?,
+        };
+        let pooled_embeddings = pooling::pool(&embeddings, &pooling_mask, components.pooling)?;
+        let final_embeddings = pooling::finalize(pooled_embeddings, normalize, dimensions)?;
+
+        tracing::debug!("Pooled embeddings {:?}", final_embeddings.shape());
+        Ok(final_embeddings)
+    }
+
+    /// Prepend the prompt named by the request to each text; failing that, the model's
+    /// instruction for the request type, and failing that its default prompt
+    fn apply_prompt(
+        config: &ModelConfig,
+        prompt_name: Option<&str>,
+        request_type: Option<RequestType>,
+        texts: &[String],
+    ) -> Result<Vec<String>> {
+        let instruction = request_type.and_then(|request_type| config.instruction(request_type));
+        if let (None, Some(instruction)) = (prompt_name, instruction) {
+            return Ok(texts.iter().map(|text| format!("{}{}", instruction, text)).collect());
+        }
+        let Some(prompt_name) = prompt_name.or(config.default_prompt_name.as_deref()) else {
+            return Ok(texts.to_vec());
+        };
+        let prompt = config.prompts.get(prompt_name).ok_or_else(|| {
+            let mut available: Vec<&str> = config.prompts.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            InferenceError::InvalidInput {
+                message: format!(
+                    "Unknown prompt '{}' for model {} (available: {})",
+                    prompt_name,
+                    config.model_id,
+                    available.join(", ")
+                ),
+            }
+        })?;
+        Ok(texts.iter().map(|text| format!("{}{}", prompt, text)).collect())
     }
 }
 
 #[async_trait::async_trait]
 impl EmbeddingService for SentenceTransformerService {
     async fn encode(&self, request: EmbeddingRequest) -> Result<EmbeddingResponse> {
-        let embeddings = self.encode_texts(&[request.text.clone()], request.normalize).await?;
-        let config = self.model_loader.get_current_config().await?;
+        let model_name = request.model_name();
+        let cache_key = (
+            model_name.to_string(),
+            request.text.clone(),
+            request.normalize,
+            request.dimensions,
+            request.prompt_name.clone(),
+            request.request_type,
+        );
+        if let Some(cache) = &self.cache {
+            if let Some(mut response) = cache.get(&cache_key) {
+                metrics::cache_hits_total().inc();
+                response.cache_hit = true;
+                return Ok(response);
+            }
+            metrics::cache_misses_total().inc();
+        }
+
+        let config = self.model_loader.get_model_config(model_name).await?;
+        let texts = Self::apply_prompt(
+            &config,
+            request.prompt_name.as_deref(),
+            request.request_type,
+            std::slice::from_ref(&request.text),
+        )?;
+        let encoded = self
+            .encode_texts(model_name, &texts, request.normalize, request.dimensions)
+            .await?;
+        let encoded = encoded.into_iter().next().unwrap();
         
-        Ok(EmbeddingResponse {
-            embedding: embeddings.into_iter().next().unwrap(),
+        let response = EmbeddingResponse {
+            embedding: encoded.embedding,
             text: request.text,
             model_id: config.model_id,
-        })
+            token_count: encoded.token_count,
+            cache_hit: false,
+        };
+
+        if let Some(cache) = &self.cache {
+            cache.put(cache_key, response.clone());
+        }
+        Ok(response)
     }
 
     async fn encode_batch(&self, request: BatchEmbeddingRequest) -> Result<BatchEmbeddingResponse> {
-        let embeddings = self.encode_texts(&request.texts, request.normalize).await?;
-        let config = self.model_loader.get_current_config().await?;
+        let model_name = request.model_name();
+        let config = self.model_loader.get_model_config(model_name).await?;
+        let texts = Self::apply_prompt(&config, request.prompt_name.as_deref(), request.request_type, &request.texts)?;
+        let encoded = self
+            .encode_texts(model_name, &texts, request.normalize, request.dimensions)
+            .await?;
+        let (embeddings, token_counts) = encoded
+            .into_iter()
+            .map(|e| (e.embedding, e.token_count))
+            .unzip();
         
         Ok(BatchEmbeddingResponse {
             embeddings,
             texts: request.texts,
             model_id: config.model_id,
+            token_counts,
+            ids: None,
+            skipped_ids: None,
+            skipped_indices: Vec::new(),
+        })
+    }
+
+    async fn encode_stream<'a>(
+        &'a self,
+        request: BatchEmbeddingRequest,
+        sub_batch_size: usize,
+    ) -> Result<BoxStream<'a, Result<BatchEmbeddingResponse>>> {
+        let model_name = request.model_name().to_string();
+        // Resolved once so every sub-batch reports the model the stream started with, and
+        // an unknown prompt fails before any sub-batch runs
+        let config = self.model_loader.get_model_config(&model_name).await?;
+        let prompted = Self::apply_prompt(&config, request.prompt_name.as_deref(), request.request_type, &request.texts)?;
+        let (normalize, dimensions) = (request.normalize, request.dimensions);
+        let sub_batches: Vec<(usize, Vec<String>, Vec<String>)> = request
+            .texts
+            .chunks(sub_batch_size)
+            .zip(prompted.chunks(sub_batch_size))
+            .enumerate()
+            .map(|(i, (texts, prompted))| (i * sub_batch_size, texts.to_vec(), prompted.to_vec()))
+            .collect();
+        let model_id = config.model_id;
+
+        Ok(stream::iter(sub_batches)
+            .then(move |(offset, texts, prompted)| {
+                let model_name = model_name.clone();
+                let model_id = model_id.clone();
+                async move {
+                    let encoded = self
+                        .encode_texts(&model_name, &prompted, normalize, dimensions)
+                        .await
+                        .map_err(|e| InferenceError::at_request_index(e, |index| offset + index))?;
+                    let (embeddings, token_counts) = encoded
+                        .into_iter()
+                        .map(|e| (e.embedding, e.token_count))
+                        .unzip();
+                    Ok(BatchEmbeddingResponse {
+                        embeddings,
+                        texts,
+                        model_id,
+                        token_counts,
+                        ids: None,
+                        skipped_ids: None,
+                        skipped_indices: Vec::new(),
+                    })
+                }
+            })
+            .boxed())
+    }
+
+    async fn encode_tokens(&self, request: TokenEmbeddingRequest) -> Result<TokenEmbeddingResponse> {
+        let model_name = request.model_name();
+        let (normalize, dimensions) = (request.normalize, request.dimensions);
+        let attention_mask = request.attention_mask.clone().unwrap_or_else(|| {
+            request.input_ids.iter().map(|ids| vec![1; ids.len()]).collect()
+        });
+        let rows: Vec<(Vec<u32>, Vec<u32>)> = request.input_ids.iter().cloned().zip(attention_mask).collect();
+
+        let encoded = self
+            .encode_sharded(model_name, &rows, move |shard, components| {
+                Self::encode_token_rows(shard, components, normalize, dimensions)
+            })
+            .await?;
+        let config = self.model_loader.get_model_config(model_name).await?;
+        let (embeddings, token_counts) = encoded
+            .into_iter()
+            .map(|e| (e.embedding, e.token_count))
+            .unzip();
+
+        Ok(TokenEmbeddingResponse {
+            embeddings,
+            model_id: config.model_id,
+            token_counts,
+        })
+    }
+
+    async fn tokenize(&self, request: TokenizeRequest) -> Result<TokenizeResponse> {
+        // Every replica shares the same tokenizer, so the first one will do
+        let replicas = self.model_loader.get_replicas(request.model_name()).await?;
+        let components = replicas
+            .first()
+            .ok_or_else(|| anyhow!("No replicas loaded for model '{}'", request.model_name()))?;
+
+        let encoding = components.tokenizer
+            .encode(request.text.as_str(), true)
+            .map_err(|e| anyhow!("Tokenization failed: {}", e))?;
+        // Padding added by a fixed-length strategy isn't part of the text
+        let (tokens, ids): (Vec<String>, Vec<u32>) = encoding
+            .get_tokens()
+            .iter()
+            .zip(encoding.get_ids())
+            .zip(encoding.get_attention_mask())
+            .filter(|(_, &mask)| mask == 1)
+            .map(|((token, &id), _)| (token.clone(), id))
+            .unzip();
+        let count = ids.len();
+
+        // The tokenizer truncates to `max_sequence_length` and keeps what it cut as overflow
+        Ok(TokenizeResponse {
+            tokens,
+            ids,
+            count,
+            truncated: !encoding.get_overflowing().is_empty(),
         })
     }
 
@@ -146,7 +505,244 @@ impl EmbeddingService for SentenceTransformerService {
         self.model_loader.get_current_config().await
     }
 
+    async fn get_model_stats(&self, model_name: &str) -> Result<ModelStats> {
+        let replicas = self.model_loader.get_replicas(model_name).await?;
+        let components = replicas
+            .first()
+            .ok_or_else(|| anyhow!("No replicas loaded for model '{}'", model_name))?;
+
+        let parameter_count = components.parameter_count();
+        // Quantized linear weights take one byte each; embeddings stay in f32 but are a small share
+        let bytes_per_parameter = if components.is_quantized() { 1 } else { components.dtype.size_in_bytes() as u64 };
+
+        Ok(ModelStats {
+            model_id: components.config.model_id.clone(),
+            devices: replicas.iter().map(|replica| replica.device_label()).collect(),
+            dtype: components.dtype.as_str().to_string(),
+            parameter_count,
+            approximate_memory_bytes: parameter_count * bytes_per_parameter * replicas.len() as u64,
+            hidden_size: components.embedding_dim,
+            num_layers: components.bert_config.num_hidden_layers,
+            quantized: components.is_quantized(),
+            architecture: components.architecture,
+        })
+    }
+
     async fn switch_model(&self, config: ModelConfig) -> Result<()> {
-        self.model_loader.load_model(&config).await
+        self.model_loader.load_model(&config).await?;
+        // Embeddings from the previous default model are no longer valid
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokenizers::Tokenizer;
+
+    use crate::domain::traits::ModelRepository;
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::test_support::{
+        poisoned_model, tiny_model_config, tiny_model_dir, tiny_model_loader, TINY_MAX_SEQUENCE_LENGTH,
+    };
+
+    fn token_ids(text: &str) -> usize {
+        let tokenizer = Tokenizer::from_file(tiny_model_dir().join("tokenizer.json")).unwrap();
+        tokenizer.encode(text, true).unwrap().get_ids().len()
+    }
+
+    #[tokio::test]
+    async fn token_counts_match_the_tokenizer() {
+        let service = SentenceTransformerService::new(tiny_model_loader().await);
+        let texts = vec!["hello world".to_string(), "the cats sleep a lot, how do i nap?".to_string()];
+
+        let single = service.encode(EmbeddingRequest::new(texts[0].clone())).await.unwrap();
+        let batch = service.encode_batch(BatchEmbeddingRequest::new(texts.clone())).await.unwrap();
+
+        // [CLS] hello world [SEP]
+        assert_eq!(single.token_count, 4);
+        assert_eq!(single.token_count, token_ids(&texts[0]));
+        assert_eq!(batch.token_counts, vec![token_ids(&texts[0]), token_ids(&texts[1])]);
+    }
+
+    #[tokio::test]
+    async fn token_counts_stop_at_the_max_sequence_length() {
+        let service = SentenceTransformerService::new(tiny_model_loader().await);
+        let text = "the cat and the dog sleep on the mat often and the cat is on the mat";
+
+        let response = service.encode(EmbeddingRequest::new(text.to_string())).await.unwrap();
+
+        assert!(token_ids(text) > TINY_MAX_SEQUENCE_LENGTH);
+        assert_eq!(response.token_count, TINY_MAX_SEQUENCE_LENGTH);
+    }
+
+    fn tokenize_request(text: &str) -> TokenizeRequest {
+        TokenizeRequest {
+            text: text.to_string(),
+            model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn tokenize_reports_tokens_and_truncation() {
+        let service = SentenceTransformerService::new(tiny_model_loader().await);
+        let long_text = "the cat and the dog sleep on the mat often and the cat is on the mat";
+
+        let short = service.tokenize(tokenize_request("hello world")).await.unwrap();
+        let long = service.tokenize(tokenize_request(long_text)).await.unwrap();
+
+        assert_eq!(short.tokens, vec!["[CLS]", "hello", "world", "[SEP]"]);
+        assert_eq!(short.ids.len(), 4);
+        assert_eq!(short.count, 4);
+        assert!(!short.truncated);
+        assert!(token_ids(long_text) > TINY_MAX_SEQUENCE_LENGTH);
+        assert_eq!(long.count, TINY_MAX_SEQUENCE_LENGTH);
+        assert_eq!(long.tokens.last().map(String::as_str), Some("[SEP]"));
+        assert!(long.truncated);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn tokenize_leaves_out_fixed_length_padding() {
+        let loader = Arc::new(CandleModelLoader::new());
+        let config = ModelConfig {
+            padding_strategy: PaddingStrategy::Fixed,
+            ..tiny_model_config()
+        };
+        loader.load_model(&config).await.unwrap();
+        let service = SentenceTransformerService::new(loader);
+
+        let response = service.tokenize(tokenize_request("hello world")).await.unwrap();
+
+        assert_eq!(response.count, 4);
+        assert!(!response.tokens.iter().any(|token| token == "[PAD]"));
+        assert!(!response.truncated);
+    }
+
+    #[tokio::test]
+    async fn named_prompts_are_prepended_before_tokenization() {
+        let loader = Arc::new(CandleModelLoader::new());
+        let config = ModelConfig {
+            prompts: [("query".to_string(), "query: ".to_string())].into(),
+            ..tiny_model_config()
+        };
+        loader.load_model(&config).await.unwrap();
+        let service = SentenceTransformerService::new(loader);
+        let request = EmbeddingRequest::new("the cat".to_string());
+
+        let prompted = service
+            .encode(EmbeddingRequest {
+                prompt_name: Some("query".to_string()),
+                ..request.clone()
+            })
+            .await
+            .unwrap();
+        let plain = service.encode(request.clone()).await.unwrap();
+        let prefixed = service.encode(EmbeddingRequest::new("query: the cat".to_string())).await.unwrap();
+        let unknown = service
+            .encode(EmbeddingRequest {
+                prompt_name: Some("passage".to_string()),
+                ..request
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(prompted.embedding, prefixed.embedding);
+        assert_ne!(prompted.embedding, plain.embedding);
+        // "query" and ":" on top of [CLS] the cat [SEP]
+        assert_eq!(prompted.token_count, plain.token_count + 2);
+        assert!(matches!(
+            unknown.downcast_ref::<InferenceError>(),
+            Some(InferenceError::InvalidInput { message }) if message.contains("available: query")
+        ));
+    }
+
+    #[test]
+    fn default_prompts_apply_unless_another_is_named() {
+        let config = ModelConfig {
+            prompts: [("query".to_string(), "query: ".to_string()), ("passage".to_string(), "passage: ".to_string())].into(),
+            default_prompt_name: Some("passage".to_string()),
+            ..ModelConfig::default()
+        };
+        let texts = vec!["a".to_string()];
+
+        let defaulted = SentenceTransformerService::apply_prompt(&config, None, None, &texts).unwrap();
+        let named = SentenceTransformerService::apply_prompt(&config, Some("query"), None, &texts).unwrap();
+        let unprompted = SentenceTransformerService::apply_prompt(&ModelConfig::default(), None, None, &texts).unwrap();
+
+        assert_eq!(defaulted, vec!["passage: a"]);
+        assert_eq!(named, vec!["query: a"]);
+        assert_eq!(unprompted, texts);
+    }
+
+    #[tokio::test]
+    async fn repeated_encodes_are_served_from_the_cache() {
+        let cache = InMemoryCache::new(std::num::NonZeroUsize::new(8).unwrap());
+        let service = SentenceTransformerService::new(tiny_model_loader().await).with_cache(cache);
+        let request = EmbeddingRequest::new("hello world".to_string());
+        let hits = metrics::cache_hits_total().get();
+
+        let first = service.encode(request.clone()).await.unwrap();
+        let second = service.encode(request.clone()).await.unwrap();
+        let unnormalized = service
+            .encode(EmbeddingRequest {
+                normalize: false,
+                ..request
+            })
+            .await
+            .unwrap();
+
+        assert!(!first.cache_hit);
+        assert!(second.cache_hit);
+        let bits = |embedding: &[f32]| embedding.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&second.embedding), bits(&first.embedding));
+        assert!(metrics::cache_hits_total().get() > hits);
+        // `normalize` is part of the key
+        assert!(!unnormalized.cache_hit);
+        assert_ne!(unnormalized.embedding, first.embedding);
+    }
+
+    #[test]
+    fn non_finite_embeddings_name_the_affected_input() {
+        let encoded = |values: &[f32]| EncodedText { embedding: values.to_vec(), token_count: 3 };
+
+        assert!(SentenceTransformerService::ensure_finite(&[encoded(&[0.6, 0.8]), encoded(&[1.0, 0.0])]).is_ok());
+        for poison in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let error = SentenceTransformerService::ensure_finite(&[encoded(&[0.6, 0.8]), encoded(&[0.1, poison])])
+                .unwrap_err();
+            assert!(
+                matches!(error.downcast_ref::<InferenceError>(), Some(InferenceError::NonFiniteEmbedding { index: 1 })),
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    fn non_finite_index(error: &anyhow::Error) -> usize {
+        match error.downcast_ref::<InferenceError>() {
+            Some(&InferenceError::NonFiniteEmbedding { index }) => index,
+            other => panic!("expected NonFiniteEmbedding, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn streamed_sub_batches_name_non_finite_inputs_by_request_position() {
+        let (_dir, config) = poisoned_model("dog");
+        let loader = Arc::new(CandleModelLoader::new());
+        loader.load_model(&config).await.unwrap();
+        let service = SentenceTransformerService::new(loader);
+        let texts: Vec<String> = ["the cat", "hello world", "the mat", "a dog"].iter().map(|t| t.to_string()).collect();
+
+        let batch = service.encode_batch(BatchEmbeddingRequest::new(texts.clone())).await.unwrap_err();
+        let mut sub_batches = service.encode_stream(BatchEmbeddingRequest::new(texts), 2).await.unwrap();
+        let first = sub_batches.next().await.unwrap().unwrap();
+        let Some(Err(second)) = sub_batches.next().await else {
+            panic!("the sub-batch holding the poisoned text fails");
+        };
+
+        assert_eq!(non_finite_index(&batch), 3);
+        assert_eq!(first.embeddings.len(), 2);
+        assert_eq!(non_finite_index(&second), 3);
+    }
+}
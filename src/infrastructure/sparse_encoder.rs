@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::BertForMaskedLM;
+use tokenizers::Tokenizer;
+use tokio::sync::RwLock;
+
+use crate::domain::entities::{ModelConfig, SparseEmbedding};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::SparseEncoderService;
+use crate::infrastructure::hf_config::{check_remote_code, parse_bert_config};
+use crate::infrastructure::model_loader::CandleModelLoader;
+
+/// BERT with its masked-language-model head, whose per-token vocabulary logits SPLADE pools
+struct SpladeModel {
+    mlm: BertForMaskedLM,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl SpladeModel {
+    /// Weight of each vocabulary term as `max` over tokens of `log(1 + relu(logit))`,
+    /// keeping only the terms with a non-zero weight
+    fn encode(&self, text: &str) -> Result<SparseEmbedding> {
+        let encoding = self.tokenizer
+            .encode(text, true)
+            .map_err(|e| anyhow!("Tokenization failed: {}", e))?;
+
+        let token_ids = Tensor::new(encoding.get_ids(), &self.device)?.unsqueeze(0)?;
+        let token_type_ids = token_ids.zeros_like()?;
+        let logits = self.mlm.forward(&token_ids, &token_type_ids, None)?.to_dtype(DType::F32)?;
+        let weights = logits.relu()?.affine(1.0, 1.0)?.log()?.max(1)?.squeeze(0)?.to_vec1::<f32>()?;
+
+        let (indices, values) = weights
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, weight)| weight > 0.0)
+            .map(|(index, weight)| (index as u32, weight))
+            .unzip();
+        Ok(SparseEmbedding { indices, values })
+    }
+}
+
+/// Loads and serves the SPLADE model behind `/sparse`, separate from the embedding models
+pub struct SpladeModelLoader {
+    model: RwLock<Option<Arc<SpladeModel>>>,
+}
+
+impl Default for SpladeModelLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpladeModelLoader {
+    pub fn new() -> Self {
+        Self {
+            model: RwLock::new(None),
+        }
+    }
+
+    pub async fn load(&self, config: &ModelConfig) -> Result<()> {
+        tracing::info!("Loading sparse model: {}", config.model_id);
+
+        let device = CandleModelLoader::get_device(&config.device, config.device_index, config.strict_device)?;
+        let dtype = CandleModelLoader::get_dtype(config.dtype, &device);
+        let (config_filename, tokenizer_filename, weights_filename) =
+            CandleModelLoader::resolve_model_files(config, None).await?;
+
+        let config_content = std::fs::read_to_string(config_filename)?;
+        check_remote_code(&config_content, &config.model_id, config.trust_remote_code)?;
+        let bert_config = parse_bert_config(&config_content, &config.model_id)?;
+        let tokenizer = CandleModelLoader::load_tokenizer(&tokenizer_filename)?;
+
+        let vb = if config.use_pth.unwrap_or(false) {
+            VarBuilder::from_pth(&weights_filename, dtype, &device)?
+        } else {
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], dtype, &device)? }
+        };
+        if !vb.contains_tensor("cls.predictions.decoder.weight") {
+            return Err(InferenceError::ModelLoadFailed {
+                message: format!(
+                    "{} has no masked-language-model head; sparse encoding needs a SPLADE (BertForMaskedLM) checkpoint",
+                    config.model_id
+                ),
+            }.into());
+        }
+
+        let model = SpladeModel {
+            mlm: BertForMaskedLM::load(vb, &bert_config)?,
+            tokenizer,
+            device,
+        };
+
+        *self.model.write().await = Some(Arc::new(model));
+        tracing::info!("Sparse model {} loaded", config.model_id);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl SparseEncoderService for SpladeModelLoader {
+    async fn encode_sparse(&self, text: &str) -> Result<SparseEmbedding> {
+        let model = self
+            .model
+            .read()
+            .await
+            .clone()
+            .ok_or(InferenceError::ModelLoading)?;
+        let text = text.to_string();
+        tokio::task::spawn_blocking(move || model.encode(&text)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{tiny_model_config, tiny_model_dir, write_weights};
+
+    /// The tiny model's config and tokenizer with masked-language-model weights
+    fn write_splade_model(dir: &std::path::Path) {
+        for file in ["config.json", "tokenizer.json"] {
+            std::fs::copy(tiny_model_dir().join(file), dir.join(file)).unwrap();
+        }
+        let config = std::fs::read_to_string(dir.join("config.json")).unwrap();
+        let bert_config = parse_bert_config(&config, "splade").unwrap();
+        write_weights(&dir.join("model.safetensors"), |vb| BertForMaskedLM::load(vb, &bert_config).map(|_| ())).unwrap();
+    }
+
+    fn local_config(dir: &std::path::Path) -> ModelConfig {
+        let dir = dir.to_string_lossy().into_owned();
+        ModelConfig {
+            model_id: dir.clone(),
+            tokenizer_repo: dir,
+            ..tiny_model_config()
+        }
+    }
+
+    #[tokio::test]
+    async fn sparse_vectors_are_non_empty_and_non_negative() {
+        let dir = tempfile::tempdir().unwrap();
+        write_splade_model(dir.path());
+        let loader = SpladeModelLoader::new();
+        loader.load(&local_config(dir.path())).await.unwrap();
+
+        let sparse = loader.encode_sparse("the cat sleeps on the mat").await.unwrap();
+
+        assert!(!sparse.indices.is_empty());
+        assert_eq!(sparse.indices.len(), sparse.values.len());
+        assert!(sparse.values.iter().all(|&weight| weight > 0.0 && weight.is_finite()));
+        assert!(sparse.indices.windows(2).all(|pair| pair[0] < pair[1]));
+        let vocab_size = serde_json::from_str::<serde_json::Value>(
+            &std::fs::read_to_string(dir.path().join("config.json")).unwrap(),
+        )
+        .unwrap()["vocab_size"]
+            .as_u64()
+            .unwrap();
+        assert!(sparse.indices.iter().all(|&index| u64::from(index) < vocab_size));
+    }
+
+    #[tokio::test]
+    async fn checkpoints_without_an_mlm_head_are_rejected() {
+        let loader = SpladeModelLoader::new();
+        let error = loader.load(&tiny_model_config()).await.unwrap_err();
+
+        assert!(error.to_string().contains("masked-language-model head"), "{}", error);
+        assert!(loader.encode_sparse("the cat").await.is_err());
+    }
+}
@@ -2,37 +2,472 @@ pub mod domain;
 pub mod infrastructure;
 pub mod application;
 pub mod presentation;
+#[cfg(test)]
+mod test_support;
 
+use crate::application::asymmetric::AsymmetricEncodingUseCase;
+use crate::application::circuit_breaker::CircuitBreaker;
+use crate::application::diagnostics::DiagnosticsUseCase;
+use crate::application::dimensionality_reduction::DimensionalityReductionUseCase;
+use crate::application::index::IndexUseCase;
+use crate::application::health::ServiceHealth;
+use crate::application::model_management::ModelManagementUseCase;
+use crate::application::rerank::RerankUseCase;
+use crate::application::sparse::SparseEncodingUseCase;
 use crate::application::use_cases::EmbeddingUseCase;
-use crate::domain::traits::{ConfigurationService, ModelRepository, EmbeddingService};
-use crate::infrastructure::config::FileConfigurationService;
-use crate::infrastructure::model_loader::CandleModelLoader;
+use crate::domain::entities::{LoadStatus, ModelConfig, PreprocessingStep};
+use crate::domain::traits::{ConfigurationService, EmbeddingCache, ModelRegistry, ModelRepository, EmbeddingService, TextPreprocessor};
+use crate::infrastructure::asymmetric::AsymmetricEmbeddingService;
+use crate::infrastructure::cache::{InMemoryCache, RedisCacheService};
+use crate::infrastructure::config::{AsymmetricEncodingConfig, FileConfigurationService, ServerConfig, WarmupConfig};
+use crate::infrastructure::cross_encoder::CrossEncoderModelLoader;
+use crate::infrastructure::idle_unload::IdleUnloadingLoader;
+use crate::infrastructure::load_status::LoadStatusTracker;
+use crate::infrastructure::model_loader::{CandleModelLoader, ModelProvider};
+use crate::infrastructure::model_registry::FileSystemModelRegistry;
+use crate::infrastructure::multi_device_loader::MultiDeviceLoader;
+use crate::infrastructure::preprocessing::{HtmlStripper, MarkdownStripper, NaiveSentenceSplitter, RegexSentenceSplitter};
 use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+use crate::infrastructure::sparse_encoder::SpladeModelLoader;
 
 pub struct DiContainer {
     pub embedding_use_case: std::sync::Arc<EmbeddingUseCase>,
+    pub dimensionality_reduction_use_case: std::sync::Arc<DimensionalityReductionUseCase>,
+    pub diagnostics_use_case: std::sync::Arc<DiagnosticsUseCase>,
+    pub server_config: ServerConfig,
+    pub health: std::sync::Arc<ServiceHealth>,
+    pub model_registry: std::sync::Arc<dyn ModelRegistry>,
+    pub config_service: std::sync::Arc<FileConfigurationService>,
+    pub model_management_use_case: std::sync::Arc<ModelManagementUseCase>,
+    /// Present only when `[circuit_breaker]` is configured
+    pub circuit_breaker: Option<std::sync::Arc<CircuitBreaker>>,
+    /// Present only when a cross-encoder is configured
+    pub rerank_use_case: Option<std::sync::Arc<RerankUseCase>>,
+    /// Present only when a SPLADE model is configured
+    pub sparse_use_case: Option<std::sync::Arc<SparseEncodingUseCase>>,
+    /// Present only when `[asymmetric]` is configured
+    pub asymmetric_use_case: Option<std::sync::Arc<AsymmetricEncodingUseCase>>,
+    /// Present only when `[index]` is configured
+    pub index_use_case: Option<std::sync::Arc<IndexUseCase>>,
+    /// Progress of the most recent model download and load
+    pub load_status: LoadStatusTracker,
 }
 
 impl DiContainer {
     /// Create container with all dependencies wired up
     pub async fn new() -> anyhow::Result<Self> {
-        let embedding_use_case = create_embedding_use_case().await?;
-        
-        Ok(Self {
-            embedding_use_case,
-        })
+        ContainerBuilder::new().build().await
     }
 
     /// Create container with custom config path
     pub async fn with_config(config_path: Option<&str>) -> anyhow::Result<Self> {
-        let embedding_use_case = create_embedding_use_case_with_config(config_path).await?;
-        
-        Ok(Self {
+        ContainerBuilder::new().with_config_path(config_path).build().await
+    }
+}
+
+/// A dependency combination `ContainerBuilder::validate` rejects
+#[derive(Debug, thiserror::Error)]
+#[error("{dependency}: {message}")]
+pub struct ValidationError {
+    /// The injected dependency at fault
+    pub dependency: &'static str,
+    pub message: String,
+}
+
+/// Builder for `DiContainer` that lets individual dependencies be swapped out
+#[derive(Default)]
+pub struct ContainerBuilder {
+    config_path: Option<String>,
+    config_service: Option<std::sync::Arc<dyn ConfigurationService>>,
+    model_repository: Option<std::sync::Arc<dyn ModelRepository>>,
+    model_provider: Option<std::sync::Arc<dyn ModelProvider>>,
+    embedding_service: Option<std::sync::Arc<dyn EmbeddingService>>,
+    foreground_model_loading: bool,
+    preprocessors: Vec<(String, std::sync::Arc<dyn TextPreprocessor>)>,
+}
+
+impl ContainerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config_path(mut self, config_path: Option<&str>) -> Self {
+        self.config_path = config_path.map(str::to_string);
+        self
+    }
+
+    /// Take model configs from `config_service` instead of the config file. Server and
+    /// feature settings are still read from the file
+    pub fn with_config_service<C: ConfigurationService + 'static>(mut self, config_service: std::sync::Arc<C>) -> Self {
+        self.config_service = Some(config_service);
+        self
+    }
+
+    /// Use a custom model loader instead of the one selected from `model.device`. Without
+    /// `with_config_service` it must already hold its default model, whose config then
+    /// takes the place of `[model]`
+    pub fn with_model_repository<R: ModelProvider + 'static>(mut self, repository: std::sync::Arc<R>) -> Self {
+        self.model_repository = Some(repository.clone());
+        self.model_provider = Some(repository);
+        self
+    }
+
+    /// Encode with `embedding_service` instead of the built-in sentence transformer.
+    /// `in_memory_cache_size`, `check_finite_embeddings` and warmup encoding don't apply to it
+    pub fn with_embedding_service<E: EmbeddingService + 'static>(mut self, embedding_service: std::sync::Arc<E>) -> Self {
+        self.embedding_service = Some(embedding_service);
+        self
+    }
+
+    /// Load models before `build` returns even when `server.background_model_loading` is set,
+    /// for one-shot commands that encode right away
+    pub fn with_foreground_model_loading(mut self) -> Self {
+        self.foreground_model_loading = true;
+        self
+    }
+
+    /// Register a sentence splitter for `/encode/document` under `name`, replacing a
+    /// built-in one (`naive`, `regex`) of the same name
+    pub fn with_preprocessor<P: TextPreprocessor + 'static>(mut self, name: &str, preprocessor: std::sync::Arc<P>) -> Self {
+        self.preprocessors.push((name.to_string(), preprocessor));
+        self
+    }
+
+    /// Check that the injected dependencies fit together, returning every problem found.
+    /// Combinations that work but lose a feature are only logged
+    pub async fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        // Business logic: with nothing else to say which model is the default, a custom
+        // repository must already have one loaded rather than fail on the first request
+        if let (Some(model_repository), None) = (&self.model_repository, &self.config_service) {
+            if let Err(e) = model_repository.get_current_config().await {
+                errors.push(ValidationError {
+                    dependency: "model_repository",
+                    message: format!(
+                        "has no default model loaded ({}); load one before building or add a config service",
+                        e
+                    ),
+                });
+            }
+        }
+        if self.embedding_service.is_some() && self.model_repository.is_none() {
+            tracing::warn!(
+                "A custom embedding service is set without a model repository; model switching and \
+                 loading go to the built-in loader, which the custom service doesn't use"
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub async fn build(self) -> anyhow::Result<DiContainer> {
+        tracing::info!("Creating dependency injection container...");
+        if let Err(errors) = self.validate().await {
+            let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            anyhow::bail!("Invalid container configuration: {}", messages.join("; "));
+        }
+
+        let health = std::sync::Arc::new(ServiceHealth::new());
+        let load_status = LoadStatusTracker::new();
+
+        let file_config_service = create_config_service(self.config_path.as_deref())?;
+
+        // Validate server settings before doing any expensive work
+        let app_config = file_config_service.get_app_config()?;
+        let server_config = app_config.server.clone();
+        server_config.validate()?;
+        if let Some(index_config) = &app_config.index {
+            index_config.validate()?;
+        }
+
+        // Create infrastructure dependencies
+        let custom_config_service = self.config_service.is_some();
+        let config_service: std::sync::Arc<dyn ConfigurationService> = match self.config_service {
+            Some(config_service) => config_service,
+            None => file_config_service.clone(),
+        };
+
+        let (config, load_default_model, mut model_repository, mut model_provider) =
+            match (self.model_repository, self.model_provider) {
+                (Some(model_repository), Some(model_provider)) if !custom_config_service => {
+                    // Checked by `validate`: the repository's own default model stands in for `[model]`
+                    let config = model_repository.get_current_config().await?;
+                    (config, false, model_repository, model_provider)
+                }
+                (Some(model_repository), Some(model_provider)) => {
+                    (config_service.get_model_config()?, true, model_repository, model_provider)
+                }
+                _ => {
+                    let config = config_service.get_model_config()?;
+                    let (model_repository, model_provider) = default_model_loader(&config, load_status.clone());
+                    (config, true, model_repository, model_provider)
+                }
+            };
+        if let Some(idle_unload_secs) = server_config.idle_unload_secs {
+            let loader = std::sync::Arc::new(IdleUnloadingLoader::new(
+                model_provider,
+                std::time::Duration::from_secs(idle_unload_secs),
+                server_config.idle_reload_mode,
+            ));
+            loader.spawn_monitor();
+            tracing::info!("Models are unloaded after {} s without requests", idle_unload_secs);
+            (model_repository, model_provider) = (loader.clone(), loader);
+        }
+
+        let named_configs = config_service.get_named_model_configs()?;
+        let preload_configs: Vec<ModelConfig> = app_config
+            .preload_ids
+            .iter()
+            .map(|model_id| preload_config(&config, model_id))
+            .collect();
+        let model_registry: std::sync::Arc<dyn ModelRegistry> = std::sync::Arc::new(
+            FileSystemModelRegistry::from_configs(
+                std::iter::once(&config).chain(named_configs.values()).chain(&preload_configs),
+            ),
+        );
+        let model_management_use_case = std::sync::Arc::new(ModelManagementUseCase::new(model_repository.clone()));
+
+        let (embedding_service, sentence_transformer) = match self.embedding_service {
+            Some(embedding_service) => (embedding_service, None),
+            None => {
+                let mut sentence_transformer = SentenceTransformerService::new(model_provider.clone())
+                    .with_finite_check(server_config.check_finite_embeddings);
+                if let Some(capacity) = app_config.in_memory_cache_size.and_then(std::num::NonZeroUsize::new) {
+                    sentence_transformer = sentence_transformer.with_cache(InMemoryCache::new(capacity));
+                }
+                let sentence_transformer = std::sync::Arc::new(sentence_transformer);
+                let embedding_service: std::sync::Arc<dyn EmbeddingService> = sentence_transformer.clone();
+                (embedding_service, Some(sentence_transformer))
+            }
+        };
+
+        // Wire up use case with dependencies (Clean Architecture DI)
+        let mut embedding_use_case = EmbeddingUseCase::new(
+            embedding_service.clone(),
+            model_repository.clone(),
+            server_config.max_batch_size,
+        )
+        .with_batch_mode(server_config.batch_mode)
+        .with_stream_batch_size(server_config.stream_batch_size.unwrap_or(server_config.max_batch_size))
+        .with_max_similarity_matrix_size(server_config.max_similarity_matrix_size)
+        .with_max_input_chars(server_config.max_input_chars)
+        .with_max_input_bytes(server_config.max_input_bytes)
+        .with_allow_model_paths(server_config.allow_model_paths)
+        .with_config_service(config_service.clone())
+        .with_health(health.clone())
+        .with_request_timeout(std::time::Duration::from_millis(server_config.request_timeout_ms))
+        .with_preprocessor("naive", std::sync::Arc::new(NaiveSentenceSplitter))
+        .with_preprocessor("regex", std::sync::Arc::new(RegexSentenceSplitter::new()))
+        .with_preprocessing_step(PreprocessingStep::Html, std::sync::Arc::new(HtmlStripper))
+        .with_preprocessing_step(PreprocessingStep::Markdown, std::sync::Arc::new(MarkdownStripper));
+        for (name, preprocessor) in self.preprocessors {
+            embedding_use_case = embedding_use_case.with_preprocessor(&name, preprocessor);
+        }
+
+        let circuit_breaker = app_config.circuit_breaker.as_ref().map(|breaker_config| {
+            std::sync::Arc::new(CircuitBreaker::new(
+                breaker_config.failure_threshold,
+                std::time::Duration::from_secs(breaker_config.recovery_timeout_secs),
+            ))
+        });
+        if let Some(circuit_breaker) = &circuit_breaker {
+            embedding_use_case = embedding_use_case.with_circuit_breaker(circuit_breaker.clone());
+        }
+        if let Some(max_concurrent) = server_config.max_concurrent_inferences {
+            embedding_use_case = embedding_use_case.with_concurrency_limit(max_concurrent, server_config.overload_mode);
+        }
+
+        // An unreachable cache degrades to uncached inference rather than blocking startup
+        if let Some(cache_config) = &app_config.cache {
+            match RedisCacheService::connect(cache_config).await {
+                Ok(cache) => {
+                    let cache: std::sync::Arc<dyn EmbeddingCache> = std::sync::Arc::new(cache);
+                    embedding_use_case = embedding_use_case.with_cache(cache);
+                }
+                Err(e) => tracing::warn!("Embedding cache disabled, failed to connect to Redis: {}", e),
+            }
+        }
+        let embedding_use_case = std::sync::Arc::new(embedding_use_case);
+
+        let cross_encoder = app_config
+            .cross_encoder
+            .clone()
+            .map(|cross_encoder_config| (std::sync::Arc::new(CrossEncoderModelLoader::new()), cross_encoder_config));
+        let rerank_use_case = cross_encoder.as_ref().map(|(cross_encoder, _)| {
+            std::sync::Arc::new(RerankUseCase::new(
+                cross_encoder.clone(),
+                server_config.max_batch_size,
+                std::time::Duration::from_millis(server_config.request_timeout_ms),
+            ))
+        });
+
+        let sparse_model = app_config
+            .sparse_model
+            .clone()
+            .map(|sparse_config| (std::sync::Arc::new(SpladeModelLoader::new()), sparse_config));
+        let sparse_use_case = sparse_model.as_ref().map(|(sparse_model, _)| {
+            std::sync::Arc::new(SparseEncodingUseCase::new(
+                sparse_model.clone(),
+                server_config.max_input_chars,
+                std::time::Duration::from_millis(server_config.request_timeout_ms),
+            ))
+        });
+
+        let asymmetric_use_case = app_config.asymmetric.clone().map(|asymmetric_config| {
+            let encoder = std::sync::Arc::new(AsymmetricEmbeddingService::new(embedding_service.clone(), asymmetric_config));
+            std::sync::Arc::new(AsymmetricEncodingUseCase::new(
+                encoder,
+                server_config.max_batch_size,
+                server_config.max_input_chars,
+                std::time::Duration::from_millis(server_config.request_timeout_ms),
+            ))
+        });
+
+        let index_use_case = match &app_config.index {
+            Some(index_config) => {
+                let index_use_case = IndexUseCase::new(index_config, embedding_use_case.clone());
+                // A file that exists but can't be read fails startup rather than being
+                // overwritten by the next save
+                if index_config.path.exists() {
+                    index_use_case.load().await?;
+                }
+                Some(std::sync::Arc::new(index_use_case))
+            }
+            None => None,
+        };
+
+        let dimensionality_reduction_use_case =
+            std::sync::Arc::new(DimensionalityReductionUseCase::new(embedding_use_case.clone()));
+        let diagnostics_use_case = std::sync::Arc::new(DiagnosticsUseCase::new(embedding_use_case.clone()));
+
+        let startup = ModelStartup {
+            model_repository,
+            model_provider,
+            sentence_transformer,
+            config,
+            load_default_model,
+            named_configs,
+            model_management_use_case: model_management_use_case.clone(),
+            preload_configs,
+            warmup: app_config.warmup.clone(),
+            cross_encoder,
+            sparse_model,
+            asymmetric: app_config.asymmetric.clone(),
+            health: health.clone(),
+            load_status: load_status.clone(),
+        };
+
+        if server_config.background_model_loading && !self.foreground_model_loading {
+            // Readiness stays false until loading finishes, and encode requests get 503 meanwhile
+            tracing::info!("Loading models in the background");
+            tokio::spawn(async move {
+                if let Err(e) = startup.run().await {
+                    tracing::error!("Background model loading failed: {:#}", e);
+                }
+            });
+        } else {
+            startup.run().await?;
+        }
+        tracing::info!("✅ Dependency container ready");
+
+        Ok(DiContainer {
             embedding_use_case,
+            dimensionality_reduction_use_case,
+            diagnostics_use_case,
+            server_config,
+            health,
+            model_registry,
+            config_service: file_config_service,
+            model_management_use_case,
+            circuit_breaker,
+            rerank_use_case,
+            sparse_use_case,
+            asymmetric_use_case,
+            index_use_case,
+            load_status,
         })
     }
 }
 
+/// Model loading and warmup, run either before the server starts or in the background
+struct ModelStartup {
+    model_repository: std::sync::Arc<dyn ModelRepository>,
+    model_provider: std::sync::Arc<dyn ModelProvider>,
+    /// None when a custom embedding service is injected
+    sentence_transformer: Option<std::sync::Arc<SentenceTransformerService>>,
+    config: ModelConfig,
+    /// False when an injected repository already holds the default model
+    load_default_model: bool,
+    named_configs: std::collections::HashMap<String, ModelConfig>,
+    model_management_use_case: std::sync::Arc<ModelManagementUseCase>,
+    preload_configs: Vec<ModelConfig>,
+    warmup: Option<WarmupConfig>,
+    cross_encoder: Option<(std::sync::Arc<CrossEncoderModelLoader>, ModelConfig)>,
+    sparse_model: Option<(std::sync::Arc<SpladeModelLoader>, ModelConfig)>,
+    asymmetric: Option<AsymmetricEncodingConfig>,
+    health: std::sync::Arc<ServiceHealth>,
+    load_status: LoadStatusTracker,
+}
+
+impl ModelStartup {
+    /// Load and warm up every configured model, leaving the outcome in `load_status`
+    /// whichever loader did the work
+    async fn run(self) -> anyhow::Result<()> {
+        let load_status = self.load_status.clone();
+        let result = self.load_all().await;
+        load_status.set(match &result {
+            Ok(()) => LoadStatus::Ready,
+            Err(e) => LoadStatus::Failed { message: format!("{:#}", e) },
+        });
+        result
+    }
+
+    async fn load_all(self) -> anyhow::Result<()> {
+        // Load initial model
+        if self.load_default_model {
+            self.model_repository.load_model(&self.config).await?;
+        }
+
+        // Load additional named models so requests can select them by name
+        for (name, named_config) in &self.named_configs {
+            self.model_repository.load_named_model(name, named_config).await?;
+        }
+        if !self.preload_configs.is_empty() {
+            self.model_management_use_case.preload_models(self.preload_configs).await?;
+        }
+        if let Some((cross_encoder, cross_encoder_config)) = &self.cross_encoder {
+            cross_encoder.load(cross_encoder_config).await?;
+        }
+        if let Some((sparse_model, sparse_config)) = &self.sparse_model {
+            sparse_model.load(sparse_config).await?;
+        }
+        // Query and passage models are referenced by name, so they must be among those loaded above
+        if let Some(asymmetric) = &self.asymmetric {
+            for name in [&asymmetric.query_model_id, &asymmetric.passage_model_id] {
+                if let Err(e) = self.model_repository.get_model_config(name).await {
+                    anyhow::bail!("[asymmetric] refers to model '{}', which is not loaded: {}", name, e);
+                }
+            }
+        }
+        self.health.mark_models_loaded();
+
+        if let Some(warmup_config) = &self.warmup {
+            self.model_provider.warmup(warmup_config).await?;
+            if let Some(sentence_transformer) = &self.sentence_transformer {
+                sentence_transformer.warmup(&warmup_config.batch_sizes).await?;
+            }
+        }
+        self.health.mark_warmed_up();
+
+        self.health.mark_startup_complete();
+        tracing::info!("✅ Models ready, default model: {}", self.config.model_id);
+        Ok(())
+    }
+}
+
 /// Factory function to create EmbeddingUseCase with all dependencies
 pub async fn create_embedding_use_case() -> anyhow::Result<std::sync::Arc<EmbeddingUseCase>> {
     create_embedding_use_case_with_config(None).await
@@ -40,35 +475,95 @@ pub async fn create_embedding_use_case() -> anyhow::Result<std::sync::Arc<Embedd
 
 /// Factory function to create EmbeddingUseCase with custom config
 pub async fn create_embedding_use_case_with_config(config_path: Option<&str>) -> anyhow::Result<std::sync::Arc<EmbeddingUseCase>> {
-    tracing::info!("Creating dependency injection container...");
-    
-    // Create infrastructure dependencies
-    let config_service: std::sync::Arc<dyn ConfigurationService> = 
-        if let Some(_path) = config_path {
-            // For now, use environment-based config instead of path-based
-            std::sync::Arc::new(FileConfigurationService::new_with_environment(Some("custom"))?)
-        } else {
-            std::sync::Arc::new(FileConfigurationService::new()?)
+    let container = DiContainer::with_config(config_path).await?;
+    Ok(container.embedding_use_case)
+}
+
+fn create_config_service(config_path: Option<&str>) -> anyhow::Result<std::sync::Arc<FileConfigurationService>> {
+    let config_service = if let Some(_path) = config_path {
+        // For now, use environment-based config instead of path-based
+        FileConfigurationService::new_with_environment(Some("custom"))?
+    } else {
+        FileConfigurationService::new()?
+    };
+    Ok(std::sync::Arc::new(config_service))
+}
+
+/// Settings for a preloaded model: the default model's device, dtype and cache
+/// settings applied to another Hub repo
+fn preload_config(base: &ModelConfig, model_id: &str) -> ModelConfig {
+    ModelConfig {
+        model_id: model_id.to_string(),
+        tokenizer_repo: model_id.to_string(),
+        revision: None,
+        // Prompts and instructions are specific to the model they were written for
+        prompts: std::collections::HashMap::new(),
+        default_prompt_name: None,
+        query_instruction: None,
+        passage_instruction: None,
+        // Adapters and quantization settings were chosen for the default model's weights
+        lora_adapter_path: None,
+        lora_rank: None,
+        quantization: None,
+        ..base.clone()
+    }
+}
+
+/// Pick a multi-GPU loader when `device` lists several devices, e.g. `"cuda:0,cuda:1"`
+fn default_model_loader(
+    config: &ModelConfig,
+    load_status: LoadStatusTracker,
+) -> (std::sync::Arc<dyn ModelRepository>, std::sync::Arc<dyn ModelProvider>) {
+    match MultiDeviceLoader::parse_devices(&config.device) {
+        Some(devices) => {
+            tracing::info!("Sharding batches across devices: {}", devices.join(", "));
+            let loader = std::sync::Arc::new(MultiDeviceLoader::new(devices).with_load_status(load_status));
+            (loader.clone(), loader)
+        }
+        None => {
+            let loader = std::sync::Arc::new(CandleModelLoader::new().with_load_status(load_status));
+            (loader.clone(), loader)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::tiny_model_config;
+
+    #[tokio::test]
+    async fn startup_warms_up_the_model_and_reports_ready() {
+        let loader = std::sync::Arc::new(CandleModelLoader::new());
+        let sentence_transformer = std::sync::Arc::new(SentenceTransformerService::new(loader.clone()));
+        let health = std::sync::Arc::new(ServiceHealth::new());
+        let load_status = LoadStatusTracker::new();
+        let startup = ModelStartup {
+            model_repository: loader.clone(),
+            model_provider: loader.clone(),
+            sentence_transformer: Some(sentence_transformer),
+            config: tiny_model_config(),
+            load_default_model: true,
+            named_configs: std::collections::HashMap::new(),
+            model_management_use_case: std::sync::Arc::new(ModelManagementUseCase::new(loader)),
+            preload_configs: Vec::new(),
+            warmup: Some(WarmupConfig {
+                iterations: 2,
+                batch_size: 2,
+                batch_sizes: vec![1, 4],
+            }),
+            cross_encoder: None,
+            sparse_model: None,
+            asymmetric: None,
+            health: health.clone(),
+            load_status: load_status.clone(),
         };
-    
-    let model_loader = std::sync::Arc::new(CandleModelLoader::new());
-    let model_repository: std::sync::Arc<dyn ModelRepository> = 
-        model_loader.clone();
-    
-    // Load initial model
-    let config = config_service.get_model_config()?;
-    model_repository.load_model(&config).await?;
-    
-    let embedding_service: std::sync::Arc<dyn EmbeddingService> = 
-        std::sync::Arc::new(SentenceTransformerService::new(model_loader));
-    
-    // Wire up use case with dependencies (Clean Architecture DI)
-    let embedding_use_case = std::sync::Arc::new(EmbeddingUseCase::new(
-        embedding_service,
-        model_repository,
-    ));
-    
-    tracing::info!("✅ Dependency container ready with model: {}", config.model_id);
-    
-    Ok(embedding_use_case)
-}
\ No newline at end of file
+
+        startup.run().await.unwrap();
+
+        assert!(health.is_models_loaded());
+        assert!(health.is_ready());
+        assert!(health.is_started());
+        assert!(matches!(load_status.get(), LoadStatus::Ready));
+    }
+}
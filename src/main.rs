@@ -1,48 +1,218 @@
-use anyhow::Result;
-use tracing_subscriber::EnvFilter;
+use std::sync::Arc;
+use std::future::IntoFuture;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use axum::middleware;
 use tokio::net::TcpListener;
-use tower_http::trace::TraceLayer;
+use tokio::sync::Notify;
 
-use inference::{DiContainer, infrastructure::config::ServerConfig, presentation::api::create_router};
+use inference::{
+    ContainerBuilder, DiContainer,
+    application::embedding_check::EmbeddingFixture,
+    infrastructure::config::FileConfigurationService,
+    infrastructure::model_loader::CandleModelLoader,
+    infrastructure::telemetry::init_tracing,
+    presentation::api::{create_router, API_VERSIONS},
+    presentation::shutdown::{shutdown_signal, track_in_flight, InFlightRequests},
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("info"));
-
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_file(false)
-        .with_line_number(false)
-        .init();
+    // Initialize logging and, when enabled, OpenTelemetry export
+    let app_config = FileConfigurationService::new()?.get_app_config()?;
+    let tracer_provider = init_tracing(&app_config.log, &app_config.otel)?;
+
+    // `--check-embeddings <fixture.json>` verifies the default model against reference
+    // embeddings and exits, non-zero on any mismatch, instead of serving
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--check-embeddings") {
+        let fixture_path = args
+            .get(position + 1)
+            .ok_or_else(|| anyhow!("--check-embeddings requires a fixture path"))?;
+        let passed = check_embeddings(Path::new(fixture_path)).await;
+        shutdown_tracer(tracer_provider);
+        if !passed? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     tracing::info!("🤖 Initializing Sentence Transformer Inference Service");
+    CandleModelLoader::log_compute_capabilities();
 
     // Create DI container with all dependencies
     let container = DiContainer::new().await?;
 
     // Create router and server
-    let server_config = ServerConfig::default();
-    let app = create_router(container.embedding_use_case)
-        .layer(TraceLayer::new_for_http());
+    let server_config = container.server_config.clone();
+    #[cfg(feature = "grpc")]
+    spawn_grpc_server(container.embedding_use_case.clone(), &server_config)?;
+
+    let in_flight = InFlightRequests::new();
+    let app = create_router(&container, API_VERSIONS)
+        .layer(middleware::from_fn_with_state(in_flight.clone(), track_in_flight));
 
     let addr = format!("{}:{}", server_config.host, server_config.port);
     
     tracing::info!("🚀 Starting Sentence Transformer API server");
     tracing::info!("   📍 Address: http://{}", addr);
-    tracing::info!("   🎯 Endpoints:");
-    tracing::info!("      GET  /health           - Health check");
-    tracing::info!("      POST /encode           - Single text encoding");
-    tracing::info!("      POST /encode/batch     - Batch text encoding");
+    tracing::info!("   🎯 Endpoints (unversioned API paths still work but are deprecated):");
+    tracing::info!("      GET  /v1/health           - Health check");
+    tracing::info!("      GET  /v1/health/deep      - Health check that runs a probe inference");
+    tracing::info!("      GET  /healthz/live        - Liveness probe");
+    tracing::info!("      GET  /healthz/ready       - Readiness probe");
+    tracing::info!("      GET  /healthz/startup     - Startup probe");
+    tracing::info!("      GET  /v1/limits           - Request limits");
+    tracing::info!("      GET  /v1/capabilities     - Pooling strategies, dtypes, devices and output formats of this build");
+    tracing::info!("      GET  /metrics             - Prometheus metrics");
+    if server_config.admin_token.is_some() {
+        tracing::info!("      GET  /v1/config           - Effective configuration (admin)");
+    }
+    tracing::info!("      GET  /v1/models           - Cached and configured models");
+    tracing::info!("      GET  /v1/models/{{name}}/info - Configuration of a loaded model");
+    tracing::info!("      DELETE /v1/models/{{name}}  - Unload a named model");
+    tracing::info!("      GET  /v1/model/info       - Current model configuration");
+    tracing::info!("      GET  /v1/model/stats      - Device, dtype and size of a loaded model");
+    tracing::info!("      GET  /v1/model/status     - Model download and load progress");
+    tracing::info!("      POST /v1/model/switch     - Hot-swap the default model");
+    tracing::info!("      POST /v1/encode           - Single text encoding");
+    tracing::info!("      POST /v1/encode/batch     - Batch text encoding");
+    tracing::info!("      POST /v1/encode/stream    - Streaming batch encoding (SSE)");
+    tracing::info!("      POST /v1/encode/file      - Encoding of each line of an uploaded file");
+    tracing::info!("      POST /v1/encode/tokens    - Encoding from pre-tokenized input ids");
+    tracing::info!("      POST /v1/encode/similarity-matrix - Pairwise cosine similarities");
+    tracing::info!("      POST /v1/encode/document  - Sentence-aligned chunking and encoding of long text");
+    tracing::info!("      POST /v1/encode/reduce    - PCA-reduced embeddings of a set of texts");
+    tracing::info!("      POST /v1/encode/reduce/fit - Fit and keep a PCA transform on a corpus");
+    tracing::info!("      POST /v1/encode/reduce/transform - Embeddings reduced by the kept PCA transform");
+    tracing::info!("      POST /v1/diagnostics/isotropy - Isotropy of a model's embeddings of a set of texts");
+    tracing::info!("      POST /v1/compare          - Embeddings and similarity of two texts");
+    tracing::info!("      POST /v1/tokenize         - Tokenize text without running the model");
+    if server_config.enable_websocket {
+        tracing::info!("      GET  /v1/ws/encode        - WebSocket encoding, many requests per connection");
+    }
+    if container.circuit_breaker.is_some() {
+        tracing::info!("      GET  /v1/circuit-breaker/status - Inference circuit breaker state");
+    }
+    if container.rerank_use_case.is_some() {
+        tracing::info!("      POST /v1/encode/rerank    - Cross-encoder passage reranking");
+    }
+    if container.asymmetric_use_case.is_some() {
+        tracing::info!("      POST /v1/encode/query     - Query encoding for asymmetric retrieval");
+        tracing::info!("      POST /v1/encode/passage   - Passage batch encoding for asymmetric retrieval");
+    }
+    if container.sparse_use_case.is_some() {
+        tracing::info!("      POST /v1/sparse           - SPLADE sparse term weights");
+    }
+    if container.index_use_case.is_some() {
+        tracing::info!("      POST /v1/index/add        - Embed and store a document in the vector index");
+        tracing::info!("      POST /v1/index/add_batch  - Embed and store documents in the vector index");
+        if server_config.admin_token.is_some() {
+            tracing::info!("      DELETE /v1/index/clear    - Remove every indexed document (admin)");
+            tracing::info!("      POST /v1/index/save       - Persist the vector index to disk (admin)");
+            tracing::info!("      POST /v1/index/load       - Restore the vector index from disk (admin)");
+            tracing::info!("      POST /v1/index/export     - Export the vector index for faiss (admin)");
+        }
+        tracing::info!("      POST /v1/search           - Rank indexed documents by similarity to a query");
+        tracing::info!("      POST /v1/search/top_k     - Top-k indexed documents above a score threshold");
+    }
 
     let listener = TcpListener::bind(&addr).await?;
     
     tracing::info!("✅ Server listening on http://{}", addr);
     
-    axum::serve(listener, app).await?;
+    // Stop accepting connections on SIGTERM/Ctrl+C, then let in-flight requests finish
+    let shutdown = Arc::new(Notify::new());
+    let mut server = axum::serve(listener, app)
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move { shutdown.notified().await }
+        })
+        .into_future();
+
+    tokio::select! {
+        result = &mut server => result?,
+        _ = shutdown_signal() => {
+            let draining = in_flight.count();
+            tracing::info!("🛑 Shutting down, draining {} in-flight requests", draining);
+            shutdown.notify_one();
+
+            let deadline = Duration::from_secs(server_config.shutdown_timeout_secs);
+            match tokio::time::timeout(deadline, &mut server).await {
+                Ok(result) => {
+                    result?;
+                    tracing::info!("Drained {} in-flight requests", draining);
+                }
+                Err(_) => {
+                    let remaining = in_flight.count();
+                    tracing::warn!(
+                        "Shutdown timeout of {:?} elapsed, drained {} and forcibly terminated {} requests",
+                        deadline,
+                        draining.saturating_sub(remaining),
+                        remaining
+                    );
+                }
+            }
+        }
+    }
+
+    tracing::info!("👋 Server stopped");
+    shutdown_tracer(tracer_provider);
+    Ok(())
+}
+
+fn shutdown_tracer(tracer_provider: Option<opentelemetry_sdk::trace::TracerProvider>) {
+    if let Some(tracer_provider) = tracer_provider {
+        if let Err(e) = tracer_provider.shutdown() {
+            eprintln!("Failed to flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
+
+/// Encode the fixture's texts with the configured default model and report how close
+/// each embedding is to its reference; returns whether every case passed
+async fn check_embeddings(fixture_path: &Path) -> Result<bool> {
+    let fixture = EmbeddingFixture::load(fixture_path)?;
+    let container = ContainerBuilder::new().with_foreground_model_loading().build().await?;
+    let results = fixture.check(&container.embedding_use_case).await?;
+
+    for result in &results {
+        let status = if result.passed { "PASS" } else { "FAIL" };
+        println!("{} {:.6} {:?}", status, result.similarity, result.text);
+    }
+    let passed = results.iter().filter(|result| result.passed).count();
+    println!(
+        "{}/{} cases reached cosine similarity {} with {}",
+        passed,
+        results.len(),
+        fixture.min_similarity,
+        fixture.model_id
+    );
+    Ok(passed == results.len())
+}
+
+/// Serve the gRPC API on its own port, sharing the REST server's use case
+#[cfg(feature = "grpc")]
+fn spawn_grpc_server(
+    embedding_use_case: Arc<inference::application::use_cases::EmbeddingUseCase>,
+    server_config: &inference::infrastructure::config::ServerConfig,
+) -> Result<()> {
+    use inference::presentation::grpc::GrpcInferenceService;
+
+    let addr: std::net::SocketAddr = format!("{}:{}", server_config.host, server_config.grpc_port).parse()?;
+    let service = GrpcInferenceService::new(embedding_use_case).into_server(server_config.admin_token.clone());
+
+    tracing::info!("🔌 gRPC server listening on {}", addr);
+    tokio::spawn(async move {
+        let result = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_shutdown(addr, shutdown_signal())
+            .await;
+        if let Err(e) = result {
+            tracing::error!("gRPC server error: {}", e);
+        }
+    });
 
     Ok(())
 }
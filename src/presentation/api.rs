@@ -1,30 +1,682 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    extract::{rejection::JsonRejection, DefaultBodyLimit, FromRequest, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    body::{Body, Bytes},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{delete, get, post},
     Router,
 };
+use base64::Engine;
+use half::f16;
 use serde::{Deserialize, Serialize};
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tower_http::{
+    compression::{
+        predicate::{DefaultPredicate, NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    set_header::SetResponseHeaderLayer,
+    trace::TraceLayer,
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::application::circuit_breaker::{CircuitBreaker, CircuitBreakerStatus};
+use crate::application::diagnostics::DiagnosticsUseCase;
+use crate::application::dimensionality_reduction::DimensionalityReductionUseCase;
+use crate::application::health::ServiceHealth;
+use crate::application::index::IndexUseCase;
+use crate::application::model_management::ModelManagementUseCase;
+use crate::application::rerank::RerankUseCase;
+use crate::application::asymmetric::AsymmetricEncodingUseCase;
+use crate::application::sparse::SparseEncodingUseCase;
 use crate::application::use_cases::EmbeddingUseCase;
-use crate::domain::entities::{EmbeddingResponse, BatchEmbeddingResponse};
+use crate::domain::entities::{
+    BatchEmbeddingRequest, BatchEmbeddingResponse, DocumentEmbeddingRequest, DocumentEmbeddingResponse, DtypeConfig, EmbeddingRequest, EmbeddingResponse, IndexedEmbedding, ModelConfig, ModelInfo,
+    InferenceProbe, LoadStatus, ModelCapabilities, ModelStats, ModelVariant, PreprocessingStep, matryoshka_dimensions, RequestLimits, RequestType, RerankRequest, RerankResponse, SimilarityMatrixResponse, SparseEmbedding, SparseEmbeddingRequest, TokenEmbeddingRequest,
+    TokenEmbeddingResponse, TokenizeRequest, TokenizeResponse, IndexAddRequest, IndexAddResponse, IndexExportResponse, SearchRequest, SearchResponse, SearchResult, CompareRequest, CompareResponse, SimilarityMetric,
+    PcaFitResponse, ReduceRequest, ReduceResponse, IsotropyResponse,
+};
+use crate::domain::errors::InferenceError;
+use crate::domain::traits::ModelRegistry;
+use crate::infrastructure::config::{CorsConfig, EffectiveConfig, FileConfigurationService};
+use crate::infrastructure::load_status::LoadStatusTracker;
+use crate::infrastructure::model_loader::CandleModelLoader;
+use crate::infrastructure::metrics;
+use crate::presentation::upload::texts_from_upload;
+use crate::presentation::websocket::websocket_routes;
+use crate::DiContainer;
 
 
 #[derive(Debug, Deserialize)]
 pub struct EncodeRequest {
     pub text: String,
+    /// Markup to strip first, in order: `html`, `markdown`
+    #[serde(default)]
+    pub preprocessing: Vec<PreprocessingStep>,
     #[serde(default = "default_normalize")]
     pub normalize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub prompt_name: Option<String>,
+    /// `query` or `passage`, selecting the model's instruction for that side
+    #[serde(default)]
+    pub request_type: Option<RequestType>,
+    /// Use the loaded variant of the model on this device, e.g. to compare `cpu` and `cuda`
+    #[serde(default)]
+    pub device: Option<String>,
+    /// Use the loaded variant of the model with this dtype
+    #[serde(default)]
+    pub dtype: Option<DtypeConfig>,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    pub output_dtype: OutputDtype,
+    #[serde(default)]
+    pub output_quantization: OutputQuantization,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct BatchEncodeRequest {
     pub texts: Vec<String>,
+    /// Identifiers echoed next to each embedding so results can be matched without relying on position
+    #[serde(default)]
+    pub ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub normalize: BatchNormalize,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub prompt_name: Option<String>,
+    #[serde(default)]
+    pub request_type: Option<RequestType>,
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub dtype: Option<DtypeConfig>,
+    /// Respond with newline-delimited JSON, one `{index, embedding}` line per text as
+    /// its sub-batch completes, instead of a single response
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    pub output_dtype: OutputDtype,
+    #[serde(default)]
+    pub output_quantization: OutputQuantization,
+}
+
+/// Query parameters of `/encode/file`, whose body is the uploaded file
+#[derive(Debug, Deserialize)]
+pub struct EncodeFileQuery {
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub prompt_name: Option<String>,
+    #[serde(default)]
+    pub request_type: Option<RequestType>,
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub dtype: Option<DtypeConfig>,
+    /// Respond with newline-delimited JSON as in a streamed `/encode/batch`
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    pub output_dtype: OutputDtype,
+    #[serde(default)]
+    pub output_quantization: OutputQuantization,
+}
+
+/// Body of `/model/switch`; unknown fields are rejected so a misspelled option isn't
+/// silently ignored
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchModelRequest {
+    pub model_id: String,
+    /// Defaults to `model_id`
+    #[serde(default)]
+    pub tokenizer_repo: Option<String>,
+    #[serde(default)]
+    pub revision: Option<String>,
+    #[serde(default = "default_max_sequence_length")]
+    pub max_sequence_length: usize,
+    #[serde(default = "default_device")]
+    pub device: String,
+    /// GPU ordinal for `cuda` or `metal`; defaults to the first device
+    #[serde(default)]
+    pub device_index: Option<usize>,
+    /// Load `pytorch_model.bin` instead of `model.safetensors`
+    #[serde(default)]
+    pub use_pth: bool,
+    /// Use the tanh approximation of GELU; BERT-family models only
+    #[serde(default)]
+    pub approximate_gelu: bool,
+    #[serde(default)]
+    pub dtype: DtypeConfig,
+}
+
+impl SwitchModelRequest {
+    /// Build the new model's config, keeping deployment settings such as the cache
+    /// directory and download retries from the current one
+    fn into_model_config(self, current: ModelConfig) -> ModelConfig {
+        ModelConfig {
+            tokenizer_repo: self.tokenizer_repo.unwrap_or_else(|| self.model_id.clone()),
+            model_id: self.model_id,
+            revision: self.revision,
+            max_sequence_length: self.max_sequence_length,
+            device: self.device,
+            device_index: self.device_index,
+            use_pth: Some(self.use_pth),
+            approximate_gelu: Some(self.approximate_gelu),
+            dtype: self.dtype,
+            // Prompts and instructions are specific to the model they were written for
+            prompts: HashMap::new(),
+            default_prompt_name: None,
+            query_instruction: None,
+            passage_instruction: None,
+            // Adapters and quantization settings were chosen for the current model's weights
+            lora_adapter_path: None,
+            lora_rank: None,
+            quantization: None,
+            // Describes the loaded model, so it's filled in again once the new one loads
+            embedding_dim: None,
+            ..current
+        }
+    }
+}
+
+fn default_max_sequence_length() -> usize {
+    512
+}
+
+fn default_device() -> String {
+    "auto".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EncodeDocumentRequest {
+    pub text: String,
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_chunk_tokens: Option<usize>,
+    /// `regex` (default), `naive`, or a splitter registered with `ContainerBuilder::with_preprocessor`
+    #[serde(default)]
+    pub splitter: Option<String>,
+}
+
+/// Body of `/encode/query`; the model and prefix come from `[asymmetric]`
+#[derive(Debug, Deserialize)]
+pub struct QueryEncodeRequest {
+    pub text: String,
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    pub output_dtype: OutputDtype,
+    #[serde(default)]
+    pub output_quantization: OutputQuantization,
+}
+
+/// Body of `/encode/passage`; the model and prefix come from `[asymmetric]`
+#[derive(Debug, Deserialize)]
+pub struct PassageEncodeRequest {
+    pub texts: Vec<String>,
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    pub output_dtype: OutputDtype,
+    #[serde(default)]
+    pub output_quantization: OutputQuantization,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RerankApiRequest {
+    pub query: String,
+    pub passages: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexAddApiRequest {
+    pub text: String,
+    /// Replaces the document stored under this id; a sequential id is assigned when unset
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexAddBatchApiRequest {
+    pub texts: Vec<String>,
+    #[serde(default)]
+    pub ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchApiRequest {
+    pub query: String,
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    /// Must produce embeddings from the model the index was built with
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+fn default_top_k() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopKSearchApiRequest {
+    pub query: String,
+    #[serde(default = "default_top_k")]
+    pub k: usize,
+    /// Lowest cosine similarity returned; every top-k match is returned when unset
+    #[serde(default)]
+    pub threshold: Option<f32>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopKSearchResponse {
+    pub results: Vec<SearchResult>,
+    pub query_model_id: String,
+}
+
+/// Documents affected by clearing, saving or loading the index
+#[derive(Debug, Serialize)]
+pub struct IndexDocumentCount {
+    pub documents: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SparseEncodeRequest {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarityMatrixRequest {
+    pub texts: Vec<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub prompt_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareApiRequest {
+    pub text_a: String,
+    pub text_b: String,
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    pub prompt_name: Option<String>,
+    /// `dot` and `euclidean`, reported next to the cosine similarity
+    #[serde(default)]
+    pub metrics: Vec<SimilarityMetric>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReduceApiRequest {
+    pub texts: Vec<String>,
+    pub target_dim: usize,
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Keep the fitted transform for `/encode/reduce/transform`
+    #[serde(default)]
+    pub save: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReduceTransformApiRequest {
+    pub texts: Vec<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IsotropyApiRequest {
+    pub texts: Vec<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenEncodeRequest {
+    pub input_ids: Vec<Vec<u32>>,
+    #[serde(default)]
+    pub attention_mask: Option<Vec<Vec<u32>>>,
     #[serde(default = "default_normalize")]
     pub normalize: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, alias = "output_dimensions")]
+    pub dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelStatsQuery {
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenizeApiRequest {
+    pub text: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Wire format for returned embeddings, mirroring the OpenAI API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingFormat {
+    /// JSON array of numbers
+    #[default]
+    Float,
+    /// Base64 of the little-endian f32 bytes
+    Base64,
+}
+
+impl EncodingFormat {
+    pub const ALL: &'static [Self] = &[Self::Float, Self::Base64];
+}
+
+/// `normalize` of a batch request: one flag for every text, or one per text
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BatchNormalize {
+    All(bool),
+    Each(Vec<bool>),
+}
+
+impl Default for BatchNormalize {
+    fn default() -> Self {
+        Self::All(default_normalize())
+    }
+}
+
+impl BatchNormalize {
+    /// Split into the domain's batch-wide flag and optional per-text override
+    fn into_parts(self) -> (bool, Option<Vec<bool>>) {
+        match self {
+            Self::All(normalize) => (normalize, None),
+            Self::Each(flags) => (default_normalize(), Some(flags)),
+        }
+    }
+}
+
+/// Precision of returned embedding values, independent of the inference dtype
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputDtype {
+    #[default]
+    F32,
+    /// Half precision: 2 bytes per value in base64, and short numbers in JSON arrays
+    F16,
+}
+
+impl OutputDtype {
+    pub const ALL: &'static [Self] = &[Self::F32, Self::F16];
+}
+
+/// Scalar quantization applied to returned embeddings, for vector stores that keep int8
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputQuantization {
+    #[default]
+    None,
+    /// `{"quantized": [...], "scale": ...}` per embedding; `output_dtype` doesn't apply
+    Int8,
+}
+
+impl OutputQuantization {
+    pub const ALL: &'static [Self] = &[Self::None, Self::Int8];
+}
+
+/// How a request wants its embeddings written
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddingOutput {
+    pub format: EncodingFormat,
+    pub dtype: OutputDtype,
+    pub quantization: OutputQuantization,
+}
+
+impl EmbeddingOutput {
+    fn of(format: EncodingFormat, dtype: OutputDtype, quantization: OutputQuantization) -> Self {
+        Self { format, dtype, quantization }
+    }
+}
+
+/// Options this build accepts, for clients that present them rather than hardcode them
+#[derive(Debug, Serialize)]
+pub struct CapabilitiesResponse {
+    #[serde(flatten)]
+    pub model: ModelCapabilities,
+    pub output_formats: OutputCapabilities,
+}
+
+/// Values of the `encoding_format`, `output_dtype` and `output_quantization` request fields
+#[derive(Debug, Serialize)]
+pub struct OutputCapabilities {
+    pub encoding_formats: Vec<EncodingFormat>,
+    pub dtypes: Vec<OutputDtype>,
+    pub quantization: Vec<OutputQuantization>,
+}
+
+/// Model configuration plus the embedding sizes `dimensions` accepts for it
+#[derive(Debug, Serialize)]
+pub struct ModelInfoResponse {
+    #[serde(flatten)]
+    pub config: ModelConfig,
+    /// Empty until the model has loaded and its embedding size is known
+    pub supported_dimensions: Vec<usize>,
+}
+
+impl From<ModelConfig> for ModelInfoResponse {
+    fn from(config: ModelConfig) -> Self {
+        let supported_dimensions = config.embedding_dim.map(matryoshka_dimensions).unwrap_or_default();
+        Self {
+            config,
+            supported_dimensions,
+        }
+    }
+}
+
+/// Embedding serialized according to the requested `EmbeddingOutput`
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EmbeddingData {
+    Float(Vec<f32>),
+    Base64(String),
+    Int8(QuantizedEmbedding),
+}
+
+/// Int8 codes of an embedding; `quantized[i] * scale` approximates value `i`
+#[derive(Debug, Serialize)]
+pub struct QuantizedEmbedding {
+    /// A JSON array, or base64 of the raw bytes for `encoding_format: base64`
+    pub quantized: QuantizedValues,
+    pub scale: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum QuantizedValues {
+    Int8(Vec<i8>),
+    Base64(String),
+}
+
+/// Symmetric scalar quantization: the largest magnitude maps to ±127, so the error per
+/// value is at most `scale / 2`. An all-zero embedding gets a scale of 0
+pub fn quantize_int8(embedding: &[f32]) -> (Vec<i8>, f32) {
+    let max_abs = embedding.iter().fold(0.0f32, |max, value| max.max(value.abs()));
+    if max_abs == 0.0 || !max_abs.is_finite() {
+        return (vec![0; embedding.len()], 0.0);
+    }
+    let scale = max_abs / 127.0;
+    let quantized = embedding
+        .iter()
+        .map(|value| (value / scale).round().clamp(-127.0, 127.0) as i8)
+        .collect();
+    (quantized, scale)
+}
+
+impl EmbeddingData {
+    /// Embeddings arrive normalized in f32; any downcast happens here, last
+    pub fn encode(embedding: Vec<f32>, output: EmbeddingOutput) -> Self {
+        if output.quantization == OutputQuantization::Int8 {
+            let (quantized, scale) = quantize_int8(&embedding);
+            let quantized = match output.format {
+                EncodingFormat::Float => QuantizedValues::Int8(quantized),
+                EncodingFormat::Base64 => {
+                    let bytes: Vec<u8> = quantized.iter().map(|&value| value as u8).collect();
+                    QuantizedValues::Base64(base64::engine::general_purpose::STANDARD.encode(bytes))
+                }
+            };
+            return Self::Int8(QuantizedEmbedding { quantized, scale });
+        }
+        match (output.format, output.dtype) {
+            (EncodingFormat::Float, OutputDtype::F32) => Self::Float(embedding),
+            (EncodingFormat::Float, OutputDtype::F16) => {
+                Self::Float(embedding.into_iter().map(round_to_f16).collect())
+            }
+            (EncodingFormat::Base64, OutputDtype::F32) => {
+                let bytes: Vec<u8> = embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+                Self::Base64(base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+            (EncodingFormat::Base64, OutputDtype::F16) => {
+                let bytes: Vec<u8> = embedding
+                    .iter()
+                    .flat_map(|&value| f16::from_f32(value).to_le_bytes())
+                    .collect();
+                Self::Base64(base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+        }
+    }
+}
+
+/// Round to the nearest f16, then to the 5 significant digits that identify it, so the
+/// value serializes as a short JSON number that still parses back to the same f16
+fn round_to_f16(value: f32) -> f32 {
+    let half = f16::from_f32(value).to_f32();
+    format!("{:.4e}", half).parse().unwrap_or(half)
+}
+
+#[derive(Debug, Serialize)]
+pub struct EncodeResponse {
+    pub embedding: EmbeddingData,
+    pub text: String,
+    pub model_id: String,
+    pub token_count: usize,
+    pub cache_hit: bool,
+}
+
+impl EncodeResponse {
+    fn new(response: EmbeddingResponse, output: EmbeddingOutput) -> Self {
+        Self {
+            embedding: EmbeddingData::encode(response.embedding, output),
+            text: response.text,
+            model_id: response.model_id,
+            token_count: response.token_count,
+            cache_hit: response.cache_hit,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchEncodeResponse {
+    pub embeddings: Vec<EmbeddingData>,
+    pub texts: Vec<String>,
+    pub model_id: String,
+    pub token_counts: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped_ids: Option<Vec<String>>,
+    /// Request positions of empty texts that were dropped; always present so clients can
+    /// realign results without checking for it
+    pub skipped_indices: Vec<usize>,
+}
+
+impl BatchEncodeResponse {
+    fn new(response: BatchEmbeddingResponse, output: EmbeddingOutput) -> Self {
+        Self {
+            embeddings: response
+                .embeddings
+                .into_iter()
+                .map(|embedding| EmbeddingData::encode(embedding, output))
+                .collect(),
+            texts: response.texts,
+            model_id: response.model_id,
+            token_counts: response.token_counts,
+            ids: response.ids,
+            skipped_ids: response.skipped_ids,
+            skipped_indices: response.skipped_indices,
+        }
+    }
+}
+
+/// One embedding of a streamed batch, tagged with its input position and serialized
+/// according to the requested `EmbeddingOutput`
+#[derive(Debug, Serialize)]
+pub struct StreamedEmbedding {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub embedding: EmbeddingData,
+}
+
+impl StreamedEmbedding {
+    fn new(item: IndexedEmbedding, output: EmbeddingOutput) -> Self {
+        Self {
+            index: item.index,
+            id: item.id,
+            embedding: EmbeddingData::encode(item.embedding, output),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -52,54 +704,1872 @@ impl<T> ApiResponse<T> {
     }
 }
 
-type ApiResult<T> = Result<Json<ApiResponse<T>>, StatusCode>;
+type ApiError<T> = (StatusCode, HeaderMap, Json<ApiResponse<T>>);
 
-fn handle_result<T>(result: anyhow::Result<T>) -> ApiResult<T> {
-    match result {
-        Ok(data) => Ok(Json(ApiResponse::success(data))),
-        Err(e) => {
-            tracing::error!("API error: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+/// `Json` extractor whose rejections use the `ApiResponse` envelope, naming the
+/// offending field and the expected type rather than axum's plain-text body
+pub struct ApiJson<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError<()>;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await.map_err(json_rejection_response)?;
+        Ok(Self(value))
+    }
+}
+
+/// Malformed and mistyped bodies are client errors like any other invalid input, so
+/// both get 400; the serde message carries the field path, e.g. `text: invalid type:
+/// integer `123`, expected a string`
+fn json_rejection_response(rejection: JsonRejection) -> ApiError<()> {
+    let detail = std::error::Error::source(&rejection).map(ToString::to_string);
+    let (status, message) = match (&rejection, detail) {
+        (JsonRejection::JsonDataError(_), Some(detail)) => {
+            (StatusCode::BAD_REQUEST, format!("Invalid request body: {}", detail))
         }
+        (JsonRejection::JsonSyntaxError(_), Some(detail)) => {
+            (StatusCode::BAD_REQUEST, format!("Request body is not valid JSON: {}", detail))
+        }
+        _ => (rejection.status(), rejection.body_text()),
+    };
+    tracing::debug!("Rejected request body: {}", message);
+    (status, HeaderMap::new(), Json(ApiResponse::error(message)))
+}
+
+type ApiResult<T> = Result<Json<ApiResponse<T>>, ApiError<T>>;
+
+fn handle_result<T>(result: anyhow::Result<T>) -> ApiResult<T> {
+    result
+        .map(|data| Json(ApiResponse::success(data)))
+        .map_err(error_response)
+}
+
+fn error_response<T>(error: anyhow::Error) -> ApiError<T> {
+    tracing::error!("API error: {}", error);
+    let mut headers = HeaderMap::new();
+    if let Some(InferenceError::CircuitOpen { retry_after_secs }) = error.downcast_ref::<InferenceError>() {
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(*retry_after_secs));
+    }
+    (error_status(&error), headers, Json(ApiResponse::error(error.to_string())))
+}
+
+pub(crate) fn error_status(error: &anyhow::Error) -> StatusCode {
+    match error.downcast_ref::<InferenceError>() {
+        Some(InferenceError::BatchSizeExceeded { .. } | InferenceError::TextTooLong { .. }) => StatusCode::PAYLOAD_TOO_LARGE,
+        Some(InferenceError::ModelNotFound { .. }) => StatusCode::NOT_FOUND,
+        Some(
+            InferenceError::InvalidConfig { .. }
+            | InferenceError::InvalidDimensions { .. }
+            | InferenceError::InvalidInput { .. }
+            | InferenceError::EmptyInput
+            | InferenceError::InputTooLong { .. },
+        ) => StatusCode::BAD_REQUEST,
+        Some(InferenceError::Timeout { .. }) => StatusCode::GATEWAY_TIMEOUT,
+        Some(
+            InferenceError::ModelLoading
+            | InferenceError::ModelNotLoaded
+            | InferenceError::CircuitOpen { .. }
+            | InferenceError::Overloaded { .. },
+        ) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
+/// Span for each HTTP request, parented to the caller's trace when a `traceparent` header is present.
+/// `request_id` is the caller's `X-Request-ID`, or one generated by `SetRequestIdLayer`
+fn request_span(request: &Request) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok());
+    let span = tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id,
+        model_id = tracing::field::Empty,
+    );
+    let parent_context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&opentelemetry_http::HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent_context);
+    span
+}
+
+const MIN_COMPRESSED_RESPONSE_BYTES: u16 = 1024;
+/// Content type of streamed `/encode/batch` responses, which are never compressed
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// API versions served under `/v{N}`; the unversioned paths alias the latest one
+pub const API_VERSIONS: &[u8] = &[1];
+
+static API_VERSION_HEADER: HeaderName = HeaderName::from_static("x-api-version");
+static DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+
 fn default_normalize() -> bool {
     true
 }
 
-pub fn create_router(embedding_use_case: Arc<EmbeddingUseCase>) -> Router {
+/// Serve the API under `/v{N}` for each of `versions`, plus the deprecated unversioned
+/// paths, and the version-independent probes and metrics
+pub fn create_router(container: &DiContainer, versions: &[u8]) -> Router {
+    let probes = Router::new()
+        .route("/healthz/live", get(liveness_probe))
+        .route("/healthz/ready", get(readiness_probe))
+        .route("/healthz/startup", get(startup_probe))
+        .with_state(container.health.clone());
+
+    let mut router = Router::new()
+        .route("/metrics", get(get_metrics))
+        .merge(probes);
+
+    for &version in versions {
+        router = router.nest(
+            &format!("/v{}", version),
+            api_routes(container, version).layer(SetResponseHeaderLayer::overriding(
+                API_VERSION_HEADER.clone(),
+                HeaderValue::from(u16::from(version)),
+            )),
+        );
+    }
+
+    if let Some(&latest) = versions.iter().max() {
+        router = router.merge(
+            api_routes(container, latest)
+                .layer(middleware::from_fn(move |request: Request, next: Next| deprecated_unversioned(latest, request, next))),
+        );
+    }
+
+    let router = if container.server_config.enable_compression {
+        // Small responses such as /health aren't worth the encoding overhead; streams stay
+        // uncompressed so results aren't held back in the encoder (the default predicate
+        // already covers SSE)
+        router.layer(
+            CompressionLayer::new().compress_when(
+                DefaultPredicate::new()
+                    .and(SizeAbove::new(MIN_COMPRESSED_RESPONSE_BYTES))
+                    .and(NotForContentType::const_new(NDJSON_CONTENT_TYPE)),
+            ),
+        )
+    } else {
+        router
+    };
+
+    router
+        // The configured limit replaces axum's default 2 MB cap on JSON bodies
+        .layer(DefaultBodyLimit::disable())
+        // `api_routes` sets the per-route limits; this one only has to admit the larger
+        .layer(RequestBodyLimitLayer::new(
+            container.server_config.max_request_body_bytes.max(container.server_config.max_upload_bytes),
+        ))
+        .layer(middleware::map_response(body_too_large_as_json))
+        .layer(cors_layer(&container.server_config.cors))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        // Outermost so the request span and the response both see the ID
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+/// Routes making up one API version; shapes that change in a later version branch on `version`
+fn api_routes(container: &DiContainer, _version: u8) -> Router {
+    // Reranking is only routed when a cross-encoder is configured
+    let rerank = match &container.rerank_use_case {
+        Some(rerank_use_case) => Router::new()
+            .route("/encode/rerank", post(rerank))
+            .with_state(rerank_use_case.clone()),
+        None => Router::new(),
+    };
+
+    let sparse = match &container.sparse_use_case {
+        Some(sparse_use_case) => Router::new()
+            .route("/sparse", post(encode_sparse))
+            .with_state(sparse_use_case.clone()),
+        None => Router::new(),
+    };
+
+    let asymmetric = match &container.asymmetric_use_case {
+        Some(asymmetric_use_case) => Router::new()
+            .route("/encode/query", post(encode_query))
+            .route("/encode/passage", post(encode_passages))
+            .with_state(asymmetric_use_case.clone()),
+        None => Router::new(),
+    };
+
+    let index = match &container.index_use_case {
+        Some(index_use_case) => Router::new()
+            .route("/index/add", post(index_add))
+            .route("/index/add_batch", post(index_add_batch))
+            .route("/search", post(search))
+            .route("/search/top_k", post(search_top_k))
+            .with_state(index_use_case.clone()),
+        None => Router::new(),
+    };
+
+    let dimensionality_reduction = Router::new()
+        .route("/encode/reduce", post(reduce))
+        .route("/encode/reduce/fit", post(reduce_fit))
+        .route("/encode/reduce/transform", post(reduce_transform))
+        .with_state(container.dimensionality_reduction_use_case.clone());
+
+    let diagnostics = Router::new()
+        .route("/diagnostics/isotropy", post(isotropy))
+        .with_state(container.diagnostics_use_case.clone());
+
+    let load_status = Router::new()
+        .route("/model/status", get(model_load_status))
+        .with_state(container.load_status.clone());
+
+    let registry = Router::new()
+        .route("/models", get(list_models))
+        .with_state(container.model_registry.clone());
+
+    // Loaded models by the name requests select them with; URL-encode `/` in Hub ids
+    let loaded_models = Router::new()
+        .route("/models/:name/info", get(get_loaded_model_info))
+        .with_state(container.model_management_use_case.clone());
+
+    let circuit_breaker = match &container.circuit_breaker {
+        Some(circuit_breaker) => Router::new()
+            .route("/circuit-breaker/status", get(circuit_breaker_status))
+            .with_state(circuit_breaker.clone()),
+        None => Router::new(),
+    };
+
+    let websocket = if container.server_config.enable_websocket {
+        websocket_routes(container.embedding_use_case.clone())
+    } else {
+        Router::new()
+    };
+
+    // Admin endpoints exist only when a token to protect them is configured; they change
+    // which models are loaded, wipe the shared index or touch files on the server, so each
+    // request must carry it
+    let admin = match &container.server_config.admin_token {
+        Some(token) => {
+            let admin_index = match &container.index_use_case {
+                Some(index_use_case) => Router::new()
+                    .route("/index/clear", delete(index_clear))
+                    .route("/index/save", post(index_save))
+                    .route("/index/load", post(index_load))
+                    .route("/index/export", post(index_export))
+                    .with_state(index_use_case.clone()),
+                None => Router::new(),
+            };
+            Router::new()
+                .route("/config", get(get_config))
+                .with_state(container.config_service.clone())
+                .merge(
+                    Router::new()
+                        .route("/model/switch", post(switch_model))
+                        .with_state(container.embedding_use_case.clone()),
+                )
+                .merge(
+                    Router::new()
+                        .route("/models/:name", delete(unload_model))
+                        .with_state(container.model_management_use_case.clone()),
+                )
+                .merge(admin_index)
+                .route_layer(middleware::from_fn_with_state(Arc::<str>::from(token.as_str()), require_admin_token))
+        }
+        None => Router::new(),
+    };
+
     Router::new()
         .route("/health", get(health_check))
+        .route("/health/deep", get(deep_health_check))
+        .route("/limits", get(get_limits))
+        .route("/capabilities", get(get_capabilities))
+        .route("/model/info", get(get_model_info))
+        .route("/model/stats", get(get_model_stats))
         .route("/encode", post(encode_single))
         .route("/encode/batch", post(encode_batch))
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
-        .with_state(embedding_use_case)
+        .route("/encode/stream", post(encode_stream))
+        .route("/encode/tokens", post(encode_tokens))
+        .route("/encode/similarity-matrix", post(similarity_matrix))
+        .route("/similarity/matrix", post(similarity_matrix))
+        .route("/encode/document", post(encode_document))
+        .route("/compare", post(compare))
+        .route("/tokenize", post(tokenize))
+        .with_state(container.embedding_use_case.clone())
+        .merge(rerank)
+        .merge(sparse)
+        .merge(asymmetric)
+        .merge(index)
+        .merge(dimensionality_reduction)
+        .merge(diagnostics)
+        .merge(load_status)
+        .merge(registry)
+        .merge(loaded_models)
+        .merge(circuit_breaker)
+        .merge(websocket)
+        .merge(admin)
+        .route_layer(RequestBodyLimitLayer::new(container.server_config.max_request_body_bytes))
+        // Added after the layer above, so only `max_upload_bytes` bounds uploads
+        .merge(
+            Router::new()
+                .route("/encode/file", post(encode_file))
+                .layer(RequestBodyLimitLayer::new(container.server_config.max_upload_bytes))
+                .with_state(container.embedding_use_case.clone()),
+        )
+}
+
+/// Compare without short-circuiting so response timing doesn't leak the token
+pub(crate) fn token_matches(provided: &[u8], expected: &[u8]) -> bool {
+    provided.len() == expected.len()
+        && provided.iter().zip(expected).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Let a request through to an admin endpoint only with `Authorization: Bearer <admin_token>`
+async fn require_admin_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !token_matches(provided.as_bytes(), token.as_bytes()) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(ApiResponse::<()>::error("Missing or invalid admin token".to_string())),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Mark unversioned paths as deprecated and link the versioned path that replaces them
+async fn deprecated_unversioned(latest: u8, request: Request, next: Next) -> Response {
+    let successor = format!("</v{}{}>; rel=\"successor-version\"", latest, request.uri().path());
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION_HEADER.clone(), HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
+
+/// Give 413s from the body limit the same JSON shape as every other error
+async fn body_too_large_as_json(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        let body = ApiResponse::<()>::error("Request body too large".to_string());
+        return (StatusCode::PAYLOAD_TOO_LARGE, Json(body)).into_response();
+    }
+    response
+}
+
+/// Build the CORS policy; entries were checked by `ServerConfig::validate` at startup
+fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    if config.is_permissive() {
+        return CorsLayer::permissive();
+    }
+
+    let wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+
+
+    let methods = if wildcard(&config.allowed_methods) {
+        AllowMethods::any()
+    } else {
+        AllowMethods::list(
+            config
+                .allowed_methods
+                .iter()
+                .filter_map(|method| method.parse().ok()),
+        )
+    };
+
+    let headers = if wildcard(&config.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(
+            config
+                .allowed_headers
+                .iter()
+                .filter_map(|header| header.parse().ok()),
+        )
+    };
+
+    let layer = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| origin.parse().ok()),
+        ))
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.allow_credentials);
+
+    match config.max_age_secs {
+        Some(secs) => layer.max_age(std::time::Duration::from_secs(secs)),
+        None => layer,
+    }
 }
 
 async fn health_check() -> Json<ApiResponse<&'static str>> {
     Json(ApiResponse::success("Sentence Transformer API is running"))
 }
 
-async fn encode_single(
+/// Run a tiny inference, failing with 503 when the model can't produce a sound embedding,
+/// e.g. because the device is in a bad state
+async fn deep_health_check(
     State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
-    Json(request): Json<EncodeRequest>,
-) -> ApiResult<EmbeddingResponse> {
-    let result = embedding_use_case
-        .encode_single(request.text, request.normalize)
-        .await;
-    handle_result(result)
+) -> ApiResult<InferenceProbe> {
+    embedding_use_case
+        .probe_inference()
+        .await
+        .map(|probe| Json(ApiResponse::success(probe)))
+        .map_err(|error| {
+            tracing::error!("Deep health check failed: {}", error);
+            (StatusCode::SERVICE_UNAVAILABLE, HeaderMap::new(), Json(ApiResponse::error(error.to_string())))
+        })
 }
 
-async fn encode_batch(
-    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
-    Json(request): Json<BatchEncodeRequest>,
-) -> ApiResult<BatchEmbeddingResponse> {
-    let result = embedding_use_case
-        .encode_batch(request.texts, request.normalize)
-        .await;
-    handle_result(result)
+/// Succeeds for as long as the process is able to answer
+async fn liveness_probe() -> StatusCode {
+    StatusCode::OK
 }
 
+/// Succeeds only once a model is loaded and warmed up
+async fn readiness_probe(State(health): State<Arc<ServiceHealth>>) -> StatusCode {
+    probe_status(health.is_ready())
+}
+
+/// Succeeds only after all initialization has completed
+async fn startup_probe(State(health): State<Arc<ServiceHealth>>) -> StatusCode {
+    probe_status(health.is_started())
+}
+
+fn probe_status(healthy: bool) -> StatusCode {
+    if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Prometheus metrics in the text exposition format
+async fn get_metrics() -> Result<String, StatusCode> {
+    metrics::render().map_err(|e| {
+        tracing::error!("Failed to render metrics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn get_limits(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+) -> ApiResult<RequestLimits> {
+    let result = embedding_use_case.get_limits().await;
+    handle_result(result)
+}
+
+async fn get_capabilities() -> ApiResult<CapabilitiesResponse> {
+    handle_result(Ok(CapabilitiesResponse {
+        model: CandleModelLoader::capabilities(),
+        output_formats: OutputCapabilities {
+            encoding_formats: EncodingFormat::ALL.to_vec(),
+            dtypes: OutputDtype::ALL.to_vec(),
+            quantization: OutputQuantization::ALL.to_vec(),
+        },
+    }))
+}
+
+/// Effective configuration with the source of each value, for debugging override precedence
+async fn get_config(State(config_service): State<Arc<FileConfigurationService>>) -> ApiResult<EffectiveConfig> {
+    handle_result(config_service.effective_config())
+}
+
+/// Models in the local cache or configuration, with their download state
+async fn list_models(
+    State(model_registry): State<Arc<dyn ModelRegistry>>,
+) -> ApiResult<Vec<ModelInfo>> {
+    let result = model_registry.list_models().await;
+    handle_result(result)
+}
+
+async fn get_loaded_model_info(
+    State(model_management_use_case): State<Arc<ModelManagementUseCase>>,
+    Path(name): Path<String>,
+) -> ApiResult<ModelInfoResponse> {
+    let result = model_management_use_case.get_model_info(&name).await;
+    handle_result(result.map(ModelInfoResponse::from))
+}
+
+/// State of the most recent model load, with byte counts while a file downloads
+async fn model_load_status(State(load_status): State<LoadStatusTracker>) -> Json<ApiResponse<LoadStatus>> {
+    Json(ApiResponse::success(load_status.get()))
+}
+
+async fn circuit_breaker_status(
+    State(circuit_breaker): State<Arc<CircuitBreaker>>,
+) -> Json<ApiResponse<CircuitBreakerStatus>> {
+    Json(ApiResponse::success(circuit_breaker.status()))
+}
+
+/// Unload a named model to free its memory
+async fn unload_model(
+    State(model_management_use_case): State<Arc<ModelManagementUseCase>>,
+    Path(name): Path<String>,
+) -> ApiResult<()> {
+    let result = model_management_use_case.unload_model(&name).await;
+    handle_result(result)
+}
+
+async fn get_model_info(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+) -> ApiResult<ModelInfoResponse> {
+    let result = embedding_use_case.get_model_info().await;
+    handle_result(result.map(ModelInfoResponse::from))
+}
+
+/// Device, dtype and size of a loaded model, selected with `?model=<name>`
+async fn get_model_stats(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    Query(query): Query<ModelStatsQuery>,
+) -> ApiResult<ModelStats> {
+    let result = embedding_use_case.get_model_stats(query.model.as_deref()).await;
+    handle_result(result)
+}
+
+/// Load the new default model alongside the current one, then swap it in; requests
+/// already running finish on the old model
+async fn switch_model(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<SwitchModelRequest>,
+) -> ApiResult<ModelConfig> {
+    let result = async {
+        let current = embedding_use_case.get_model_info().await?;
+        embedding_use_case.switch_model(request.into_model_config(current)).await?;
+        embedding_use_case.get_model_info().await
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn encode_single(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<EncodeRequest>,
+) -> ApiResult<EncodeResponse> {
+    let result = encode_text(&embedding_use_case, request).await;
+    handle_result(result)
+}
+
+/// Encode one text into its wire response, shared by the HTTP and WebSocket endpoints
+pub(crate) async fn encode_text(embedding_use_case: &EmbeddingUseCase, request: EncodeRequest) -> anyhow::Result<EncodeResponse> {
+    let output = EmbeddingOutput::of(request.encoding_format, request.output_dtype, request.output_quantization);
+    let request = EmbeddingRequest {
+        text: request.text,
+        normalize: request.normalize,
+        model: request.model,
+        dimensions: request.dimensions,
+        prompt_name: request.prompt_name,
+        preprocessing: request.preprocessing,
+        request_type: request.request_type,
+        variant: ModelVariant { device: request.device, dtype: request.dtype },
+    };
+    let response = embedding_use_case.encode_single(request).await?;
+    Ok(EncodeResponse::new(response, output))
+}
+
+async fn encode_batch(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<BatchEncodeRequest>,
+) -> Response {
+    let output = EmbeddingOutput::of(request.encoding_format, request.output_dtype, request.output_quantization);
+    let stream = request.stream;
+    let (normalize, normalize_each) = request.normalize.into_parts();
+    let request = BatchEmbeddingRequest {
+        texts: request.texts,
+        normalize,
+        normalize_each,
+        model: request.model,
+        dimensions: request.dimensions,
+        prompt_name: request.prompt_name,
+        ids: request.ids,
+        request_type: request.request_type,
+        variant: ModelVariant { device: request.device, dtype: request.dtype },
+    };
+    if stream {
+        return encode_batch_ndjson(&embedding_use_case, request, output).await.into_response();
+    }
+    let result = embedding_use_case.encode_batch(request).await;
+    handle_result(result.map(|response| BatchEncodeResponse::new(response, output))).into_response()
+}
+
+/// Stream a batch as newline-delimited JSON over a chunked response; a failed
+/// sub-batch becomes an `{"error": ...}` line since the status is already sent
+async fn encode_batch_ndjson(
+    embedding_use_case: &EmbeddingUseCase,
+    request: BatchEmbeddingRequest,
+    output: EmbeddingOutput,
+) -> Result<Response, ApiError<()>> {
+    let receiver = embedding_use_case
+        .encode_stream(request)
+        .await
+        .map_err(error_response)?;
+
+    let lines = ReceiverStream::new(receiver).map(move |result| {
+        let line = match result {
+            Ok(item) => serde_json::to_vec(&StreamedEmbedding::new(item, output)),
+            Err(e) => {
+                tracing::error!("Stream encoding error: {}", e);
+                serde_json::to_vec(&serde_json::json!({ "error": e.to_string() }))
+            }
+        };
+        line.map(|mut line| {
+            line.push(b'\n');
+            line
+        })
+    });
+    Ok(([(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)], Body::from_stream(lines)).into_response())
+}
+
+/// Embed each line of an uploaded file, sent either as the raw body or as the file
+/// part of a multipart form; blank lines are skipped but keep their index
+async fn encode_file(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    Query(query): Query<EncodeFileQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let texts = match texts_from_upload(content_type, &body) {
+        Ok(texts) => texts,
+        Err(e) => return error_response::<()>(e.into()).into_response(),
+    };
+    drop(body);
+
+    let output = EmbeddingOutput::of(query.encoding_format, query.output_dtype, query.output_quantization);
+    let request = BatchEmbeddingRequest {
+        normalize: query.normalize,
+        model: query.model,
+        dimensions: query.dimensions,
+        prompt_name: query.prompt_name,
+        request_type: query.request_type,
+        variant: ModelVariant { device: query.device, dtype: query.dtype },
+        ..BatchEmbeddingRequest::new(texts)
+    };
+    if query.stream {
+        return encode_batch_ndjson(&embedding_use_case, request, output).await.into_response();
+    }
+    let result = embedding_use_case.encode_lines(request).await;
+    handle_result(result.map(|response| BatchEncodeResponse::new(response, output)))
+        .into_response()
+}
+
+/// Embed a long text as sentence-aligned chunks that each fit the model
+async fn encode_document(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<EncodeDocumentRequest>,
+) -> ApiResult<DocumentEmbeddingResponse> {
+    let request = DocumentEmbeddingRequest {
+        text: request.text,
+        normalize: request.normalize,
+        model: request.model,
+        max_chunk_tokens: request.max_chunk_tokens,
+        splitter: request.splitter,
+    };
+    let result = embedding_use_case.encode_document(request).await;
+    handle_result(result)
+}
+
+/// Embed a search query with the configured query model and prefix
+async fn encode_query(
+    State(asymmetric_use_case): State<Arc<AsymmetricEncodingUseCase>>,
+    ApiJson(request): ApiJson<QueryEncodeRequest>,
+) -> ApiResult<EncodeResponse> {
+    let output = EmbeddingOutput::of(request.encoding_format, request.output_dtype, request.output_quantization);
+    let request = EmbeddingRequest {
+        normalize: request.normalize,
+        dimensions: request.dimensions,
+        ..EmbeddingRequest::new(request.text)
+    };
+    let result = asymmetric_use_case.encode_query(request).await;
+    handle_result(result.map(|response| EncodeResponse::new(response, output)))
+}
+
+/// Embed passages for indexing with the configured passage model and prefix
+async fn encode_passages(
+    State(asymmetric_use_case): State<Arc<AsymmetricEncodingUseCase>>,
+    ApiJson(request): ApiJson<PassageEncodeRequest>,
+) -> ApiResult<BatchEncodeResponse> {
+    let output = EmbeddingOutput::of(request.encoding_format, request.output_dtype, request.output_quantization);
+    let request = BatchEmbeddingRequest {
+        dimensions: request.dimensions,
+        ..BatchEmbeddingRequest::with_normalize(request.texts, request.normalize)
+    };
+    let result = asymmetric_use_case.encode_passages(request).await;
+    handle_result(result.map(|response| BatchEncodeResponse::new(response, output)))
+}
+
+/// Score passages against a query with the cross-encoder, most relevant first in `ranked_indices`
+async fn rerank(
+    State(rerank_use_case): State<Arc<RerankUseCase>>,
+    ApiJson(request): ApiJson<RerankApiRequest>,
+) -> ApiResult<RerankResponse> {
+    let request = RerankRequest {
+        query: request.query,
+        passages: request.passages,
+    };
+    let result = rerank_use_case.rerank(request).await;
+    handle_result(result)
+}
+
+/// Embed a document and store it in the vector index
+async fn index_add(
+    State(index_use_case): State<Arc<IndexUseCase>>,
+    ApiJson(request): ApiJson<IndexAddApiRequest>,
+) -> ApiResult<IndexAddResponse> {
+    let request = IndexAddRequest {
+        texts: vec![request.text],
+        ids: request.id.map(|id| vec![id]),
+        model: request.model,
+    };
+    let result = index_use_case.add(request).await;
+    handle_result(result)
+}
+
+/// Embed documents and store them in the vector index, replacing any with the same ids
+async fn index_add_batch(
+    State(index_use_case): State<Arc<IndexUseCase>>,
+    ApiJson(request): ApiJson<IndexAddBatchApiRequest>,
+) -> ApiResult<IndexAddResponse> {
+    let request = IndexAddRequest {
+        texts: request.texts,
+        ids: request.ids,
+        model: request.model,
+    };
+    let result = index_use_case.add(request).await;
+    handle_result(result)
+}
+
+async fn index_clear(State(index_use_case): State<Arc<IndexUseCase>>) -> ApiResult<IndexDocumentCount> {
+    handle_result(Ok(IndexDocumentCount { documents: index_use_case.clear() }))
+}
+
+/// Persist the index to the configured file
+async fn index_save(State(index_use_case): State<Arc<IndexUseCase>>) -> ApiResult<IndexDocumentCount> {
+    let result = index_use_case.save().await;
+    handle_result(result.map(|documents| IndexDocumentCount { documents }))
+}
+
+/// Write the index in faiss's flat inner-product format, for migrating to an external store
+async fn index_export(State(index_use_case): State<Arc<IndexUseCase>>) -> ApiResult<IndexExportResponse> {
+    let result = index_use_case.export_faiss().await;
+    handle_result(result)
+}
+
+/// Replace the index with the one last saved to the configured file
+async fn index_load(State(index_use_case): State<Arc<IndexUseCase>>) -> ApiResult<IndexDocumentCount> {
+    let result = index_use_case.load().await;
+    handle_result(result.map(|documents| IndexDocumentCount { documents }))
+}
+
+/// Rank indexed documents by cosine similarity to a query
+async fn search(
+    State(index_use_case): State<Arc<IndexUseCase>>,
+    ApiJson(request): ApiJson<SearchApiRequest>,
+) -> ApiResult<SearchResponse> {
+    let request = SearchRequest {
+        query: request.query,
+        top_k: request.top_k,
+        threshold: None,
+        model: request.model,
+    };
+    let result = index_use_case.search(request).await;
+    handle_result(result)
+}
+
+/// The `k` indexed documents most similar to a query, optionally above a score threshold
+async fn search_top_k(
+    State(index_use_case): State<Arc<IndexUseCase>>,
+    ApiJson(request): ApiJson<TopKSearchApiRequest>,
+) -> ApiResult<TopKSearchResponse> {
+    let request = SearchRequest {
+        query: request.query,
+        top_k: request.k,
+        threshold: request.threshold,
+        model: request.model,
+    };
+    let result = index_use_case.search(request).await;
+    handle_result(result.map(|response| TopKSearchResponse {
+        results: response.results,
+        query_model_id: response.model_id,
+    }))
+}
+
+/// SPLADE term weights of a text, as parallel vocabulary id and weight lists
+async fn encode_sparse(
+    State(sparse_use_case): State<Arc<SparseEncodingUseCase>>,
+    ApiJson(request): ApiJson<SparseEncodeRequest>,
+) -> ApiResult<SparseEmbedding> {
+    let request = SparseEmbeddingRequest { text: request.text };
+    let result = sparse_use_case.encode(request).await;
+    handle_result(result)
+}
+
+/// Pairwise cosine similarities of all submitted texts
+async fn similarity_matrix(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<SimilarityMatrixRequest>,
+) -> ApiResult<SimilarityMatrixResponse> {
+    let request = BatchEmbeddingRequest {
+        texts: request.texts,
+        normalize: true,
+        normalize_each: None,
+        model: request.model,
+        dimensions: request.dimensions,
+        prompt_name: request.prompt_name,
+        ids: None,
+        request_type: None,
+        variant: ModelVariant::default(),
+    };
+    let result = embedding_use_case.similarity_matrix(request).await;
+    handle_result(result)
+}
+
+/// Embed two texts and report their cosine similarity, plus any requested distances
+async fn compare(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<CompareApiRequest>,
+) -> ApiResult<CompareResponse> {
+    let request = CompareRequest {
+        text_a: request.text_a,
+        text_b: request.text_b,
+        normalize: request.normalize,
+        model: request.model,
+        dimensions: request.dimensions,
+        prompt_name: request.prompt_name,
+        metrics: request.metrics,
+    };
+    let result = embedding_use_case.compare(request).await;
+    handle_result(result)
+}
+
+/// Encode texts and project them onto their leading principal components
+async fn reduce(
+    State(reduction_use_case): State<Arc<DimensionalityReductionUseCase>>,
+    ApiJson(request): ApiJson<ReduceApiRequest>,
+) -> ApiResult<ReduceResponse> {
+    let request = ReduceRequest {
+        texts: request.texts,
+        target_dim: request.target_dim,
+        normalize: request.normalize,
+        model: request.model,
+        save: request.save,
+    };
+    let result = reduction_use_case.reduce(request).await;
+    handle_result(result)
+}
+
+/// Fit a PCA transform on a corpus and keep it for `/encode/reduce/transform`
+async fn reduce_fit(
+    State(reduction_use_case): State<Arc<DimensionalityReductionUseCase>>,
+    ApiJson(request): ApiJson<ReduceApiRequest>,
+) -> ApiResult<PcaFitResponse> {
+    let request = ReduceRequest {
+        texts: request.texts,
+        target_dim: request.target_dim,
+        normalize: request.normalize,
+        model: request.model,
+        save: true,
+    };
+    let result = reduction_use_case.fit(request).await;
+    handle_result(result)
+}
+
+async fn reduce_transform(
+    State(reduction_use_case): State<Arc<DimensionalityReductionUseCase>>,
+    ApiJson(request): ApiJson<ReduceTransformApiRequest>,
+) -> ApiResult<ReduceResponse> {
+    let result = reduction_use_case.transform(request.texts, request.model).await;
+    handle_result(result)
+}
+
+/// How evenly the model spreads the texts' embeddings, to spot collapsed models
+async fn isotropy(
+    State(diagnostics_use_case): State<Arc<DiagnosticsUseCase>>,
+    ApiJson(request): ApiJson<IsotropyApiRequest>,
+) -> ApiResult<IsotropyResponse> {
+    let result = diagnostics_use_case.isotropy(request.texts, request.model).await;
+    handle_result(result)
+}
+
+/// Encode pre-tokenized input ids, bypassing the built-in tokenizer
+async fn encode_tokens(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<TokenEncodeRequest>,
+) -> ApiResult<TokenEmbeddingResponse> {
+    let request = TokenEmbeddingRequest {
+        input_ids: request.input_ids,
+        attention_mask: request.attention_mask,
+        normalize: request.normalize,
+        model: request.model,
+        dimensions: request.dimensions,
+    };
+    let result = embedding_use_case.encode_tokens(request).await;
+    handle_result(result)
+}
+
+/// Tokenize text with the model's tokenizer, without running the model
+async fn tokenize(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<TokenizeApiRequest>,
+) -> ApiResult<TokenizeResponse> {
+    let request = TokenizeRequest {
+        text: request.text,
+        model: request.model,
+    };
+    let result = embedding_use_case.tokenize(request).await;
+    handle_result(result)
+}
+
+/// Stream batch embeddings as Server-Sent Events, one `{index, embedding}` event per text
+async fn encode_stream(
+    State(embedding_use_case): State<Arc<EmbeddingUseCase>>,
+    ApiJson(request): ApiJson<BatchEncodeRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError<()>> {
+    let output = EmbeddingOutput::of(request.encoding_format, request.output_dtype, request.output_quantization);
+    let (normalize, normalize_each) = request.normalize.into_parts();
+    let request = BatchEmbeddingRequest {
+        texts: request.texts,
+        normalize,
+        normalize_each,
+        model: request.model,
+        dimensions: request.dimensions,
+        prompt_name: request.prompt_name,
+        ids: request.ids,
+        request_type: request.request_type,
+        variant: ModelVariant { device: request.device, dtype: request.dtype },
+    };
+    let receiver = embedding_use_case
+        .encode_stream(request)
+        .await
+        .map_err(error_response)?;
+
+    let stream = ReceiverStream::new(receiver).map(move |result| match result {
+        Ok(item) => Event::default().json_data(StreamedEmbedding::new(item, output)),
+        Err(e) => {
+            tracing::error!("Stream encoding error: {}", e);
+            Ok(Event::default().event("error").data(e.to_string()))
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+    use crate::domain::traits::ModelRepository;
+    use crate::infrastructure::config::{IndexConfig, ServerConfig};
+    use crate::infrastructure::model_loader::CandleModelLoader;
+    use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+    use crate::test_support::{
+        embedding_use_case_with, floats, norm, router, send_json, send_raw, test_container, test_container_with,
+        tiny_model_config, tiny_model_dir, tiny_model_loader, NanEmbeddingService, SlowEmbeddingService, TINY_HIDDEN_SIZE,
+    };
+
+    /// `data` payloads of a Server-Sent Events body, with the event type when one is set
+    fn sse_events(body: &[u8]) -> Vec<(Option<String>, String)> {
+        String::from_utf8_lossy(body)
+            .split("\n\n")
+            .filter(|event| !event.trim().is_empty())
+            .map(|event| {
+                let mut kind = None;
+                let mut data = String::new();
+                for line in event.lines() {
+                    if let Some(value) = line.strip_prefix("event: ") {
+                        kind = Some(value.to_string());
+                    } else if let Some(value) = line.strip_prefix("data: ") {
+                        data.push_str(value);
+                    }
+                }
+                (kind, data)
+            })
+            .collect()
+    }
+
+    fn post(uri: &str, body: serde_json::Value) -> axum::http::Request<Body> {
+        axum::http::Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn encode_stream_sends_one_event_per_text_with_its_index() {
+        let container = test_container(ServerConfig {
+            stream_batch_size: Some(2),
+            ..ServerConfig::default()
+        })
+        .await;
+        let router = router(&container);
+
+        let texts = serde_json::json!({"texts": ["the cat", "", "a dog", "hello world", "the mat"], "ids": ["a", "b", "c", "d", "e"]});
+        let (status, headers, body) = send_raw(&router, post("/v1/encode/stream", texts)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/event-stream");
+        let events = sse_events(&body);
+        let items: Vec<serde_json::Value> = events
+            .iter()
+            .map(|(kind, data)| {
+                assert_eq!(kind, &None);
+                serde_json::from_str(data).unwrap()
+            })
+            .collect();
+        // The empty text is skipped, the rest keep their request positions and ids
+        let indices: Vec<u64> = items.iter().map(|item| item["index"].as_u64().unwrap()).collect();
+        assert_eq!(indices, vec![0, 2, 3, 4]);
+        let ids: Vec<&str> = items.iter().map(|item| item["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["a", "c", "d", "e"]);
+        for item in &items {
+            assert_eq!(floats(&item["embedding"]).len(), TINY_HIDDEN_SIZE);
+        }
+    }
+
+    #[tokio::test]
+    async fn encode_stream_embeddings_match_batch_encoding() {
+        let container = test_container(ServerConfig {
+            stream_batch_size: Some(1),
+            ..ServerConfig::default()
+        })
+        .await;
+        let router = router(&container);
+        let texts = serde_json::json!({"texts": ["the cat", "hello world"]});
+
+        let (_, _, body) = send_raw(&router, post("/v1/encode/stream", texts.clone())).await;
+        let (status, batch) = send_json(&router, Method::POST, "/v1/encode/batch", Some(texts)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        for (i, (_, data)) in sse_events(&body).iter().enumerate() {
+            let item: serde_json::Value = serde_json::from_str(data).unwrap();
+            let streamed = floats(&item["embedding"]);
+            let batched = floats(&batch["data"]["embeddings"][i]);
+            for (a, b) in streamed.iter().zip(&batched) {
+                assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_over_max_batch_size_are_payload_too_large() {
+        let router = router(
+            &test_container(ServerConfig {
+                max_batch_size: 2,
+                ..ServerConfig::default()
+            })
+            .await,
+        );
+
+        let (rejected, body) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": ["the cat", "a dog", "the mat"]})),
+        )
+        .await;
+        let (accepted, _) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": ["the cat", "a dog"]})),
+        )
+        .await;
+
+        assert_eq!(rejected, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(body["error"].as_str().unwrap().contains("exceeds"), "{}", body);
+        assert_eq!(accepted, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn encoding_past_the_request_timeout_is_a_gateway_timeout() {
+        let loader = tiny_model_loader().await;
+        let service = Arc::new(SlowEmbeddingService::new(loader.clone(), std::time::Duration::from_secs(5)));
+        let server_config = ServerConfig {
+            request_timeout_ms: 50,
+            ..ServerConfig::default()
+        };
+        let use_case = embedding_use_case_with(service, loader.clone(), &server_config);
+        let router = router(&test_container_with(loader, use_case, server_config));
+
+        let (status, body) =
+            send_json(&router, Method::POST, "/v1/encode", Some(serde_json::json!({"text": "the cat"}))).await;
 
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["success"], false);
+    }
+
+    #[tokio::test]
+    async fn model_stats_describe_the_loaded_model() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (status, body) = send_json(&router, Method::GET, "/v1/model/stats", None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let stats = &body["data"];
+        assert_eq!(stats["hidden_size"], TINY_HIDDEN_SIZE);
+        assert_eq!(stats["num_layers"], 1);
+        assert_eq!(stats["devices"], serde_json::json!(["cpu"]));
+        assert_eq!(stats["dtype"], "f32");
+        assert_eq!(stats["quantized"], false);
+        // Every weight the encoder loads is in the fixture's safetensors file
+        let weights = candle_core::safetensors::load(tiny_model_dir().join("model.safetensors"), &candle_core::Device::Cpu)
+            .unwrap();
+        let parameters: usize = weights.values().map(|tensor| tensor.elem_count()).sum();
+        assert_eq!(stats["parameter_count"], parameters);
+        assert_eq!(stats["approximate_memory_bytes"], parameters * 4);
+    }
+
+    #[tokio::test]
+    async fn dimensions_above_the_hidden_size_are_rejected() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (status, body) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode",
+            Some(serde_json::json!({"text": "the cat", "dimensions": TINY_HIDDEN_SIZE * 2})),
+        )
+        .await;
+        let (accepted, full) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode",
+            Some(serde_json::json!({"text": "the cat", "dimensions": TINY_HIDDEN_SIZE})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["success"], false);
+        assert_eq!(accepted, StatusCode::OK);
+        assert_eq!(floats(&full["data"]["embedding"]).len(), TINY_HIDDEN_SIZE);
+    }
+
+    fn decode_base64_f32(value: &serde_json::Value) -> Vec<f32> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(value.as_str().expect("base64 embedding is a string"))
+            .unwrap();
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn base64_embeddings_decode_to_the_float_form() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let encode = |format: &str| serde_json::json!({"text": "hello world", "encoding_format": format});
+        let encode_batch = |format: &str| serde_json::json!({"texts": ["the cat", "a dog"], "encoding_format": format});
+
+        let (_, float) = send_json(&router, Method::POST, "/v1/encode", Some(encode("float"))).await;
+        let (status, base64) = send_json(&router, Method::POST, "/v1/encode", Some(encode("base64"))).await;
+        let (_, float_batch) = send_json(&router, Method::POST, "/v1/encode/batch", Some(encode_batch("float"))).await;
+        let (batch_status, base64_batch) =
+            send_json(&router, Method::POST, "/v1/encode/batch", Some(encode_batch("base64"))).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(decode_base64_f32(&base64["data"]["embedding"]), floats(&float["data"]["embedding"]));
+        assert_eq!(batch_status, StatusCode::OK);
+        for i in 0..2 {
+            assert_eq!(
+                decode_base64_f32(&base64_batch["data"]["embeddings"][i]),
+                floats(&float_batch["data"]["embeddings"][i])
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn token_ids_encode_like_the_text_they_came_from() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let texts = ["the cat sleeps on the mat", "hello world"];
+        let mut input_ids = Vec::new();
+        let mut expected = Vec::new();
+        for text in texts {
+            let (_, tokens) =
+                send_json(&router, Method::POST, "/v1/tokenize", Some(serde_json::json!({"text": text}))).await;
+            input_ids.push(tokens["data"]["ids"].clone());
+            let (_, encoded) =
+                send_json(&router, Method::POST, "/v1/encode", Some(serde_json::json!({"text": text}))).await;
+            expected.push(floats(&encoded["data"]["embedding"]));
+        }
+
+        // Rows of different lengths are padded, so the shorter one gets a padded attention mask
+        let (status, body) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/tokens",
+            Some(serde_json::json!({"input_ids": input_ids})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        for (i, expected) in expected.iter().enumerate() {
+            let embedding = floats(&body["data"]["embeddings"][i]);
+            assert_eq!(embedding.len(), expected.len());
+            for (a, b) in embedding.iter().zip(expected) {
+                assert!((a - b).abs() < 1e-5, "row {}: {} != {}", i, a, b);
+            }
+        }
+        assert_eq!(body["data"]["token_counts"], serde_json::json!([8, 4]));
+    }
+
+    fn switch_request(token: Option<&str>, model_id: &str) -> axum::http::Request<Body> {
+        let mut request = post("/v1/model/switch", serde_json::json!({"model_id": model_id, "max_sequence_length": 8}));
+        if let Some(token) = token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+            request.headers_mut().insert(header::AUTHORIZATION, value);
+        }
+        request
+    }
+
+    /// Container serving a vector index that saves and exports into `dir`
+    async fn index_container(server_config: ServerConfig, dir: &std::path::Path) -> crate::DiContainer {
+        let mut container = test_container(server_config).await;
+        let config = IndexConfig {
+            path: dir.join("index.msgpack"),
+            autosave_every: None,
+            export_path: dir.join("index.faiss"),
+        };
+        container.index_use_case = Some(Arc::new(IndexUseCase::new(&config, container.embedding_use_case.clone())));
+        container
+    }
+
+    fn index_admin_request(method: Method, uri: &str, token: Option<&str>) -> axum::http::Request<Body> {
+        let request = axum::http::Request::builder().method(method).uri(uri);
+        let request = match token {
+            Some(token) => request.header(header::AUTHORIZATION, format!("Bearer {}", token)),
+            None => request,
+        };
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn admin_routes_require_the_admin_token() {
+        let model_dir = tiny_model_dir().to_string_lossy().into_owned();
+        let dir = tempfile::tempdir().unwrap();
+        let container = index_container(
+            ServerConfig {
+                admin_token: Some("secret".to_string()),
+                allow_model_paths: true,
+                ..ServerConfig::default()
+            },
+            dir.path(),
+        )
+        .await;
+        let router = router(&container);
+        let (status, _) =
+            send_json(&router, Method::POST, "/v1/index/add", Some(serde_json::json!({"text": "the cat"}))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        for token in [None, Some("wrong"), Some("")] {
+            let (status, _, _) = send_raw(&router, switch_request(token, &model_dir)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", token);
+            for (method, uri) in [(Method::POST, "/v1/index/export"), (Method::DELETE, "/v1/index/clear")] {
+                let (status, _, _) = send_raw(&router, index_admin_request(method, uri, token)).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {:?}", uri, token);
+            }
+        }
+        assert!(!dir.path().join("index.faiss").exists());
+        let (_, search) =
+            send_json(&router, Method::POST, "/v1/search", Some(serde_json::json!({"query": "the cat"}))).await;
+        assert_eq!(search["data"]["results"].as_array().unwrap().len(), 1);
+
+        let (status, _, _) =
+            send_raw(&router, index_admin_request(Method::POST, "/v1/index/export", Some("secret"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(dir.path().join("index.faiss").exists());
+        let (status, _, body) =
+            send_raw(&router, index_admin_request(Method::DELETE, "/v1/index/clear", Some("secret"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["data"]["documents"], 1);
+        let (status, _, _) = send_raw(&router, switch_request(Some("secret"), &model_dir)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, info) = send_json(&router, Method::GET, "/v1/model/info", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(info["data"]["max_sequence_length"], 8);
+    }
+
+    #[tokio::test]
+    async fn config_reports_the_source_of_each_field_to_admins() {
+        let router = router(
+            &test_container(ServerConfig {
+                admin_token: Some("secret".to_string()),
+                ..ServerConfig::default()
+            })
+            .await,
+        );
+        let get_config = |token: Option<&str>| {
+            let request = axum::http::Request::builder().uri("/v1/config");
+            let request = match token {
+                Some(token) => request.header(header::AUTHORIZATION, format!("Bearer {}", token)),
+                None => request,
+            };
+            request.body(Body::empty()).unwrap()
+        };
+
+        let (unauthorized, _, _) = send_raw(&router, get_config(None)).await;
+        let (status, _, body) = send_raw(&router, get_config(Some("secret"))).await;
+
+        assert_eq!(unauthorized, StatusCode::UNAUTHORIZED);
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let sources = &body["data"]["sources"];
+        assert!(["file", "env_var"].contains(&sources["model.model_id"].as_str().unwrap()), "{}", sources);
+        assert!(sources["server.max_batch_size"].is_string());
+    }
+
+    #[tokio::test]
+    async fn admin_routes_are_absent_without_an_admin_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let router = router(&index_container(ServerConfig::default(), dir.path()).await);
+
+        let (status, _, _) = send_raw(&router, switch_request(None, "sentence-transformers/all-MiniLM-L6-v2")).await;
+        let (export, _, _) = send_raw(&router, index_admin_request(Method::POST, "/v1/index/export", None)).await;
+        let (clear, _, _) = send_raw(&router, index_admin_request(Method::DELETE, "/v1/index/clear", None)).await;
+        let (search, _) =
+            send_json(&router, Method::POST, "/v1/search", Some(serde_json::json!({"query": "the cat"}))).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(export, StatusCode::NOT_FOUND);
+        assert_eq!(clear, StatusCode::NOT_FOUND);
+        assert_eq!(search, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn switching_to_a_local_path_is_a_bad_request() {
+        let model_dir = tiny_model_dir().to_string_lossy().into_owned();
+        let router = router(
+            &test_container(ServerConfig {
+                admin_token: Some("secret".to_string()),
+                ..ServerConfig::default()
+            })
+            .await,
+        );
+
+        let (status, _, body) = send_raw(&router, switch_request(Some("secret"), &model_dir)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8_lossy(&body).contains("allow_model_paths"));
+    }
+
+    #[tokio::test]
+    async fn similarity_matrix_is_symmetric_with_a_unit_diagonal() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let texts = serde_json::json!({"texts": ["the cat sleeps", "a dog sleeps", "hello world"]});
+
+        let (status, body) = send_json(&router, Method::POST, "/v1/similarity/matrix", Some(texts.clone())).await;
+        let (_, legacy) = send_json(&router, Method::POST, "/v1/encode/similarity-matrix", Some(texts)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let matrix: Vec<Vec<f32>> = (0..3).map(|i| floats(&body["data"]["matrix"][i])).collect();
+        for (i, row) in matrix.iter().enumerate() {
+            assert_eq!(row.len(), 3);
+            assert!((row[i] - 1.0).abs() < 1e-5, "diagonal {}", row[i]);
+            for (j, value) in row.iter().enumerate() {
+                assert!((value - matrix[j][i]).abs() < 1e-6);
+            }
+        }
+        assert_eq!(legacy["data"]["matrix"], body["data"]["matrix"]);
+    }
+
+    #[tokio::test]
+    async fn encode_is_unavailable_until_background_loading_finishes() {
+        let loader = Arc::new(CandleModelLoader::new());
+        let health = Arc::new(ServiceHealth::new());
+        let service = Arc::new(SentenceTransformerService::new(loader.clone()));
+        let use_case = EmbeddingUseCase::new(service, loader.clone(), 100).with_health(health.clone());
+        let mut container = test_container_with(loader.clone(), use_case, ServerConfig::default());
+        container.health = health.clone();
+        let router = router(&container);
+        let encode = || send_json(&router, Method::POST, "/v1/encode", Some(serde_json::json!({"text": "the cat"})));
+        let probe = |uri: &'static str| {
+            let router = router.clone();
+            async move { send_raw(&router, axum::http::Request::builder().uri(uri).body(Body::empty()).unwrap()).await.0 }
+        };
+
+        let (loading, body) = encode().await;
+        assert_eq!(loading, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body["error"].as_str().unwrap().contains("still loading"), "{}", body);
+        assert_eq!(probe("/healthz/live").await, StatusCode::OK);
+        assert_eq!(probe("/healthz/ready").await, StatusCode::SERVICE_UNAVAILABLE);
+
+        // What the background `ModelStartup` task does once the download completes
+        loader.load_model(&tiny_model_config()).await.unwrap();
+        health.mark_models_loaded();
+        health.mark_warmed_up();
+        health.mark_startup_complete();
+
+        let (loaded, _) = encode().await;
+        assert_eq!(loaded, StatusCode::OK);
+        assert_eq!(probe("/healthz/ready").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn deep_health_runs_a_probe_inference() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (status, body) = send_json(&router, Method::GET, "/v1/health/deep", None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["embedding_dim"], TINY_HIDDEN_SIZE);
+    }
+
+    #[tokio::test]
+    async fn deep_health_fails_when_the_model_returns_nan() {
+        let loader = tiny_model_loader().await;
+        let service = Arc::new(NanEmbeddingService::new(loader.clone()));
+        let use_case = embedding_use_case_with(service, loader.clone(), &ServerConfig::default());
+        let router = router(&test_container_with(loader, use_case, ServerConfig::default()));
+
+        let (status, body) = send_json(&router, Method::GET, "/v1/health/deep", None).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body["error"].as_str().unwrap().contains("non-finite"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn batch_ids_are_echoed_in_order_and_skipped_ones_reported() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (status, body) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": ["the cat", "", "a dog", "  ", "hello world"], "ids": ["c", "b", "a", "e", "d"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let data = &body["data"];
+        assert_eq!(data["ids"], serde_json::json!(["c", "a", "d"]));
+        assert_eq!(data["texts"], serde_json::json!(["the cat", "a dog", "hello world"]));
+        assert_eq!(data["embeddings"].as_array().unwrap().len(), 3);
+        assert_eq!(data["skipped_ids"], serde_json::json!(["b", "e"]));
+        assert_eq!(data["skipped_indices"], serde_json::json!([1, 3]));
+
+        let (status, body) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": ["the cat", "a dog"], "ids": ["only-one"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("ids"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn model_status_follows_the_load_through_each_state() {
+        use hf_hub::api::tokio::Progress;
+        use crate::infrastructure::load_status::DownloadProgress;
+
+        let container = test_container(ServerConfig::default()).await;
+        let router = router(&container);
+        let status = || async {
+            let (status, body) = send_json(&router, Method::GET, "/v1/model/status", None).await;
+            assert_eq!(status, StatusCode::OK);
+            body["data"].clone()
+        };
+        assert_eq!(status().await, serde_json::json!({"state": "idle"}));
+
+        let mut progress = DownloadProgress::new(container.load_status.clone(), "org/model/model.safetensors".to_string());
+        progress.init(1000, "model.safetensors").await;
+        assert_eq!(
+            status().await,
+            serde_json::json!({"state": "downloading", "file": "org/model/model.safetensors", "bytes": 0, "total": 1000})
+        );
+        // Parallel chunks each report through their own clone
+        progress.clone().update(300).await;
+        progress.update(200).await;
+        assert_eq!(status().await["bytes"], 500);
+
+        container.load_status.set(LoadStatus::Loading);
+        assert_eq!(status().await, serde_json::json!({"state": "loading"}));
+
+        let loader = CandleModelLoader::new().with_load_status(container.load_status.clone());
+        loader.load_model(&tiny_model_config()).await.unwrap();
+        assert_eq!(status().await, serde_json::json!({"state": "ready"}));
+
+        let empty = tempfile::tempdir().unwrap();
+        let missing = ModelConfig {
+            model_id: empty.path().to_string_lossy().into_owned(),
+            ..tiny_model_config()
+        };
+        assert!(loader.load_model(&missing).await.is_err());
+        let failed = status().await;
+        assert_eq!(failed["state"], "failed");
+        assert!(failed["message"].as_str().unwrap().contains("config.json not found"), "{}", failed);
+    }
+
+    #[tokio::test]
+    async fn capabilities_list_cpu_and_every_pooling_strategy() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (status, body) = send_json(&router, Method::GET, "/v1/capabilities", None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let data = &body["data"];
+        let strings = |key: &str| -> Vec<String> {
+            data[key].as_array().unwrap().iter().map(|value| value.as_str().unwrap().to_string()).collect()
+        };
+        assert!(strings("devices").contains(&"cpu".to_string()));
+        assert_eq!(strings("pooling_strategies"), vec!["mean", "cls", "max", "mean_sqrt_len", "last_token"]);
+        assert_eq!(strings("architectures"), vec!["bert", "distilbert", "roberta", "mpnet"]);
+        assert_eq!(strings("features").contains(&"cuda".to_string()), cfg!(feature = "cuda"));
+        assert_eq!(strings("features").contains(&"mkl".to_string()), cfg!(feature = "mkl"));
+        assert_eq!(
+            strings("devices").contains(&"cuda".to_string()),
+            candle_core::utils::cuda_is_available()
+        );
+        assert_eq!(data["output_formats"]["encoding_formats"], serde_json::json!(["float", "base64"]));
+        assert_eq!(data["output_formats"]["quantization"], serde_json::json!(["none", "int8"]));
+    }
+
+    #[tokio::test]
+    async fn compare_returns_both_embeddings_and_their_similarity() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let compare = |body: serde_json::Value| {
+            let router = router.clone();
+            async move {
+                let (status, body) = send_json(&router, Method::POST, "/v1/compare", Some(body)).await;
+                assert_eq!(status, StatusCode::OK, "{}", body);
+                body["data"].clone()
+            }
+        };
+
+        let same = compare(serde_json::json!({"text_a": "the cat", "text_b": "the cat", "metrics": ["dot", "euclidean"]}))
+            .await;
+        assert_eq!(floats(&same["embedding_a"]).len(), TINY_HIDDEN_SIZE);
+        assert_eq!(floats(&same["embedding_b"]).len(), TINY_HIDDEN_SIZE);
+        assert!((same["cosine_similarity"].as_f64().unwrap() - 1.0).abs() < 1e-4, "{}", same);
+        assert!((same["dot_product"].as_f64().unwrap() - 1.0).abs() < 1e-4);
+        assert!(same["euclidean_distance"].as_f64().unwrap().abs() < 1e-3);
+
+        // Unnormalized, the metrics still agree with the returned embeddings
+        let different = compare(serde_json::json!({
+            "text_a": "the cat", "text_b": "hello world", "normalize": false, "metrics": ["dot", "euclidean"],
+        }))
+        .await;
+        let (a, b) = (floats(&different["embedding_a"]), floats(&different["embedding_b"]));
+        let dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        let distance = a.iter().zip(&b).map(|(x, y)| (x - y).powi(2)).sum::<f32>().sqrt();
+        let close = |key: &str, expected: f32| {
+            let actual = different[key].as_f64().unwrap() as f32;
+            assert!((actual - expected).abs() < 1e-3 * expected.abs().max(1.0), "{}: {} != {}", key, actual, expected);
+        };
+        close("cosine_similarity", dot / (norm(&a) * norm(&b)));
+        close("dot_product", dot);
+        close("euclidean_distance", distance);
+        assert!(different["cosine_similarity"].as_f64().unwrap() < 0.9999);
+    }
+
+    #[test]
+    fn int8_quantization_round_trips_within_half_a_step() {
+        let embedding: Vec<f32> = (0..64).map(|i| (i as f32 * 0.73).sin() * 0.3).collect();
+
+        let (quantized, scale) = quantize_int8(&embedding);
+
+        let max_abs = embedding.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+        assert!((scale - max_abs / 127.0).abs() < 1e-9);
+        assert!(quantized.iter().any(|&q| q.abs() == 127));
+        for (&q, &v) in quantized.iter().zip(&embedding) {
+            assert!((f32::from(q) * scale - v).abs() <= scale / 2.0 + 1e-7, "{} * {} != {}", q, scale, v);
+        }
+        assert_eq!(quantize_int8(&[0.0; 4]), (vec![0; 4], 0.0));
+    }
+
+    #[tokio::test]
+    async fn int8_output_dequantizes_close_to_the_float_embedding() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let encode = |body: serde_json::Value| {
+            let router = router.clone();
+            async move {
+                let (status, body) = send_json(&router, Method::POST, "/v1/encode", Some(body)).await;
+                assert_eq!(status, StatusCode::OK, "{}", body);
+                body["data"]["embedding"].clone()
+            }
+        };
+
+        let float = floats(&encode(serde_json::json!({"text": "the cat"})).await);
+        let int8 = encode(serde_json::json!({"text": "the cat", "output_quantization": "int8"})).await;
+        let base64 = encode(serde_json::json!({"text": "the cat", "output_quantization": "int8", "encoding_format": "base64"}))
+            .await;
+
+        let scale = int8["scale"].as_f64().unwrap() as f32;
+        let codes: Vec<i8> = int8["quantized"].as_array().unwrap().iter().map(|q| q.as_i64().unwrap() as i8).collect();
+        assert_eq!(codes.len(), TINY_HIDDEN_SIZE);
+        for (&q, &v) in codes.iter().zip(&float) {
+            assert!((f32::from(q) * scale - v).abs() <= scale / 2.0 + 1e-6);
+        }
+        let bytes = base64::engine::general_purpose::STANDARD.decode(base64["quantized"].as_str().unwrap()).unwrap();
+        assert_eq!(bytes.into_iter().map(|b| b as i8).collect::<Vec<_>>(), codes);
+        assert_eq!(base64["scale"], int8["scale"]);
+    }
+
+    fn multipart_upload(file: &str) -> axum::http::Request<Body> {
+        let body = format!(
+            "--XyZ\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nignored\r\n\
+             --XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"texts.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n{}\r\n--XyZ--\r\n",
+            file
+        );
+        axum::http::Request::builder()
+            .method(Method::POST)
+            .uri("/v1/encode/file")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XyZ")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn uploaded_files_get_one_embedding_per_non_empty_line() {
+        let router = router(
+            &test_container(ServerConfig {
+                max_upload_bytes: 512,
+                ..ServerConfig::default()
+            })
+            .await,
+        );
+
+        let (status, _, body) = send_raw(&router, multipart_upload("the cat\r\n\r\na dog\r\nhello world\r\n   ")).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["texts"], serde_json::json!(["the cat", "a dog", "hello world"]));
+        assert_eq!(body["data"]["embeddings"].as_array().unwrap().len(), 3);
+        assert_eq!(body["data"]["skipped_indices"], serde_json::json!([1, 4]));
+
+        let too_large = vec!["the cat"; 100].join("\n");
+        let (status, _, body) = send_raw(&router, multipart_upload(&too_large)).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["success"], false);
+    }
+
+    fn raw_post(body: &'static str, content_type: Option<&str>) -> axum::http::Request<Body> {
+        let request = axum::http::Request::builder().method(Method::POST).uri("/v1/encode");
+        let request = match content_type {
+            Some(content_type) => request.header(header::CONTENT_TYPE, content_type),
+            None => request,
+        };
+        request.body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn malformed_request_bodies_get_structured_errors() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let cases = [
+            (raw_post(r#"{"text": 123}"#, Some("application/json")), StatusCode::BAD_REQUEST, vec!["text", "expected a string"]),
+            (raw_post("{}", Some("application/json")), StatusCode::BAD_REQUEST, vec!["missing field `text`"]),
+            (raw_post(r#"{"text": "#, Some("application/json")), StatusCode::BAD_REQUEST, vec!["not valid JSON"]),
+            (raw_post(r#"{"text": "hi"}"#, None), StatusCode::UNSUPPORTED_MEDIA_TYPE, vec!["Content-Type"]),
+        ];
+        for (request, expected_status, fragments) in cases {
+            let (status, _, body) = send_raw(&router, request).await;
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(status, expected_status, "{}", body);
+            assert_eq!(body["success"], false);
+            assert!(body["data"].is_null());
+            let error = body["error"].as_str().unwrap();
+            for fragment in fragments {
+                assert!(error.contains(fragment), "{:?} not in {}", fragment, error);
+            }
+        }
+    }
+
+    fn gzip_accepting(mut request: axum::http::Request<Body>) -> axum::http::Request<Body> {
+        request.headers_mut().insert(header::ACCEPT_ENCODING, "gzip".parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_gzipped_when_the_client_accepts_it() {
+        use std::io::Read;
+
+        let compressed = router(&test_container(ServerConfig::default()).await);
+        let batch = serde_json::json!({"texts": ["the cat", "a dog", "hello world", "the mat", "cats sleep"]});
+
+        let (status, headers, body) = send_raw(&compressed, gzip_accepting(post("/v1/encode/batch", batch.clone()))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+        let decoded: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded["data"]["embeddings"].as_array().unwrap().len(), 5);
+
+        // Small responses and streams go out as they are
+        let (_, headers, _) = send_raw(
+            &compressed,
+            gzip_accepting(axum::http::Request::builder().uri("/v1/health").body(Body::empty()).unwrap()),
+        )
+        .await;
+        assert!(headers.get(header::CONTENT_ENCODING).is_none());
+        let (_, headers, _) = send_raw(&compressed, gzip_accepting(post("/v1/encode/stream", batch.clone()))).await;
+        assert!(headers.get(header::CONTENT_ENCODING).is_none());
+
+        let uncompressed = router(
+            &test_container(ServerConfig {
+                enable_compression: false,
+                ..ServerConfig::default()
+            })
+            .await,
+        );
+        let (_, headers, _) = send_raw(&uncompressed, gzip_accepting(post("/v1/encode/batch", batch))).await;
+        assert!(headers.get(header::CONTENT_ENCODING).is_none());
+    }
+
+    fn preflight(origin: &str) -> axum::http::Request<Body> {
+        axum::http::Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/v1/encode")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cors_allows_only_configured_origins() {
+        let router = router(
+            &test_container(ServerConfig {
+                cors: CorsConfig {
+                    allowed_origins: vec!["https://app.example.com".to_string()],
+                    allow_credentials: true,
+                    allowed_headers: vec!["content-type".to_string()],
+                    ..CorsConfig::default()
+                },
+                ..ServerConfig::default()
+            })
+            .await,
+        );
+
+        let (status, headers, _) = send_raw(&router, preflight("https://app.example.com")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("POST"));
+
+        let (_, headers, _) = send_raw(&router, preflight("https://evil.example.com")).await;
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        let mut request = post("/v1/encode", serde_json::json!({"text": "hello world"}));
+        request.headers_mut().insert(header::ORIGIN, "https://evil.example.com".parse().unwrap());
+        let (_, headers, _) = send_raw(&router, request).await;
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn cors_is_permissive_by_default() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (_, headers, _) = send_raw(&router, preflight("https://anywhere.example.com")).await;
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    fn uuid_like(id: &str) -> bool {
+        id.len() == 36 && id.chars().filter(|&c| c == '-').count() == 4
+    }
+
+    #[tokio::test]
+    async fn responses_carry_a_request_id() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let health = || axum::http::Request::builder().uri("/v1/health");
+
+        let (_, generated, _) = send_raw(&router, health().body(Body::empty()).unwrap()).await;
+        let (_, echoed, _) =
+            send_raw(&router, health().header("x-request-id", "trace-abc-123").body(Body::empty()).unwrap()).await;
+
+        let generated = generated["x-request-id"].to_str().unwrap();
+        assert!(uuid_like(generated), "{}", generated);
+        assert_eq!(echoed["x-request-id"], "trace-abc-123");
+    }
+
+    #[tokio::test]
+    async fn f16_output_round_trips_within_half_precision() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let encode = |body: serde_json::Value| send_json(&router, Method::POST, "/v1/encode", Some(body));
+
+        let (_, full) = encode(serde_json::json!({"text": "hello world"})).await;
+        let (status, packed) =
+            encode(serde_json::json!({"text": "hello world", "output_dtype": "f16", "encoding_format": "base64"})).await;
+        let (_, array) = encode(serde_json::json!({"text": "hello world", "output_dtype": "f16"})).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let full = floats(&full["data"]["embedding"]);
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(packed["data"]["embedding"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(bytes.len(), full.len() * 2);
+        let decoded: Vec<f32> = bytes
+            .chunks_exact(2)
+            .map(|chunk| half::f16::from_le_bytes([chunk[0], chunk[1]]).to_f32())
+            .collect();
+        for (value, expected) in decoded.iter().zip(&full).chain(floats(&array["data"]["embedding"]).iter().zip(&full)) {
+            // Normalized values are below one, where f16 keeps about three decimal digits
+            assert!((value - expected).abs() < 1e-3, "{} != {}", value, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn per_text_normalize_flags_apply_to_their_own_embeddings() {
+        let router = router(&test_container(ServerConfig::default()).await);
+        let texts = ["the cat", "a dog", "hello world"];
+
+        let (status, mixed) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": texts, "normalize": [true, false, true]})),
+        )
+        .await;
+        let (_, raw) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": texts, "normalize": false})),
+        )
+        .await;
+        let (mismatched, _) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": texts, "normalize": [true, false]})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let norms: Vec<f32> = (0..3).map(|i| norm(&floats(&mixed["data"]["embeddings"][i]))).collect();
+        assert!((norms[0] - 1.0).abs() < 1e-5);
+        assert!((norms[1] - 1.0).abs() > 1e-3, "{}", norms[1]);
+        assert!((norms[2] - 1.0).abs() < 1e-5);
+        assert_eq!(mixed["data"]["embeddings"][1], raw["data"]["embeddings"][1]);
+        assert_eq!(mismatched, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn encode_stream_rejects_an_all_empty_batch_before_streaming() {
+        let router = router(&test_container(ServerConfig::default()).await);
+
+        let (status, body) =
+            send_json(&router, Method::POST, "/v1/encode/stream", Some(serde_json::json!({"texts": ["", " "]}))).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["success"], false);
+    }
+}
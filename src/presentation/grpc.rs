@@ -0,0 +1,356 @@
+use std::sync::Arc;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::{Request, Response, Status};
+
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::domain::entities::{BatchEmbeddingRequest, EmbeddingRequest, ModelConfig, ModelVariant};
+use crate::domain::errors::InferenceError;
+use crate::presentation::api::token_matches;
+
+pub mod proto {
+    tonic::include_proto!("inference");
+}
+
+use proto::inference_server::{Inference, InferenceServer};
+
+/// gRPC front end sharing the REST API's use case and loaded models
+pub struct GrpcInferenceService {
+    embedding_use_case: Arc<EmbeddingUseCase>,
+}
+
+impl GrpcInferenceService {
+    pub fn new(embedding_use_case: Arc<EmbeddingUseCase>) -> Self {
+        Self { embedding_use_case }
+    }
+
+    /// Serve every RPC, letting admin RPCs through only with `admin_token`; without one
+    /// they are refused, as the REST admin routes are absent
+    pub fn into_server(
+        self,
+        admin_token: Option<String>,
+    ) -> InterceptedService<InferenceServer<Self>, AdminTokenInterceptor> {
+        InferenceServer::with_interceptor(self, AdminTokenInterceptor {
+            token: admin_token.map(Arc::from),
+        })
+    }
+}
+
+/// Marks requests that carried the configured admin token
+#[derive(Debug, Clone, Copy)]
+struct AdminAuthorized;
+
+/// Tags requests sending `authorization: Bearer <admin_token>` so admin RPCs can check for
+/// it; other RPCs ignore the tag, so requests are never rejected here
+#[derive(Clone)]
+pub struct AdminTokenInterceptor {
+    token: Option<Arc<str>>,
+}
+
+impl Interceptor for AdminTokenInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.token {
+            let provided = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .unwrap_or_default();
+            if token_matches(provided.as_bytes(), token.as_bytes()) {
+                request.extensions_mut().insert(AdminAuthorized);
+            }
+        }
+        Ok(request)
+    }
+}
+
+fn error_status(error: anyhow::Error) -> Status {
+    tracing::error!("gRPC error: {}", error);
+    let message = error.to_string();
+    match error.downcast_ref::<InferenceError>() {
+        Some(InferenceError::BatchSizeExceeded { .. } | InferenceError::TextTooLong { .. }) => Status::resource_exhausted(message),
+        Some(InferenceError::ModelNotFound { .. }) => Status::not_found(message),
+        Some(InferenceError::Timeout { .. }) => Status::deadline_exceeded(message),
+        Some(
+            InferenceError::ModelLoading
+            | InferenceError::ModelNotLoaded
+            | InferenceError::CircuitOpen { .. }
+            | InferenceError::Overloaded { .. },
+        ) => Status::unavailable(message),
+        Some(
+            InferenceError::InvalidConfig { .. }
+            | InferenceError::InvalidDimensions { .. }
+            | InferenceError::InvalidInput { .. }
+            | InferenceError::EmptyInput
+            | InferenceError::InputTooLong { .. },
+        ) => {
+            Status::invalid_argument(message)
+        }
+        _ => Status::internal(message),
+    }
+}
+
+impl From<ModelConfig> for proto::ModelInfo {
+    fn from(config: ModelConfig) -> Self {
+        Self {
+            model_id: config.model_id,
+            tokenizer_repo: config.tokenizer_repo,
+            revision: config.revision,
+            max_sequence_length: config.max_sequence_length as u32,
+            device: config.device,
+            embedding_dim: config.embedding_dim.map(|dim| dim as u32),
+        }
+    }
+}
+
+impl From<proto::ModelInfo> for ModelConfig {
+    fn from(info: proto::ModelInfo) -> Self {
+        Self {
+            model_id: info.model_id,
+            tokenizer_repo: info.tokenizer_repo,
+            revision: info.revision,
+            max_sequence_length: info.max_sequence_length as usize,
+            device: info.device,
+            ..ModelConfig::default()
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Inference for GrpcInferenceService {
+    async fn encode(
+        &self,
+        request: Request<proto::EncodeRequest>,
+    ) -> Result<Response<proto::EncodeResponse>, Status> {
+        let request = request.into_inner();
+        let request = EmbeddingRequest {
+            text: request.text,
+            normalize: request.normalize.unwrap_or(true),
+            model: request.model,
+            dimensions: request.dimensions.map(|dimensions| dimensions as usize),
+            prompt_name: request.prompt_name,
+            preprocessing: Vec::new(),
+            request_type: None,
+            variant: ModelVariant::default(),
+        };
+        let response = self
+            .embedding_use_case
+            .encode_single(request)
+            .await
+            .map_err(error_status)?;
+
+        Ok(Response::new(proto::EncodeResponse {
+            embedding: response.embedding,
+            text: response.text,
+            model_id: response.model_id,
+            token_count: response.token_count as u32,
+        }))
+    }
+
+    async fn encode_batch(
+        &self,
+        request: Request<proto::EncodeBatchRequest>,
+    ) -> Result<Response<proto::EncodeBatchResponse>, Status> {
+        let request = request.into_inner();
+        let request = BatchEmbeddingRequest {
+            texts: request.texts,
+            normalize: request.normalize.unwrap_or(true),
+            normalize_each: None,
+            model: request.model,
+            dimensions: request.dimensions.map(|dimensions| dimensions as usize),
+            prompt_name: request.prompt_name,
+            ids: None,
+            request_type: None,
+            variant: ModelVariant::default(),
+        };
+        let response = self
+            .embedding_use_case
+            .encode_batch(request)
+            .await
+            .map_err(error_status)?;
+
+        Ok(Response::new(proto::EncodeBatchResponse {
+            embeddings: response
+                .embeddings
+                .into_iter()
+                .map(|values| proto::Embedding { values })
+                .collect(),
+            texts: response.texts,
+            model_id: response.model_id,
+            token_counts: response.token_counts.into_iter().map(|count| count as u32).collect(),
+            skipped_indices: response.skipped_indices.into_iter().map(|index| index as u32).collect(),
+        }))
+    }
+
+    async fn get_model_info(
+        &self,
+        _request: Request<proto::GetModelInfoRequest>,
+    ) -> Result<Response<proto::ModelInfo>, Status> {
+        let config = self
+            .embedding_use_case
+            .get_model_info()
+            .await
+            .map_err(error_status)?;
+        Ok(Response::new(config.into()))
+    }
+
+    async fn switch_model(
+        &self,
+        request: Request<proto::ModelInfo>,
+    ) -> Result<Response<proto::SwitchModelResponse>, Status> {
+        if request.extensions().get::<AdminAuthorized>().is_none() {
+            return Err(Status::permission_denied(
+                "SwitchModel requires the configured admin_token as `authorization: Bearer <token>`",
+            ));
+        }
+        self.embedding_use_case
+            .switch_model(request.into_inner().into())
+            .await
+            .map_err(error_status)?;
+        Ok(Response::new(proto::SwitchModelResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{Method, StatusCode};
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use proto::inference_client::InferenceClient;
+    use tonic::transport::Channel;
+    use crate::infrastructure::config::ServerConfig;
+    use crate::test_support::{floats, router, send_json, test_container, tiny_model_dir};
+
+    fn assert_close(grpc: &[f32], rest: &[f32]) {
+        assert_eq!(grpc.len(), rest.len());
+        for (a, b) in grpc.iter().zip(rest) {
+            assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+        }
+    }
+
+    /// Serve `embedding_use_case` on an ephemeral port and connect a client to it
+    async fn serve(embedding_use_case: Arc<EmbeddingUseCase>, admin_token: Option<&str>) -> InferenceClient<Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = GrpcInferenceService::new(embedding_use_case).into_server(admin_token.map(str::to_string));
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(server)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        InferenceClient::connect(format!("http://{}", addr)).await.unwrap()
+    }
+
+    /// Switch to the tiny model at a shorter `max_sequence_length`, sending `token` if given
+    async fn switch(client: &mut InferenceClient<Channel>, token: Option<&str>) -> Result<(), Status> {
+        let model_dir = tiny_model_dir().to_string_lossy().into_owned();
+        let mut request = Request::new(proto::ModelInfo {
+            model_id: model_dir.clone(),
+            tokenizer_repo: model_dir,
+            max_sequence_length: 8,
+            device: "cpu".to_string(),
+            ..Default::default()
+        });
+        if let Some(token) = token {
+            request
+                .metadata_mut()
+                .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        }
+        client.switch_model(request).await.map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn grpc_embeddings_match_the_rest_api() {
+        let container = test_container(ServerConfig::default()).await;
+        let mut client = serve(container.embedding_use_case.clone(), None).await;
+        let router = router(&container);
+
+        let single = client
+            .encode(proto::EncodeRequest {
+                text: "hello world".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let batch = client
+            .encode_batch(proto::EncodeBatchRequest {
+                texts: vec!["the cat".to_string(), "a dog".to_string()],
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let (status, rest_single) =
+            send_json(&router, Method::POST, "/v1/encode", Some(serde_json::json!({"text": "hello world"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, rest_batch) = send_json(
+            &router,
+            Method::POST,
+            "/v1/encode/batch",
+            Some(serde_json::json!({"texts": ["the cat", "a dog"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        assert_close(&single.embedding, &floats(&rest_single["data"]["embedding"]));
+        assert_eq!(u64::from(single.token_count), rest_single["data"]["token_count"].as_u64().unwrap());
+        for (i, embedding) in batch.embeddings.iter().enumerate() {
+            assert_close(&embedding.values, &floats(&rest_batch["data"]["embeddings"][i]));
+        }
+    }
+
+    #[tokio::test]
+    async fn switch_model_requires_the_admin_token() {
+        let container = test_container(ServerConfig {
+            admin_token: Some("secret".to_string()),
+            allow_model_paths: true,
+            ..ServerConfig::default()
+        })
+        .await;
+        let mut client = serve(container.embedding_use_case, Some("secret")).await;
+
+        for token in [None, Some("wrong"), Some("")] {
+            let status = switch(&mut client, token).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::PermissionDenied, "{:?}", token);
+        }
+        switch(&mut client, Some("secret")).await.unwrap();
+        let info = client
+            .get_model_info(proto::GetModelInfoRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.max_sequence_length, 8);
+    }
+
+    #[tokio::test]
+    async fn switch_model_is_refused_without_an_admin_token() {
+        let container = test_container(ServerConfig {
+            allow_model_paths: true,
+            ..ServerConfig::default()
+        })
+        .await;
+        let mut client = serve(container.embedding_use_case, None).await;
+
+        let status = switch(&mut client, Some("anything")).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn grpc_maps_unknown_models_to_not_found() {
+        let service = GrpcInferenceService::new(test_container(ServerConfig::default()).await.embedding_use_case);
+
+        let status = service
+            .encode(Request::new(proto::EncodeRequest {
+                text: "hello".to_string(),
+                model: Some("missing".to_string()),
+                ..Default::default()
+            }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+}
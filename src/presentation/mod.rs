@@ -1,3 +1,8 @@
 pub mod api;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod shutdown;
+pub mod upload;
+pub mod websocket;
 
-pub use api::*;
\ No newline at end of file
+pub use api::*;
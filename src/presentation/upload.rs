@@ -0,0 +1,75 @@
+use crate::domain::errors::InferenceError;
+
+/// Texts of an uploaded file, one per line. The body is either the file itself or a
+/// `multipart/form-data` form whose file part is used. Blank lines are kept so each
+/// result's index is the line's position in the file.
+pub fn texts_from_upload(content_type: Option<&str>, body: &[u8]) -> Result<Vec<String>, InferenceError> {
+    let file = match content_type.and_then(multipart_boundary) {
+        Some(boundary) => multipart_file(body, &boundary)?,
+        None => body,
+    };
+    let text = std::str::from_utf8(file).map_err(|e| InferenceError::InvalidInput {
+        message: format!("uploaded file is not valid UTF-8: {}", e),
+    })?;
+    // `lines` also strips the `\r` of CRLF line endings
+    Ok(text.lines().map(str::to_string).collect())
+}
+
+/// Boundary parameter of a `multipart/form-data` content type
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Content of the form's file part: the first part with a filename, or else the
+/// part named `file`
+fn multipart_file<'a>(body: &'a [u8], boundary: &str) -> Result<&'a [u8], InferenceError> {
+    let malformed = || InferenceError::InvalidInput {
+        message: "malformed multipart body".to_string(),
+    };
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let part_end = format!("\r\n--{}", boundary).into_bytes();
+
+    let mut rest = &body[find(body, &delimiter).ok_or_else(malformed)? + delimiter.len()..];
+    let mut named_file = None;
+    // Each part starts right after a delimiter; `--` after one marks the end of the form
+    while !rest.starts_with(b"--") {
+        rest = rest.strip_prefix(b"\r\n").ok_or_else(malformed)?;
+        let headers_end = find(rest, b"\r\n\r\n").ok_or_else(malformed)?;
+        let headers = String::from_utf8_lossy(&rest[..headers_end]);
+        rest = &rest[headers_end + 4..];
+        let content_end = find(rest, &part_end).ok_or_else(malformed)?;
+        let content = &rest[..content_end];
+        rest = &rest[content_end + part_end.len()..];
+
+        let Some(disposition) = headers
+            .lines()
+            .find_map(|line| line.split_once(':').filter(|(name, _)| name.trim().eq_ignore_ascii_case("content-disposition")))
+            .map(|(_, value)| value.to_string())
+        else {
+            continue;
+        };
+        let params: Vec<&str> = disposition.split(';').map(str::trim).collect();
+        if params.iter().any(|param| param.starts_with("filename=") || param.starts_with("filename*=")) {
+            return Ok(content);
+        }
+        if named_file.is_none() && params.contains(&"name=\"file\"") {
+            named_file = Some(content);
+        }
+    }
+    named_file.ok_or_else(|| InferenceError::InvalidInput {
+        message: "multipart body has no file part".to_string(),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Router,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::Instrument;
+
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::presentation::api::{encode_text, ApiResponse, EncodeRequest, EncodeResponse};
+
+/// Requests a single connection may have encoding at once; reading further frames
+/// waits until one completes, so a fast client can't spawn unbounded work
+const MAX_IN_FLIGHT_PER_CONNECTION: usize = 64;
+
+/// One encode request on the socket, tagged so its response can be matched up
+#[derive(Debug, Deserialize)]
+pub struct WsEncodeRequest {
+    /// Any JSON value chosen by the client, echoed back unchanged
+    pub id: serde_json::Value,
+    #[serde(flatten)]
+    pub request: EncodeRequest,
+}
+
+/// Response frame; frames arrive in completion order, not request order
+#[derive(Debug, Serialize)]
+pub struct WsEncodeResponse {
+    pub id: serde_json::Value,
+    #[serde(flatten)]
+    pub response: ApiResponse<EncodeResponse>,
+}
+
+pub fn websocket_routes(embedding_use_case: Arc<EmbeddingUseCase>) -> Router {
+    Router::new()
+        .route("/ws/encode", get(upgrade))
+        .with_state(embedding_use_case)
+}
+
+async fn upgrade(State(embedding_use_case): State<Arc<EmbeddingUseCase>>, ws: WebSocketUpgrade) -> Response {
+    // The connection outlives the upgrade request, but its logs still belong to it
+    let span = tracing::Span::current();
+    ws.on_upgrade(move |socket| serve(socket, embedding_use_case).instrument(span))
+}
+
+/// Read newline-delimited JSON requests from text frames, encode each concurrently and
+/// write responses back as they complete
+async fn serve(socket: WebSocket, embedding_use_case: Arc<EmbeddingUseCase>) {
+    let (mut sink, mut stream) = socket.split();
+    let (sender, mut receiver) = mpsc::channel::<WsEncodeResponse>(MAX_IN_FLIGHT_PER_CONNECTION);
+
+    let writer = tokio::spawn(async move {
+        while let Some(response) = receiver.recv().await {
+            let frame = match serde_json::to_string(&response) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    tracing::error!("Failed to serialize WebSocket response: {}", e);
+                    continue;
+                }
+            };
+            if sink.send(Message::Text(frame)).await.is_err() {
+                tracing::debug!("WebSocket closed by client, dropping remaining responses");
+                return;
+            }
+        }
+    });
+
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT_PER_CONNECTION));
+    while let Some(Ok(message)) = stream.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            // Pings are answered by axum; binary frames aren't part of the protocol
+            _ => continue,
+        };
+
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let frame = match serde_json::from_str::<WsEncodeRequest>(line) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    let response = ApiResponse::error(format!("Invalid request frame: {}", e));
+                    let _ = sender.send(WsEncodeResponse { id: serde_json::Value::Null, response }).await;
+                    continue;
+                }
+            };
+
+            let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                return;
+            };
+            let embedding_use_case = embedding_use_case.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let response = match encode_text(&embedding_use_case, frame.request).await {
+                    Ok(response) => ApiResponse::success(response),
+                    Err(e) => {
+                        tracing::error!("WebSocket encode error: {}", e);
+                        ApiResponse::error(e.to_string())
+                    }
+                };
+                let _ = sender.send(WsEncodeResponse { id: frame.id, response }).await;
+                drop(permit);
+            }.in_current_span());
+        }
+    }
+
+    // The writer finishes once every in-flight request has sent its response
+    drop(sender);
+    let _ = writer.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::{SinkExt, StreamExt};
+
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    use crate::infrastructure::config::ServerConfig;
+    use crate::test_support::{floats, norm, router, test_container, TINY_HIDDEN_SIZE};
+
+    /// Serve the test router on an ephemeral port and return its address
+    async fn serve(server_config: ServerConfig) -> std::net::SocketAddr {
+        let app = router(&test_container(server_config).await);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn requests_on_one_socket_get_responses_tagged_with_their_ids() {
+        let addr = serve(ServerConfig {
+            enable_websocket: true,
+            ..ServerConfig::default()
+        })
+        .await;
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/v1/ws/encode", addr)).await.unwrap();
+
+        // Two requests in one frame, newline-delimited, then a malformed one
+        let frame = "{\"id\": 1, \"text\": \"hello world\"}\n{\"id\": \"b\", \"text\": \"the cat\", \"normalize\": false}";
+        socket.send(ClientMessage::Text(frame.to_string())).await.unwrap();
+        socket.send(ClientMessage::Text("{\"text\": 3}".to_string())).await.unwrap();
+
+        let mut responses = HashMap::new();
+        while responses.len() < 3 {
+            let message = socket.next().await.unwrap().unwrap();
+            let response: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            responses.insert(response["id"].to_string(), response);
+        }
+
+        let first = &responses["1"];
+        assert_eq!(first["success"], true);
+        let embedding = floats(&first["data"]["embedding"]);
+        assert_eq!(embedding.len(), TINY_HIDDEN_SIZE);
+        assert!((norm(&embedding) - 1.0).abs() < 1e-4);
+        assert_eq!(responses["\"b\""]["success"], true);
+        assert_eq!(floats(&responses["\"b\""]["data"]["embedding"]).len(), TINY_HIDDEN_SIZE);
+        let invalid = &responses["null"];
+        assert_eq!(invalid["success"], false);
+        assert!(invalid["error"].as_str().unwrap().contains("Invalid request frame"));
+
+        socket.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_endpoint_is_absent_unless_enabled() {
+        let addr = serve(ServerConfig::default()).await;
+        assert!(tokio_tungstenite::connect_async(format!("ws://{}/v1/ws/encode", addr)).await.is_err());
+    }
+}
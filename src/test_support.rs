@@ -0,0 +1,514 @@
+//! Fixtures shared by the unit tests. A tiny BERT model is written to a temporary
+//! directory once per test run, so the real loader, tokenizer and forward pass run
+//! without network access and produce the same embeddings every time.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::{VarBuilder, VarMap};
+use candle_transformers::models::bert::BertModel;
+use futures::stream::BoxStream;
+use tokenizers::models::wordpiece::WordPiece;
+use tokenizers::normalizers::BertNormalizer;
+use tokenizers::pre_tokenizers::bert::BertPreTokenizer;
+use tokenizers::processors::bert::BertProcessing;
+use tokenizers::{AddedToken, Tokenizer};
+use tower::ServiceExt;
+
+use crate::application::diagnostics::DiagnosticsUseCase;
+use crate::application::dimensionality_reduction::DimensionalityReductionUseCase;
+use crate::application::health::ServiceHealth;
+use crate::application::model_management::ModelManagementUseCase;
+use crate::application::use_cases::EmbeddingUseCase;
+use crate::domain::entities::{
+    BatchEmbeddingRequest, BatchEmbeddingResponse, EmbeddingRequest, EmbeddingResponse, ModelConfig, ModelStats,
+    PreprocessingStep, TokenEmbeddingRequest, TokenEmbeddingResponse, TokenizeRequest, TokenizeResponse,
+};
+use crate::domain::traits::{EmbeddingService, ModelRepository};
+use crate::infrastructure::config::{FileConfigurationService, ServerConfig};
+use crate::infrastructure::hf_config::parse_bert_config;
+use crate::infrastructure::load_status::LoadStatusTracker;
+use crate::infrastructure::model_loader::CandleModelLoader;
+use crate::infrastructure::model_registry::FileSystemModelRegistry;
+use crate::infrastructure::preprocessing::{HtmlStripper, MarkdownStripper, NaiveSentenceSplitter, RegexSentenceSplitter};
+use crate::infrastructure::sentence_transformer::SentenceTransformerService;
+use crate::presentation::api::{create_router, API_VERSIONS};
+use crate::DiContainer;
+
+/// Hidden size, and so embedding length, of the tiny model
+pub const TINY_HIDDEN_SIZE: usize = 32;
+/// `max_sequence_length` of `tiny_model_config`
+pub const TINY_MAX_SEQUENCE_LENGTH: usize = 16;
+
+const SPECIAL_TOKENS: &[&str] = &["[PAD]", "[UNK]", "[CLS]", "[SEP]"];
+const WORDS: &[&str] = &[
+    "the", "cat", "cats", "dog", "dogs", "sleep", "sleeps", "lot", "hello", "world", "how", "do", "is", "are",
+    "on", "mat", "and", "nap", "often", "first", "second", "third", "sentence", "apple", "fruit", "car", "query",
+    "passage", "der", "die", "das", "ist", "ein", "hund", "katze", "schlaft", "gut", "health", "check",
+];
+const PUNCTUATION: &[&str] = &[".", ",", "!", "?", "'", "-", ":", ";", "(", ")"];
+
+/// Word-piece vocabulary of the tiny model: special tokens, then letters, digits,
+/// punctuation and a few whole words, so any lowercase ASCII text tokenizes
+fn tiny_vocab() -> HashMap<String, u32> {
+    let letters = ('a'..='z').map(String::from);
+    let continuations = ('a'..='z').map(|c| format!("##{}", c));
+    let digits = ('0'..='9').map(String::from);
+    SPECIAL_TOKENS
+        .iter()
+        .map(|token| token.to_string())
+        .chain(letters)
+        .chain(continuations)
+        .chain(digits)
+        .chain(PUNCTUATION.iter().map(|token| token.to_string()))
+        .chain(WORDS.iter().map(|token| token.to_string()))
+        .enumerate()
+        .map(|(id, token)| (token, id as u32))
+        .collect()
+}
+
+/// Directory holding the tiny model's `config.json`, `tokenizer.json` and
+/// `model.safetensors`, written on first use
+pub fn tiny_model_dir() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("inference-test-model-{}", std::process::id()));
+        write_tiny_model(&dir).expect("tiny test model can be written");
+        dir
+    })
+}
+
+fn write_tiny_model(dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let vocab = tiny_vocab();
+    let config = serde_json::json!({
+        "model_type": "bert",
+        "vocab_size": vocab.len(),
+        "hidden_size": TINY_HIDDEN_SIZE,
+        "num_hidden_layers": 1,
+        "num_attention_heads": 2,
+        "intermediate_size": 64,
+        "hidden_act": "gelu",
+        "max_position_embeddings": 64,
+        "type_vocab_size": 2,
+        "pad_token_id": 0,
+    });
+    let config = serde_json::to_string_pretty(&config)?;
+    std::fs::write(dir.join("config.json"), &config)?;
+
+    let wordpiece = WordPiece::builder()
+        .vocab(vocab)
+        .unk_token("[UNK]".to_string())
+        .build()
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let mut tokenizer = Tokenizer::new(wordpiece);
+    tokenizer
+        .with_normalizer(Some(BertNormalizer::default()))
+        .with_pre_tokenizer(Some(BertPreTokenizer))
+        .with_post_processor(Some(BertProcessing::new(("[SEP]".to_string(), 3), ("[CLS]".to_string(), 2))));
+    let special: Vec<AddedToken> = SPECIAL_TOKENS.iter().map(|token| AddedToken::from(*token, true)).collect();
+    tokenizer.add_special_tokens(&special);
+    tokenizer
+        .save(dir.join("tokenizer.json"), false)
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    let bert_config = parse_bert_config(&config, "tiny")?;
+    write_weights(&dir.join("model.safetensors"), |vb| BertModel::load(vb, &bert_config).map(|_| ()))
+}
+
+/// Copy of the tiny model whose embedding for `word` is NaN, so only texts containing
+/// it come out non-finite, with a config loading it
+pub fn poisoned_model(word: &str) -> (tempfile::TempDir, ModelConfig) {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    for file in ["config.json", "tokenizer.json"] {
+        std::fs::copy(tiny_model_dir().join(file), dir.path().join(file)).expect("tiny model file copies");
+    }
+    let mut tensors = candle_core::safetensors::load(tiny_model_dir().join("model.safetensors"), &Device::Cpu)
+        .expect("tiny model weights load");
+    let (_, word_embeddings) = tensors
+        .iter_mut()
+        .find(|(name, _)| name.ends_with("word_embeddings.weight"))
+        .expect("tiny model has word embeddings");
+    let mut rows = word_embeddings.to_vec2::<f32>().unwrap();
+    rows[tiny_vocab()[word] as usize].fill(f32::NAN);
+    *word_embeddings = Tensor::new(rows, &Device::Cpu).unwrap();
+    candle_core::safetensors::save(&tensors, dir.path().join("model.safetensors")).expect("weights are saved");
+
+    let path = dir.path().to_string_lossy().into_owned();
+    let config = ModelConfig {
+        model_id: path.clone(),
+        tokenizer_repo: path,
+        ..tiny_model_config()
+    };
+    (dir, config)
+}
+
+/// Create the variables `load` reads, give them fixed values and save them; layer
+/// norm scales stay at one so activations keep a sensible range
+pub fn write_weights(
+    path: &Path,
+    load: impl FnOnce(VarBuilder) -> candle_core::Result<()>,
+) -> anyhow::Result<()> {
+    let varmap = VarMap::new();
+    load(VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu))?;
+    let mut names: Vec<(String, candle_core::Var)> = varmap.data().lock().unwrap().clone().into_iter().collect();
+    names.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (seed, (name, var)) in names.iter().enumerate() {
+        if name.ends_with("LayerNorm.weight") || name.ends_with("layer_norm.weight") {
+            continue;
+        }
+        let count = var.elem_count();
+        let values: Vec<f32> = (0..count)
+            .map(|i| ((i as f32 * 0.37 + seed as f32 * 1.3).sin()) * 0.2)
+            .collect();
+        var.set(&Tensor::from_vec(values, var.shape(), &Device::Cpu)?)?;
+    }
+    varmap.save(path)?;
+    Ok(())
+}
+
+/// Config loading the tiny model from its local directory on CPU
+pub fn tiny_model_config() -> ModelConfig {
+    let dir = tiny_model_dir().to_string_lossy().into_owned();
+    ModelConfig {
+        model_id: dir.clone(),
+        tokenizer_repo: dir,
+        revision: None,
+        max_sequence_length: TINY_MAX_SEQUENCE_LENGTH,
+        device: "cpu".to_string(),
+        ..ModelConfig::default()
+    }
+}
+
+/// Loader holding the tiny model as the default model
+pub async fn tiny_model_loader() -> Arc<CandleModelLoader> {
+    let loader = Arc::new(CandleModelLoader::new());
+    loader.load_model(&tiny_model_config()).await.expect("tiny model loads");
+    loader
+}
+
+/// Use case over the tiny model with the settings `lib.rs` derives from `server_config`
+pub fn embedding_use_case(loader: Arc<CandleModelLoader>, server_config: &ServerConfig) -> EmbeddingUseCase {
+    let service = Arc::new(SentenceTransformerService::new(loader.clone()));
+    embedding_use_case_with(service, loader, server_config)
+}
+
+/// Use case encoding with `service` instead of the tiny model's own
+pub fn embedding_use_case_with(
+    service: Arc<dyn EmbeddingService>,
+    loader: Arc<CandleModelLoader>,
+    server_config: &ServerConfig,
+) -> EmbeddingUseCase {
+    let health = Arc::new(ServiceHealth::new());
+    health.mark_models_loaded();
+    let mut use_case = EmbeddingUseCase::new(service, loader, server_config.max_batch_size);
+    if let Some(max_concurrent) = server_config.max_concurrent_inferences {
+        use_case = use_case.with_concurrency_limit(max_concurrent, server_config.overload_mode);
+    }
+    use_case
+        .with_batch_mode(server_config.batch_mode)
+        .with_stream_batch_size(server_config.stream_batch_size.unwrap_or(server_config.max_batch_size))
+        .with_max_similarity_matrix_size(server_config.max_similarity_matrix_size)
+        .with_max_input_chars(server_config.max_input_chars)
+        .with_max_input_bytes(server_config.max_input_bytes)
+        .with_allow_model_paths(server_config.allow_model_paths)
+        .with_health(health)
+        .with_request_timeout(std::time::Duration::from_millis(server_config.request_timeout_ms))
+        .with_preprocessor("naive", Arc::new(NaiveSentenceSplitter))
+        .with_preprocessor("regex", Arc::new(RegexSentenceSplitter::new()))
+        .with_preprocessing_step(PreprocessingStep::Html, Arc::new(HtmlStripper))
+        .with_preprocessing_step(PreprocessingStep::Markdown, Arc::new(MarkdownStripper))
+}
+
+/// Container serving the tiny model with `server_config`; optional features stay off
+pub async fn test_container(server_config: ServerConfig) -> DiContainer {
+    let loader = tiny_model_loader().await;
+    test_container_with(loader.clone(), embedding_use_case(loader, &server_config), server_config)
+}
+
+/// Embedding service that waits `delay` before each encode, standing in for a stalled
+/// or busy device, and records how many encodes ran at once
+pub struct SlowEmbeddingService {
+    inner: SentenceTransformerService,
+    delay: Duration,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl SlowEmbeddingService {
+    pub fn new(loader: Arc<CandleModelLoader>, delay: Duration) -> Self {
+        Self {
+            inner: SentenceTransformerService::new(loader),
+            delay,
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Most encodes that were in progress at the same time
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    async fn wait(&self) {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl EmbeddingService for SlowEmbeddingService {
+    async fn encode(&self, request: EmbeddingRequest) -> anyhow::Result<EmbeddingResponse> {
+        self.wait().await;
+        self.inner.encode(request).await
+    }
+
+    async fn encode_batch(&self, request: BatchEmbeddingRequest) -> anyhow::Result<BatchEmbeddingResponse> {
+        self.wait().await;
+        self.inner.encode_batch(request).await
+    }
+
+    async fn encode_stream<'a>(
+        &'a self,
+        request: BatchEmbeddingRequest,
+        sub_batch_size: usize,
+    ) -> anyhow::Result<BoxStream<'a, anyhow::Result<BatchEmbeddingResponse>>> {
+        self.wait().await;
+        self.inner.encode_stream(request, sub_batch_size).await
+    }
+
+    async fn encode_tokens(&self, request: TokenEmbeddingRequest) -> anyhow::Result<TokenEmbeddingResponse> {
+        self.wait().await;
+        self.inner.encode_tokens(request).await
+    }
+
+    async fn tokenize(&self, request: TokenizeRequest) -> anyhow::Result<TokenizeResponse> {
+        self.inner.tokenize(request).await
+    }
+
+    async fn get_model_info(&self) -> anyhow::Result<ModelConfig> {
+        self.inner.get_model_info().await
+    }
+
+    async fn get_model_stats(&self, model_name: &str) -> anyhow::Result<ModelStats> {
+        self.inner.get_model_stats(model_name).await
+    }
+
+    async fn switch_model(&self, config: ModelConfig) -> anyhow::Result<()> {
+        self.inner.switch_model(config).await
+    }
+}
+
+/// Embedding service whose embeddings come back as NaN, like a device in a bad state
+pub struct NanEmbeddingService {
+    inner: SentenceTransformerService,
+}
+
+impl NanEmbeddingService {
+    pub fn new(loader: Arc<CandleModelLoader>) -> Self {
+        Self {
+            inner: SentenceTransformerService::new(loader).with_finite_check(false),
+        }
+    }
+}
+
+fn poison(embedding: &mut [f32]) {
+    embedding.fill(f32::NAN);
+}
+
+#[async_trait]
+impl EmbeddingService for NanEmbeddingService {
+    async fn encode(&self, request: EmbeddingRequest) -> anyhow::Result<EmbeddingResponse> {
+        let mut response = self.inner.encode(request).await?;
+        poison(&mut response.embedding);
+        Ok(response)
+    }
+
+    async fn encode_batch(&self, request: BatchEmbeddingRequest) -> anyhow::Result<BatchEmbeddingResponse> {
+        let mut response = self.inner.encode_batch(request).await?;
+        response.embeddings.iter_mut().for_each(|embedding| poison(embedding));
+        Ok(response)
+    }
+
+    async fn encode_stream<'a>(
+        &'a self,
+        request: BatchEmbeddingRequest,
+        sub_batch_size: usize,
+    ) -> anyhow::Result<BoxStream<'a, anyhow::Result<BatchEmbeddingResponse>>> {
+        self.inner.encode_stream(request, sub_batch_size).await
+    }
+
+    async fn encode_tokens(&self, request: TokenEmbeddingRequest) -> anyhow::Result<TokenEmbeddingResponse> {
+        self.inner.encode_tokens(request).await
+    }
+
+    async fn tokenize(&self, request: TokenizeRequest) -> anyhow::Result<TokenizeResponse> {
+        self.inner.tokenize(request).await
+    }
+
+    async fn get_model_info(&self) -> anyhow::Result<ModelConfig> {
+        self.inner.get_model_info().await
+    }
+
+    async fn get_model_stats(&self, model_name: &str) -> anyhow::Result<ModelStats> {
+        self.inner.get_model_stats(model_name).await
+    }
+
+    async fn switch_model(&self, config: ModelConfig) -> anyhow::Result<()> {
+        self.inner.switch_model(config).await
+    }
+}
+
+/// Embedding service that reports one named model as running on `device` in `dtype`,
+/// standing in for a GPU variant on a host that only has a CPU
+pub struct RelabeledVariantService {
+    inner: SentenceTransformerService,
+    model_name: String,
+    device: String,
+    dtype: String,
+}
+
+impl RelabeledVariantService {
+    pub fn new(loader: Arc<CandleModelLoader>, model_name: &str, device: &str, dtype: &str) -> Self {
+        Self {
+            inner: SentenceTransformerService::new(loader),
+            model_name: model_name.to_string(),
+            device: device.to_string(),
+            dtype: dtype.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingService for RelabeledVariantService {
+    async fn encode(&self, request: EmbeddingRequest) -> anyhow::Result<EmbeddingResponse> {
+        self.inner.encode(request).await
+    }
+
+    async fn encode_batch(&self, request: BatchEmbeddingRequest) -> anyhow::Result<BatchEmbeddingResponse> {
+        self.inner.encode_batch(request).await
+    }
+
+    async fn encode_stream<'a>(
+        &'a self,
+        request: BatchEmbeddingRequest,
+        sub_batch_size: usize,
+    ) -> anyhow::Result<BoxStream<'a, anyhow::Result<BatchEmbeddingResponse>>> {
+        self.inner.encode_stream(request, sub_batch_size).await
+    }
+
+    async fn encode_tokens(&self, request: TokenEmbeddingRequest) -> anyhow::Result<TokenEmbeddingResponse> {
+        self.inner.encode_tokens(request).await
+    }
+
+    async fn tokenize(&self, request: TokenizeRequest) -> anyhow::Result<TokenizeResponse> {
+        self.inner.tokenize(request).await
+    }
+
+    async fn get_model_info(&self) -> anyhow::Result<ModelConfig> {
+        self.inner.get_model_info().await
+    }
+
+    async fn get_model_stats(&self, model_name: &str) -> anyhow::Result<ModelStats> {
+        let mut stats = self.inner.get_model_stats(model_name).await?;
+        if model_name == self.model_name {
+            stats.devices = vec![self.device.clone()];
+            stats.dtype = self.dtype.clone();
+        }
+        Ok(stats)
+    }
+
+    async fn switch_model(&self, config: ModelConfig) -> anyhow::Result<()> {
+        self.inner.switch_model(config).await
+    }
+}
+
+/// Container around an already configured use case and the repository it encodes with
+pub fn test_container_with(
+    model_repository: Arc<dyn ModelRepository>,
+    embedding_use_case: EmbeddingUseCase,
+    server_config: ServerConfig,
+) -> DiContainer {
+    let embedding_use_case = Arc::new(embedding_use_case);
+    let health = Arc::new(ServiceHealth::new());
+    health.mark_models_loaded();
+    health.mark_warmed_up();
+    health.mark_startup_complete();
+    DiContainer {
+        dimensionality_reduction_use_case: Arc::new(DimensionalityReductionUseCase::new(embedding_use_case.clone())),
+        diagnostics_use_case: Arc::new(DiagnosticsUseCase::new(embedding_use_case.clone())),
+        model_management_use_case: Arc::new(ModelManagementUseCase::new(model_repository)),
+        embedding_use_case,
+        server_config,
+        health,
+        model_registry: Arc::new(FileSystemModelRegistry::from_configs([&tiny_model_config()])),
+        config_service: Arc::new(FileConfigurationService::new_with_environment(Some("test")).expect("test config loads")),
+        circuit_breaker: None,
+        rerank_use_case: None,
+        sparse_use_case: None,
+        asymmetric_use_case: None,
+        index_use_case: None,
+        load_status: LoadStatusTracker::new(),
+    }
+}
+
+/// Router for `container`, as `main` serves it
+pub fn router(container: &DiContainer) -> Router {
+    create_router(container, API_VERSIONS)
+}
+
+/// Send one request through `router`, returning the status, headers and raw body
+pub async fn send_raw(router: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+    let response = router.clone().oneshot(request).await.expect("router is infallible");
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.expect("body can be read");
+    (status, headers, body)
+}
+
+/// Send `body` as JSON, or nothing, to `uri` and parse the JSON response
+pub async fn send_json(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder().method(method).uri(uri);
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .expect("request is valid");
+    let (status, _, body) = send_raw(router, request).await;
+    let json = serde_json::from_slice(&body).unwrap_or_else(|_| {
+        panic!("{} {} returned non-JSON body: {}", status, uri, String::from_utf8_lossy(&body))
+    });
+    (status, json)
+}
+
+/// Values of a JSON embedding array
+pub fn floats(value: &serde_json::Value) -> Vec<f32> {
+    value
+        .as_array()
+        .expect("embedding is an array")
+        .iter()
+        .map(|v| v.as_f64().expect("embedding value is a number") as f32)
+        .collect()
+}
+
+/// L2 norm of an embedding
+pub fn norm(embedding: &[f32]) -> f32 {
+    embedding.iter().map(|v| v * v).sum::<f32>().sqrt()
+}